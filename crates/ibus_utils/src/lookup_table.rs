@@ -126,4 +126,61 @@ impl<'a> IBusLookupTable<'a> {
         self.labels.clear();
         self.cursor_pos = 0;
     }
+
+    /// Populate this table with the closest of `candidates` to `typed`, for "did-you-mean"
+    /// presentation on a near-miss trigger.
+    ///
+    /// Candidates are ranked by Levenshtein edit distance, ties broken alphabetically, and
+    /// only those within a threshold scaled to the typed text's length are kept. Exact matches
+    /// (distance 0) are skipped, since those already expand on their own. Returns the indices
+    /// into `candidates` in the order they were appended, so a caller can map a selected page
+    /// slot back to the originating match.
+    pub fn populate_fuzzy_candidates(&mut self, typed: &str, candidates: &[&'a str]) -> Vec<usize> {
+        let threshold = (typed.chars().count() / 3).max(1);
+
+        let mut ranked: Vec<(usize, usize)> = candidates
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, &candidate)| {
+                let distance = levenshtein_distance(typed, candidate);
+                (distance > 0 && distance <= threshold).then_some((idx, distance))
+            })
+            .collect();
+
+        ranked.sort_by(|&(a_idx, a_dist), &(b_idx, b_dist)| {
+            a_dist
+                .cmp(&b_dist)
+                .then_with(|| candidates[a_idx].cmp(candidates[b_idx]))
+        });
+        ranked.truncate(self.page_size as usize);
+
+        let mapping = ranked.iter().map(|&(idx, _)| idx).collect();
+        for (idx, _) in ranked {
+            self.append_candidate(candidates[idx]);
+        }
+
+        mapping
+    }
+}
+
+/// Levenshtein edit distance between two strings, compared by Unicode scalar value.
+fn levenshtein_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    let mut prev_row: Vec<usize> = (0..=b.len()).collect();
+    let mut cur_row = vec![0usize; b.len() + 1];
+
+    for i in 1..=a.len() {
+        cur_row[0] = i;
+        for j in 1..=b.len() {
+            let substitution_cost = usize::from(a[i - 1] != b[j - 1]);
+            cur_row[j] = (prev_row[j] + 1)
+                .min(cur_row[j - 1] + 1)
+                .min(prev_row[j - 1] + substitution_cost);
+        }
+        std::mem::swap(&mut prev_row, &mut cur_row);
+    }
+
+    prev_row[b.len()]
 }
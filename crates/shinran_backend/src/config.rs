@@ -14,7 +14,7 @@ use rkyv::{
 };
 use shinran_config::{
     all_config_files,
-    config::{generate_match_paths, ParsedConfig, ProfileRef, ProfileStore},
+    config::{generate_match_paths, ParsedConfig, ProfileRef, ProfileStore, RMLVOConfig},
     matches::store::MatchStore,
 };
 
@@ -110,6 +110,18 @@ impl Configuration {
         };
         self.profile_store.active_config(&info)
     }
+
+    /// The active profile's keyboard layout override, if any. On Wayland this is the only way
+    /// to build a keymap before the compositor has sent us one of its own (see
+    /// `shinran_wayland::input_context`), since there's no X11-style auto-detection there.
+    pub fn keyboard_layout(&self) -> Option<RMLVOConfig> {
+        let info = shinran_config::config::AppProperties {
+            title: None,
+            class: None,
+            exec: None,
+        };
+        self.profile_store.active_config(&info).keyboard_layout()
+    }
 }
 
 fn load_cache(cache_path: &Path, config_dir: &Path) -> Result<Configuration> {
@@ -142,8 +154,11 @@ fn load_cache(cache_path: &Path, config_dir: &Path) -> Result<Configuration> {
     }
 
     // Check whether there are any new files that were not present when the cache was created.
-    if all_config_files(config_dir)
-        .with_context(|| "Failed to list all configuration files".to_string())?
+    // Paths skipped by a `.shinranignore`/`.gitignore` rule are irrelevant to the cache check.
+    let (found_config_files, _ignored_config_files) = all_config_files(config_dir)
+        .with_context(|| "Failed to list all configuration files".to_string())?;
+    if found_config_files
+        .iter()
         .any(|found_path| !config_paths_set.contains(&found_path.as_path()))
     {
         anyhow::bail!("New configuration files have been added since cache was created");
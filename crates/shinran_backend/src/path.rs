@@ -31,8 +31,23 @@ pub struct Paths {
     pub config: PathBuf,
     pub runtime: PathBuf,
     pub packages: PathBuf,
+
+    /// Extra package search roots parsed from `SHINRAN_PATH`, in the order they are listed there.
+    /// `resolve_paths` only collects them: nothing here searches them for imports or packages,
+    /// and they never affect `config`, `runtime`, or `packages` resolution above.
+    pub extra_package_dirs: Vec<PathBuf>,
 }
 
+/// Resolve the config/runtime/packages directories, plus the `SHINRAN_PATH` search list.
+///
+/// `config`/`runtime`/`packages` are resolved with the existing portable-mode ->
+/// `$HOME/.shinran`/`$HOME/.config/shinran` -> OS-default precedence (see [`get_config_dir`]),
+/// each overridable by its `force_*_dir` argument. `SHINRAN_PATH` is unrelated to that precedence
+/// chain: borrowing the `RUST_PATH` idea, it's a separate, ordered list of extra roots (parsed
+/// with [`std::env::split_paths`], so `:`-separated on Unix and `;`-separated on Windows) that a
+/// caller resolving a bare-name import/package reference should fall back to, in order, only
+/// after it isn't found under the resolved `packages` dir -- letting users keep shared packages
+/// outside their main config tree.
 pub fn resolve_paths(
     force_config_dir: Option<&Path>,
     force_package_dir: Option<&Path>,
@@ -82,9 +97,18 @@ pub fn resolve_paths(
         config: config_dir,
         runtime: runtime_dir,
         packages: packages_dir,
+        extra_package_dirs: get_extra_package_dirs(),
     }
 }
 
+/// Parse `SHINRAN_PATH` into an ordered list of extra package search roots, or an empty list if
+/// it's unset.
+fn get_extra_package_dirs() -> Vec<PathBuf> {
+    std::env::var_os("SHINRAN_PATH")
+        .map(|paths| std::env::split_paths(&paths).collect())
+        .unwrap_or_default()
+}
+
 fn get_config_dir() -> Option<PathBuf> {
     if let Some(portable_dir) = get_portable_config_dir() {
         // Portable mode
@@ -281,3 +305,58 @@ pub fn load_and_mod_time(path: &Path) -> Result<(Vec<u8>, SystemTime)> {
 
     Ok((content, mod_time))
 }
+
+/// Search `PATH` entry-by-entry for `name`, the same resolution a shell would do when launching
+/// an external program, so the expansion engine can fail early with a clear error instead of
+/// spawning and getting an opaque "not found" -- and so a portable-mode bundle can prepend its
+/// own bin directory onto `PATH` and have it take priority.
+///
+/// `name` containing a path separator (e.g. `./script.sh`, `/usr/bin/foo`) bypasses the `PATH`
+/// scan entirely and is validated directly instead.
+///
+/// On Windows, each `PATH` entry is tried with every extension in `PATHEXT` (falling back to
+/// `.COM;.EXE;.BAT;.CMD` if unset, same as `cmd.exe`) and lookups are case-insensitive, since
+/// Windows filesystems already resolve paths that way. On Unix, a candidate must be a regular
+/// file with at least one executable permission bit set.
+pub fn find_executable(name: &str) -> Option<PathBuf> {
+    if name.contains('/') || name.contains('\\') {
+        let candidate = Path::new(name);
+        return is_executable_file(candidate).then(|| candidate.to_path_buf());
+    }
+
+    let path_var = std::env::var_os("PATH")?;
+    std::env::split_paths(&path_var).find_map(|dir| find_in_dir(&dir, name))
+}
+
+#[cfg(windows)]
+fn find_in_dir(dir: &Path, name: &str) -> Option<PathBuf> {
+    let pathext = std::env::var("PATHEXT").unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".to_string());
+
+    // An already-extensioned name (e.g. `foo.exe`) is tried as-is first.
+    std::iter::once(String::new())
+        .chain(pathext.split(';').map(str::to_string))
+        .find_map(|ext| {
+            let candidate = dir.join(format!("{name}{ext}"));
+            candidate.is_file().then_some(candidate)
+        })
+}
+
+#[cfg(not(windows))]
+fn find_in_dir(dir: &Path, name: &str) -> Option<PathBuf> {
+    let candidate = dir.join(name);
+    is_executable_file(&candidate).then_some(candidate)
+}
+
+#[cfg(windows)]
+fn is_executable_file(path: &Path) -> bool {
+    path.is_file()
+}
+
+#[cfg(not(windows))]
+fn is_executable_file(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    path.metadata()
+        .map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
@@ -0,0 +1,368 @@
+//! Which external tool (if any) espanso shells out to in order to read/write the clipboard, in
+//! the style of Helix's selectable clipboard provider. This only matters for the `Clipboard`
+//! match effect/backend; injection-based expansion never touches the clipboard at all.
+
+use serde::Deserialize;
+
+/// A single shell command run to read or write one clipboard selection.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ClipboardAction {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// The tool used to read/write the clipboard. Deserialized directly from the `clipboard_provider`
+/// config field: either a bare string (`xclip`, `wayland`, ...) or, for `Custom`, a nested
+/// `custom:` mapping giving the yank/paste commands to run.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClipboardProvider {
+    /// Detect a provider from the running session; see `detect_clipboard_provider`.
+    Auto,
+    Wayland,
+    XClip,
+    XSel,
+    Tmux,
+    Termux,
+    /// Shell out to `win32yank.exe` to reach the Windows clipboard from WSL, the way VS Code's
+    /// remote-WSL extension does.
+    Win32Yank,
+    /// Write via the OSC 52 terminal escape sequence (see [`osc52_sequence`]) instead of talking
+    /// to a display server or external tool. The only provider that works over SSH or inside a
+    /// tmux pane with no display server at all, but terminals intentionally expose no "paste"
+    /// side of it, so callers must skip `preserve_clipboard`/restore logic for this provider.
+    Osc52,
+    Custom {
+        yank: ClipboardAction,
+        paste: ClipboardAction,
+        /// Used for the X11 primary selection instead of the clipboard proper, if given.
+        #[serde(default)]
+        primary_yank: Option<ClipboardAction>,
+        #[serde(default)]
+        primary_paste: Option<ClipboardAction>,
+    },
+}
+
+impl ClipboardProvider {
+    /// Map the old `x11_use_xclip_backend`/`x11_use_xdotool_backend` booleans onto the
+    /// equivalent provider, for configs that haven't migrated to `clipboard_provider` yet.
+    /// `xclip` takes precedence if both are somehow set, matching the order the two booleans
+    /// used to be checked in.
+    fn from_legacy_booleans(use_xclip: bool, use_xdotool: bool) -> Option<Self> {
+        if use_xclip {
+            Some(Self::XClip)
+        } else if use_xdotool {
+            Some(Self::Custom {
+                yank: ClipboardAction {
+                    command: "xdotool".to_string(),
+                    args: vec!["key".to_string(), "--clearmodifiers".to_string()],
+                },
+                paste: ClipboardAction {
+                    command: "xdotool".to_string(),
+                    args: vec!["key".to_string(), "--clearmodifiers".to_string()],
+                },
+                primary_yank: None,
+                primary_paste: None,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Resolve the effective provider for a profile: the explicit `clipboard_provider`, or the
+    /// legacy booleans mapped onto an equivalent provider, or `Auto` if neither was set.
+    pub(super) fn resolve(
+        clipboard_provider: Option<&Self>,
+        use_xclip: bool,
+        use_xdotool: bool,
+    ) -> Self {
+        clipboard_provider
+            .cloned()
+            .or_else(|| Self::from_legacy_booleans(use_xclip, use_xdotool))
+            .unwrap_or(Self::Auto)
+    }
+}
+
+/// Which selection an OSC 52 sequence sets: the system clipboard (`c`) or the X11/Wayland
+/// primary selection (`p`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Osc52Selection {
+    Clipboard,
+    Primary,
+}
+
+impl Osc52Selection {
+    fn id(self) -> u8 {
+        match self {
+            Self::Clipboard => b'c',
+            Self::Primary => b'p',
+        }
+    }
+}
+
+/// Build the terminal escape sequence that sets `selection` to `payload` via OSC 52:
+/// `ESC ] 52 ; <selection> ; <base64-of-payload> BEL`. This is self-contained rather than
+/// pulling in a base64 crate, since it's the only place in espanso that needs one.
+pub fn osc52_sequence(payload: &str, selection: Osc52Selection) -> Vec<u8> {
+    let mut sequence = vec![0x1b, 0x5d, 0x35, 0x32, 0x3b, selection.id(), 0x3b];
+    sequence.extend(base64_encode(payload.as_bytes()));
+    sequence.push(0x07);
+    sequence
+}
+
+/// Like [`osc52_sequence`], but `None` if `payload` is over `max_bytes` UTF-8 bytes -- terminals
+/// cap how much of an OSC 52 payload they'll forward to the real clipboard, so callers should
+/// fall back to a plain inject rather than emit a sequence the terminal will just truncate or
+/// drop. `max_bytes` is `ProfileFile::clipboard_osc52_max_bytes()`.
+pub fn osc52_sequence_within_limit(
+    payload: &str,
+    selection: Osc52Selection,
+    max_bytes: usize,
+) -> Option<Vec<u8>> {
+    if payload.len() > max_bytes {
+        return None;
+    }
+    Some(osc52_sequence(payload, selection))
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Standard-alphabet base64 encoding, with `=` padding. Not in the hot path, so clarity over
+/// cleverness: one three-byte input group becomes four 6-bit output indices at a time.
+fn base64_encode(input: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity((input.len() + 2) / 3 * 4);
+    for chunk in input.chunks(3) {
+        let b0 = chunk[0];
+        let b1 = chunk.get(1).copied();
+        let b2 = chunk.get(2).copied();
+
+        output.push(BASE64_ALPHABET[(b0 >> 2) as usize]);
+        output.push(BASE64_ALPHABET[(((b0 & 0b11) << 4) | (b1.unwrap_or(0) >> 4)) as usize]);
+        output.push(match b1 {
+            Some(b1) => BASE64_ALPHABET[(((b1 & 0b1111) << 2) | (b2.unwrap_or(0) >> 6)) as usize],
+            None => b'=',
+        });
+        output.push(match b2 {
+            Some(b2) => BASE64_ALPHABET[(b2 & 0b111111) as usize],
+            None => b'=',
+        });
+    }
+    output
+}
+
+/// A report of what [`diagnose`] found in the running environment, alongside the provider it
+/// resolved `ClipboardProvider::Auto` to. Meant both for startup logging and as something a
+/// future health-check UI can render directly.
+#[derive(Debug, Clone)]
+pub struct ClipboardDiagnostics {
+    pub wayland_display: bool,
+    pub x11_display: bool,
+    pub in_tmux: bool,
+    pub wl_copy_found: bool,
+    pub xclip_found: bool,
+    pub xsel_found: bool,
+    pub tmux_found: bool,
+    pub termux_clipboard_set_found: bool,
+    pub in_wsl: bool,
+    pub win32yank_found: bool,
+    pub ssh_tty: bool,
+    pub chosen: ClipboardProvider,
+}
+
+impl ClipboardDiagnostics {
+    /// A human-readable one-liner explaining the choice, e.g. "wayland detected but wl-copy
+    /// missing, falling back to OSC 52" -- meant to be logged as-is at startup.
+    pub fn summary(&self) -> String {
+        match &self.chosen {
+            ClipboardProvider::Wayland => "wayland session with wl-copy available".to_string(),
+            ClipboardProvider::XClip => "X11 session with xclip available".to_string(),
+            ClipboardProvider::XSel => "X11 session with xsel available".to_string(),
+            ClipboardProvider::Tmux => "running inside tmux with the tmux binary available".to_string(),
+            ClipboardProvider::Termux => "termux-clipboard-set available".to_string(),
+            ClipboardProvider::Win32Yank => {
+                "WSL session with win32yank.exe available".to_string()
+            }
+            ClipboardProvider::Osc52 => {
+                if self.wayland_display && !self.wl_copy_found {
+                    "wayland detected but wl-copy missing, falling back to OSC 52".to_string()
+                } else if self.x11_display && !self.xclip_found && !self.xsel_found {
+                    "X11 detected but neither xclip nor xsel found, falling back to OSC 52"
+                        .to_string()
+                } else if self.ssh_tty {
+                    "remote SSH session with no display server, falling back to OSC 52"
+                        .to_string()
+                } else {
+                    "no display server or clipboard tool detected, falling back to OSC 52"
+                        .to_string()
+                }
+            }
+            ClipboardProvider::Auto | ClipboardProvider::Custom { .. } => {
+                unreachable!("diagnose() only ever resolves to a concrete, non-Custom provider")
+            }
+        }
+    }
+}
+
+/// Probe the environment for the session/tools a concrete clipboard provider would need, and
+/// resolve `ClipboardProvider::Auto` to one of them. Precedence, mirroring Helix's health/
+/// fallback-chain approach: a Wayland session with `wl-copy` on `PATH`, then an X11 session with
+/// `xclip`, then `xsel`, then `tmux` (if running inside a tmux pane), then a WSL distro with
+/// `win32yank.exe` on `PATH`, then Termux's `termux-clipboard-set`, finally OSC 52 -- which is
+/// also where a bare `SSH_TTY` with no display server ends up, since there's no local clipboard
+/// tool to shell out to over a remote session.
+pub fn diagnose() -> ClipboardDiagnostics {
+    let wayland_display = std::env::var_os("WAYLAND_DISPLAY").is_some();
+    let x11_display = std::env::var_os("DISPLAY").is_some();
+    let in_tmux = std::env::var_os("TMUX").is_some();
+    let in_wsl = std::env::var_os("WSL_DISTRO_NAME").is_some();
+    let ssh_tty = std::env::var_os("SSH_TTY").is_some();
+
+    let wl_copy_found = is_on_path("wl-copy");
+    let xclip_found = is_on_path("xclip");
+    let xsel_found = is_on_path("xsel");
+    let tmux_found = is_on_path("tmux");
+    let win32yank_found = is_on_path("win32yank.exe");
+    let termux_clipboard_set_found = is_on_path("termux-clipboard-set");
+
+    let chosen = if wayland_display && wl_copy_found {
+        ClipboardProvider::Wayland
+    } else if x11_display && xclip_found {
+        ClipboardProvider::XClip
+    } else if x11_display && xsel_found {
+        ClipboardProvider::XSel
+    } else if in_tmux && tmux_found {
+        ClipboardProvider::Tmux
+    } else if in_wsl && win32yank_found {
+        ClipboardProvider::Win32Yank
+    } else if termux_clipboard_set_found {
+        ClipboardProvider::Termux
+    } else {
+        ClipboardProvider::Osc52
+    };
+
+    ClipboardDiagnostics {
+        wayland_display,
+        x11_display,
+        in_tmux,
+        wl_copy_found,
+        xclip_found,
+        xsel_found,
+        tmux_found,
+        termux_clipboard_set_found,
+        in_wsl,
+        win32yank_found,
+        ssh_tty,
+        chosen,
+    }
+}
+
+/// Resolve `ClipboardProvider::Auto` to a concrete provider via [`diagnose`]; any other variant
+/// (including `Custom`) is returned unchanged.
+pub fn detect_clipboard_provider(provider: ClipboardProvider) -> ClipboardProvider {
+    match provider {
+        ClipboardProvider::Auto => diagnose().chosen,
+        other => other,
+    }
+}
+
+/// Whether `name` resolves to an executable file somewhere on `PATH`, without shelling out.
+fn is_on_path(name: &str) -> bool {
+    let Some(path_var) = std::env::var_os("PATH") else {
+        return false;
+    };
+    std::env::split_paths(&path_var).any(|dir| is_executable_file(&dir.join(name)))
+}
+
+#[cfg(unix)]
+fn is_executable_file(path: &std::path::Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::metadata(path)
+        .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable_file(path: &std::path::Path) -> bool {
+    path.is_file()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_encodes_without_padding() {
+        assert_eq!(base64_encode(b"Man"), b"TWFu");
+    }
+
+    #[test]
+    fn base64_pads_one_leftover_byte() {
+        assert_eq!(base64_encode(b"M"), b"TQ==");
+    }
+
+    #[test]
+    fn base64_pads_two_leftover_bytes() {
+        assert_eq!(base64_encode(b"Ma"), b"TWE=");
+    }
+
+    #[test]
+    fn osc52_sequence_wraps_clipboard_selection() {
+        let sequence = osc52_sequence("hi", Osc52Selection::Clipboard);
+        assert_eq!(sequence, b"\x1b]52;c;aGk=\x07");
+    }
+
+    #[test]
+    fn osc52_sequence_wraps_primary_selection() {
+        let sequence = osc52_sequence("hi", Osc52Selection::Primary);
+        assert_eq!(sequence, b"\x1b]52;p;aGk=\x07");
+    }
+
+    #[test]
+    fn osc52_sequence_within_limit_passes_through_short_payloads() {
+        assert_eq!(
+            osc52_sequence_within_limit("hi", Osc52Selection::Clipboard, 100),
+            Some(osc52_sequence("hi", Osc52Selection::Clipboard))
+        );
+    }
+
+    #[test]
+    fn osc52_sequence_within_limit_rejects_oversized_payloads() {
+        assert_eq!(
+            osc52_sequence_within_limit("hello", Osc52Selection::Clipboard, 4),
+            None
+        );
+    }
+
+    #[test]
+    fn is_on_path_finds_a_real_binary() {
+        // `sh` is about as safe a bet as any for "definitely on PATH" in a test environment.
+        assert!(is_on_path("sh"));
+    }
+
+    #[test]
+    fn is_on_path_rejects_a_made_up_name() {
+        assert!(!is_on_path("definitely-not-a-real-binary-xyz"));
+    }
+
+    #[test]
+    fn detect_clipboard_provider_passes_through_non_auto() {
+        assert_eq!(
+            detect_clipboard_provider(ClipboardProvider::Osc52),
+            ClipboardProvider::Osc52
+        );
+        assert_eq!(
+            detect_clipboard_provider(ClipboardProvider::Win32Yank),
+            ClipboardProvider::Win32Yank
+        );
+    }
+
+    #[test]
+    fn detect_clipboard_provider_resolves_auto_to_something_concrete() {
+        assert_ne!(
+            detect_clipboard_provider(ClipboardProvider::Auto),
+            ClipboardProvider::Auto
+        );
+    }
+}
@@ -0,0 +1,189 @@
+/*
+ * This file is part of espanso.
+ *
+ * Copyright (C) 2019-2021 Federico Terzi
+ *
+ * espanso is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * espanso is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with espanso.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Resolve a Linux app's `desktop_id` (for `filter_desktop`) from a bare `exec`: the
+//! `StartupWMClass` of the `.desktop` entry whose `Exec=` names that program, so apps that share a
+//! generic `WM_CLASS` (e.g. every Chromium-based browser reporting `class: "Chrome"`) can still be
+//! told apart, the same way Chromium's own shell integration derives its window class.
+
+use std::path::{Path, PathBuf};
+
+/// Directories `.desktop` files live in, per the XDG base directory spec: `XDG_DATA_HOME`
+/// (defaulting to `~/.local/share`) followed by each of `XDG_DATA_DIRS` (defaulting to
+/// `/usr/local/share:/usr/share`), each with `applications` appended. Earlier entries win, since
+/// they shadow the same desktop ids in later ones.
+fn desktop_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+
+    let data_home = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")));
+    if let Some(data_home) = data_home {
+        dirs.push(data_home.join("applications"));
+    }
+
+    let data_dirs = std::env::var("XDG_DATA_DIRS")
+        .unwrap_or_else(|_| "/usr/local/share:/usr/share".to_string());
+    for dir in data_dirs.split(':').filter(|dir| !dir.is_empty()) {
+        dirs.push(PathBuf::from(dir).join("applications"));
+    }
+
+    dirs
+}
+
+/// Whether a `.desktop` file's `Exec=` command line (e.g. `google-chrome-stable %U`) refers to the
+/// same program as `exec` (e.g. `google-chrome-stable` or a full path to it): compare the first
+/// whitespace-separated token's file name, ignoring arguments and `%`-field codes.
+fn exec_matches(desktop_exec: &str, exec: &str) -> bool {
+    let desktop_name = desktop_exec
+        .split_whitespace()
+        .next()
+        .and_then(|cmd| Path::new(cmd).file_name())
+        .and_then(|name| name.to_str());
+    let exec_name = Path::new(exec).file_name().and_then(|name| name.to_str());
+
+    matches!((desktop_name, exec_name), (Some(a), Some(b)) if a == b)
+}
+
+/// Pull the `Exec=` and `StartupWMClass=` values out of a `.desktop` file's `[Desktop Entry]`
+/// group. Espanso only cares about these two keys, so this doesn't bother with a full INI parser.
+fn parse_desktop_entry(contents: &str) -> (Option<&str>, Option<&str>) {
+    let mut exec = None;
+    let mut startup_wm_class = None;
+    let mut in_desktop_entry_group = false;
+
+    for line in contents.lines() {
+        let line = line.trim();
+        if let Some(group) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            in_desktop_entry_group = group == "Desktop Entry";
+            continue;
+        }
+        if !in_desktop_entry_group {
+            continue;
+        }
+
+        if let Some(value) = line.strip_prefix("Exec=") {
+            exec = Some(value);
+        } else if let Some(value) = line.strip_prefix("StartupWMClass=") {
+            startup_wm_class = Some(value);
+        }
+    }
+
+    (exec, startup_wm_class)
+}
+
+/// Resolve the `desktop_id` `filter_desktop` should match against for a running app's `exec`: the
+/// `StartupWMClass` of the `.desktop` entry whose `Exec=` names the same program, or (if that key
+/// isn't set) the entry's own file stem (e.g. `google-chrome.desktop` -> `google-chrome`). Returns
+/// `None` if no matching entry is found.
+#[cfg(target_os = "linux")]
+pub fn resolve_desktop_id(exec: &str) -> Option<String> {
+    for dir in desktop_dirs() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("desktop") {
+                continue;
+            }
+
+            let Ok(contents) = std::fs::read_to_string(&path) else {
+                continue;
+            };
+            let (desktop_exec, startup_wm_class) = parse_desktop_entry(&contents);
+            let Some(desktop_exec) = desktop_exec else {
+                continue;
+            };
+            if !exec_matches(desktop_exec, exec) {
+                continue;
+            }
+
+            return startup_wm_class.map(str::to_string).or_else(|| {
+                path.file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .map(str::to_string)
+            });
+        }
+    }
+
+    None
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn resolve_desktop_id(_exec: &str) -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exec_matches_ignores_args_and_field_codes() {
+        assert!(exec_matches(
+            "google-chrome-stable %U",
+            "google-chrome-stable"
+        ));
+        assert!(exec_matches(
+            "/usr/bin/google-chrome-stable %U",
+            "google-chrome-stable"
+        ));
+        assert!(!exec_matches("firefox %u", "google-chrome-stable"));
+    }
+
+    #[test]
+    fn parse_desktop_entry_reads_exec_and_startup_wm_class() {
+        let contents = "\
+[Desktop Entry]
+Name=Google Chrome
+Exec=/usr/bin/google-chrome-stable %U
+StartupWMClass=Google-chrome
+Icon=google-chrome
+";
+
+        let (exec, startup_wm_class) = parse_desktop_entry(contents);
+        assert_eq!(exec, Some("/usr/bin/google-chrome-stable %U"));
+        assert_eq!(startup_wm_class, Some("Google-chrome"));
+    }
+
+    #[test]
+    fn parse_desktop_entry_ignores_keys_outside_the_desktop_entry_group() {
+        let contents = "\
+[Desktop Action NewWindow]
+Exec=/usr/bin/google-chrome-stable --new-window
+
+[Desktop Entry]
+Exec=/usr/bin/google-chrome-stable %U
+";
+
+        let (exec, startup_wm_class) = parse_desktop_entry(contents);
+        assert_eq!(exec, Some("/usr/bin/google-chrome-stable %U"));
+        assert_eq!(startup_wm_class, None);
+    }
+
+    #[test]
+    fn parse_desktop_entry_missing_startup_wm_class_falls_back_to_none() {
+        let contents = "[Desktop Entry]\nExec=vim %F\n";
+        let (exec, startup_wm_class) = parse_desktop_entry(contents);
+        assert_eq!(exec, Some("vim %F"));
+        assert_eq!(startup_wm_class, None);
+    }
+}
@@ -0,0 +1,815 @@
+/*
+ * This file is part of espanso.
+ *
+ * Copyright (C) 2019-2021 Federico Terzi
+ *
+ * espanso is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * espanso is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with espanso.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! A small `cfg(...)`-like boolean expression language for matching profiles
+//! against [`AppProperties`], e.g. `any(class = "firefox", all(title ~ "Terminal", not(exec = "vim")))`.
+//! `os = "linux"` (or `~`) matches against the running platform rather than `AppProperties`;
+//! see [`Key::Os`].
+
+use globset::{Glob, GlobBuilder, GlobMatcher};
+use regex::Regex;
+use serde::Deserialize;
+use thiserror::Error;
+
+use super::AppProperties;
+
+/// How a `filter_title`/`filter_class`/`filter_exec` pattern's case is matched, set via the
+/// `filter_case` config field. Defaults to `Sensitive`, so existing configs keep matching exactly
+/// as before.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterCase {
+    Sensitive,
+    Insensitive,
+    /// Case-insensitive unless `pattern` itself contains an uppercase character, the same
+    /// heuristic `fd` uses for its `--smart-case` flag.
+    Smart,
+}
+
+impl FilterCase {
+    fn is_case_insensitive(self, pattern: &str) -> bool {
+        match self {
+            FilterCase::Sensitive => false,
+            FilterCase::Insensitive => true,
+            FilterCase::Smart => !pattern.chars().any(char::is_uppercase),
+        }
+    }
+}
+
+impl Default for FilterCase {
+    fn default() -> Self {
+        FilterCase::Sensitive
+    }
+}
+
+/// One of the properties an [`Atom`] can test against. `Os` doesn't come from [`AppProperties`]
+/// at all; it's always `std::env::consts::OS` (`"linux"`, `"macos"`, `"windows"`, ...), letting a
+/// profile be restricted to a platform the same way `filter_title`/`filter_class`/`filter_exec`
+/// restrict it to an application.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Title,
+    Class,
+    Exec,
+    /// The app's full executable path, e.g. `/usr/bin/google-chrome-stable`.
+    Path,
+    /// The window's `WM_WINDOW_ROLE` (X11) or equivalent, which can distinguish a browser's main
+    /// window from its picture-in-picture or settings windows even though they share a `class`.
+    Role,
+    /// The resolved desktop entry id (see [`crate::config::desktop_entry`]): typically the
+    /// `StartupWMClass` of the app's `.desktop` file, which can disambiguate apps that share a
+    /// generic `class`/`WM_CLASS` (e.g. every Chromium-based app reporting `class: "Chrome"`).
+    Desktop,
+    Os,
+    /// The running CPU architecture, always `std::env::consts::ARCH` (`"x86_64"`, `"aarch64"`,
+    /// ...) -- lets a profile narrow an `os` filter further, e.g. `linux` on `aarch64`.
+    Arch,
+}
+
+/// Either a single string or a list of them, for config fields that accept both a bare value and
+/// a YAML sequence of alternatives (OR-matched).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl OneOrMany {
+    fn as_slice(&self) -> &[String] {
+        match self {
+            Self::One(value) => std::slice::from_ref(value),
+            Self::Many(values) => values,
+        }
+    }
+}
+
+/// The `filter_os` config value: a bare OS name or list of them (`linux`, `[linux, macos]`), or a
+/// structured form that further scopes to one or more CPU architectures via [`Key::Arch`], e.g.
+/// `{ os: linux, arch: aarch64 }` to target only Linux on ARM.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum FilterOsValue {
+    Bare(OneOrMany),
+    Scoped {
+        os: OneOrMany,
+        #[serde(default)]
+        arch: Option<OneOrMany>,
+    },
+}
+
+impl FilterOsValue {
+    fn os(&self) -> &[String] {
+        match self {
+            Self::Bare(os) | Self::Scoped { os, .. } => os.as_slice(),
+        }
+    }
+
+    fn arch(&self) -> &[String] {
+        match self {
+            Self::Bare(_) => &[],
+            Self::Scoped { arch, .. } => arch.as_ref().map_or(&[], OneOrMany::as_slice),
+        }
+    }
+}
+
+/// How an [`Atom`]'s value is compared against the property.
+#[derive(Debug, Clone)]
+pub enum Op {
+    /// `=`, literal equality.
+    Eq(String),
+    /// `~`, regex match.
+    Match(Regex),
+    /// A legacy `filter_title`/`filter_class`/`filter_exec` pattern, regex or glob (see
+    /// [`FilterPattern`]).
+    Pattern(FilterPattern),
+}
+
+/// A `filter_title`/`filter_class`/`filter_exec` value compiled once at load time. A leading
+/// `glob:` selects glob syntax (the same `glob`/brace-alternate dialect `includes`/`excludes`
+/// use, see [`crate::matches::group::path`]); anything else is a regex, as it always has been.
+#[derive(Debug, Clone)]
+pub enum FilterPattern {
+    Regex(Regex),
+    Glob(GlobMatcher),
+}
+
+impl FilterPattern {
+    const GLOB_PREFIX: &'static str = "glob:";
+
+    pub fn parse(pattern: &str) -> Result<Self, FilterExprError> {
+        Self::parse_with_case(pattern, FilterCase::default())
+    }
+
+    /// Like [`Self::parse`], but matches case-(in)sensitively according to `case` (see
+    /// [`FilterCase`]) instead of always case-sensitively.
+    pub fn parse_with_case(pattern: &str, case: FilterCase) -> Result<Self, FilterExprError> {
+        if let Some(glob_pattern) = pattern.strip_prefix(Self::GLOB_PREFIX) {
+            let glob = GlobBuilder::new(glob_pattern)
+                .case_insensitive(case.is_case_insensitive(glob_pattern))
+                .build()
+                .map_err(|e| FilterExprError::InvalidGlob(e.to_string()))?;
+            Ok(Self::Glob(glob.compile_matcher()))
+        } else {
+            // The case-insensitive flag is embedded as an inline `(?i)` modifier rather than set
+            // via `RegexBuilder`, so `Regex::as_str()` (which `ConfigMatcher` re-parses patterns
+            // from) keeps reflecting it.
+            let source = if case.is_case_insensitive(pattern) {
+                format!("(?i){pattern}")
+            } else {
+                pattern.to_string()
+            };
+            Ok(Self::Regex(Regex::new(&source).map_err(|e| {
+                FilterExprError::InvalidRegex(e.to_string())
+            })?))
+        }
+    }
+
+    fn is_match(&self, value: &str) -> bool {
+        match self {
+            Self::Regex(regex) => regex.is_match(value),
+            Self::Glob(glob) => glob.is_match(value),
+        }
+    }
+}
+
+/// A single `key op "value"` test, e.g. `class = "firefox"`.
+#[derive(Debug, Clone)]
+pub struct Atom {
+    pub key: Key,
+    pub op: Op,
+}
+
+/// The AST of a filter expression.
+#[derive(Debug, Clone)]
+pub enum Expr {
+    All(Vec<Expr>),
+    Any(Vec<Expr>),
+    Not(Box<Expr>),
+    Atom(Atom),
+}
+
+impl Expr {
+    /// Build an `all(...)` of atoms from the existing `filter_title`/`filter_class`/`filter_exec`
+    /// pattern lists (each OR-matched: the filter is satisfied if any pattern in the list matches),
+    /// their `_not` negative counterparts (also OR-matched, then vetoing the whole match if any of
+    /// them matches), `filter_path`/`filter_role`/`filter_desktop` (OR-matched the same way as
+    /// their counterparts, no negative form), and `filter_os` (an exact match against [`Key::Os`]),
+    /// so the legacy fields and `filter_expr` can coexist.
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_legacy_filters(
+        title: &[FilterPattern],
+        class: &[FilterPattern],
+        exec: &[FilterPattern],
+        title_not: &[FilterPattern],
+        class_not: &[FilterPattern],
+        exec_not: &[FilterPattern],
+        path: &[FilterPattern],
+        role: &[FilterPattern],
+        desktop: &[FilterPattern],
+        os: Option<&FilterOsValue>,
+    ) -> Option<Self> {
+        let mut atoms = Vec::new();
+        Self::push_positive(&mut atoms, Key::Title, title);
+        Self::push_positive(&mut atoms, Key::Class, class);
+        Self::push_positive(&mut atoms, Key::Exec, exec);
+        Self::push_negative(&mut atoms, Key::Title, title_not);
+        Self::push_negative(&mut atoms, Key::Class, class_not);
+        Self::push_negative(&mut atoms, Key::Exec, exec_not);
+        Self::push_positive(&mut atoms, Key::Path, path);
+        Self::push_positive(&mut atoms, Key::Role, role);
+        Self::push_positive(&mut atoms, Key::Desktop, desktop);
+        if let Some(os) = os {
+            Self::push_positive_eq(&mut atoms, Key::Os, os.os());
+            Self::push_positive_eq(&mut atoms, Key::Arch, os.arch());
+        }
+
+        if atoms.is_empty() {
+            None
+        } else {
+            Some(Expr::All(atoms))
+        }
+    }
+
+    /// Push `any(key = p1, key = p2, ...)` (or a bare atom, for a single pattern) onto `atoms`.
+    fn push_positive(atoms: &mut Vec<Expr>, key: Key, patterns: &[FilterPattern]) {
+        if let Some(expr) = Self::any_of(key, patterns) {
+            atoms.push(expr);
+        }
+    }
+
+    /// Push `not(any(key = p1, key = p2, ...))` onto `atoms`: none of `patterns` may match.
+    fn push_negative(atoms: &mut Vec<Expr>, key: Key, patterns: &[FilterPattern]) {
+        if let Some(expr) = Self::any_of(key, patterns) {
+            atoms.push(Expr::Not(Box::new(expr)));
+        }
+    }
+
+    /// Like [`Self::push_positive`], but for `Op::Eq` values (`filter_os`/its `arch` scope)
+    /// rather than `FilterPattern`s.
+    fn push_positive_eq(atoms: &mut Vec<Expr>, key: Key, values: &[String]) {
+        match values {
+            [] => {}
+            [value] => atoms.push(Expr::Atom(Atom {
+                key,
+                op: Op::Eq(value.clone()),
+            })),
+            values => atoms.push(Expr::Any(
+                values
+                    .iter()
+                    .map(|value| {
+                        Expr::Atom(Atom {
+                            key,
+                            op: Op::Eq(value.clone()),
+                        })
+                    })
+                    .collect(),
+            )),
+        }
+    }
+
+    fn any_of(key: Key, patterns: &[FilterPattern]) -> Option<Expr> {
+        match patterns {
+            [] => None,
+            [pattern] => Some(Expr::Atom(Atom {
+                key,
+                op: Op::Pattern(pattern.clone()),
+            })),
+            patterns => Some(Expr::Any(
+                patterns
+                    .iter()
+                    .map(|pattern| {
+                        Expr::Atom(Atom {
+                            key,
+                            op: Op::Pattern(pattern.clone()),
+                        })
+                    })
+                    .collect(),
+            )),
+        }
+    }
+
+    pub fn is_match(&self, app: &AppProperties) -> bool {
+        match self {
+            Expr::All(exprs) => exprs.iter().all(|expr| expr.is_match(app)),
+            Expr::Any(exprs) => exprs.iter().any(|expr| expr.is_match(app)),
+            Expr::Not(expr) => !expr.is_match(app),
+            Expr::Atom(atom) => atom.is_match(app),
+        }
+    }
+}
+
+impl Atom {
+    fn is_match(&self, app: &AppProperties) -> bool {
+        let Some(value) = (match self.key {
+            Key::Title => app.title,
+            Key::Class => app.class,
+            Key::Exec => app.exec,
+            Key::Path => app.path,
+            Key::Role => app.window_role,
+            Key::Desktop => app.desktop_id,
+            Key::Os => Some(std::env::consts::OS),
+            Key::Arch => Some(std::env::consts::ARCH),
+        }) else {
+            return false;
+        };
+
+        match &self.op {
+            Op::Eq(expected) => value == expected,
+            Op::Match(regex) => regex.is_match(value),
+            Op::Pattern(pattern) => pattern.is_match(value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Eq,
+    Tilde,
+    Comma,
+    LParen,
+    RParen,
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, FilterExprError> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+
+    while let Some(&(_, ch)) = chars.peek() {
+        match ch {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::LParen);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::RParen);
+            }
+            ',' => {
+                chars.next();
+                tokens.push(Token::Comma);
+            }
+            '=' => {
+                chars.next();
+                tokens.push(Token::Eq);
+            }
+            '~' => {
+                chars.next();
+                tokens.push(Token::Tilde);
+            }
+            '"' => {
+                chars.next();
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, c)) => value.push(c),
+                        None => return Err(FilterExprError::UnterminatedString),
+                    }
+                }
+                tokens.push(Token::Str(value));
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut ident = String::new();
+                while let Some(&(_, c)) = chars.peek() {
+                    if c.is_alphanumeric() || c == '_' {
+                        ident.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                tokens.push(Token::Ident(ident));
+            }
+            c => return Err(FilterExprError::UnexpectedChar(c)),
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: &Token) -> Result<(), FilterExprError> {
+        match self.next() {
+            Some(token) if &token == expected => Ok(()),
+            other => Err(FilterExprError::UnexpectedToken(format!("{other:?}"))),
+        }
+    }
+
+    fn parse_expr(&mut self) -> Result<Expr, FilterExprError> {
+        match self.next() {
+            Some(Token::Ident(ident)) => match ident.as_str() {
+                "all" => Ok(Expr::All(self.parse_expr_list()?)),
+                "any" => Ok(Expr::Any(self.parse_expr_list()?)),
+                "not" => {
+                    self.expect(&Token::LParen)?;
+                    let inner = self.parse_expr()?;
+                    self.expect(&Token::RParen)?;
+                    Ok(Expr::Not(Box::new(inner)))
+                }
+                key => {
+                    let key = match key {
+                        "title" => Key::Title,
+                        "class" => Key::Class,
+                        "exec" => Key::Exec,
+                        "path" => Key::Path,
+                        "role" => Key::Role,
+                        "desktop" => Key::Desktop,
+                        "os" => Key::Os,
+                        "arch" => Key::Arch,
+                        other => return Err(FilterExprError::UnknownKey(other.to_string())),
+                    };
+                    let op = match self.next() {
+                        Some(Token::Eq) => Op::Eq(self.parse_str()?),
+                        Some(Token::Tilde) => {
+                            let pattern = self.parse_str()?;
+                            Op::Match(
+                                Regex::new(&pattern)
+                                    .map_err(|e| FilterExprError::InvalidRegex(e.to_string()))?,
+                            )
+                        }
+                        other => {
+                            return Err(FilterExprError::UnexpectedToken(format!("{other:?}")))
+                        }
+                    };
+                    Ok(Expr::Atom(Atom { key, op }))
+                }
+            },
+            other => Err(FilterExprError::UnexpectedToken(format!("{other:?}"))),
+        }
+    }
+
+    fn parse_expr_list(&mut self) -> Result<Vec<Expr>, FilterExprError> {
+        self.expect(&Token::LParen)?;
+
+        let mut exprs = Vec::new();
+        if self.peek() == Some(&Token::RParen) {
+            self.next();
+            return Ok(exprs);
+        }
+
+        loop {
+            exprs.push(self.parse_expr()?);
+            match self.next() {
+                Some(Token::Comma) => continue,
+                Some(Token::RParen) => break,
+                other => return Err(FilterExprError::UnexpectedToken(format!("{other:?}"))),
+            }
+        }
+
+        Ok(exprs)
+    }
+
+    fn parse_str(&mut self) -> Result<String, FilterExprError> {
+        match self.next() {
+            Some(Token::Str(value)) => Ok(value),
+            other => Err(FilterExprError::UnexpectedToken(format!("{other:?}"))),
+        }
+    }
+}
+
+/// Parse a `filter_expr` string such as `any(class = "firefox", not(title ~ "Terminal"))`.
+pub fn parse(input: &str) -> Result<Expr, FilterExprError> {
+    let tokens = tokenize(input)?;
+    let mut parser = Parser { tokens, pos: 0 };
+    let expr = parser.parse_expr()?;
+
+    if parser.pos != parser.tokens.len() {
+        return Err(FilterExprError::TrailingTokens);
+    }
+
+    Ok(expr)
+}
+
+#[derive(Error, Debug)]
+pub enum FilterExprError {
+    #[error("unterminated string literal")]
+    UnterminatedString,
+    #[error("unexpected character: {0}")]
+    UnexpectedChar(char),
+    #[error("unexpected token: {0}")]
+    UnexpectedToken(String),
+    #[error("unknown filter key: {0}")]
+    UnknownKey(String),
+    #[error("invalid regex: {0}")]
+    InvalidRegex(String),
+    #[error("invalid glob: {0}")]
+    InvalidGlob(String),
+    #[error("trailing tokens after expression")]
+    TrailingTokens,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app<'a>(
+        title: Option<&'a str>,
+        class: Option<&'a str>,
+        exec: Option<&'a str>,
+    ) -> AppProperties<'a> {
+        AppProperties {
+            title,
+            class,
+            exec,
+            path: None,
+            window_role: None,
+            desktop_id: None,
+        }
+    }
+
+    #[test]
+    fn parses_and_matches_any() {
+        let expr = parse(r#"any(class = "firefox", title ~ "Terminal")"#).unwrap();
+        assert!(expr.is_match(&app(None, Some("firefox"), None)));
+        assert!(expr.is_match(&app(Some("My Terminal"), None, None)));
+        assert!(!expr.is_match(&app(Some("firefox"), None, None)));
+    }
+
+    #[test]
+    fn parses_and_matches_all_with_not() {
+        let expr = parse(r#"all(title ~ "Terminal", not(exec = "vim"))"#).unwrap();
+        assert!(expr.is_match(&app(Some("Terminal"), None, Some("bash"))));
+        assert!(!expr.is_match(&app(Some("Terminal"), None, Some("vim"))));
+    }
+
+    #[test]
+    fn all_is_true_over_empty_list() {
+        let expr = Expr::All(vec![]);
+        assert!(expr.is_match(&app(None, None, None)));
+    }
+
+    #[test]
+    fn any_is_false_over_empty_list() {
+        let expr = Expr::Any(vec![]);
+        assert!(!expr.is_match(&app(None, None, None)));
+    }
+
+    #[test]
+    fn atom_is_false_when_property_missing() {
+        let expr = parse(r#"title = "foo""#).unwrap();
+        assert!(!expr.is_match(&app(None, None, None)));
+    }
+
+    #[test]
+    fn filter_pattern_parses_regex_by_default() {
+        let pattern = FilterPattern::parse("Chrome$").unwrap();
+        assert!(pattern.is_match("Google Chrome"));
+        assert!(!pattern.is_match("Chromium"));
+    }
+
+    #[test]
+    fn filter_pattern_parses_glob_with_prefix() {
+        let pattern = FilterPattern::parse("glob:*.exe").unwrap();
+        assert!(pattern.is_match("chrome.exe"));
+        assert!(!pattern.is_match("chrome"));
+    }
+
+    #[test]
+    fn filter_pattern_parse_with_case_insensitive_ignores_case() {
+        let pattern = FilterPattern::parse_with_case("chrome$", FilterCase::Insensitive).unwrap();
+        assert!(pattern.is_match("Google Chrome"));
+    }
+
+    #[test]
+    fn filter_pattern_parse_with_case_smart_is_insensitive_for_lowercase_pattern() {
+        let pattern = FilterPattern::parse_with_case("chrome$", FilterCase::Smart).unwrap();
+        assert!(pattern.is_match("Google Chrome"));
+    }
+
+    #[test]
+    fn filter_pattern_parse_with_case_smart_is_sensitive_once_pattern_has_uppercase() {
+        let pattern = FilterPattern::parse_with_case("Chrome$", FilterCase::Smart).unwrap();
+        assert!(pattern.is_match("Google Chrome"));
+        assert!(!pattern.is_match("google chrome"));
+    }
+
+    #[test]
+    fn filter_pattern_parse_with_case_sensitive_matches_parse_default() {
+        let pattern = FilterPattern::parse_with_case("Chrome$", FilterCase::Sensitive).unwrap();
+        assert!(!pattern.is_match("google chrome"));
+    }
+
+    #[test]
+    fn from_legacy_filters_ands_regex_and_glob() {
+        let title = FilterPattern::parse("Youtube").unwrap();
+        let exec = FilterPattern::parse("glob:*.exe").unwrap();
+        let expr =
+            Expr::from_legacy_filters(&[title], &[], &[exec], &[], &[], &[], &[], &[], &[], None)
+                .unwrap();
+
+        assert!(expr.is_match(&app(
+            Some("Youtube - Broadcast Yourself"),
+            None,
+            Some("chrome.exe")
+        )));
+        // The glob filter alone isn't enough; both must match.
+        assert!(!expr.is_match(&app(Some("Gmail"), None, Some("chrome.exe"))));
+    }
+
+    #[test]
+    fn from_legacy_filters_ors_a_pattern_list() {
+        let chrome = FilterPattern::parse("chrome.exe").unwrap();
+        let firefox = FilterPattern::parse("firefox.exe").unwrap();
+        let expr = Expr::from_legacy_filters(
+            &[],
+            &[],
+            &[chrome, firefox],
+            &[],
+            &[],
+            &[],
+            &[],
+            &[],
+            &[],
+            None,
+        )
+        .unwrap();
+
+        assert!(expr.is_match(&app(None, None, Some("chrome.exe"))));
+        assert!(expr.is_match(&app(None, None, Some("firefox.exe"))));
+        assert!(!expr.is_match(&app(None, None, Some("zoom.exe"))));
+    }
+
+    #[test]
+    fn from_legacy_filters_negative_vetoes_a_positive_match() {
+        let chrome = FilterPattern::parse("chrome.exe").unwrap();
+        let incognito = FilterPattern::parse("Incognito").unwrap();
+        let expr = Expr::from_legacy_filters(
+            &[],
+            &[],
+            &[chrome],
+            &[incognito],
+            &[],
+            &[],
+            &[],
+            &[],
+            &[],
+            None,
+        )
+        .unwrap();
+
+        assert!(expr.is_match(&app(Some("Gmail"), None, Some("chrome.exe"))));
+        assert!(!expr.is_match(&app(Some("Gmail - Incognito"), None, Some("chrome.exe"))));
+    }
+
+    #[test]
+    fn from_legacy_filters_negative_only_still_requires_absence() {
+        let incognito = FilterPattern::parse("Incognito").unwrap();
+        let expr = Expr::from_legacy_filters(
+            &[incognito.clone()],
+            &[],
+            &[],
+            &[],
+            &[],
+            &[],
+            &[],
+            &[],
+            &[],
+            None,
+        )
+        .unwrap();
+
+        assert!(expr.is_match(&app(Some("Incognito"), None, None)));
+
+        let expr =
+            Expr::from_legacy_filters(&[], &[], &[], &[incognito], &[], &[], &[], &[], &[], None)
+                .unwrap();
+        assert!(!expr.is_match(&app(Some("Incognito"), None, None)));
+        assert!(expr.is_match(&app(Some("Gmail"), None, None)));
+    }
+
+    #[test]
+    fn parses_and_matches_os_atom() {
+        let expr = parse(&format!(r#"os = "{}""#, std::env::consts::OS)).unwrap();
+        assert!(expr.is_match(&app(None, None, None)));
+
+        let expr = parse(r#"os = "not-a-real-os""#).unwrap();
+        assert!(!expr.is_match(&app(None, None, None)));
+    }
+
+    #[test]
+    fn from_legacy_filters_os_ands_with_the_other_filters() {
+        let expr = Expr::from_legacy_filters(
+            &[],
+            &[],
+            &[],
+            &[],
+            &[],
+            &[],
+            &[],
+            &[],
+            &[],
+            Some(&FilterOsValue::Bare(OneOrMany::One(
+                std::env::consts::OS.to_string(),
+            ))),
+        )
+        .unwrap();
+        assert!(expr.is_match(&app(None, None, None)));
+
+        let expr = Expr::from_legacy_filters(
+            &[],
+            &[],
+            &[],
+            &[],
+            &[],
+            &[],
+            &[],
+            &[],
+            &[],
+            Some(&FilterOsValue::Bare(OneOrMany::One(
+                "not-a-real-os".to_string(),
+            ))),
+        )
+        .unwrap();
+        assert!(!expr.is_match(&app(None, None, None)));
+    }
+
+    #[test]
+    fn parses_and_matches_path_role_and_desktop_atoms() {
+        let app = AppProperties {
+            title: None,
+            class: None,
+            exec: None,
+            path: Some("/usr/bin/google-chrome-stable"),
+            window_role: Some("pop-up"),
+            desktop_id: Some("google-chrome"),
+        };
+
+        let expr = parse(r#"path ~ "^/usr/bin/""#).unwrap();
+        assert!(expr.is_match(&app));
+
+        let expr = parse(r#"role = "browser""#).unwrap();
+        assert!(!expr.is_match(&app));
+
+        let expr = parse(r#"desktop = "google-chrome""#).unwrap();
+        assert!(expr.is_match(&app));
+    }
+
+    #[test]
+    fn from_legacy_filters_ors_desktop_id_patterns() {
+        let chrome = FilterPattern::parse("google-chrome").unwrap();
+        let chromium = FilterPattern::parse("chromium").unwrap();
+        let expr = Expr::from_legacy_filters(
+            &[],
+            &[],
+            &[],
+            &[],
+            &[],
+            &[],
+            &[],
+            &[],
+            &[chrome, chromium],
+            None,
+        )
+        .unwrap();
+
+        let app_with_desktop_id = |desktop_id| AppProperties {
+            title: None,
+            class: None,
+            exec: None,
+            path: None,
+            window_role: None,
+            desktop_id,
+        };
+
+        assert!(expr.is_match(&app_with_desktop_id(Some("google-chrome"))));
+        assert!(expr.is_match(&app_with_desktop_id(Some("chromium"))));
+        assert!(!expr.is_match(&app_with_desktop_id(Some("firefox"))));
+    }
+}
@@ -0,0 +1,283 @@
+/*
+ * This file is part of espanso.
+ *
+ * Copyright (C) 2019-2021 Federico Terzi
+ *
+ * espanso is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * espanso is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with espanso.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use glob::Pattern;
+use log::warn;
+
+const IGNORE_FILE_NAME: &str = ".shinranignore";
+
+/// Something that can decide whether a path should be skipped during config/match discovery.
+pub(crate) trait PathFilter {
+    fn matches_path(&self, path: &Path) -> bool;
+}
+
+/// One line of a `.shinranignore` file, compiled the way `git`/`fd` interpret a `.gitignore`
+/// line: `!`-negated lines re-include a path an earlier pattern excluded, a trailing `/` only
+/// matches directories (and anything beneath them), a leading `/` (or any `/` other than a
+/// trailing one) anchors the pattern to the directory the ignore file lives in, and a pattern
+/// with no slash at all matches a path component at any depth beneath that directory.
+#[derive(Debug, Clone)]
+struct IgnorePattern {
+    negated: bool,
+    dir_only: bool,
+    glob: Pattern,
+}
+
+impl IgnorePattern {
+    /// Parse one `.shinranignore` line, relative to `base_dir` (the directory the ignore file was
+    /// read from). Returns `None` for blank lines and `#` comments, the same as a blank line in
+    /// `.gitignore`.
+    fn parse(line: &str, base_dir: &Path) -> Option<Self> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        // Anchored if the pattern has a `/` anywhere (leading or internal); otherwise it can
+        // match a path component at any depth, the same distinction `.gitignore` makes.
+        let anchored = line.contains('/');
+        let body = line.strip_prefix('/').unwrap_or(line);
+
+        let glob_path = if anchored {
+            base_dir.join(body)
+        } else {
+            base_dir.join("**").join(body)
+        };
+        let glob = match glob_path.to_str().map(Pattern::new) {
+            Some(Ok(glob)) => glob,
+            _ => {
+                warn!("invalid ignore pattern {line:?} in {}", base_dir.display());
+                return None;
+            }
+        };
+
+        Some(Self {
+            negated,
+            dir_only,
+            glob,
+        })
+    }
+
+    /// Whether this pattern applies to `path`: either directly (unless it's directory-only), or
+    /// because it matches one of `path`'s ancestor directories, in which case everything beneath
+    /// that directory is covered too.
+    fn is_match(&self, path: &Path) -> bool {
+        if !self.dir_only && self.glob.matches_path(path) {
+            return true;
+        }
+        path.ancestors()
+            .skip(1)
+            .any(|ancestor| self.glob.matches_path(ancestor))
+    }
+}
+
+/// Patterns collected from `.shinranignore` files and the `ignore_paths` key in `default.yml`,
+/// deciding whether a config or match file should be skipped during discovery: a skipped path
+/// never reaches `load_from_path` and never ends up in the resolved `file_map`.
+///
+/// `.shinranignore` files are discovered hierarchically: one in `config_dir` always applies, and
+/// [`matches_path`](PathFilter::matches_path) additionally walks every ancestor directory between
+/// `config_dir` and the candidate path's own directory, collecting that directory's
+/// `.shinranignore` too. Patterns are then applied in shallow-to-deep, top-to-bottom order, so a
+/// nested `!pattern` can resurrect a path an ancestor directory's file excluded -- the last
+/// matching pattern wins, exactly as in `.gitignore`.
+///
+/// Each ancestor directory's cumulative pattern list is computed once and memoized in
+/// `pattern_cache`, keyed by directory, so scanning many files under the same directory doesn't
+/// re-read every ancestor's `.shinranignore` (and re-clone `base_patterns`) on every call.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct IgnoreList {
+    config_dir: PathBuf,
+    base_patterns: Rc<Vec<IgnorePattern>>,
+    pattern_cache: RefCell<HashMap<PathBuf, Rc<Vec<IgnorePattern>>>>,
+}
+
+impl IgnoreList {
+    pub(crate) fn load(config_dir: &Path, ignore_paths: &[String]) -> Self {
+        let mut base_patterns = Vec::new();
+        Self::load_dir_patterns(config_dir, &mut base_patterns);
+
+        // `ignore_paths` predates per-directory `.shinranignore` files and has always taken
+        // arbitrary globs matched against the full path, so keep that behavior unchanged rather
+        // than reinterpreting every existing config's entries under gitignore anchoring rules.
+        for raw_pattern in ignore_paths {
+            match Pattern::new(raw_pattern) {
+                Ok(glob) => base_patterns.push(IgnorePattern {
+                    negated: false,
+                    dir_only: false,
+                    glob,
+                }),
+                Err(err) => warn!("invalid ignore pattern {raw_pattern:?}: {err}"),
+            }
+        }
+
+        Self {
+            config_dir: config_dir.to_owned(),
+            base_patterns: Rc::new(base_patterns),
+            pattern_cache: RefCell::new(HashMap::new()),
+        }
+    }
+
+    fn load_dir_patterns(dir: &Path, patterns: &mut Vec<IgnorePattern>) {
+        let Ok(content) = std::fs::read_to_string(dir.join(IGNORE_FILE_NAME)) else {
+            return;
+        };
+        for line in content.lines() {
+            if let Some(pattern) = IgnorePattern::parse(line, dir) {
+                patterns.push(pattern);
+            }
+        }
+    }
+
+    /// The cumulative, shallow-to-deep pattern list that applies to `dir` (which must be
+    /// `config_dir` or one of its descendants): `base_patterns` followed by every ancestor
+    /// directory's own `.shinranignore`, down to and including `dir`'s. Memoized per directory in
+    /// `pattern_cache` so a directory walk re-reads each `.shinranignore` at most once.
+    fn cumulative_patterns(&self, dir: &Path) -> Rc<Vec<IgnorePattern>> {
+        if dir == self.config_dir {
+            return Rc::clone(&self.base_patterns);
+        }
+        if let Some(cached) = self.pattern_cache.borrow().get(dir) {
+            return Rc::clone(cached);
+        }
+
+        let parent = dir.parent().unwrap_or(&self.config_dir);
+        let mut patterns = (*self.cumulative_patterns(parent)).clone();
+        Self::load_dir_patterns(dir, &mut patterns);
+
+        let patterns = Rc::new(patterns);
+        self.pattern_cache
+            .borrow_mut()
+            .insert(dir.to_owned(), Rc::clone(&patterns));
+        patterns
+    }
+}
+
+impl PathFilter for IgnoreList {
+    fn matches_path(&self, path: &Path) -> bool {
+        let patterns = match path.parent() {
+            Some(parent) if parent.strip_prefix(&self.config_dir).is_ok() => {
+                self.cumulative_patterns(parent)
+            }
+            _ => Rc::clone(&self.base_patterns),
+        };
+
+        let mut ignored = false;
+        for pattern in patterns.iter() {
+            if pattern.is_match(path) {
+                ignored = !pattern.negated;
+            }
+        }
+        ignored
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(dir: &Path, relative: &str, content: &str) {
+        let path = dir.join(relative);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).unwrap();
+        }
+        std::fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn ignores_a_path_matched_in_the_config_dir_ignore_file() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), ".shinranignore", "experimental.yml\n");
+
+        let ignore = IgnoreList::load(dir.path(), &[]);
+        assert!(ignore.matches_path(&dir.path().join("match/experimental.yml")));
+        assert!(!ignore.matches_path(&dir.path().join("match/stable.yml")));
+    }
+
+    #[test]
+    fn comments_and_blank_lines_are_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), ".shinranignore", "# a comment\n\nexperimental.yml\n");
+
+        let ignore = IgnoreList::load(dir.path(), &[]);
+        assert!(ignore.matches_path(&dir.path().join("match/experimental.yml")));
+    }
+
+    #[test]
+    fn a_nested_ignore_file_can_negate_a_shallower_exclusion() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), ".shinranignore", "*.yml\n");
+        write(dir.path(), "match/wip/.shinranignore", "!keep.yml\n");
+
+        let ignore = IgnoreList::load(dir.path(), &[]);
+        assert!(ignore.matches_path(&dir.path().join("match/wip/other.yml")));
+        assert!(!ignore.matches_path(&dir.path().join("match/wip/keep.yml")));
+    }
+
+    #[test]
+    fn trailing_slash_only_excludes_the_directory_subtree() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), ".shinranignore", "wip/\n");
+
+        let ignore = IgnoreList::load(dir.path(), &[]);
+        assert!(ignore.matches_path(&dir.path().join("match/wip/draft.yml")));
+        assert!(!ignore.matches_path(&dir.path().join("match/stable.yml")));
+    }
+
+    #[test]
+    fn leading_slash_anchors_to_the_ignore_files_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), "match/.shinranignore", "/top_level_only.yml\n");
+
+        let ignore = IgnoreList::load(dir.path(), &[]);
+        assert!(ignore.matches_path(&dir.path().join("match/top_level_only.yml")));
+        assert!(!ignore.matches_path(&dir.path().join("match/nested/top_level_only.yml")));
+    }
+
+    #[test]
+    fn unanchored_pattern_matches_at_any_depth() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), ".shinranignore", "secret.yml\n");
+
+        let ignore = IgnoreList::load(dir.path(), &[]);
+        assert!(ignore.matches_path(&dir.path().join("match/a/b/secret.yml")));
+    }
+
+    #[test]
+    fn ignore_paths_config_entries_still_match_as_plain_globs() {
+        let dir = tempfile::tempdir().unwrap();
+        let ignore = IgnoreList::load(dir.path(), &["**/legacy_*.yml".to_string()]);
+        assert!(ignore.matches_path(&dir.path().join("match/legacy_old.yml")));
+    }
+}
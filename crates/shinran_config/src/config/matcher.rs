@@ -0,0 +1,241 @@
+/*
+ * This file is part of espanso.
+ *
+ * Copyright (C) 2019-2021 Federico Terzi
+ *
+ * espanso is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * espanso is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with espanso.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! A `RegexSet`-backed prefilter over every custom profile's `filter_title`/`filter_class`/
+//! `filter_exec` patterns, so picking the active config for a window doesn't need to test every
+//! profile's full filter expression one at a time -- the same technique `fd` uses
+//! (`RegexSetBuilder`) to test many patterns against one haystack in a single pass. `Filters`
+//! already compiles each pattern into a `regex::Regex`/`GlobMatcher` once at load time (see
+//! [`super::filter_expr`]); [`ConfigMatcher`] builds on top of that by aggregating those
+//! already-compiled patterns into three sets, one per key, indexed by profile position.
+
+use regex::RegexSet;
+
+use super::{filter_expr::FilterPattern, AppProperties, ProfileFile};
+
+/// Build one `RegexSet`, one entry per profile in `profiles`, out of each profile's positive
+/// filter patterns for a single key (`filter_title`, `filter_class`, or `filter_exec`). A profile
+/// without any pattern for that key gets `.*` (vacuously satisfied: that key doesn't restrict it),
+/// and so does one with a glob pattern, since `RegexSet` can't express "regex or glob" -- either
+/// way this only widens which profiles [`ConfigMatcher::candidate_indices`] lets through, it never
+/// narrows past what `Filters::is_match` would actually accept.
+fn build_key_set<'a>(patterns_per_profile: impl Iterator<Item = &'a [FilterPattern]>) -> RegexSet {
+    let combined: Vec<String> = patterns_per_profile.map(combined_pattern).collect();
+    RegexSet::new(combined).expect("every pattern was already compiled and validated at load time")
+}
+
+/// OR together every regex in `patterns` into one alternation, or `.*` if `patterns` is empty or
+/// contains a glob pattern.
+fn combined_pattern(patterns: &[FilterPattern]) -> String {
+    let regex_sources: Vec<&str> = patterns
+        .iter()
+        .map(|pattern| match pattern {
+            FilterPattern::Regex(regex) => Some(regex.as_str()),
+            FilterPattern::Glob(_) => None,
+        })
+        .collect::<Option<Vec<_>>>()
+        .unwrap_or_default();
+
+    if regex_sources.is_empty() {
+        ".*".to_string()
+    } else {
+        regex_sources
+            .into_iter()
+            .map(|source| format!("(?:{source})"))
+            .collect::<Vec<_>>()
+            .join("|")
+    }
+}
+
+/// A prefilter built from the `filter_title`/`filter_class`/`filter_exec` patterns of a fixed
+/// slice of [`ProfileFile`]s, in declaration order. Must be rebuilt whenever that slice of
+/// profiles changes; [`ConfigMatcher::candidate_indices`] otherwise returns indices into the
+/// wrong slice.
+pub struct ConfigMatcher {
+    title_set: RegexSet,
+    class_set: RegexSet,
+    exec_set: RegexSet,
+}
+
+impl ConfigMatcher {
+    pub fn new(profiles: &[ProfileFile]) -> Self {
+        Self {
+            title_set: build_key_set(profiles.iter().map(|p| p.filter.title.as_slice())),
+            class_set: build_key_set(profiles.iter().map(|p| p.filter.class.as_slice())),
+            exec_set: build_key_set(profiles.iter().map(|p| p.filter.exec.as_slice())),
+        }
+    }
+
+    /// Indices (in the same order as the slice this matcher was built from) of every profile
+    /// whose `filter_title`/`filter_class`/`filter_exec` patterns are consistent with `app`.
+    /// Every other constraint a candidate might have (`_not` patterns, `filter_path`/`filter_role`/
+    /// `filter_desktop`, `filter_os`, `filter_expr`) still needs to be confirmed by the caller via
+    /// `Filters::is_match`, since this prefilter can't rule those out.
+    pub fn candidate_indices<'a>(
+        &'a self,
+        app: &AppProperties,
+    ) -> impl Iterator<Item = usize> + 'a {
+        let title_matches = self.title_set.matches(app.title.unwrap_or(""));
+        let class_matches = self.class_set.matches(app.class.unwrap_or(""));
+        let exec_matches = self.exec_set.matches(app.exec.unwrap_or(""));
+
+        (0..self.title_set.len())
+            .filter(move |&i| title_matches.matched(i))
+            .filter(move |&i| class_matches.matched(i))
+            .filter(move |&i| exec_matches.matched(i))
+    }
+
+    /// The new entry point for selecting the active config: narrow `profiles` down to
+    /// [`Self::candidate_indices`], then confirm each candidate's full filter (in declaration
+    /// order) against `app`, falling back to `default` if none match. `profiles` must be the same
+    /// slice (and order) this matcher was built from.
+    pub fn active_config<'a>(
+        &self,
+        default: &'a ProfileFile,
+        profiles: &'a [ProfileFile],
+        app: &AppProperties,
+    ) -> &'a ProfileFile {
+        self.candidate_indices(app)
+            .filter_map(|i| profiles.get(i))
+            .find(|candidate| candidate.filter.is_match(app))
+            .unwrap_or(default)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::parse::ParsedConfig;
+    use crate::config::resolve::Filters;
+
+    fn profile_with_filters(label: &str, filter: Filters) -> ProfileFile {
+        ProfileFile {
+            content: ParsedConfig {
+                label: Some(label.to_string()),
+                ..Default::default()
+            },
+            filter,
+            ..Default::default()
+        }
+    }
+
+    fn app<'a>(
+        title: Option<&'a str>,
+        class: Option<&'a str>,
+        exec: Option<&'a str>,
+    ) -> AppProperties<'a> {
+        AppProperties {
+            title,
+            class,
+            exec,
+            path: None,
+            window_role: None,
+            desktop_id: None,
+        }
+    }
+
+    fn filters_matching_class(class_pattern: &str) -> Filters {
+        let class = vec![FilterPattern::parse(class_pattern).unwrap()];
+        Filters {
+            expr: super::super::filter_expr::Expr::from_legacy_filters(
+                &[],
+                &class,
+                &[],
+                &[],
+                &[],
+                &[],
+                &[],
+                &[],
+                &[],
+                None,
+            ),
+            class,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn candidate_indices_narrows_to_matching_profiles() {
+        let profiles = vec![
+            profile_with_filters("chrome", filters_matching_class("Chrome")),
+            profile_with_filters("firefox", filters_matching_class("Firefox")),
+        ];
+        let matcher = ConfigMatcher::new(&profiles);
+
+        let candidates: Vec<usize> = matcher
+            .candidate_indices(&app(None, Some("Chrome"), None))
+            .collect();
+        assert_eq!(candidates, vec![0]);
+    }
+
+    #[test]
+    fn candidate_indices_includes_unconstrained_profiles() {
+        let profiles = vec![
+            profile_with_filters("unconstrained", Filters::default()),
+            profile_with_filters("chrome", filters_matching_class("Chrome")),
+        ];
+        let matcher = ConfigMatcher::new(&profiles);
+
+        let candidates: Vec<usize> = matcher
+            .candidate_indices(&app(None, Some("Firefox"), None))
+            .collect();
+        assert_eq!(candidates, vec![0]);
+    }
+
+    #[test]
+    fn candidate_indices_treats_glob_patterns_as_unconstrained() {
+        let glob = vec![FilterPattern::parse("glob:*.exe").unwrap()];
+        let exec_glob_filters = Filters {
+            exec: glob,
+            ..Default::default()
+        };
+        let profiles = vec![profile_with_filters("glob", exec_glob_filters)];
+        let matcher = ConfigMatcher::new(&profiles);
+
+        // The RegexSet prefilter can't evaluate a glob pattern, so it always lets the profile
+        // through; whether it actually matches is down to `Filters::is_match`.
+        let candidates: Vec<usize> = matcher
+            .candidate_indices(&app(None, None, Some("anything")))
+            .collect();
+        assert_eq!(candidates, vec![0]);
+    }
+
+    #[test]
+    fn active_config_confirms_candidates_and_falls_back_to_default() {
+        let default = profile_with_filters("default", Filters::default());
+        let profiles = vec![profile_with_filters(
+            "chrome",
+            filters_matching_class("Chrome"),
+        )];
+        let matcher = ConfigMatcher::new(&profiles);
+
+        assert_eq!(
+            matcher
+                .active_config(&default, &profiles, &app(None, Some("Chrome"), None))
+                .label(),
+            "chrome"
+        );
+        assert_eq!(
+            matcher
+                .active_config(&default, &profiles, &app(None, Some("Firefox"), None))
+                .label(),
+            "default"
+        );
+    }
+}
@@ -0,0 +1,294 @@
+/*
+ * This file is part of espanso.
+ *
+ * Copyright (C) 2019-2021 Federico Terzi
+ *
+ * espanso is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * espanso is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with espanso.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+
+use crate::error::ErrorRecord;
+
+/// Expand `patterns` (each a glob relative to `base_dir`, e.g. `"../match/**/[!_]*.yml"` or
+/// `"../match/{work,personal}/*.yml"`) into the set of concrete files they match, alongside a
+/// non-fatal warning for every pattern that matched nothing.
+///
+/// [`super::resolve::generate_match_paths`] calls this once for a profile's `includes` and once
+/// for its `excludes`, then takes the set difference itself, so this function doesn't need to
+/// know which side it's computing. Each pattern is split into its literal leading path and a
+/// wildcard tail, so only the relevant subtree is walked instead of the whole config directory.
+/// The walk also honors per-directory `.shinranignore`/`.gitignore` files along the way (see
+/// [`crate::ignore_walk`]), so a large match library can be carved into subsets without touching
+/// `includes`/`excludes`; anything they skip is reported as a non-fatal warning rather than
+/// silently vanishing. Invalid or empty patterns are logged and reported as a non-fatal warning
+/// rather than aborting config load.
+pub(crate) fn calculate_paths<'a>(
+    base_dir: &Path,
+    patterns: impl Iterator<Item = &'a String>,
+) -> (HashSet<PathBuf>, Vec<ErrorRecord>) {
+    let mut paths = HashSet::new();
+    let mut non_fatal_errors = Vec::new();
+
+    for pattern in patterns {
+        if pattern.is_empty() {
+            log::warn!("ignoring empty match-file glob pattern");
+            non_fatal_errors.push(ErrorRecord::warn(anyhow::anyhow!(
+                "ignoring empty match-file glob pattern"
+            )));
+            continue;
+        }
+
+        let (base, tail) = split_glob_base(base_dir, pattern);
+        let Ok(canonical_base) = dunce::canonicalize(&base) else {
+            // A pattern whose base directory doesn't exist yet (e.g. an optional `packages/`
+            // directory that hasn't been created) simply contributes no files.
+            non_fatal_errors.push(ErrorRecord::warn(anyhow::anyhow!(
+                "match-file glob pattern {pattern:?} matched no files: base directory {base:?} does not exist"
+            )));
+            continue;
+        };
+        if !canonical_base.is_dir() {
+            non_fatal_errors.push(ErrorRecord::warn(anyhow::anyhow!(
+                "match-file glob pattern {pattern:?} matched no files: base directory {base:?} does not exist"
+            )));
+            continue;
+        }
+
+        let full_pattern = canonical_base.join(&tail);
+        let glob = match Glob::new(&full_pattern.to_string_lossy()) {
+            Ok(glob) => glob,
+            Err(err) => {
+                log::warn!("ignoring invalid match-file glob pattern {pattern:?}: {err}");
+                non_fatal_errors.push(ErrorRecord::warn(anyhow::anyhow!(
+                    "ignoring invalid match-file glob pattern {pattern:?}: {err}"
+                )));
+                continue;
+            }
+        };
+
+        let mut builder = GlobSetBuilder::new();
+        builder.add(glob);
+        let glob_set = match builder.build() {
+            Ok(glob_set) => glob_set,
+            Err(err) => {
+                log::warn!("ignoring invalid match-file glob pattern {pattern:?}: {err}");
+                non_fatal_errors.push(ErrorRecord::warn(anyhow::anyhow!(
+                    "ignoring invalid match-file glob pattern {pattern:?}: {err}"
+                )));
+                continue;
+            }
+        };
+
+        let wanted = |path: &Path| glob_set.is_match(path);
+        let (pattern_matches, pattern_ignored) =
+            crate::ignore_walk::walk_respecting_ignore_files(&canonical_base, &wanted, &|_| true);
+        if pattern_matches.is_empty() {
+            non_fatal_errors.push(ErrorRecord::warn(anyhow::anyhow!(
+                "match-file glob pattern {pattern:?} matched no files"
+            )));
+        }
+        for path in pattern_ignored {
+            non_fatal_errors.push(ErrorRecord::warn(anyhow::anyhow!(
+                "skipping {:?}: excluded by a `.shinranignore`/`.gitignore` rule",
+                path
+            )));
+        }
+        paths.extend(pattern_matches);
+    }
+
+    (paths, non_fatal_errors)
+}
+
+/// Whether `component` should be treated as a glob rather than a literal path segment.
+fn is_glob_pattern(component: &str) -> bool {
+    component.contains(['*', '?', '[', '{'])
+}
+
+/// Split `pattern` into its leading (non-wildcard) components, joined onto `base_dir` as-is, and
+/// the remaining wildcard tail (e.g. `"../match/**/[!_]*.yml"` with `base_dir` `/cfg/default`
+/// becomes `/cfg/default/../match` and `**/[!_]*.yml`). `..` is not resolved here; the caller
+/// canonicalizes the base.
+fn split_glob_base(base_dir: &Path, pattern: &str) -> (PathBuf, String) {
+    let components: Vec<&str> = pattern.split('/').collect();
+    let wildcard_idx = components
+        .iter()
+        .position(|component| is_glob_pattern(component))
+        .unwrap_or(components.len());
+
+    let mut base = base_dir.to_path_buf();
+    for component in &components[..wildcard_idx] {
+        base.push(component);
+    }
+    let tail = components[wildcard_idx..].join("/");
+
+    (base, tail)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shinran_helpers::use_test_directory;
+    use std::fs::create_dir_all;
+
+    #[test]
+    fn calculate_paths_expands_recursive_glob() {
+        use_test_directory(|_, match_dir, _| {
+            let sub_dir = match_dir.join("sub");
+            create_dir_all(&sub_dir).unwrap();
+
+            let top_file = match_dir.join("top.yml");
+            std::fs::write(&top_file, "test").unwrap();
+            let nested_file = sub_dir.join("nested.yml");
+            std::fs::write(&nested_file, "test").unwrap();
+            let ignored_file = match_dir.join("top.txt");
+            std::fs::write(&ignored_file, "test").unwrap();
+
+            let patterns = ["**/*.yml".to_string()];
+            let (paths, errors) = calculate_paths(match_dir, patterns.iter());
+
+            assert_eq!(
+                paths,
+                [top_file, nested_file].into_iter().collect::<HashSet<_>>()
+            );
+            assert!(errors.is_empty());
+        });
+    }
+
+    #[test]
+    fn calculate_paths_skips_underscore_prefixed_files() {
+        use_test_directory(|_, match_dir, _| {
+            let visible_file = match_dir.join("base.yml");
+            std::fs::write(&visible_file, "test").unwrap();
+            let hidden_file = match_dir.join("_draft.yml");
+            std::fs::write(&hidden_file, "test").unwrap();
+
+            let patterns = ["[!_]*.yml".to_string()];
+            let (paths, errors) = calculate_paths(match_dir, patterns.iter());
+
+            assert_eq!(paths, [visible_file].into_iter().collect::<HashSet<_>>());
+            assert!(errors.is_empty());
+        });
+    }
+
+    #[test]
+    fn calculate_paths_expands_brace_alternatives() {
+        use_test_directory(|_, match_dir, _| {
+            let work_dir = match_dir.join("work");
+            create_dir_all(&work_dir).unwrap();
+            let personal_dir = match_dir.join("personal");
+            create_dir_all(&personal_dir).unwrap();
+
+            let work_file = work_dir.join("a.yml");
+            std::fs::write(&work_file, "test").unwrap();
+            let personal_file = personal_dir.join("b.yml");
+            std::fs::write(&personal_file, "test").unwrap();
+            let other_dir = match_dir.join("other");
+            create_dir_all(&other_dir).unwrap();
+            std::fs::write(other_dir.join("c.yml"), "test").unwrap();
+
+            let patterns = ["{work,personal}/*.yml".to_string()];
+            let (paths, errors) = calculate_paths(match_dir, patterns.iter());
+
+            assert_eq!(
+                paths,
+                [work_file, personal_file]
+                    .into_iter()
+                    .collect::<HashSet<_>>()
+            );
+            assert!(errors.is_empty());
+        });
+    }
+
+    #[test]
+    fn calculate_paths_ignores_missing_base_directory() {
+        use_test_directory(|_, match_dir, _| {
+            let patterns = ["missing/*.yml".to_string()];
+            let (paths, errors) = calculate_paths(match_dir, patterns.iter());
+
+            assert!(paths.is_empty());
+            assert_eq!(errors.len(), 1);
+        });
+    }
+
+    #[test]
+    fn calculate_paths_ignores_empty_pattern() {
+        use_test_directory(|_, match_dir, _| {
+            let patterns = [String::new()];
+            let (paths, errors) = calculate_paths(match_dir, patterns.iter());
+
+            assert!(paths.is_empty());
+            assert_eq!(errors.len(), 1);
+        });
+    }
+
+    #[test]
+    fn calculate_paths_reports_pattern_matching_no_files() {
+        use_test_directory(|_, match_dir, _| {
+            let patterns = ["*.txt".to_string()];
+            let (paths, errors) = calculate_paths(match_dir, patterns.iter());
+
+            assert!(paths.is_empty());
+            assert_eq!(errors.len(), 1);
+        });
+    }
+
+    #[test]
+    fn calculate_paths_honors_gitignore_files() {
+        use_test_directory(|_, match_dir, _| {
+            let drafts_dir = match_dir.join("drafts");
+            create_dir_all(&drafts_dir).unwrap();
+
+            std::fs::write(match_dir.join(".gitignore"), "drafts/\n").unwrap();
+
+            let kept_file = match_dir.join("kept.yml");
+            std::fs::write(&kept_file, "test").unwrap();
+            let draft_file = drafts_dir.join("draft.yml");
+            std::fs::write(&draft_file, "test").unwrap();
+
+            let patterns = ["**/*.yml".to_string()];
+            let (paths, errors) = calculate_paths(match_dir, patterns.iter());
+
+            assert_eq!(paths, [kept_file].into_iter().collect::<HashSet<_>>());
+            assert_eq!(errors.len(), 1);
+        });
+    }
+
+    #[test]
+    fn calculate_paths_nested_gitignore_can_reinclude_a_file() {
+        use_test_directory(|_, match_dir, _| {
+            let sub_dir = match_dir.join("sub");
+            create_dir_all(&sub_dir).unwrap();
+
+            std::fs::write(match_dir.join(".gitignore"), "*.yml\n").unwrap();
+            std::fs::write(sub_dir.join(".gitignore"), "!keep.yml\n").unwrap();
+
+            let top_file = match_dir.join("top.yml");
+            std::fs::write(&top_file, "test").unwrap();
+            let kept_file = sub_dir.join("keep.yml");
+            std::fs::write(&kept_file, "test").unwrap();
+
+            let patterns = ["**/*.yml".to_string()];
+            let (paths, errors) = calculate_paths(match_dir, patterns.iter());
+
+            assert_eq!(paths, [kept_file].into_iter().collect::<HashSet<_>>());
+            assert!(!errors.is_empty());
+        });
+    }
+}
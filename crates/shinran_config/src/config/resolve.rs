@@ -18,19 +18,22 @@
  */
 
 use super::{
+    clipboard::{detect_clipboard_provider, ClipboardProvider},
     default::{
-        DEFAULT_CLIPBOARD_THRESHOLD, DEFAULT_POST_FORM_DELAY, DEFAULT_POST_SEARCH_DELAY,
-        DEFAULT_PRE_PASTE_DELAY, DEFAULT_RESTORE_CLIPBOARD_DELAY, DEFAULT_SHORTCUT_EVENT_DELAY,
+        DEFAULT_CLIPBOARD_OSC52_MAX_BYTES, DEFAULT_CLIPBOARD_THRESHOLD, DEFAULT_POST_FORM_DELAY,
+        DEFAULT_POST_SEARCH_DELAY, DEFAULT_PRE_PASTE_DELAY, DEFAULT_RESTORE_CLIPBOARD_DELAY,
+        DEFAULT_SHORTCUT_EVENT_DELAY,
     },
+    filter_expr::{self, Expr as FilterExpr, FilterCase, FilterOsValue, FilterPattern},
     parse::ParsedConfig,
     path::calculate_paths,
     AppProperties, RMLVOConfig,
 };
-use crate::{matches::group::MatchFileRef, merge};
+use crate::{error::ErrorRecord, matches::group::MatchFileRef, merge};
 use anyhow::Result;
 use indoc::formatdoc;
 use log::error;
-use regex::Regex;
+use shinran_types::MatchEffect;
 use std::{collections::HashMap, path::PathBuf};
 use std::{collections::HashSet, path::Path};
 use thiserror::Error;
@@ -43,55 +46,40 @@ pub struct Filters {
     // TODO: Any config file with non-None filters should probably be ignored on Wayland.
     // TODO: Should we throw an error if the user specifies filters in the default file?
     //       (We're currently implicitly ignoring filters in the default file.)
-    pub(crate) title: Option<Regex>,
-    pub(crate) class: Option<Regex>,
-    pub(crate) exec: Option<Regex>,
+    pub(crate) title: Vec<FilterPattern>,
+    pub(crate) class: Vec<FilterPattern>,
+    pub(crate) exec: Vec<FilterPattern>,
+
+    /// `filter_title_not`/`filter_class_not`/`filter_exec_not`: patterns which, if any matches,
+    /// veto an otherwise-matching profile (e.g. "every browser except an Incognito window").
+    pub(crate) title_not: Vec<FilterPattern>,
+    pub(crate) class_not: Vec<FilterPattern>,
+    pub(crate) exec_not: Vec<FilterPattern>,
+
+    /// `filter_path`/`filter_role`/`filter_desktop`: the app's full executable path, window role,
+    /// and resolved desktop entry id (see [`crate::config::desktop_entry`]) respectively, useful
+    /// on Linux where `class` alone is too coarse to tell some apps apart.
+    pub(crate) path: Vec<FilterPattern>,
+    pub(crate) role: Vec<FilterPattern>,
+    pub(crate) desktop: Vec<FilterPattern>,
+
+    /// A `filter_expr` boolean expression (see [`filter_expr`]), lowered at load time to also
+    /// cover the `title`/`class`/`exec` patterns above so both forms can be evaluated uniformly.
+    pub(crate) expr: Option<FilterExpr>,
 }
 
 impl Filters {
     pub fn is_match(&self, app: &AppProperties) -> bool {
-        if self.title.is_none() && self.exec.is_none() && self.class.is_none() {
-            return false;
+        match &self.expr {
+            Some(expr) => expr.is_match(app),
+            None => false,
         }
+    }
 
-        // let is_os_match = if let Some(filter_os) = self.parsed.filter_os.as_deref() {
-        //     os_matches(filter_os)
-        // } else {
-        //     true
-        // };
-
-        let is_title_match = if let Some(title_regex) = self.title.as_ref() {
-            if let Some(title) = app.title {
-                title_regex.is_match(title)
-            } else {
-                false
-            }
-        } else {
-            true
-        };
-
-        let is_exec_match = if let Some(exec_regex) = self.exec.as_ref() {
-            if let Some(exec) = app.exec {
-                exec_regex.is_match(exec)
-            } else {
-                false
-            }
-        } else {
-            true
-        };
-
-        let is_class_match = if let Some(class_regex) = self.class.as_ref() {
-            if let Some(class) = app.class {
-                class_regex.is_match(class)
-            } else {
-                false
-            }
-        } else {
-            true
-        };
-
-        // All the filters that have been specified must be true to define a match
-        is_exec_match && is_title_match && is_class_match
+    /// Whether any filter was set at all. `title`/`class`/`exec` are folded into `expr` at
+    /// load time, so `expr.is_some()` alone tells us whether this profile can ever match.
+    pub fn has_any(&self) -> bool {
+        self.expr.is_some()
     }
 }
 
@@ -105,42 +93,77 @@ pub struct LoadedProfileFile {
     pub(crate) match_file_paths: Vec<PathBuf>,
 
     pub(crate) filter: Filters,
+
+    /// Non-fatal warnings produced while resolving this profile's `includes`/`excludes`
+    /// patterns, e.g. a glob that matched no files. Surfaced by the caller alongside the
+    /// other [`crate::error::NonFatalErrorSet`]s produced while loading the config store.
+    pub(crate) non_fatal_errors: Vec<ErrorRecord>,
 }
 
 impl LoadedProfileFile {
-    pub fn load_from_path(path: &Path, parent: Option<&Self>) -> Result<Self> {
+    /// `env_overrides`/`cli_overrides` are folded in on top of `parent`'s inheritance via
+    /// [`layered_merge`] — see its doc comment for the precedence chain this implements.
+    pub fn load_from_path(
+        path: &Path,
+        parent: Option<&Self>,
+        env_overrides: &ParsedConfig,
+        cli_overrides: &ParsedConfig,
+    ) -> Result<Self> {
         let mut config = ParsedConfig::load(path)?;
 
-        // Inherit from the parent config if present
-        if let Some(parent) = parent {
-            inherit(&mut config, &parent.content);
-        }
+        layered_merge(
+            &mut config,
+            parent.map(|parent| &parent.content),
+            env_overrides,
+            cli_overrides,
+        );
 
         // Extract the base directory
         let base_dir = path
             .parent()
             .ok_or_else(ResolveError::ParentResolveFailed)?;
 
-        let match_paths = generate_match_paths(&config, base_dir)
-            .into_iter()
-            .collect();
-
-        let filter_title = if let Some(filter_title) = config.filter_title.as_deref() {
-            Some(Regex::new(filter_title)?)
-        } else {
-            None
-        };
-
-        let filter_class = if let Some(filter_class) = config.filter_class.as_deref() {
-            Some(Regex::new(filter_class)?)
-        } else {
-            None
-        };
+        let (match_paths, non_fatal_errors) = generate_match_paths(&config, base_dir);
+        let match_paths = match_paths.into_iter().collect();
+
+        let filter_case = config.filter_case.unwrap_or_default();
+        let filter_title = parse_filter_patterns(config.filter_title.as_deref(), filter_case)?;
+        let filter_class = parse_filter_patterns(config.filter_class.as_deref(), filter_case)?;
+        let filter_exec = parse_filter_patterns(config.filter_exec.as_deref(), filter_case)?;
+        let filter_title_not =
+            parse_filter_patterns(config.filter_title_not.as_deref(), filter_case)?;
+        let filter_class_not =
+            parse_filter_patterns(config.filter_class_not.as_deref(), filter_case)?;
+        let filter_exec_not =
+            parse_filter_patterns(config.filter_exec_not.as_deref(), filter_case)?;
+        let filter_path =
+            parse_filter_patterns(config.filter_path.as_deref(), FilterCase::Sensitive)?;
+        let filter_role =
+            parse_filter_patterns(config.filter_role.as_deref(), FilterCase::Sensitive)?;
+        let filter_desktop =
+            parse_filter_patterns(config.filter_desktop.as_deref(), FilterCase::Sensitive)?;
+
+        let legacy_expr = FilterExpr::from_legacy_filters(
+            &filter_title,
+            &filter_class,
+            &filter_exec,
+            &filter_title_not,
+            &filter_class_not,
+            &filter_exec_not,
+            &filter_path,
+            &filter_role,
+            &filter_desktop,
+            config.filter_os.as_ref(),
+        );
 
-        let filter_exec = if let Some(filter_exec) = config.filter_exec.as_deref() {
-            Some(Regex::new(filter_exec)?)
+        let expr = if let Some(filter_expr) = config.filter_expr.as_deref() {
+            let parsed_expr = filter_expr::parse(filter_expr)?;
+            Some(match legacy_expr {
+                Some(legacy_expr) => FilterExpr::All(vec![parsed_expr, legacy_expr]),
+                None => parsed_expr,
+            })
         } else {
-            None
+            legacy_expr
         };
 
         Ok(Self {
@@ -151,7 +174,15 @@ impl LoadedProfileFile {
                 title: filter_title,
                 class: filter_class,
                 exec: filter_exec,
+                title_not: filter_title_not,
+                class_not: filter_class_not,
+                exec_not: filter_exec_not,
+                path: filter_path,
+                role: filter_role,
+                desktop: filter_desktop,
+                expr,
             },
+            non_fatal_errors,
         })
     }
 
@@ -222,6 +253,42 @@ impl ProfileFile {
         self.content.enable.unwrap_or(true)
     }
 
+    /// Whether `ProfileStore::active_config_merged` should fold every matching profile into
+    /// one instead of returning only the first match. Off by default, so existing
+    /// first-match behavior (`active_config`) is unaffected unless a profile opts in.
+    pub fn merge_profiles(&self) -> bool {
+        self.content.merge_profiles.unwrap_or(false)
+    }
+
+    /// The `aliases` table this profile defines, mapping a short name to the concrete effect
+    /// a `MatchEffect::Alias` referencing it should expand to.
+    pub(crate) fn aliases(&self) -> &HashMap<String, MatchEffect> {
+        &self.content.aliases
+    }
+
+    /// Fold `overlay` on top of `self`, treating `overlay` as the higher-priority layer: its
+    /// scalar fields win wherever they're set (falling back to `self`'s otherwise, same
+    /// precedence as [`inherit`]), and its match-file references are appended after this
+    /// profile's rather than replacing them.
+    pub(crate) fn merged_with(&self, overlay: &ProfileFile) -> ProfileFile {
+        let mut content = overlay.content.clone();
+        inherit(&mut content, &self.content);
+
+        let mut match_file_paths = self.match_file_paths.clone();
+        for file_ref in &overlay.match_file_paths {
+            if !match_file_paths.contains(file_ref) {
+                match_file_paths.push(*file_ref);
+            }
+        }
+
+        ProfileFile {
+            content,
+            source_path: overlay.source_path.clone(),
+            match_file_paths,
+            filter: overlay.filter.clone(),
+        }
+    }
+
     // Number of chars after which a match is injected with the clipboard
     // backend instead of the default one. This is done for efficiency
     // reasons, as injecting a long match through separate events becomes
@@ -285,6 +352,7 @@ impl ProfileFile {
     // key events based on XTestFakeKeyEvent instead of XSendEvent.
     // From my experiements, disabling fast inject becomes particularly slow when
     // using the Gnome desktop environment.
+    #[cfg(feature = "x11")]
     pub fn disable_x11_fast_inject(&self) -> bool {
         self.content.disable_x11_fast_inject.unwrap_or(false)
     }
@@ -444,6 +512,7 @@ impl ProfileFile {
     // This is needed to filter out the software-generated events, including
     // those from espanso, but might need to be disabled when using some software-level keyboards.
     // Disabling this option might conflict with the undo feature.
+    #[cfg(target_os = "windows")]
     pub fn win32_exclude_orphan_events(&self) -> bool {
         self.content.win32_exclude_orphan_events.unwrap_or(true)
     }
@@ -452,6 +521,7 @@ impl ProfileFile {
     // This is useful on Wayland if espanso is injecting seemingly random
     // cased letters, for example "Hi theRE1" instead of "Hi there!".
     // Increase if necessary, decrease to speed up the injection.
+    #[cfg(feature = "wayland")]
     pub fn evdev_modifier_delay(&self) -> Option<usize> {
         self.content.evdev_modifier_delay
     }
@@ -460,6 +530,7 @@ impl ProfileFile {
     // can be cached. If switching often between different layouts, you
     // could lower this amount to avoid the "lost detection" effect described
     // in this issue: https://github.com/espanso/espanso/issues/745
+    #[cfg(target_os = "windows")]
     pub fn win32_keyboard_layout_cache_interval(&self) -> i64 {
         self.content
             .win32_keyboard_layout_cache_interval
@@ -468,18 +539,44 @@ impl ProfileFile {
 
     // If true, use an alternative injection backend based on the `xdotool` library.
     // This might improve the situation for certain locales/layouts on X11.
+    #[cfg(feature = "x11")]
     pub fn x11_use_xclip_backend(&self) -> bool {
         self.content.x11_use_xclip_backend.unwrap_or(false)
     }
 
     // If true, use an alternative injection backend based on the `xdotool` library.
     // This might improve the situation for certain locales/layouts on X11.
+    #[cfg(feature = "x11")]
     pub fn x11_use_xdotool_backend(&self) -> bool {
         self.content.x11_use_xdotool_backend.unwrap_or(false)
     }
 
+    /// The tool used to read/write the clipboard for the `Clipboard` match effect/backend.
+    /// Falls back to mapping the older `x11_use_xclip_backend`/`x11_use_xdotool_backend`
+    /// booleans onto an equivalent provider, so existing configs keep working unchanged; with
+    /// neither the new field nor the old booleans set, this is `ClipboardProvider::Auto`. Builds
+    /// without the `x11` feature never had those booleans to begin with, so they always take
+    /// this last branch.
+    pub fn clipboard_provider(&self) -> ClipboardProvider {
+        #[cfg(feature = "x11")]
+        let (use_xclip, use_xdotool) = (self.x11_use_xclip_backend(), self.x11_use_xdotool_backend());
+        #[cfg(not(feature = "x11"))]
+        let (use_xclip, use_xdotool) = (false, false);
+
+        ClipboardProvider::resolve(self.content.clipboard_provider.as_ref(), use_xclip, use_xdotool)
+    }
+
+    /// Largest payload, in UTF-8 bytes, the `Osc52` clipboard provider will send as an escape
+    /// sequence; terminals cap how much they'll forward to the real clipboard, so callers should
+    /// fall back to a plain inject for anything over this.
+    pub fn clipboard_osc52_max_bytes(&self) -> usize {
+        self.content
+            .clipboard_osc52_max_bytes
+            .unwrap_or(DEFAULT_CLIPBOARD_OSC52_MAX_BYTES)
+    }
+
     pub fn pretty_dump(&self) -> String {
-        formatdoc! {"
+        let mut dump = formatdoc! {"
           [espanso config: {:?}]
 
           enable: {:?}
@@ -491,7 +588,6 @@ impl ProfileFile {
 
           preserve_clipboard: {:?}
           clipboard_threshold: {:?}
-          disable_x11_fast_inject: {}
           pre_paste_delay: {}
           paste_shortcut_event_delay: {}
           auto_restart: {:?}
@@ -509,12 +605,9 @@ impl ProfileFile {
           show_notifications: {:?}
           secure_input_notification: {:?}
 
-          x11_use_xclip_backend: {:?}
-          x11_use_xdotool_backend: {:?}
-          win32_exclude_orphan_events: {:?}
-          win32_keyboard_layout_cache_interval: {:?}
-
-          match_file_paths: {:#?}
+          clipboard_provider: {:?}
+          clipboard_provider_resolved: {:?}
+          clipboard_osc52_max_bytes: {}
         ",
           self.label(),
           self.enable(),
@@ -526,7 +619,6 @@ impl ProfileFile {
 
           self.preserve_clipboard(),
           self.clipboard_threshold(),
-          self.disable_x11_fast_inject(),
           self.pre_paste_delay(),
           self.paste_shortcut_event_delay(),
           self.auto_restart(),
@@ -544,13 +636,49 @@ impl ProfileFile {
           self.show_notifications(),
           self.secure_input_notification(),
 
+          self.clipboard_provider(),
+          detect_clipboard_provider(self.clipboard_provider()),
+          self.clipboard_osc52_max_bytes(),
+        };
+
+        // Only the platform(s) a given build was compiled for have meaningful values here, so
+        // keep their sections out of builds that don't have them instead of printing dead config.
+        #[cfg(feature = "x11")]
+        dump.push_str(&formatdoc! {"
+          disable_x11_fast_inject: {}
+          x11_use_xclip_backend: {:?}
+          x11_use_xdotool_backend: {:?}
+        ",
+          self.disable_x11_fast_inject(),
           self.x11_use_xclip_backend(),
           self.x11_use_xdotool_backend(),
+        });
+        #[cfg(feature = "wayland")]
+        dump.push_str(&formatdoc! {"
+          evdev_modifier_delay: {:?}
+        ",
+          self.evdev_modifier_delay(),
+        });
+        #[cfg(target_os = "windows")]
+        dump.push_str(&formatdoc! {"
+          win32_exclude_orphan_events: {:?}
+          win32_keyboard_layout_cache_interval: {:?}
+        ",
           self.win32_exclude_orphan_events(),
           self.win32_keyboard_layout_cache_interval(),
+        });
 
+        dump.push_str(&formatdoc! {"
+
+          filters: {:#?}
+
+          match_file_paths: {:#?}
+        ",
+          self.filter,
           self.match_file_paths(),
-        }
+        });
+
+        dump
     }
 }
 
@@ -596,18 +724,131 @@ fn aggregate_excludes(config: &ParsedConfig) -> HashSet<String> {
     excludes
 }
 
-fn generate_match_paths(config: &ParsedConfig, base_dir: &Path) -> HashSet<PathBuf> {
+/// Compile a `filter_title`/`filter_class`/`filter_exec`(`_not`) config field, which may list more
+/// than one pattern, into the [`FilterPattern`]s `Expr::from_legacy_filters` OR-matches together.
+/// `case` controls case-(in)sensitivity (see [`FilterCase`]); callers outside the scope of
+/// `filter_case` (`filter_path`/`filter_role`/`filter_desktop`) pass `FilterCase::Sensitive`.
+///
+/// The field itself deserializes a bare YAML scalar (`filter_exec: chrome.exe`) the same way as a
+/// one-element sequence, so this function only ever has to deal with the list form.
+fn parse_filter_patterns(
+    patterns: Option<&[String]>,
+    case: FilterCase,
+) -> Result<Vec<FilterPattern>> {
+    patterns
+        .unwrap_or_default()
+        .iter()
+        .map(|pattern| FilterPattern::parse_with_case(pattern, case).map_err(Into::into))
+        .collect()
+}
+
+fn generate_match_paths(
+    config: &ParsedConfig,
+    base_dir: &Path,
+) -> (HashSet<PathBuf>, Vec<ErrorRecord>) {
     let includes = aggregate_includes(config);
     let excludes = aggregate_excludes(config);
 
     // Extract the paths
-    let exclude_paths = calculate_paths(base_dir, excludes.iter());
-    let include_paths = calculate_paths(base_dir, includes.iter());
+    let (exclude_paths, exclude_errors) = calculate_paths(base_dir, excludes.iter());
+    let (include_paths, include_errors) = calculate_paths(base_dir, includes.iter());
 
-    include_paths
+    let match_paths = include_paths
         .difference(&exclude_paths)
         .cloned()
-        .collect::<HashSet<_>>()
+        .collect::<HashSet<_>>();
+
+    let mut non_fatal_errors = include_errors;
+    non_fatal_errors.extend(exclude_errors);
+
+    (match_paths, non_fatal_errors)
+}
+
+/// Decode the environment-variable override layer into a `ParsedConfig`, one `SHINRAN_PROFILE__*`
+/// variable per field (e.g. `SHINRAN_PROFILE__BACKEND` sets `backend`). Only a handful of
+/// string-valued fields are wired up here rather than all of [`inherit`]'s list; extend this (and
+/// [`ParsedConfig::from_cli_overrides`] alongside it) as more fields need to be override-able this
+/// way. See [`layered_merge`].
+pub fn env_overrides() -> ParsedConfig {
+    let mut overrides = ParsedConfig::default();
+    if let Ok(value) = std::env::var("SHINRAN_PROFILE__LABEL") {
+        overrides.label = Some(value);
+    }
+    if let Ok(value) = std::env::var("SHINRAN_PROFILE__BACKEND") {
+        overrides.backend = Some(value);
+    }
+    if let Ok(value) = std::env::var("SHINRAN_PROFILE__TOGGLE_KEY") {
+        overrides.toggle_key = Some(value);
+    }
+    if let Ok(value) = std::env::var("SHINRAN_PROFILE__SEARCH_TRIGGER") {
+        overrides.search_trigger = Some(value);
+    }
+    if let Ok(value) = std::env::var("SHINRAN_PROFILE__SEARCH_SHORTCUT") {
+        overrides.search_shortcut = Some(value);
+    }
+    if let Ok(value) = std::env::var("SHINRAN_PROFILE__PASTE_SHORTCUT") {
+        overrides.paste_shortcut = Some(value);
+    }
+    overrides
+}
+
+/// Like [`env_overrides`], but reading `profile.<field>` out of `cli_overrides` instead of
+/// `SHINRAN_PROFILE__<FIELD>` environment variables — the same `profile.*` key grammar used
+/// everywhere else a CLI override reaches into the profile config.
+pub fn cli_profile_overrides(cli_overrides: &HashMap<String, String>) -> ParsedConfig {
+    let mut overrides = ParsedConfig::default();
+    if let Some(value) = cli_overrides.get("profile.label") {
+        overrides.label = Some(value.clone());
+    }
+    if let Some(value) = cli_overrides.get("profile.backend") {
+        overrides.backend = Some(value.clone());
+    }
+    if let Some(value) = cli_overrides.get("profile.toggle_key") {
+        overrides.toggle_key = Some(value.clone());
+    }
+    if let Some(value) = cli_overrides.get("profile.search_trigger") {
+        overrides.search_trigger = Some(value.clone());
+    }
+    if let Some(value) = cli_overrides.get("profile.search_shortcut") {
+        overrides.search_shortcut = Some(value.clone());
+    }
+    if let Some(value) = cli_overrides.get("profile.paste_shortcut") {
+        overrides.paste_shortcut = Some(value.clone());
+    }
+    overrides
+}
+
+/// A list-valued config field whose first entry is this sentinel opts out of
+/// [`merge_list_field`]'s accumulation: the sentinel is dropped and the rest of the list stands
+/// alone as a full override of the parent's entries, the same way a plain scalar field shadows
+/// its parent once set.
+const RESET_SENTINEL: &str = "!reset";
+
+/// Accumulate `child`'s list onto `parent`'s instead of letting it fully shadow the parent, the
+/// way [`merge!`]'s generic `@fill` fallback does for every other field. Used for
+/// `includes`/`excludes`/`extra_includes`/`extra_excludes`, so a top-level `parent.yml` can
+/// define shared excludes that every descendant profile extends rather than having to restate.
+/// Entries aren't deduplicated here -- [`aggregate_includes`]/[`aggregate_excludes`] already fold
+/// the combined list into a `HashSet`, so a repeated entry is harmless.
+///
+/// A child list whose first element is [`RESET_SENTINEL`] opts back out: the sentinel is dropped
+/// and the remaining entries replace the parent's list outright.
+fn merge_list_field(child: &mut Option<Vec<String>>, parent: &Option<Vec<String>>) {
+    let (Some(child_list), Some(parent_list)) = (child.as_mut(), parent) else {
+        if child.is_none() {
+            *child = parent.clone();
+        }
+        return;
+    };
+
+    if child_list.first().map(String::as_str) == Some(RESET_SENTINEL) {
+        child_list.remove(0);
+        return;
+    }
+
+    let mut merged = parent_list.clone();
+    merged.append(child_list);
+    *child_list = merged;
 }
 
 /// Override the `None` fields in the child with the parent's value.
@@ -651,6 +892,8 @@ fn inherit(child: &mut ParsedConfig, parent: &ParsedConfig) {
         win32_keyboard_layout_cache_interval,
         x11_use_xclip_backend,
         x11_use_xdotool_backend,
+        clipboard_provider,
+        clipboard_osc52_max_bytes,
         includes,
         excludes,
         extra_includes,
@@ -659,8 +902,118 @@ fn inherit(child: &mut ParsedConfig, parent: &ParsedConfig) {
         filter_title,
         filter_class,
         filter_exec,
-        filter_os
+        filter_title_not,
+        filter_class_not,
+        filter_exec_not,
+        filter_case,
+        filter_path,
+        filter_role,
+        filter_desktop,
+        filter_os,
+        filter_expr,
+        merge_profiles
     );
+
+    // The four fields above went through `merge!`'s plain fallback, which is a no-op once the
+    // child already sets them; reapply them with accumulating semantics instead of shadowing.
+    merge_list_field(&mut child.includes, &parent.includes);
+    merge_list_field(&mut child.excludes, &parent.excludes);
+    merge_list_field(&mut child.extra_includes, &parent.extra_includes);
+    merge_list_field(&mut child.extra_excludes, &parent.extra_excludes);
+}
+
+/// Override every named field of `config` that `overrides` sets, regardless of what `config`
+/// already has there. Unlike [`inherit`], which only fills in a profile's unset fields from its
+/// parent, this is for a layer (env vars, CLI overrides) that must win outright. See
+/// [`layered_merge`].
+fn apply_overrides(config: &mut ParsedConfig, overrides: &ParsedConfig, source_name: &'static str) {
+    merge!(
+        @override ParsedConfig,
+        config,
+        overrides,
+        source_name,
+        // Fields (kept in sync with `inherit`'s list; see `merge!`'s compile-time exhaustiveness
+        // check)
+        label,
+        backend,
+        enable,
+        clipboard_threshold,
+        auto_restart,
+        pre_paste_delay,
+        preserve_clipboard,
+        restore_clipboard_delay,
+        paste_shortcut,
+        apply_patch,
+        paste_shortcut_event_delay,
+        disable_x11_fast_inject,
+        toggle_key,
+        inject_delay,
+        key_delay,
+        evdev_modifier_delay,
+        word_separators,
+        backspace_limit,
+        keyboard_layout,
+        search_trigger,
+        search_shortcut,
+        undo_backspace,
+        show_icon,
+        show_notifications,
+        secure_input_notification,
+        emulate_alt_codes,
+        post_form_delay,
+        max_form_width,
+        max_form_height,
+        post_search_delay,
+        win32_exclude_orphan_events,
+        win32_keyboard_layout_cache_interval,
+        x11_use_xclip_backend,
+        x11_use_xdotool_backend,
+        clipboard_provider,
+        clipboard_osc52_max_bytes,
+        includes,
+        excludes,
+        extra_includes,
+        extra_excludes,
+        use_standard_includes,
+        filter_title,
+        filter_class,
+        filter_exec,
+        filter_title_not,
+        filter_class_not,
+        filter_exec_not,
+        filter_case,
+        filter_path,
+        filter_role,
+        filter_desktop,
+        filter_os,
+        filter_expr,
+        merge_profiles
+    );
+}
+
+/// Resolve a profile's effective config through the full precedence chain: parent profile
+/// (`default.yml`, via [`inherit`]) < this profile's own fields < environment variables < CLI
+/// overrides, each later source winning over every earlier one for any field it sets. (A field
+/// none of them set keeps falling back to its built-in Rust default wherever it's read, e.g.
+/// `config.filter_case.unwrap_or_default()` — that's the implicit lowest layer, and doesn't need
+/// a `ParsedConfig` of its own to merge in.)
+///
+/// `env_overrides`/`cli_overrides` are already-decoded (see [`env_overrides`] and
+/// [`cli_profile_overrides`]) rather than raw string maps, so this function doesn't need to know
+/// their key grammar; callers that want both layers applied the same way to every profile
+/// typically decode them once and pass the same two references to every
+/// [`LoadedProfileFile::load_from_path`] call.
+pub fn layered_merge(
+    child: &mut ParsedConfig,
+    parent: Option<&ParsedConfig>,
+    env_overrides: &ParsedConfig,
+    cli_overrides: &ParsedConfig,
+) {
+    if let Some(parent) = parent {
+        inherit(child, parent);
+    }
+    apply_overrides(child, env_overrides, "an environment variable");
+    apply_overrides(child, cli_overrides, "a CLI override");
 }
 
 #[derive(Error, Debug)]
@@ -849,6 +1202,51 @@ mod tests {
         assert_eq!(child.use_standard_includes, Some(false));
     }
 
+    #[test]
+    fn inherit_accumulates_excludes_instead_of_shadowing() {
+        let parent = ParsedConfig {
+            excludes: Some(vec!["shared/*.yml".to_string()]),
+            ..Default::default()
+        };
+        let mut child = ParsedConfig {
+            excludes: Some(vec!["local/*.yml".to_string()]),
+            ..Default::default()
+        };
+
+        inherit(&mut child, &parent);
+        assert_eq!(
+            child.excludes,
+            Some(vec!["shared/*.yml".to_string(), "local/*.yml".to_string()])
+        );
+    }
+
+    #[test]
+    fn inherit_falls_back_to_parent_excludes_when_child_unset() {
+        let parent = ParsedConfig {
+            excludes: Some(vec!["shared/*.yml".to_string()]),
+            ..Default::default()
+        };
+        let mut child = ParsedConfig::default();
+
+        inherit(&mut child, &parent);
+        assert_eq!(child.excludes, Some(vec!["shared/*.yml".to_string()]));
+    }
+
+    #[test]
+    fn inherit_reset_sentinel_drops_the_parents_excludes() {
+        let parent = ParsedConfig {
+            excludes: Some(vec!["shared/*.yml".to_string()]),
+            ..Default::default()
+        };
+        let mut child = ParsedConfig {
+            excludes: Some(vec!["!reset".to_string(), "local/*.yml".to_string()]),
+            ..Default::default()
+        };
+
+        inherit(&mut child, &parent);
+        assert_eq!(child.excludes, Some(vec!["local/*.yml".to_string()]));
+    }
+
     #[test]
     fn match_paths_generated_correctly() {
         use_test_directory(|_, match_dir, config_dir| {
@@ -867,7 +1265,13 @@ mod tests {
             let config_file = config_dir.join("default.yml");
             std::fs::write(&config_file, "").unwrap();
 
-            let config = LoadedProfileFile::load_from_path(&config_file, None).unwrap();
+            let config = LoadedProfileFile::load_from_path(
+                &config_file,
+                None,
+                &ParsedConfig::default(),
+                &ParsedConfig::default(),
+            )
+            .unwrap();
 
             let mut expected = vec![base_file, another_file, sub_file];
             expected.sort();
@@ -918,8 +1322,20 @@ mod tests {
             )
             .unwrap();
 
-            let parent = LoadedProfileFile::load_from_path(&parent_file, None).unwrap();
-            let child = LoadedProfileFile::load_from_path(&config_file, Some(&parent)).unwrap();
+            let parent = LoadedProfileFile::load_from_path(
+                &parent_file,
+                None,
+                &ParsedConfig::default(),
+                &ParsedConfig::default(),
+            )
+            .unwrap();
+            let child = LoadedProfileFile::load_from_path(
+                &config_file,
+                Some(&parent),
+                &ParsedConfig::default(),
+                &ParsedConfig::default(),
+            )
+            .unwrap();
 
             let mut expected = vec![sub_file, sub_under_file];
             expected.sort();
@@ -952,7 +1368,13 @@ mod tests {
             let config_file = config_dir.join("default.yml");
             std::fs::write(&config_file, "extra_includes: ['../match/_sub.yml']").unwrap();
 
-            let config = LoadedProfileFile::load_from_path(&config_file, None).unwrap();
+            let config = LoadedProfileFile::load_from_path(
+                &config_file,
+                None,
+                &ParsedConfig::default(),
+                &ParsedConfig::default(),
+            )
+            .unwrap();
 
             let mut expected = vec![base_file, another_file, sub_file, under_file];
             expected.sort();
@@ -971,7 +1393,13 @@ mod tests {
             let config_file = config_dir.join("default.yml");
             std::fs::write(&config_file, config).unwrap();
 
-            let config = LoadedProfileFile::load_from_path(&config_file, None).unwrap();
+            let config = LoadedProfileFile::load_from_path(
+                &config_file,
+                None,
+                &ParsedConfig::default(),
+                &ParsedConfig::default(),
+            )
+            .unwrap();
 
             *result_ref = config.filter.is_match(app);
         });
@@ -986,6 +1414,9 @@ mod tests {
                 title: Some("Google"),
                 class: Some("Chrome"),
                 exec: Some("chrome.exe"),
+                path: None,
+                window_role: None,
+                desktop_id: None,
             },
         ));
     }
@@ -993,29 +1424,38 @@ mod tests {
     #[test]
     fn is_match_filter_title() {
         assert!(test_filter_is_match(
-            "filter_title: Google",
+            "filter_title: [Google]",
             &AppProperties {
                 title: Some("Google Mail"),
                 class: Some("Chrome"),
                 exec: Some("chrome.exe"),
+                path: None,
+                window_role: None,
+                desktop_id: None,
             },
         ));
 
         assert!(!test_filter_is_match(
-            "filter_title: Google",
+            "filter_title: [Google]",
             &AppProperties {
                 title: Some("Yahoo"),
                 class: Some("Chrome"),
                 exec: Some("chrome.exe"),
+                path: None,
+                window_role: None,
+                desktop_id: None,
             },
         ));
 
         assert!(!test_filter_is_match(
-            "filter_title: Google",
+            "filter_title: [Google]",
             &AppProperties {
                 title: None,
                 class: Some("Chrome"),
                 exec: Some("chrome.exe"),
+                path: None,
+                window_role: None,
+                desktop_id: None,
             },
         ));
     }
@@ -1023,29 +1463,38 @@ mod tests {
     #[test]
     fn is_match_filter_class() {
         assert!(test_filter_is_match(
-            "filter_class: Chrome",
+            "filter_class: [Chrome]",
             &AppProperties {
                 title: Some("Google Mail"),
                 class: Some("Chrome"),
                 exec: Some("chrome.exe"),
+                path: None,
+                window_role: None,
+                desktop_id: None,
             },
         ));
 
         assert!(!test_filter_is_match(
-            "filter_class: Chrome",
+            "filter_class: [Chrome]",
             &AppProperties {
                 title: Some("Yahoo"),
                 class: Some("Another"),
                 exec: Some("chrome.exe"),
+                path: None,
+                window_role: None,
+                desktop_id: None,
             },
         ));
 
         assert!(!test_filter_is_match(
-            "filter_class: Chrome",
+            "filter_class: [Chrome]",
             &AppProperties {
                 title: Some("google"),
                 class: None,
                 exec: Some("chrome.exe"),
+                path: None,
+                window_role: None,
+                desktop_id: None,
             },
         ));
     }
@@ -1053,87 +1502,487 @@ mod tests {
     #[test]
     fn is_match_filter_exec() {
         assert!(test_filter_is_match(
-            "filter_exec: chrome.exe",
+            "filter_exec: [chrome.exe]",
             &AppProperties {
                 title: Some("Google Mail"),
                 class: Some("Chrome"),
                 exec: Some("chrome.exe"),
+                path: None,
+                window_role: None,
+                desktop_id: None,
             },
         ));
 
         assert!(!test_filter_is_match(
-            "filter_exec: chrome.exe",
+            "filter_exec: [chrome.exe]",
             &AppProperties {
                 title: Some("Yahoo"),
                 class: Some("Another"),
                 exec: Some("zoom.exe"),
+                path: None,
+                window_role: None,
+                desktop_id: None,
             },
         ));
 
         assert!(!test_filter_is_match(
-            "filter_exec: chrome.exe",
+            "filter_exec: [chrome.exe]",
             &AppProperties {
                 title: Some("google"),
                 class: Some("Chrome"),
                 exec: None,
+                path: None,
+                window_role: None,
+                desktop_id: None,
+            },
+        ));
+    }
+
+    #[test]
+    fn is_match_filter_exec_glob() {
+        assert!(test_filter_is_match(
+            r#"filter_exec: ["glob:*.exe"]"#,
+            &AppProperties {
+                title: Some("Google Mail"),
+                class: Some("Chrome"),
+                exec: Some("chrome.exe"),
+                path: None,
+                window_role: None,
+                desktop_id: None,
+            },
+        ));
+
+        assert!(!test_filter_is_match(
+            r#"filter_exec: ["glob:*.exe"]"#,
+            &AppProperties {
+                title: Some("Yahoo"),
+                class: Some("Another"),
+                exec: Some("chrome"),
+                path: None,
+                window_role: None,
+                desktop_id: None,
+            },
+        ));
+    }
+
+    #[test]
+    fn is_match_filter_os() {
+        let (current, another) = if cfg!(target_os = "windows") {
+            ("windows", "macos")
+        } else if cfg!(target_os = "macos") {
+            ("macos", "windows")
+        } else if cfg!(target_os = "linux") {
+            ("linux", "macos")
+        } else {
+            ("invalid", "invalid")
+        };
+
+        assert!(test_filter_is_match(
+            &format!("filter_os: {current}"),
+            &AppProperties {
+                title: Some("Google Mail"),
+                class: Some("Chrome"),
+                exec: Some("chrome.exe"),
+                path: None,
+                window_role: None,
+                desktop_id: None,
+            },
+        ));
+
+        assert!(!test_filter_is_match(
+            &format!("filter_os: {another}"),
+            &AppProperties {
+                title: Some("Google Mail"),
+                class: Some("Chrome"),
+                exec: Some("chrome.exe"),
+                path: None,
+                window_role: None,
+                desktop_id: None,
+            },
+        ));
+    }
+
+    #[test]
+    fn is_match_filter_os_accepts_a_list() {
+        let (current, another) = if cfg!(target_os = "windows") {
+            ("windows", "macos")
+        } else if cfg!(target_os = "macos") {
+            ("macos", "windows")
+        } else if cfg!(target_os = "linux") {
+            ("linux", "macos")
+        } else {
+            ("invalid", "invalid")
+        };
+
+        assert!(test_filter_is_match(
+            &format!("filter_os: [{another}, {current}]"),
+            &AppProperties {
+                title: Some("Google Mail"),
+                class: Some("Chrome"),
+                exec: Some("chrome.exe"),
+                path: None,
+                window_role: None,
+                desktop_id: None,
+            },
+        ));
+    }
+
+    #[test]
+    fn is_match_filter_os_scopes_to_arch() {
+        let current_arch = std::env::consts::ARCH;
+
+        assert!(test_filter_is_match(
+            &format!("filter_os: {{ os: {}, arch: {current_arch} }}", std::env::consts::OS),
+            &AppProperties {
+                title: Some("Google Mail"),
+                class: Some("Chrome"),
+                exec: Some("chrome.exe"),
+                path: None,
+                window_role: None,
+                desktop_id: None,
+            },
+        ));
+
+        assert!(!test_filter_is_match(
+            &format!("filter_os: {{ os: {}, arch: not-a-real-arch }}", std::env::consts::OS),
+            &AppProperties {
+                title: Some("Google Mail"),
+                class: Some("Chrome"),
+                exec: Some("chrome.exe"),
+                path: None,
+                window_role: None,
+                desktop_id: None,
+            },
+        ));
+    }
+
+    #[test]
+    fn is_match_filter_case_defaults_to_sensitive() {
+        assert!(!test_filter_is_match(
+            "filter_title: [Google]",
+            &AppProperties {
+                title: Some("google mail"),
+                class: None,
+                exec: None,
+                path: None,
+                window_role: None,
+                desktop_id: None,
             },
         ));
     }
 
-    // #[test]
-    // fn is_match_filter_os() {
-    //     let (current, another) = if cfg!(target_os = "windows") {
-    //         ("windows", "macos")
-    //     } else if cfg!(target_os = "macos") {
-    //         ("macos", "windows")
-    //     } else if cfg!(target_os = "linux") {
-    //         ("linux", "macos")
-    //     } else {
-    //         ("invalid", "invalid")
-    //     };
-
-    //     assert!(test_filter_is_match(
-    //         &format!("filter_os: {current}"),
-    //         &AppProperties {
-    //             title: Some("Google Mail"),
-    //             class: Some("Chrome"),
-    //             exec: Some("chrome.exe"),
-    //         },
-    //     ));
-
-    //     assert!(!test_filter_is_match(
-    //         &format!("filter_os: {another}"),
-    //         &AppProperties {
-    //             title: Some("Google Mail"),
-    //             class: Some("Chrome"),
-    //             exec: Some("chrome.exe"),
-    //         },
-    //     ));
-    // }
+    #[test]
+    fn is_match_filter_case_insensitive() {
+        assert!(test_filter_is_match(
+            "filter_case: insensitive\nfilter_title: [Google]",
+            &AppProperties {
+                title: Some("google mail"),
+                class: None,
+                exec: None,
+                path: None,
+                window_role: None,
+                desktop_id: None,
+            },
+        ));
+    }
+
+    #[test]
+    fn is_match_filter_case_smart_is_sensitive_once_pattern_has_uppercase() {
+        assert!(!test_filter_is_match(
+            "filter_case: smart\nfilter_title: [Google]",
+            &AppProperties {
+                title: Some("google mail"),
+                class: None,
+                exec: None,
+                path: None,
+                window_role: None,
+                desktop_id: None,
+            },
+        ));
+    }
+
+    #[test]
+    fn is_match_filter_path() {
+        assert!(test_filter_is_match(
+            "filter_path: [\"/usr/bin/google-chrome\"]",
+            &AppProperties {
+                title: None,
+                class: None,
+                exec: None,
+                path: Some("/usr/bin/google-chrome"),
+                window_role: None,
+                desktop_id: None,
+            },
+        ));
+
+        assert!(!test_filter_is_match(
+            "filter_path: [\"/usr/bin/google-chrome\"]",
+            &AppProperties {
+                title: None,
+                class: None,
+                exec: None,
+                path: Some("/usr/bin/firefox"),
+                window_role: None,
+                desktop_id: None,
+            },
+        ));
+    }
+
+    #[test]
+    fn is_match_filter_role() {
+        assert!(test_filter_is_match(
+            "filter_role: [pop-up]",
+            &AppProperties {
+                title: None,
+                class: None,
+                exec: None,
+                path: None,
+                window_role: Some("pop-up"),
+                desktop_id: None,
+            },
+        ));
+
+        assert!(!test_filter_is_match(
+            "filter_role: [pop-up]",
+            &AppProperties {
+                title: None,
+                class: None,
+                exec: None,
+                path: None,
+                window_role: Some("browser"),
+                desktop_id: None,
+            },
+        ));
+    }
+
+    #[test]
+    fn is_match_filter_desktop() {
+        assert!(test_filter_is_match(
+            "filter_desktop: [google-chrome]",
+            &AppProperties {
+                title: None,
+                class: Some("Chrome"),
+                exec: None,
+                path: None,
+                window_role: None,
+                desktop_id: Some("google-chrome"),
+            },
+        ));
+
+        assert!(!test_filter_is_match(
+            "filter_desktop: [google-chrome]",
+            &AppProperties {
+                title: None,
+                class: Some("Chrome"),
+                exec: None,
+                path: None,
+                window_role: None,
+                desktop_id: Some("chromium"),
+            },
+        ));
+    }
 
     #[test]
     fn is_match_multiple_filters() {
         assert!(test_filter_is_match(
             r#"
-      filter_exec: chrome.exe
-      filter_title: "Youtube"
+      filter_exec: [chrome.exe]
+      filter_title: ["Youtube"]
+      "#,
+            &AppProperties {
+                title: Some("Youtube - Broadcast Yourself"),
+                class: Some("Chrome"),
+                exec: Some("chrome.exe"),
+                path: None,
+                window_role: None,
+                desktop_id: None,
+            },
+        ));
+
+        assert!(!test_filter_is_match(
+            r#"
+      filter_exec: [chrome.exe]
+      filter_title: ["Youtube"]
+      "#,
+            &AppProperties {
+                title: Some("Gmail"),
+                class: Some("Chrome"),
+                exec: Some("chrome.exe"),
+                path: None,
+                window_role: None,
+                desktop_id: None,
+            },
+        ));
+    }
+
+    #[test]
+    fn is_match_multiple_filters_mixes_regex_and_glob() {
+        // A regex `filter_title` and a glob `filter_exec` must both match (AND semantics).
+        assert!(test_filter_is_match(
+            r#"
+      filter_exec: ["glob:*.exe"]
+      filter_title: ["Youtube"]
       "#,
             &AppProperties {
                 title: Some("Youtube - Broadcast Yourself"),
                 class: Some("Chrome"),
                 exec: Some("chrome.exe"),
+                path: None,
+                window_role: None,
+                desktop_id: None,
             },
         ));
 
         assert!(!test_filter_is_match(
             r#"
-      filter_exec: chrome.exe
-      filter_title: "Youtube"
+      filter_exec: ["glob:*.exe"]
+      filter_title: ["Youtube"]
       "#,
+            &AppProperties {
+                title: Some("Youtube - Broadcast Yourself"),
+                class: Some("Chrome"),
+                exec: Some("chrome"),
+                path: None,
+                window_role: None,
+                desktop_id: None,
+            },
+        ));
+    }
+
+    #[test]
+    fn is_match_filter_title_list_is_or_matched() {
+        assert!(test_filter_is_match(
+            "filter_title: [Google, Yahoo]",
+            &AppProperties {
+                title: Some("Yahoo Mail"),
+                class: Some("Chrome"),
+                exec: Some("chrome.exe"),
+                path: None,
+                window_role: None,
+                desktop_id: None,
+            },
+        ));
+
+        assert!(!test_filter_is_match(
+            "filter_title: [Google, Yahoo]",
+            &AppProperties {
+                title: Some("Bing"),
+                class: Some("Chrome"),
+                exec: Some("chrome.exe"),
+                path: None,
+                window_role: None,
+                desktop_id: None,
+            },
+        ));
+    }
+
+    #[test]
+    fn is_match_filter_title_accepts_a_bare_scalar() {
+        assert!(test_filter_is_match(
+            "filter_title: Google",
+            &AppProperties {
+                title: Some("Google Mail"),
+                class: Some("Chrome"),
+                exec: Some("chrome.exe"),
+                path: None,
+                window_role: None,
+                desktop_id: None,
+            },
+        ));
+    }
+
+    #[test]
+    fn is_match_filter_title_not_vetoes_a_positive_match() {
+        let config = r#"
+      filter_class: [Chrome]
+      filter_title_not: [Incognito]
+      "#;
+
+        assert!(test_filter_is_match(
+            config,
+            &AppProperties {
+                title: Some("Gmail"),
+                class: Some("Chrome"),
+                exec: Some("chrome.exe"),
+                path: None,
+                window_role: None,
+                desktop_id: None,
+            },
+        ));
+
+        assert!(!test_filter_is_match(
+            config,
+            &AppProperties {
+                title: Some("Gmail - Incognito"),
+                class: Some("Chrome"),
+                exec: Some("chrome.exe"),
+                path: None,
+                window_role: None,
+                desktop_id: None,
+            },
+        ));
+    }
+
+    #[test]
+    fn is_match_filter_class_not_vetoes_a_positive_match() {
+        let config = r#"
+      filter_exec: [chrome.exe]
+      filter_class_not: [Incognito]
+      "#;
+
+        assert!(test_filter_is_match(
+            config,
+            &AppProperties {
+                title: Some("Gmail"),
+                class: Some("Chrome"),
+                exec: Some("chrome.exe"),
+                path: None,
+                window_role: None,
+                desktop_id: None,
+            },
+        ));
+
+        assert!(!test_filter_is_match(
+            config,
+            &AppProperties {
+                title: Some("Gmail"),
+                class: Some("Incognito"),
+                exec: Some("chrome.exe"),
+                path: None,
+                window_role: None,
+                desktop_id: None,
+            },
+        ));
+    }
+
+    #[test]
+    fn is_match_filter_exec_not_vetoes_a_positive_match() {
+        let config = r#"
+      filter_class: [Chrome]
+      filter_exec_not: [msedge.exe]
+      "#;
+
+        assert!(test_filter_is_match(
+            config,
             &AppProperties {
                 title: Some("Gmail"),
                 class: Some("Chrome"),
                 exec: Some("chrome.exe"),
+                path: None,
+                window_role: None,
+                desktop_id: None,
+            },
+        ));
+
+        assert!(!test_filter_is_match(
+            config,
+            &AppProperties {
+                title: Some("Gmail"),
+                class: Some("Chrome"),
+                exec: Some("msedge.exe"),
+                path: None,
+                window_role: None,
+                desktop_id: None,
             },
         ));
     }
@@ -17,15 +17,20 @@
  * along with espanso.  If not, see <https://www.gnu.org/licenses/>.
  */
 use std::{
+    borrow::Cow,
     collections::{HashMap, HashSet},
     path::{Path, PathBuf},
 };
 
+use crate::config::ignore::{IgnoreList, PathFilter};
 use crate::error::NonFatalErrorSet;
-use crate::matches::group::loader::yaml::YAMLImporter;
-use crate::{config::resolve::LoadedProfileFile, matches::group::MatchFileRef};
+use crate::matches::group::loader;
+use crate::{
+    config::{parse::ParsedConfig, resolve::LoadedProfileFile},
+    matches::group::MatchFileRef,
+};
 
-use super::{resolve::ArchivedProfileFile, ConfigStoreError, ProfileFile};
+use super::{matcher::ConfigMatcher, resolve::ArchivedProfileFile, ConfigStoreError, ProfileFile};
 use anyhow::{Context, Result};
 use log::{debug, error};
 use rkyv::{Archive, Deserialize, Serialize};
@@ -55,6 +60,28 @@ impl ProfileStore {
         }
     }
 
+    /// Wire every resolved package match file into the default profile, so an installed package
+    /// applies unconditionally rather than only being picked up if some profile's `includes`
+    /// happens to reach it. This is also what lets [`crate::validate::validate`]'s
+    /// duplicate-trigger check catch a collision between two packages (or a package and a user
+    /// snippet): once it's reachable from the default profile, it's covered by that same check.
+    pub(crate) fn add_package_match_files(
+        &mut self,
+        paths: impl IntoIterator<Item = MatchFileRef>,
+    ) {
+        let mut seen: HashSet<MatchFileRef> = self
+            .default_profile
+            .match_file_paths
+            .iter()
+            .copied()
+            .collect();
+        for path_ref in paths {
+            if seen.insert(path_ref) {
+                self.default_profile.match_file_paths.push(path_ref);
+            }
+        }
+    }
+
     /// Get the active configuration for the given app.
     ///
     /// This will return the *first* custom configuration that matches the app properties.
@@ -68,9 +95,61 @@ impl ProfileStore {
         &self.default_profile
     }
 
+    /// Get the active configuration for the given app, optionally merging every matching
+    /// profile together instead of returning only the first match.
+    ///
+    /// Layers are folded default-first, then each matching custom profile in declaration
+    /// order, so a later profile's scalar fields override earlier ones while match-file
+    /// references accumulate across every matching layer. Only takes effect when the default
+    /// profile sets `merge_profiles: true`; otherwise this is identical to `active_config`.
+    pub fn active_config_merged(&self, app: &super::AppProperties) -> Cow<'_, ProfileFile> {
+        if !self.default_profile.merge_profiles() {
+            return Cow::Borrowed(self.active_config(app));
+        }
+
+        let mut merged = self.default_profile.clone();
+        for custom in self.custom_profiles.iter() {
+            if custom.filter.is_match(app) {
+                merged = merged.merged_with(custom);
+            }
+        }
+        Cow::Owned(merged)
+    }
+
     pub fn len(&self) -> usize {
         self.custom_profiles.len() + 1
     }
+
+    pub(crate) fn custom_profiles(&self) -> &[ProfileFile] {
+        &self.custom_profiles
+    }
+
+    /// The source path of every loaded profile file. The live counterpart to
+    /// [`ArchivedProfileStore::get_source_paths`].
+    pub fn get_source_paths(&self) -> impl Iterator<Item = &Path> {
+        std::iter::once(&self.default_profile)
+            .chain(self.custom_profiles.iter())
+            .map(|profile| profile.source_path.as_path())
+    }
+
+    /// Build a [`ConfigMatcher`] over this store's custom profiles. Scales to many app-specific
+    /// config files better than repeated calls to `active_config`: build this once after loading
+    /// (or whenever `custom_profiles` changes) and reuse it across window events via
+    /// `active_config_fast`, rather than recompiling/re-testing every profile's filters each time.
+    pub fn build_matcher(&self) -> ConfigMatcher {
+        ConfigMatcher::new(&self.custom_profiles)
+    }
+
+    /// Like `active_config`, but narrows candidates with `matcher` first (see [`ConfigMatcher`])
+    /// instead of testing every custom profile's filters in turn. `matcher` must have come from
+    /// `self.build_matcher()`, and be rebuilt if `custom_profiles` has changed since.
+    pub fn active_config_fast(
+        &self,
+        matcher: &ConfigMatcher,
+        app: &super::AppProperties,
+    ) -> &ProfileFile {
+        matcher.active_config(&self.default_profile, &self.custom_profiles, app)
+    }
 }
 
 impl ArchivedProfileStore {
@@ -87,6 +166,7 @@ impl ArchivedProfileStore {
 pub(crate) struct LoadedProfileStore {
     default_profile: LoadedProfileFile,
     custom_profiles: Vec<LoadedProfileFile>,
+    ignore: IgnoreList,
 }
 
 impl LoadedProfileStore {
@@ -100,10 +180,18 @@ impl LoadedProfileStore {
             paths.extend(profile.match_file_paths.iter().cloned());
         }
 
+        paths.retain(|path| !self.ignore.matches_path(path));
+
         paths
     }
 
-    pub fn load(config_dir: &Path) -> Result<(Self, Vec<NonFatalErrorSet>)> {
+    /// `env_overrides`/`cli_overrides` are folded into every profile this loads (see
+    /// [`LoadedProfileFile::load_from_path`] and `crate::config::resolve::layered_merge`).
+    pub fn load(
+        config_dir: &Path,
+        env_overrides: &ParsedConfig,
+        cli_overrides: &ParsedConfig,
+    ) -> Result<(Self, Vec<NonFatalErrorSet>)> {
         if !config_dir.is_dir() {
             return Err(ConfigStoreError::InvalidConfigDir().into());
         }
@@ -117,8 +205,22 @@ impl LoadedProfileStore {
         let mut non_fatal_errors = Vec::new();
 
         debug!("loading default config at path: {:?}", default_file);
-        let default_profile = LoadedProfileFile::load_from_path(&default_file, None)
-            .context("failed to load default.yml configuration")?;
+        let mut default_profile =
+            LoadedProfileFile::load_from_path(&default_file, None, env_overrides, cli_overrides)
+                .context("failed to load default.yml configuration")?;
+        let default_profile_errors = std::mem::take(&mut default_profile.non_fatal_errors);
+        if !default_profile_errors.is_empty() {
+            non_fatal_errors.push(NonFatalErrorSet::new(&default_file, default_profile_errors));
+        }
+
+        let ignore = IgnoreList::load(
+            config_dir,
+            default_profile
+                .content
+                .ignore_paths
+                .as_deref()
+                .unwrap_or_default(),
+        );
 
         // Then the others
         let mut custom_profiles: Vec<LoadedProfileFile> = vec![];
@@ -131,12 +233,23 @@ impl LoadedProfileStore {
             // Additional config files are loaded best-effort
             if config_file.is_file()
                 && config_file != default_file
-                && YAMLImporter::is_supported(extension)
+                && loader::is_supported(extension)
+                && !ignore.matches_path(&config_file)
             {
                 debug!("loading config at path: {:?}", config_file);
                 // TODO: Move `config_file` into `load_from_path` instead of passing it by reference
-                match LoadedProfileFile::load_from_path(&config_file, Some(&default_profile)) {
-                    Ok(config) => {
+                match LoadedProfileFile::load_from_path(
+                    &config_file,
+                    Some(&default_profile),
+                    env_overrides,
+                    cli_overrides,
+                ) {
+                    Ok(mut config) => {
+                        let profile_errors = std::mem::take(&mut config.non_fatal_errors);
+                        if !profile_errors.is_empty() {
+                            non_fatal_errors
+                                .push(NonFatalErrorSet::new(&config_file, profile_errors));
+                        }
                         custom_profiles.push(config);
                     }
                     Err(err) => {
@@ -154,6 +267,7 @@ impl LoadedProfileStore {
             Self {
                 default_profile,
                 custom_profiles,
+                ignore,
             },
             non_fatal_errors,
         ))
@@ -162,11 +276,8 @@ impl LoadedProfileStore {
 
 #[cfg(test)]
 mod tests {
-    use regex::Regex;
     use shinran_types::RegexWrapper;
 
-    use crate::config::parse::ParsedConfig;
-
     use super::*;
 
     pub fn new_mock(label: &'static str) -> ProfileFile {
@@ -190,7 +301,20 @@ mod tests {
         let default = new_mock("default");
         let custom1 = new_mock("custom1");
         let mut custom2 = new_mock("custom2");
-        custom2.filter.class = Some(RegexWrapper::new(Regex::new("foo").unwrap()));
+        let class = vec![crate::config::filter_expr::FilterPattern::parse("foo").unwrap()];
+        custom2.filter.expr = crate::config::filter_expr::Expr::from_legacy_filters(
+            &[],
+            &class,
+            &[],
+            &[],
+            &[],
+            &[],
+            &[],
+            &[],
+            &[],
+            None,
+        );
+        custom2.filter.class = class;
 
         let store = ProfileStore {
             default_profile: default,
@@ -204,6 +328,9 @@ mod tests {
                     title: None,
                     class: Some("foo"),
                     exec: None,
+                    path: None,
+                    window_role: None,
+                    desktop_id: None,
                 })
                 .label(),
             "custom2"
@@ -228,9 +355,55 @@ mod tests {
                     title: None,
                     class: None,
                     exec: None,
+                    path: None,
+                    window_role: None,
+                    desktop_id: None,
                 })
                 .label(),
             "default"
         );
     }
+
+    #[test]
+    fn config_store_active_config_fast_agrees_with_active_config() {
+        let default = new_mock("default");
+        let custom1 = new_mock("custom1");
+        let mut custom2 = new_mock("custom2");
+        let class = vec![crate::config::filter_expr::FilterPattern::Regex(
+            regex::Regex::new("foo").unwrap(),
+        )];
+        custom2.filter.expr = crate::config::filter_expr::Expr::from_legacy_filters(
+            &[],
+            &class,
+            &[],
+            &[],
+            &[],
+            &[],
+            &[],
+            &[],
+            &[],
+            None,
+        );
+        custom2.filter.class = class;
+
+        let store = ProfileStore {
+            default_profile: default,
+            custom_profiles: Box::new([custom1, custom2]),
+        };
+        let matcher = store.build_matcher();
+
+        let app = crate::config::AppProperties {
+            title: None,
+            class: Some("foo"),
+            exec: None,
+            path: None,
+            window_role: None,
+            desktop_id: None,
+        };
+
+        assert_eq!(
+            store.active_config_fast(&matcher, &app).label(),
+            store.active_config(&app).label()
+        );
+    }
 }
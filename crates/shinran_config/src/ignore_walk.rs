@@ -0,0 +1,236 @@
+/*
+ * This file is part of espanso.
+ *
+ * Copyright (C) 2019-2021 Federico Terzi
+ *
+ * espanso is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * espanso is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with espanso.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! A small, self-contained directory walker modeled on the `ignore` crate: recursively walks a
+//! directory tree, honoring per-directory ignore files (preferring `.shinranignore`, falling
+//! back to `.gitignore`) so large config/snippet collections can be organized into nested
+//! folders with drafts or backups excluded. Ignore rules are layered per directory as the walk
+//! descends: a deeper directory's ignore file is checked after every ancestor's, so its
+//! patterns take precedence over them — matching how a real `.gitignore` works, including a
+//! leading `!` negating a pattern and a trailing `/` restricting it to directories.
+
+use std::path::{Path, PathBuf};
+
+use globset::{Glob, GlobMatcher};
+
+const IGNORE_FILE_NAMES: [&str; 2] = [".shinranignore", ".gitignore"];
+
+/// One parsed line of an ignore file, anchored so it only matches within the directory the
+/// ignore file lives in.
+struct IgnoreRule {
+    matcher: GlobMatcher,
+    negated: bool,
+    dir_only: bool,
+}
+
+/// Parse `contents` (one `.shinranignore`/`.gitignore` file living in `dir`) into its rules.
+/// A malformed pattern is skipped, the same way a stray unparseable line in a real `.gitignore`
+/// would simply never match anything.
+fn parse_ignore_file(dir: &Path, contents: &str) -> Vec<IgnoreRule> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                return None;
+            }
+            let (line, negated) = match line.strip_prefix('!') {
+                Some(rest) => (rest, true),
+                None => (line, false),
+            };
+            let (pattern, dir_only) = match line.strip_suffix('/') {
+                Some(rest) => (rest, true),
+                None => (line, false),
+            };
+            // A pattern with no `/` matches at any depth under `dir`, same as a real
+            // `.gitignore`; one that does is anchored to `dir` itself.
+            let anchored = if pattern.contains('/') {
+                dir.join(pattern)
+            } else {
+                dir.join("**").join(pattern)
+            };
+            let glob = Glob::new(&anchored.to_string_lossy()).ok()?;
+            Some(IgnoreRule {
+                matcher: glob.compile_matcher(),
+                negated,
+                dir_only,
+            })
+        })
+        .collect()
+}
+
+/// Whether `path` is ignored by the currently layered `rules`: the *last* rule (across every
+/// layered ignore file, root to leaf, in file order) that matches `path` decides — a later
+/// negated match un-ignores it again, same as `.gitignore`.
+fn is_ignored(path: &Path, is_dir: bool, rules: &[IgnoreRule]) -> bool {
+    let mut ignored = false;
+    for rule in rules {
+        if rule.dir_only && !is_dir {
+            continue;
+        }
+        if rule.matcher.is_match(path) {
+            ignored = !rule.negated;
+        }
+    }
+    ignored
+}
+
+/// Recursively walk `root`, honoring any ignore file found along the way. `should_descend` is
+/// consulted for each directory so a caller can silently prune a subtree outright (e.g. an
+/// `exclude` glob elsewhere matching it) instead of just filtering the files under it one by
+/// one; pass `&|_| true` to always descend. Returns every file for which `wanted` returns `true`,
+/// plus every path an *ignore rule* (not `should_descend`) skipped, since only the ignore-file
+/// skips are meant to be surfaced as diagnostics — a `should_descend`-pruned subtree is no
+/// different from one `wanted` never matched anything in.
+pub(crate) fn walk_respecting_ignore_files(
+    root: &Path,
+    wanted: &dyn Fn(&Path) -> bool,
+    should_descend: &dyn Fn(&Path) -> bool,
+) -> (Vec<PathBuf>, Vec<PathBuf>) {
+    let mut included = Vec::new();
+    let mut ignored = Vec::new();
+    let mut rules: Vec<IgnoreRule> = Vec::new();
+    walk_dir(
+        root,
+        &mut rules,
+        wanted,
+        should_descend,
+        &mut included,
+        &mut ignored,
+    );
+    included.sort();
+    ignored.sort();
+    (included, ignored)
+}
+
+fn walk_dir(
+    dir: &Path,
+    rules: &mut Vec<IgnoreRule>,
+    wanted: &dyn Fn(&Path) -> bool,
+    should_descend: &dyn Fn(&Path) -> bool,
+    included: &mut Vec<PathBuf>,
+    ignored: &mut Vec<PathBuf>,
+) {
+    let own_rules = IGNORE_FILE_NAMES
+        .iter()
+        .find_map(|name| std::fs::read_to_string(dir.join(name)).ok())
+        .map(|contents| parse_ignore_file(dir, &contents))
+        .unwrap_or_default();
+    let pushed = own_rules.len();
+    rules.extend(own_rules);
+
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        rules.truncate(rules.len() - pushed);
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let is_dir = path.is_dir();
+        if is_ignored(&path, is_dir, rules) {
+            ignored.push(path);
+            continue;
+        }
+        if is_dir {
+            if should_descend(&path) {
+                walk_dir(&path, rules, wanted, should_descend, included, ignored);
+            }
+        } else if wanted(&path) {
+            included.push(path);
+        }
+    }
+
+    rules.truncate(rules.len() - pushed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shinran_helpers::use_test_directory;
+    use std::fs::create_dir_all;
+
+    fn yaml_only(path: &Path) -> bool {
+        path.extension().is_some_and(|ext| ext == "yml")
+    }
+
+    #[test]
+    fn walk_respecting_ignore_files_includes_everything_without_an_ignore_file() {
+        use_test_directory(|base, _, _| {
+            let sub_dir = base.join("sub");
+            create_dir_all(&sub_dir).unwrap();
+
+            let top_file = base.join("top.yml");
+            std::fs::write(&top_file, "").unwrap();
+            let sub_file = sub_dir.join("sub.yml");
+            std::fs::write(&sub_file, "").unwrap();
+
+            let (included, ignored) = walk_respecting_ignore_files(base, &yaml_only, &|_| true);
+
+            assert_eq!(included, vec![sub_file, top_file]);
+            assert!(ignored.is_empty());
+        });
+    }
+
+    #[test]
+    fn walk_respecting_ignore_files_skips_patterns_from_gitignore() {
+        use_test_directory(|base, _, _| {
+            let drafts_dir = base.join("drafts");
+            create_dir_all(&drafts_dir).unwrap();
+
+            std::fs::write(base.join(".gitignore"), "drafts/\n*.bak.yml\n").unwrap();
+
+            let kept_file = base.join("kept.yml");
+            std::fs::write(&kept_file, "").unwrap();
+            let backup_file = base.join("kept.bak.yml");
+            std::fs::write(&backup_file, "").unwrap();
+            let draft_file = drafts_dir.join("draft.yml");
+            std::fs::write(&draft_file, "").unwrap();
+
+            let (included, ignored) = walk_respecting_ignore_files(base, &yaml_only, &|_| true);
+
+            assert_eq!(included, vec![kept_file]);
+            assert_eq!(ignored, vec![drafts_dir, backup_file]);
+        });
+    }
+
+    #[test]
+    fn walk_respecting_ignore_files_deeper_ignore_file_overrides_shallower_one() {
+        use_test_directory(|base, _, _| {
+            let sub_dir = base.join("sub");
+            create_dir_all(&sub_dir).unwrap();
+
+            std::fs::write(base.join(".gitignore"), "*.yml\n").unwrap();
+            std::fs::write(sub_dir.join(".gitignore"), "!keep.yml\n").unwrap();
+
+            let top_file = base.join("top.yml");
+            std::fs::write(&top_file, "").unwrap();
+            let kept_file = sub_dir.join("keep.yml");
+            std::fs::write(&kept_file, "").unwrap();
+            let dropped_file = sub_dir.join("dropped.yml");
+            std::fs::write(&dropped_file, "").unwrap();
+
+            let (included, ignored) = walk_respecting_ignore_files(base, &yaml_only, &|_| true);
+
+            // `sub/.gitignore`'s negation re-includes `keep.yml` despite the root's blanket
+            // `*.yml` rule, but `dropped.yml` is still caught by that root rule.
+            assert_eq!(included, vec![kept_file]);
+            assert_eq!(ignored, vec![dropped_file, top_file]);
+        });
+    }
+}
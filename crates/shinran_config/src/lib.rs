@@ -17,56 +17,177 @@
  * along with espanso.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use thiserror::Error;
 
 pub mod config;
 pub mod error;
+mod ignore_walk;
 pub mod matches;
+mod packages;
 mod util;
+mod validate;
+mod watch;
+
+pub use packages::LoadedPackage;
+pub use watch::{load_and_watch, ReloadEvent};
+
+/// Overrides every named field of `$child` with `$parent`'s value, but only where `$child`'s is
+/// `None` — i.e. `$child` wins wherever it sets a field, and falls back to `$parent` otherwise.
+/// `@override` is the opposite direction: `$source` always wins wherever it sets a field,
+/// regardless of what `$child` already has, used for layers (env vars, CLI overrides) that must
+/// take precedence over whatever a profile set rather than merely fill in what it left unset.
+///
+/// Either direction first destructures `$parent`/`$source` through a pattern naming every one of
+/// `$field`'s names with no `..`: if `$ty` gains a field that isn't listed, this fails to compile
+/// instead of silently leaving the new field unmerged.
+///
+/// Used to let a profile inherit unset fields from `default.yml`, with the arguments swapped to
+/// fold layered profiles together in `ProfileStore::active_config_merged`, and (both directions)
+/// to fold the env-var/CLI-override layers on top of a profile in
+/// `config::resolve::layered_merge`.
+macro_rules! merge {
+    (@fill $ty:ty, $child:expr, $parent:expr, $source_name:literal, $($field:ident),+ $(,)?) => {
+        let $ty { $($field: _),+ } = $parent;
+        $(
+            if $child.$field.is_none() && $parent.$field.is_some() {
+                log::debug!("{}: filled in from {}", stringify!($field), $source_name);
+                $child.$field = $parent.$field.clone();
+            }
+        )+
+    };
+    (@override $ty:ty, $child:expr, $source:expr, $source_name:literal, $($field:ident),+ $(,)?) => {
+        let $ty { $($field: _),+ } = $source;
+        $(
+            if $source.$field.is_some() {
+                log::debug!("{}: overridden by {}", stringify!($field), $source_name);
+                $child.$field = $source.$field.clone();
+            }
+        )+
+    };
+    ($ty:ty, $child:expr, $parent:expr, $($field:ident),+ $(,)?) => {
+        merge!(@fill $ty, $child, $parent, "parent", $($field),+)
+    };
+}
+pub(crate) use merge;
 
 use config::ProfileStore;
-use matches::{group::loader::yaml::YAMLImporter, store::MatchStore};
-
-type LoadableConfig = (ProfileStore, MatchStore, Vec<error::NonFatalErrorSet>);
+use error::{ErrorRecord, NonFatalErrorSet};
+use matches::{
+    group::{loader, package},
+    store::MatchStore,
+};
+
+type LoadableConfig = (
+    ProfileStore,
+    MatchStore,
+    Vec<error::NonFatalErrorSet>,
+    Vec<LoadedPackage>,
+);
+
+/// Extract every `.tar.xz`/encrypted package archive at the top level of `packages_dir` into a
+/// sibling directory named after it, so match-file imports under `packages/<name>/...` work the
+/// same whether `<name>` ships as a live directory or as an archive. Should be called once before
+/// [`load`], since it only rewrites the packages directory on disk rather than returning anything
+/// for the loader to consume. See [`matches::group::package`] for format detection/decryption.
+pub fn materialize_packages(packages_dir: &Path, passphrase: Option<&str>) -> Vec<NonFatalErrorSet> {
+    package::materialize_packages(packages_dir, passphrase)
+}
 
-pub fn load(base_path: &Path) -> Result<LoadableConfig> {
+/// `cli_overrides` feeds [`config::resolve::cli_profile_overrides`]'s `profile.*` keys; combined
+/// with the process environment's `SHINRAN_PROFILE__*` variables (see
+/// [`config::resolve::env_overrides`]), both are folded into every loaded profile on top of its
+/// own fields and its inheritance from `default.yml` — see
+/// [`config::resolve::layered_merge`] for the full precedence chain.
+pub fn load(base_path: &Path, cli_overrides: &HashMap<String, String>) -> Result<LoadableConfig> {
     let config_dir = base_path.join("config");
     if !config_dir.exists() || !config_dir.is_dir() {
         return Err(ConfigError::MissingConfigDir().into());
     }
 
-    let (profile_store, non_fatal_config_errors) = config::load_store(&config_dir)?;
-    let root_paths: Vec<_> = profile_store
+    let env_overrides = config::resolve::env_overrides();
+    let cli_overrides = config::resolve::cli_profile_overrides(cli_overrides);
+    let (profile_store, non_fatal_config_errors) =
+        config::load_store(&config_dir, &env_overrides, &cli_overrides)?;
+    let (loaded_packages, non_fatal_package_errors) =
+        packages::discover_packages(&base_path.join("packages"));
+
+    let mut root_paths: Vec<_> = profile_store
         .get_all_match_file_paths()
         .into_iter()
         .collect();
+    for package in &loaded_packages {
+        root_paths.extend(package.resolved_paths.iter().cloned());
+    }
 
-    let (match_store, file_map, non_fatal_match_errors) = MatchStore::load(&root_paths);
+    // No deployment-level narrow/exclude patterns are wired up yet, so nothing is excluded here.
+    let (match_store, file_map, non_fatal_match_errors) = MatchStore::load(&root_paths, &[]);
 
-    let profile_store = ProfileStore::resolve_paths(profile_store, &file_map);
+    let mut profile_store = ProfileStore::resolve_paths(profile_store, &file_map);
+    // Packages apply unconditionally, so they're wired into the default profile rather than
+    // left to only be picked up if some profile happens to `include` them.
+    profile_store.add_package_match_files(loaded_packages.iter().flat_map(|package| {
+        package
+            .resolved_paths
+            .iter()
+            .filter_map(|path| file_map.get(path).copied())
+    }));
 
     let mut non_fatal_errors = Vec::new();
     non_fatal_errors.extend(non_fatal_config_errors);
+    non_fatal_errors.extend(non_fatal_package_errors);
     non_fatal_errors.extend(non_fatal_match_errors);
+    non_fatal_errors.extend(diagnostics_into_error_sets(validate::validate(
+        &profile_store,
+        &match_store,
+    )));
 
-    Ok((profile_store, match_store, non_fatal_errors))
+    Ok((profile_store, match_store, non_fatal_errors, loaded_packages))
 }
 
-pub fn all_config_files(config_dir: &Path) -> Result<impl Iterator<Item = PathBuf>> {
-    let iter = std::fs::read_dir(config_dir)
-        .with_context(|| format!("Failed to read directory {:?}", config_dir))?
-        .filter_map(|entry| {
-            let path = entry.ok()?.path();
-            let extension = path.extension()?;
-            if path.is_file() && YAMLImporter::is_supported(extension) {
-                Some(path)
-            } else {
-                None
-            }
-        });
-    Ok(iter)
+/// Group the [`validate::Diagnostic`]s produced by a validation pass by the file they belong to,
+/// so they surface alongside the `NonFatalErrorSet`s that loading itself can produce.
+fn diagnostics_into_error_sets(diagnostics: Vec<validate::Diagnostic>) -> Vec<NonFatalErrorSet> {
+    let mut by_file: HashMap<PathBuf, Vec<ErrorRecord>> = HashMap::new();
+
+    for diagnostic in diagnostics {
+        by_file
+            .entry(diagnostic.file)
+            .or_default()
+            .push(ErrorRecord::warn(anyhow!(
+                "{}: {}",
+                diagnostic.field,
+                diagnostic.message
+            )));
+    }
+
+    by_file
+        .into_iter()
+        .map(|(file, errors)| NonFatalErrorSet::new(&file, errors))
+        .collect()
+}
+
+/// Recursively discover every supported config file under `config_dir`, honoring per-directory
+/// `.shinranignore`/`.gitignore` files (see [`ignore_walk`]) so large profile collections can be
+/// organized into nested folders with drafts/backups excluded. Returns the included files
+/// alongside every path an ignore rule skipped, so a caller can surface the latter as non-fatal
+/// diagnostics.
+pub fn all_config_files(config_dir: &Path) -> Result<(Vec<PathBuf>, Vec<PathBuf>)> {
+    if !config_dir.is_dir() {
+        return Err(anyhow!("Failed to read directory {:?}", config_dir));
+    }
+
+    let wanted = |path: &Path| {
+        path.extension()
+            .is_some_and(|extension| loader::is_supported(extension))
+    };
+    Ok(ignore_walk::walk_respecting_ignore_files(
+        config_dir,
+        &wanted,
+        &|_| true,
+    ))
 }
 
 // pub fn load_legacy(
@@ -140,7 +261,7 @@ mod tests {
             std::fs::write(
                 custom_config_file,
                 r#"
-      filter_title: "Chrome"
+      filter_title: ["Chrome"]
 
       use_standard_includes: false
       includes: ["../match/another.yml"]
@@ -148,7 +269,7 @@ mod tests {
             )
             .unwrap();
 
-            let (config_store, match_store, errors) = load(base).unwrap();
+            let (config_store, match_store, errors, _packages) = load(base).unwrap();
 
             assert_eq!(errors.len(), 0);
             assert_eq!(config_store.default_profile.match_file_paths().len(), 2);
@@ -158,6 +279,9 @@ mod tests {
                         title: Some("Google Chrome"),
                         class: None,
                         exec: None,
+                        path: None,
+                        window_role: None,
+                        desktop_id: None,
                     })
                     .match_file_paths()
                     .len(),
@@ -181,6 +305,9 @@ mod tests {
                                 title: Some("Chrome"),
                                 class: None,
                                 exec: None,
+                                path: None,
+                                window_role: None,
+                                desktop_id: None,
                             })
                             .match_file_paths()
                     )
@@ -191,6 +318,90 @@ mod tests {
         });
     }
 
+    #[test]
+    fn load_with_packages() {
+        use_test_directory(|base, _match_dir, config_dir| {
+            let config_file = config_dir.join("default.yml");
+            // The match dir is empty, so skip the standard include's "matched no files" warning.
+            std::fs::write(config_file, "use_standard_includes: false").unwrap();
+
+            let package_dir = base.join("packages").join("my-snippets");
+            std::fs::create_dir_all(&package_dir).unwrap();
+            std::fs::write(
+                package_dir.join("manifest.yml"),
+                r#"
+      name: my-snippets
+      version: "1.0.0"
+      author: someone
+      matches:
+        - "snippets.yml"
+      "#,
+            )
+            .unwrap();
+            std::fs::write(
+                package_dir.join("snippets.yml"),
+                r#"
+      matches:
+        - trigger: "pkg"
+          replace: "from a package"
+      "#,
+            )
+            .unwrap();
+
+            let (config_store, match_store, errors, packages) = load(base).unwrap();
+
+            assert_eq!(errors.len(), 0);
+            assert_eq!(packages.len(), 1);
+            assert_eq!(packages[0].name, "my-snippets");
+            assert_eq!(packages[0].version, "1.0.0");
+            assert_eq!(packages[0].resolved_paths.len(), 1);
+
+            // The package's match file is wired into the default profile unconditionally.
+            assert_eq!(
+                match_store
+                    .collect_matches_and_global_vars(
+                        config_store.default_profile.match_file_paths()
+                    )
+                    .trigger_matches
+                    .len(),
+                1
+            );
+        });
+    }
+
+    #[test]
+    fn load_with_package_missing_match_file_is_non_fatal() {
+        use_test_directory(|base, _match_dir, config_dir| {
+            let config_file = config_dir.join("default.yml");
+            // As above, so the only error left is the package's missing match file.
+            std::fs::write(config_file, "use_standard_includes: false").unwrap();
+
+            let package_dir = base.join("packages").join("broken");
+            std::fs::create_dir_all(&package_dir).unwrap();
+            std::fs::write(
+                package_dir.join("manifest.yml"),
+                r#"
+      name: broken
+      version: "0.1.0"
+      matches:
+        - "missing.yml"
+      "#,
+            )
+            .unwrap();
+
+            let (_config_store, _match_store, errors, packages) = load(base).unwrap();
+
+            assert_eq!(errors.len(), 1);
+            assert_eq!(packages.len(), 1);
+            assert_eq!(packages[0].resolved_paths.len(), 0);
+        });
+    }
+
+    /// [`super::load`] with no CLI overrides, which is all these tests need.
+    fn load(base_path: &Path) -> Result<LoadableConfig> {
+        super::load(base_path, &HashMap::new())
+    }
+
     #[test]
     fn load_non_fatal_errors() {
         use_test_directory(|base, match_dir, config_dir| {
@@ -236,7 +447,7 @@ mod tests {
             std::fs::write(
                 custom_config_file,
                 r#"
-      filter_title: "Chrome"
+      filter_title: ["Chrome"]
       "
 
       use_standard_includes: false
@@ -245,7 +456,7 @@ mod tests {
             )
             .unwrap();
 
-            let (config_store, match_store, errors) = load(base).unwrap();
+            let (config_store, match_store, errors, _packages) = load(base).unwrap();
 
             assert_eq!(errors.len(), 3);
             // It shouldn't have loaded the "config.yml" one because of the YAML error
@@ -273,7 +484,7 @@ mod tests {
             let config_file = config_dir.join("default.yml");
             std::fs::write(config_file, r"").unwrap();
 
-            let (config_store, match_store, errors) = load(base).unwrap();
+            let (config_store, match_store, errors, _packages) = load(base).unwrap();
 
             assert_eq!(errors.len(), 1);
             assert_eq!(errors[0].file, base_file);
@@ -0,0 +1,76 @@
+/*
+ * This file is part of espanso.
+ *
+ * Copyright (C) 2019-2021 Federico Terzi
+ *
+ * espanso is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * espanso is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with espanso.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::ffi::OsStr;
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+
+use crate::error::NonFatalErrorSet;
+use crate::matches::group::LoadedMatchFile;
+
+use json::JSONImporter;
+use ron::RONImporter;
+use toml::TOMLImporter;
+use yaml::YAMLImporter;
+
+pub(crate) mod json;
+pub(crate) mod ron;
+pub(crate) mod toml;
+pub(crate) mod yaml;
+
+/// A match-file format that can be parsed into a [`LoadedMatchFile`].
+///
+/// Every importer parses into the same `matches`/`global_vars` intermediate that the YAML
+/// path produces, so `LoadedMatch`/`MatchCause`/`MatchEffect` stay the same regardless of the
+/// format a user writes their match group in.
+pub(crate) trait Importer {
+    fn is_supported(&self, extension: &OsStr) -> bool;
+    fn load_file(&self, path: PathBuf) -> Result<(LoadedMatchFile, Option<NonFatalErrorSet>)>;
+}
+
+/// Every format this build knows how to parse match files with, tried in order.
+fn importers() -> [&'static dyn Importer; 4] {
+    [
+        &YAMLImporter {},
+        &TOMLImporter {},
+        &JSONImporter {},
+        &RONImporter {},
+    ]
+}
+
+/// Returns whether some registered importer supports `extension`.
+pub(crate) fn is_supported(extension: &OsStr) -> bool {
+    importers()
+        .into_iter()
+        .any(|importer| importer.is_supported(extension))
+}
+
+/// Parse `path` with whichever registered importer supports its extension.
+pub(crate) fn load_match_file(
+    path: PathBuf,
+) -> Result<(LoadedMatchFile, Option<NonFatalErrorSet>)> {
+    let extension = path.extension().unwrap_or_default();
+    for importer in importers() {
+        if importer.is_supported(extension) {
+            return importer.load_file(path);
+        }
+    }
+    bail!("unsupported match file extension: {:?}", path.extension())
+}
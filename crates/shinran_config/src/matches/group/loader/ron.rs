@@ -0,0 +1,113 @@
+/*
+ * This file is part of espanso.
+ *
+ * Copyright (C) 2019-2021 Federico Terzi
+ *
+ * espanso is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * espanso is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with espanso.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::ffi::OsStr;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+
+use crate::{
+    error::{ErrorRecord, NonFatalErrorSet},
+    matches::group::{path::resolve_paths, LoadedMatchFile, MatchFile},
+};
+
+use super::{
+    yaml::{parse::YAMLMatchFile, try_convert_into_match, try_convert_into_variable},
+    Importer,
+};
+
+/// Parses match groups written as RON (Rusty Object Notation) instead of YAML, sharing the
+/// YAML path's intermediate representation so the rest of the pipeline doesn't need to know
+/// which format a file came from.
+pub(crate) struct RONImporter {}
+
+impl RONImporter {
+    pub fn is_supported(extension: &OsStr) -> bool {
+        extension.eq_ignore_ascii_case("ron")
+    }
+
+    pub fn load_file(path: PathBuf) -> Result<(LoadedMatchFile, Option<NonFatalErrorSet>)> {
+        let content = std::fs::read_to_string(&path)?;
+        let parsed: YAMLMatchFile =
+            ron::from_str(&content).context("failed to parse RON match group")?;
+
+        let mut non_fatal_errors = Vec::new();
+
+        let mut global_vars = Vec::new();
+        for ron_global_var in parsed.global_vars.unwrap_or_default() {
+            match try_convert_into_variable(ron_global_var, false) {
+                Ok((var, warnings)) => {
+                    global_vars.push(var);
+                    non_fatal_errors.extend(warnings.into_iter().map(ErrorRecord::warn));
+                }
+                Err(err) => {
+                    non_fatal_errors.push(ErrorRecord::error(err));
+                }
+            }
+        }
+
+        let mut trigger_matches = Vec::new();
+        let mut regex_matches = Vec::new();
+        for ron_match in parsed.matches.unwrap_or_default() {
+            if let Err(err) = try_convert_into_match(
+                ron_match,
+                &mut trigger_matches,
+                &mut regex_matches,
+                &mut non_fatal_errors,
+            ) {
+                non_fatal_errors.push(ErrorRecord::error(err));
+            }
+        }
+
+        let (import_paths, import_errors) =
+            resolve_paths(&path, &parsed.imports.unwrap_or_default())
+                .context("failed to turn RON match file imports into valid paths")?;
+        non_fatal_errors.extend(import_errors);
+
+        let non_fatal_error_set = if non_fatal_errors.is_empty() {
+            None
+        } else {
+            Some(NonFatalErrorSet::new(&path, non_fatal_errors))
+        };
+
+        Ok((
+            LoadedMatchFile {
+                import_paths,
+                content: MatchFile {
+                    global_vars,
+                    trigger_matches,
+                    regex_matches,
+                    unset_triggers: parsed.unset_triggers.unwrap_or_default(),
+                },
+                source_path: path,
+            },
+            non_fatal_error_set,
+        ))
+    }
+}
+
+impl Importer for RONImporter {
+    fn is_supported(&self, extension: &OsStr) -> bool {
+        Self::is_supported(extension)
+    }
+
+    fn load_file(&self, path: PathBuf) -> Result<(LoadedMatchFile, Option<NonFatalErrorSet>)> {
+        Self::load_file(path)
+    }
+}
@@ -17,7 +17,11 @@
  * along with espanso.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::{ffi::OsStr, path::PathBuf, sync::LazyLock};
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
 
 use crate::{
     error::{ErrorRecord, NonFatalErrorSet},
@@ -25,11 +29,14 @@ use crate::{
 };
 use anyhow::{anyhow, bail, Context, Result};
 use parse::YAMLMatchFile;
+use serde_yaml_ng::{Mapping, Value as YamlValue};
+
+use super::Importer;
 use regex::{Captures, Regex};
 use shinran_types::{
-    BaseMatch, ImageEffect, MatchCause, MatchEffect, Params, RegexCause, RegexMatch, TextEffect,
-    TextFormat, TextInjectMode, TriggerCause, TriggerMatch, UpperCasingStyle, Value, VarType,
-    Variable, WordBoundary,
+    BaseMatch, Filter, ImageEffect, MatchCause, MatchEffect, MatchFilter, Params,
+    PlatformPredicate, RegexCause, RegexMatch, TextEffect, TextFormat, TextInjectMode,
+    TriggerCause, TriggerMatch, UpperCasingStyle, Value, VarType, Variable, WordBoundary,
 };
 
 use self::{
@@ -60,20 +67,32 @@ impl YAMLImporter {
         Option<NonFatalErrorSet>,
     )> {
         let content = std::fs::read_to_string(&path)?;
-        let yaml_loaded =
-            YAMLMatchFile::parse_from_str(&content).context("failed to parse YAML match group")?;
+        let (yaml_loaded, merge_key_warnings) = YAMLMatchFile::parse_from_str(&content)
+            .map_err(|err| locate_in_file(&path, err))
+            .context("failed to parse YAML match group")?;
 
-        let mut non_fatal_errors = Vec::new();
+        let mut non_fatal_errors: Vec<ErrorRecord> = merge_key_warnings
+            .into_iter()
+            .map(|err| ErrorRecord::warn(locate_in_file(&path, err)))
+            .collect();
+
+        if let Some(anchors) = &yaml_loaded.anchors {
+            validate_anchors_section(anchors, &mut non_fatal_errors);
+        }
 
         let mut global_vars = Vec::new();
         for yaml_global_var in yaml_loaded.global_vars.unwrap_or_default() {
             match try_convert_into_variable(yaml_global_var, false) {
                 Ok((var, warnings)) => {
                     global_vars.push(var);
-                    non_fatal_errors.extend(warnings.into_iter().map(ErrorRecord::warn));
+                    non_fatal_errors.extend(
+                        warnings
+                            .into_iter()
+                            .map(|warning| ErrorRecord::warn(locate_in_file(&path, warning))),
+                    );
                 }
                 Err(err) => {
-                    non_fatal_errors.push(ErrorRecord::error(err));
+                    non_fatal_errors.push(ErrorRecord::error(locate_in_file(&path, err)));
                 }
             }
         }
@@ -89,7 +108,7 @@ impl YAMLImporter {
             ) {
                 Ok(_) => {}
                 Err(err) => {
-                    non_fatal_errors.push(ErrorRecord::error(err));
+                    non_fatal_errors.push(ErrorRecord::error(locate_in_file(&path, err)));
                 }
             }
         }
@@ -113,6 +132,7 @@ impl YAMLImporter {
                     global_vars,
                     trigger_matches,
                     regex_matches,
+                    unset_triggers: yaml_loaded.unset_triggers.unwrap_or_default(),
                 },
                 source_path: path,
             },
@@ -121,6 +141,77 @@ impl YAMLImporter {
     }
 }
 
+/// Prefixes `err` with `path`, and with a `line:column` marker too when `err` is (or wraps) a
+/// [`serde_yaml_ng::Error`] that carries a parser location, so a diagnostic reads like
+/// `base.yml:4:8: bad indentation` instead of a bare message that leaves the reader to guess which
+/// file -- out of every import in the tree -- it came from.
+fn locate_in_file(path: &Path, err: anyhow::Error) -> anyhow::Error {
+    let location = err
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<serde_yaml_ng::Error>())
+        .and_then(serde_yaml_ng::Error::location);
+
+    match location {
+        Some(location) => anyhow!(
+            "{}:{}:{}: {err}",
+            path.display(),
+            location.line(),
+            location.column()
+        ),
+        None => anyhow!("{}: {err}", path.display()),
+    }
+}
+
+/// The fields that mark a [`YAMLMatch`] as having a cause (a trigger) or an effect, used by
+/// [`validate_anchors_section`] to recognize an entry that's accidentally a full match rather
+/// than a pure anchor target.
+const MATCH_CAUSE_KEYS: [&str; 3] = ["trigger", "triggers", "regex"];
+const MATCH_EFFECT_KEYS: [&str; 6] = ["replace", "markdown", "html", "form", "image_path", "alias"];
+
+/// Validates the `_shinran_anchors` section: every entry should be a mapping (a pure anchor
+/// target with no effect of its own), so anything else -- and especially an entry that has both a
+/// cause and an effect key, i.e. looks like it was meant to be an active match -- is reported as a
+/// warning rather than silently dropped.
+fn validate_anchors_section(anchors: &Mapping, non_fatal_errors: &mut Vec<ErrorRecord>) {
+    for (name, entry) in anchors {
+        let name = match name {
+            YamlValue::String(name) => name.clone(),
+            other => format!("{other:?}"),
+        };
+
+        let Some(entry) = entry.as_mapping() else {
+            non_fatal_errors.push(ErrorRecord::warn(anyhow!(
+                "_shinran_anchors.{name} is not a mapping, ignoring it"
+            )));
+            continue;
+        };
+
+        let has_cause = entry
+            .keys()
+            .any(|key| matches!(key.as_str(), Some(key) if MATCH_CAUSE_KEYS.contains(&key)));
+        let has_effect = entry
+            .keys()
+            .any(|key| matches!(key.as_str(), Some(key) if MATCH_EFFECT_KEYS.contains(&key)));
+
+        if has_cause && has_effect {
+            non_fatal_errors.push(ErrorRecord::warn(anyhow!(
+                "_shinran_anchors.{name} looks like an active match (it has both a trigger and \
+                 an effect); did you mean to put it under 'matches:' instead?"
+            )));
+        }
+    }
+}
+
+impl Importer for YAMLImporter {
+    fn is_supported(&self, extension: &OsStr) -> bool {
+        Self::is_supported(extension)
+    }
+
+    fn load_file(&self, path: PathBuf) -> Result<(LoadedMatchFile, Option<NonFatalErrorSet>)> {
+        Self::load_file(path)
+    }
+}
+
 /// Convert a YAMLMatch into a Match.
 pub fn try_convert_into_match(
     yaml_match: YAMLMatch,
@@ -182,7 +273,13 @@ pub fn try_convert_into_match(
         })
     } else if let Some(regex) = yaml_match.regex {
         // TODO: add test case
-        MatchCause::Regex(RegexCause { regex })
+        MatchCause::Regex(RegexCause {
+            regex,
+            propagate_case: yaml_match
+                .propagate_case
+                .unwrap_or(RegexCause::default().propagate_case),
+            uppercase_style,
+        })
     } else {
         bail!("match must have either 'trigger' or 'regex' field; both are missing");
     };
@@ -200,7 +297,9 @@ pub fn try_convert_into_match(
         None
     };
 
-    let effect = if yaml_match.replace.is_some()
+    let effect = if let Some(alias) = yaml_match.alias {
+        MatchEffect::Alias(alias)
+    } else if yaml_match.replace.is_some()
         || yaml_match.markdown.is_some()
         || yaml_match.html.is_some()
     {
@@ -285,15 +384,23 @@ pub fn try_convert_into_match(
     );
     }
 
+    let platform = build_platform_predicate(yaml_match.cfg, &mut warnings);
+    let condition = build_condition(yaml_match.filter)?;
+
     let base = BaseMatch {
         effect,
         label: yaml_match.label,
         search_terms: yaml_match.search_terms.unwrap_or_default(),
+        app_filter: build_app_filter(yaml_match.filter_apps, yaml_match.exclude_apps),
+        platform,
+        condition,
     };
     match cause {
         MatchCause::Regex(regex) => regex_matches.push(RegexMatch {
             regex: regex.regex,
             base_match: base,
+            propagate_case: regex.propagate_case,
+            uppercase_style: regex.uppercase_style,
         }),
         MatchCause::Trigger(trigger) => trigger_matches.push(TriggerMatch {
             triggers: trigger.triggers,
@@ -301,6 +408,7 @@ pub fn try_convert_into_match(
             propagate_case: trigger.propagate_case,
             uppercase_style: trigger.uppercase_style,
             word_boundary: trigger.word_boundary,
+            is_override: yaml_match.is_override.unwrap_or(false),
         }),
     };
     non_fatal_errors.extend(warnings.into_iter().map(ErrorRecord::warn));
@@ -308,6 +416,68 @@ pub fn try_convert_into_match(
     Ok(())
 }
 
+/// Parse a match's `cfg` expression into a [`PlatformPredicate`], warning and falling back to
+/// "always active" (`None`) if it doesn't parse.
+fn build_platform_predicate(
+    cfg: Option<String>,
+    warnings: &mut Vec<Warning>,
+) -> Option<PlatformPredicate> {
+    let cfg = cfg?;
+    match PlatformPredicate::parse(&cfg) {
+        Ok(predicate) => Some(predicate),
+        Err(err) => {
+            warnings.push(anyhow!(
+                "invalid cfg expression {:?}: {}, match will always be active",
+                cfg,
+                err
+            ));
+            None
+        }
+    }
+}
+
+/// Parse a match's `filter` expression into a [`Filter`], failing the whole match conversion
+/// (unlike [`build_platform_predicate`]'s warn-and-fall-back) if it doesn't parse: an
+/// unrecognized or malformed condition should stop config loading rather than silently let the
+/// match fire unconditionally.
+fn build_condition(filter: Option<String>) -> Result<Option<Filter>> {
+    let Some(filter) = filter else {
+        return Ok(None);
+    };
+    let condition = Filter::parse(&filter)
+        .map_err(|err| anyhow!("invalid filter expression {:?}: {}", filter, err))?;
+    if let Some(predicate) = condition.first_unsupported_predicate() {
+        bail!(
+            "filter expression {:?} uses `{}(...)`, which isn't supported yet: it isn't wired \
+             up to live window-title/variable state, so a match using it could never fire as \
+             written",
+            filter,
+            predicate
+        );
+    }
+    Ok(Some(condition))
+}
+
+/// Combine a match's `filter_apps`/`exclude_apps` glob pattern lists into a single
+/// [`MatchFilter`], skipping empty lists so the common case (neither field set) stays `Always`.
+fn build_app_filter(
+    filter_apps: Option<Vec<String>>,
+    exclude_apps: Option<Vec<String>>,
+) -> MatchFilter {
+    let include = filter_apps.filter(|patterns| !patterns.is_empty());
+    let exclude = exclude_apps.filter(|patterns| !patterns.is_empty());
+
+    match (include, exclude) {
+        (None, None) => MatchFilter::Always,
+        (Some(include), None) => MatchFilter::Include(include),
+        (None, Some(exclude)) => MatchFilter::Exclude(exclude),
+        (Some(include), Some(exclude)) => MatchFilter::Difference(
+            Box::new(MatchFilter::Include(include)),
+            Box::new(MatchFilter::Include(exclude)),
+        ),
+    }
+}
+
 pub fn try_convert_into_variable(
     yaml_var: YAMLVariable,
     use_compatibility_mode: bool,
@@ -343,6 +513,8 @@ mod tests {
     use shinran_helpers::use_test_directory;
     use shinran_types::TextEffect;
 
+    use crate::matches::group::LoadedImportRef;
+
     use super::*;
     use std::{ffi::OsString, fs::create_dir_all};
 
@@ -790,6 +962,58 @@ mod tests {
         );
     }
 
+    #[test]
+    fn filter_field_parses_into_a_condition() {
+        let m = create_match(
+            r#"
+        trigger: "Hello"
+        replace: "world"
+        filter: app("firefox")
+        "#,
+        )
+        .unwrap();
+        assert_eq!(
+            m.base_match.condition,
+            Some(Filter::AppEquals("firefox".to_string()))
+        );
+    }
+
+    #[test]
+    fn filter_field_rejects_unparseable_expressions() {
+        let err = create_match(
+            r#"
+        trigger: "Hello"
+        replace: "world"
+        filter: "not a filter"
+        "#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("invalid filter expression"));
+    }
+
+    #[test]
+    fn filter_field_rejects_title_and_var_predicates() {
+        let err = create_match(
+            r#"
+        trigger: "Hello"
+        replace: "world"
+        filter: title("inbox")
+        "#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("title"));
+
+        let err = create_match(
+            r#"
+        trigger: "Hello"
+        replace: "world"
+        filter: var(lang, "en")
+        "#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("var"));
+    }
+
     #[test]
     fn importer_is_supported() {
         assert!(YAMLImporter::is_supported(&OsString::from("yaml")));
@@ -842,7 +1066,10 @@ mod tests {
                 file,
                 LoadedMatchFile {
                     source_path: base_file,
-                    import_paths: vec![sub_file],
+                    import_paths: vec![LoadedImportRef {
+                        path: sub_file,
+                        filter: None,
+                    }],
                     content: MatchFile {
                         global_vars: vars,
                         trigger_matches: vec![TriggerMatch {
@@ -880,4 +1107,137 @@ mod tests {
             assert!(YAMLImporter::load_file(base_file).is_err());
         });
     }
+
+    #[test]
+    fn importer_invalid_syntax_error_is_located() {
+        use_test_directory(|_, match_dir, _| {
+            let base_file = match_dir.join("base.yml");
+            std::fs::write(
+                &base_file,
+                r"
+      imports:
+        - invalid
+       - indentation
+      ",
+            )
+            .unwrap();
+
+            let err = YAMLImporter::load_file(base_file.clone()).unwrap_err();
+            let message = err.to_string();
+            assert!(
+                message.starts_with(&format!("{}:", base_file.display())),
+                "expected the error to start with the source file path, got: {message:?}"
+            );
+        });
+    }
+
+    #[test]
+    fn non_fatal_errors_are_stamped_with_the_source_path() {
+        use_test_directory(|_, match_dir, _| {
+            let base_file = match_dir.join("base.yml");
+            std::fs::write(
+                &base_file,
+                r#"
+      global_vars:
+        - name: "var1"
+          type: "not_a_real_type"
+      "#,
+            )
+            .unwrap();
+
+            let (_, non_fatal_error_set) = YAMLImporter::load_file(base_file).unwrap();
+            // The unknown var type is reported as a single non-fatal error, now carrying the
+            // source path in its message (see `locate_in_file`).
+            assert_eq!(non_fatal_error_set.unwrap().errors.len(), 1);
+        });
+    }
+
+    #[test]
+    fn anchors_section_is_excluded_and_used_for_merging() {
+        use_test_directory(|_, match_dir, _| {
+            let base_file = match_dir.join("base.yml");
+            std::fs::write(
+                &base_file,
+                r#"
+      _shinran_anchors:
+        common_vars: &common_vars
+          signature: "Best,\nJohn"
+
+      matches:
+        - trigger: "hello"
+          replace: "world {{signature}}"
+          vars:
+            - name: "signature"
+              type: "echo"
+              params:
+                <<: *common_vars
+      "#,
+            )
+            .unwrap();
+
+            let (file, non_fatal_error_set) = YAMLImporter::load_file(base_file).unwrap();
+            assert!(non_fatal_error_set.is_none());
+            assert_eq!(file.content.trigger_matches.len(), 1);
+        });
+    }
+
+    #[test]
+    fn anchors_section_entry_that_looks_like_a_match_is_a_warning() {
+        use_test_directory(|_, match_dir, _| {
+            let base_file = match_dir.join("base.yml");
+            std::fs::write(
+                &base_file,
+                r#"
+      _shinran_anchors:
+        oops: &oops
+          trigger: "hello"
+          replace: "world"
+      "#,
+            )
+            .unwrap();
+
+            let (_, non_fatal_error_set) = YAMLImporter::load_file(base_file).unwrap();
+            assert_eq!(non_fatal_error_set.unwrap().errors.len(), 1);
+        });
+    }
+
+    #[test]
+    fn anchors_section_non_mapping_entry_is_a_warning() {
+        use_test_directory(|_, match_dir, _| {
+            let base_file = match_dir.join("base.yml");
+            std::fs::write(
+                &base_file,
+                r#"
+      _shinran_anchors:
+        not_a_mapping: "just a string"
+      "#,
+            )
+            .unwrap();
+
+            let (_, non_fatal_error_set) = YAMLImporter::load_file(base_file).unwrap();
+            assert_eq!(non_fatal_error_set.unwrap().errors.len(), 1);
+        });
+    }
+
+    #[test]
+    fn load_file_recovers_from_a_single_malformed_match() {
+        use_test_directory(|_, match_dir, _| {
+            let base_file = match_dir.join("base.yml");
+            std::fs::write(
+                &base_file,
+                r"
+      matches:
+        - trigger: 'good'
+          replace: 'first'
+        - trigger: ['not', 'a', 'string']
+          replace: 'second'
+      ",
+            )
+            .unwrap();
+
+            let (file, non_fatal_error_set) = YAMLImporter::load_file(base_file).unwrap();
+            assert_eq!(file.content.trigger_matches.len(), 1);
+            assert_eq!(non_fatal_error_set.unwrap().errors.len(), 1);
+        });
+    }
 }
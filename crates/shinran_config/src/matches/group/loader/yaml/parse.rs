@@ -17,35 +17,198 @@
  * along with espanso.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{anyhow, Result};
 use compact_str::CompactString;
 use serde::Deserialize;
-use serde_yaml_ng::Mapping;
+use serde_yaml_ng::{Mapping, Value};
 
 use crate::util::is_yaml_empty;
 
 #[derive(Debug, Deserialize)]
 pub struct YAMLMatchFile {
     #[serde(default)]
-    pub imports: Option<Vec<String>>,
+    pub imports: Option<Vec<YAMLImportEntry>>,
 
     #[serde(default)]
     pub global_vars: Option<Vec<YAMLVariable>>,
 
     #[serde(default)]
     pub matches: Option<Vec<YAMLMatch>>,
+
+    /// Triggers inherited from an import that this file wants to drop, rather than just
+    /// shadowing them with a same-named local match.
+    #[serde(default)]
+    pub unset_triggers: Option<Vec<CompactString>>,
+
+    /// A reserved section purely for `&anchor`-tagged mappings a match/variable elsewhere in the
+    /// file (or in a file that imports this one) wants to `<<`-merge in or `*alias` -- it has no
+    /// effect of its own and is excluded from `global_vars`/`matches`. The importer validates that
+    /// every entry here is a mapping, and warns if one looks like an actual match (`trigger`/
+    /// `replace`, etc.) that was probably meant to be active.
+    #[serde(default, rename = "_shinran_anchors")]
+    pub anchors: Option<Mapping>,
+}
+
+/// A single entry in the `imports:` list.
+///
+/// Most entries are just a path/glob/URL string, pulling in everything the target file defines.
+/// A `from: ... import: [...]` entry instead pulls only the named triggers/global vars.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum YAMLImportEntry {
+    Full(String),
+    Selective {
+        from: String,
+        import: Vec<CompactString>,
+    },
 }
 
 impl YAMLMatchFile {
-    pub fn parse_from_str(yaml: &str) -> Result<Self> {
+    /// Parses `yaml` into a [`YAMLMatchFile`], resolving any `<<` merge keys first (anchors and
+    /// aliases themselves are already expanded by `serde_yaml_ng` while parsing, so `<<` is the
+    /// only piece of YAML's aliasing story left for us to implement). Each entry of `imports:`,
+    /// `global_vars:` and `matches:` is then deserialized independently, so a single malformed
+    /// entry (wrong field type, missing required field, ...) is skipped and reported as a warning
+    /// rather than failing the whole file. An `imports`/`global_vars`/`matches` key that isn't a
+    /// list at all is dropped with a warning too; only a document that isn't even valid YAML still
+    /// fails outright.
+    pub fn parse_from_str(yaml: &str) -> Result<(Self, Vec<anyhow::Error>)> {
         // Because an empty string is not valid YAML but we want to support it anyway
         if is_yaml_empty(yaml) {
-            return Ok(serde_yaml_ng::from_str(
-                "arbitrary_field_that_will_not_block_the_parser: true",
-            )?);
+            return Ok((
+                serde_yaml_ng::from_str("arbitrary_field_that_will_not_block_the_parser: true")?,
+                Vec::new(),
+            ));
         }
 
-        Ok(serde_yaml_ng::from_str(yaml)?)
+        let mut value: Value = serde_yaml_ng::from_str(yaml)?;
+        let mut warnings = Vec::new();
+        resolve_merge_keys(&mut value, &mut warnings);
+
+        // A top-level document that isn't a mapping at all (e.g. a bare list or scalar) has
+        // nothing worth salvaging entry-by-entry; fall back to the strict, all-or-nothing
+        // deserialization so the resulting error still names what's wrong.
+        let Value::Mapping(mapping) = value else {
+            return Ok((serde_yaml_ng::from_value(value)?, warnings));
+        };
+
+        let mut fields: HashMap<String, Value> = HashMap::new();
+        for (key, val) in mapping {
+            if let Value::String(key) = key {
+                fields.insert(key, val);
+            }
+        }
+
+        let imports = take_list(&mut fields, "imports", &mut warnings);
+        let global_vars = take_list(&mut fields, "global_vars", &mut warnings);
+        let matches = take_list(&mut fields, "matches", &mut warnings);
+
+        let unset_triggers = fields
+            .remove("unset_triggers")
+            .map(serde_yaml_ng::from_value)
+            .transpose()?;
+        let anchors = fields
+            .remove("_shinran_anchors")
+            .map(serde_yaml_ng::from_value)
+            .transpose()?;
+
+        Ok((
+            Self {
+                imports,
+                global_vars,
+                matches,
+                unset_triggers,
+                anchors,
+            },
+            warnings,
+        ))
+    }
+}
+
+/// Deserializes `fields[key]` (if present) as a list of `T`, dropping and warning about any entry
+/// that fails to convert instead of failing the whole list -- e.g. a single `matches:` entry with
+/// a `regex:` field of the wrong type doesn't take the rest of the file's matches down with it. A
+/// `key` whose value isn't a list at all is dropped wholesale, since there's no per-entry
+/// resolution to fall back to.
+fn take_list<T: serde::de::DeserializeOwned>(
+    fields: &mut HashMap<String, Value>,
+    key: &str,
+    warnings: &mut Vec<anyhow::Error>,
+) -> Option<Vec<T>> {
+    let value = fields.remove(key)?;
+    let Value::Sequence(items) = value else {
+        warnings.push(anyhow!("'{key}' must be a list, ignoring it"));
+        return None;
+    };
+
+    let mut result = Vec::with_capacity(items.len());
+    for (index, item) in items.into_iter().enumerate() {
+        match serde_yaml_ng::from_value::<T>(item) {
+            Ok(parsed) => result.push(parsed),
+            Err(err) => warnings.push(anyhow!("{key}[{index}]: {err}")),
+        }
+    }
+
+    Some(result)
+}
+
+/// Recursively resolves `<<` merge keys in every mapping reachable from `value`, depth-first so
+/// that a merge source which is itself the result of a merge is fully resolved first. Explicit
+/// local keys always win over anything coming from `<<`; when `<<` is a sequence of mappings,
+/// earlier entries win over later ones (matching the YAML 1.1 merge-key convention). A `<<` value
+/// that isn't a mapping or a sequence of mappings is dropped and reported through `warnings`,
+/// rather than failing the parse.
+fn resolve_merge_keys(value: &mut Value, warnings: &mut Vec<anyhow::Error>) {
+    match value {
+        Value::Mapping(mapping) => {
+            for (_, v) in mapping.iter_mut() {
+                resolve_merge_keys(v, warnings);
+            }
+
+            let mut merge_sources: Vec<Mapping> = Vec::new();
+            let mut resolved = Mapping::new();
+            let mut own_keys: HashSet<Value> = HashSet::new();
+            for (key, val) in mapping.clone() {
+                if matches!(&key, Value::String(s) if s == "<<") {
+                    match val {
+                        Value::Mapping(m) => merge_sources.push(m),
+                        Value::Sequence(seq) => {
+                            for entry in seq {
+                                match entry {
+                                    Value::Mapping(m) => merge_sources.push(m),
+                                    other => warnings.push(anyhow!(
+                                        "ignoring non-mapping entry in a '<<' merge list: {other:?}"
+                                    )),
+                                }
+                            }
+                        }
+                        other => warnings.push(anyhow!(
+                            "'<<' must reference a mapping or a list of mappings, found: {other:?}"
+                        )),
+                    }
+                } else {
+                    own_keys.insert(key.clone());
+                    resolved.insert(key, val);
+                }
+            }
+            for source in merge_sources {
+                for (key, val) in source {
+                    if own_keys.insert(key.clone()) {
+                        resolved.insert(key, val);
+                    }
+                }
+            }
+
+            *mapping = resolved;
+        }
+        Value::Sequence(seq) => {
+            for item in seq {
+                resolve_merge_keys(item, warnings);
+            }
+        }
+        _ => {}
     }
 }
 
@@ -66,6 +229,11 @@ pub struct YAMLMatch {
     #[serde(default)]
     pub replace: Option<String>,
 
+    /// References a name in the profile's (or default profile's) `aliases` table instead of
+    /// spelling out the effect inline. Mutually exclusive with `replace`/`form`/`image_path`.
+    #[serde(default)]
+    pub alias: Option<String>,
+
     #[serde(default)]
     pub image_path: Option<String>,
 
@@ -110,6 +278,35 @@ pub struct YAMLMatch {
 
     #[serde(default)]
     pub search_terms: Option<Vec<String>>,
+
+    /// Glob patterns (matched against window class/title/exec path) this match is restricted to.
+    /// See [`shinran_types::MatchFilter`].
+    #[serde(default)]
+    pub filter_apps: Option<Vec<String>>,
+
+    /// Glob patterns this match is hidden from, even where `filter_apps` would otherwise allow
+    /// it. See [`shinran_types::MatchFilter`].
+    #[serde(default)]
+    pub exclude_apps: Option<Vec<String>>,
+
+    /// When `true`, this match wins the same-trigger collision against a match already claimed
+    /// by an importing file, inverting the usual nearest-root-wins precedence for this one
+    /// trigger. See [`shinran_types::TriggerMatch::is_override`].
+    #[serde(default, rename = "override")]
+    pub is_override: Option<bool>,
+
+    /// A `cfg(...)`-style expression (e.g. `target_os = "linux"`) gating which platform this
+    /// match is active on. See [`shinran_types::PlatformPredicate`].
+    #[serde(default)]
+    pub cfg: Option<String>,
+
+    /// A boolean expression (e.g. `app("thunderbird")`) gating whether this match is allowed
+    /// to fire, evaluated against live app/time state. `title(...)`/`var(...)` leaves parse but
+    /// are rejected at load time (see `build_condition`): window-title detection and variable
+    /// resolution aren't wired up to the evaluation call site yet, so a condition using either
+    /// could never fire as written. See [`shinran_types::Filter`].
+    #[serde(default)]
+    pub filter: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
@@ -132,3 +329,163 @@ pub struct YAMLVariable {
 fn default_params() -> Mapping {
     Mapping::new()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_key_local_keys_win_and_merges_fields() {
+        let mut value: Value = serde_yaml_ng::from_str(
+            r"
+a: &a
+  foo: 1
+  bar: 2
+b:
+  <<: *a
+  bar: 3
+",
+        )
+        .unwrap();
+        let mut warnings = Vec::new();
+        resolve_merge_keys(&mut value, &mut warnings);
+        assert!(warnings.is_empty());
+
+        let expected: Value = serde_yaml_ng::from_str(
+            r"
+a:
+  foo: 1
+  bar: 2
+b:
+  foo: 1
+  bar: 3
+",
+        )
+        .unwrap();
+        assert_eq!(value, expected);
+    }
+
+    #[test]
+    fn merge_key_sequence_earlier_source_wins() {
+        let mut value: Value = serde_yaml_ng::from_str(
+            r"
+a: &a
+  x: 1
+c: &c
+  x: 2
+  y: 3
+b:
+  <<: [*a, *c]
+",
+        )
+        .unwrap();
+        let mut warnings = Vec::new();
+        resolve_merge_keys(&mut value, &mut warnings);
+        assert!(warnings.is_empty());
+
+        let expected: Value = serde_yaml_ng::from_str(
+            r"
+a:
+  x: 1
+c:
+  x: 2
+  y: 3
+b:
+  x: 1
+  y: 3
+",
+        )
+        .unwrap();
+        assert_eq!(value, expected);
+    }
+
+    #[test]
+    fn merge_key_non_mapping_value_is_reported_as_a_warning() {
+        let mut value: Value = serde_yaml_ng::from_str(
+            r#"
+b:
+  <<: "not a mapping"
+  foo: 1
+"#,
+        )
+        .unwrap();
+        let mut warnings = Vec::new();
+        resolve_merge_keys(&mut value, &mut warnings);
+        assert_eq!(warnings.len(), 1);
+
+        let expected: Value = serde_yaml_ng::from_str(
+            r"
+b:
+  foo: 1
+",
+        )
+        .unwrap();
+        assert_eq!(value, expected);
+    }
+
+    #[test]
+    fn parse_from_str_resolves_merge_keys_in_variable_params() {
+        let (file, warnings) = YAMLMatchFile::parse_from_str(
+            r#"
+defaults: &defaults
+  foo: 1
+  bar: 2
+global_vars:
+  - name: "var1"
+    type: "mock"
+    params:
+      <<: *defaults
+      bar: 3
+"#,
+        )
+        .unwrap();
+        assert!(warnings.is_empty());
+
+        let mut expected_params = Mapping::new();
+        expected_params.insert(Value::String("foo".to_string()), Value::Number(1.into()));
+        expected_params.insert(Value::String("bar".to_string()), Value::Number(3.into()));
+
+        assert_eq!(
+            file.global_vars.unwrap(),
+            vec![YAMLVariable {
+                name: "var1".to_string(),
+                var_type: "mock".to_string(),
+                params: expected_params,
+                inject_vars: None,
+                depends_on: Vec::new(),
+            }]
+        );
+    }
+
+    #[test]
+    fn a_malformed_match_entry_is_skipped_without_failing_the_rest() {
+        let (file, warnings) = YAMLMatchFile::parse_from_str(
+            r"
+matches:
+  - trigger: 'good'
+    replace: 'first'
+  - trigger: ['not', 'a', 'string']
+    replace: 'second'
+  - trigger: 'also good'
+    replace: 'third'
+",
+        )
+        .unwrap();
+
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(file.matches.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn a_non_list_matches_field_is_dropped_with_a_warning() {
+        let (file, warnings) = YAMLMatchFile::parse_from_str(
+            r"
+matches: 'not a list'
+",
+        )
+        .unwrap();
+
+        assert_eq!(warnings.len(), 1);
+        assert!(file.matches.is_none());
+    }
+}
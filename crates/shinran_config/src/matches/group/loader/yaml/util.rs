@@ -17,8 +17,6 @@
  * along with espanso.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::convert::TryInto;
-
 use anyhow::Result;
 use serde_yaml_ng::{Mapping, Value as YamlValue};
 use shinran_types::{Number, Params, Value};
@@ -36,38 +34,12 @@ pub(crate) fn convert_params(m: Mapping) -> Result<Params> {
     Ok(params)
 }
 
+/// `Value` now has hand-written `serde::Deserialize`/`Serialize` impls that mirror serde's own
+/// data model (see `shinran_types::value_serde`), so converting a parsed YAML value is just a
+/// generic `Deserialize` call instead of a walk matching every `YamlValue` variant by hand -- the
+/// same path every other supported format (JSON, TOML, RON) now goes through too.
 fn convert_value(value: YamlValue) -> Result<Value> {
-    Ok(match value {
-        YamlValue::Null => Value::Null,
-        YamlValue::Bool(val) => Value::Bool(val),
-        YamlValue::Number(n) => {
-            if n.is_i64() {
-                Value::Number(Number::Integer(
-                    n.as_i64().ok_or(ConversionError::InvalidNumberFormat)?,
-                ))
-            } else if n.is_u64() {
-                Value::Number(Number::Integer(
-                    n.as_u64()
-                        .ok_or(ConversionError::InvalidNumberFormat)?
-                        .try_into()?,
-                ))
-            } else if n.is_f64() {
-                Value::Number(Number::Float(
-                    n.as_f64().ok_or(ConversionError::InvalidNumberFormat)?,
-                ))
-            } else {
-                return Err(ConversionError::InvalidNumberFormat.into());
-            }
-        }
-        YamlValue::String(s) => Value::String(s),
-        YamlValue::Sequence(arr) => Value::Array(
-            arr.into_iter()
-                .map(convert_value)
-                .collect::<Result<Vec<Value>>>()?,
-        ),
-        YamlValue::Mapping(m) => Value::Object(convert_params(m)?),
-        YamlValue::Tagged(_) => return Err(ConversionError::InvalidKeyFormat.into()),
-    })
+    serde_yaml_ng::from_value(value).map_err(|_| ConversionError::InvalidNumberFormat.into())
 }
 
 #[derive(Error, Debug)]
@@ -1,4 +1,7 @@
 use std::collections::HashMap;
+
+use crate::error::{ErrorRecord, NonFatalErrorSet};
+use anyhow::anyhow;
 /*
  * This file is part of espanso.
  *
@@ -19,22 +22,47 @@ use std::collections::HashMap;
  */
 use std::path::{Path, PathBuf};
 
+use compact_str::CompactString;
 use rkyv::with::AsString;
 use rkyv::{Archive, Deserialize, Serialize};
 use shinran_types::{RegexMatch, TriggerMatch, Variable};
 
 pub(crate) mod loader;
-mod path;
+pub(crate) mod package;
+pub(crate) mod path;
 
 /// Content of a match file.
 ///
 /// This struct owns the variables and matches, and is used to store the content of a match file.
-#[derive(Debug, Clone, PartialEq, Default, Archive, Serialize, Deserialize)]
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    Default,
+    Archive,
+    Serialize,
+    Deserialize,
+    serde::Serialize,
+    serde::Deserialize,
+)]
 #[archive(check_bytes)]
 pub struct MatchFile {
     pub global_vars: Vec<Variable>,
     pub trigger_matches: Vec<TriggerMatch>,
     pub regex_matches: Vec<RegexMatch>,
+
+    /// Triggers this file wants to drop from whatever it imports, e.g. because an import defines
+    /// a trigger that doesn't make sense in this context.
+    pub unset_triggers: Vec<CompactString>,
+}
+
+/// A single entry in a [`LoadedMatchFile`]'s import list: the resolved path of the imported
+/// file, plus an optional selective-import filter (from a `from "file" import [...]` entry).
+/// `filter: None` means "import everything", matching a plain import.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LoadedImportRef {
+    pub path: PathBuf,
+    pub filter: Option<Vec<CompactString>>,
 }
 
 /// A `LoadedMatchFile` describes one file in the `match` directory.
@@ -43,14 +71,24 @@ pub struct MatchFile {
 /// The imports have been converted to paths, but they haven't been loaded yet.
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct LoadedMatchFile {
-    pub import_paths: Vec<PathBuf>,
+    pub import_paths: Vec<LoadedImportRef>,
     pub content: MatchFile,
     pub source_path: PathBuf,
 }
 
 /// A wrapper around `Vec` which only allows appending, and which returns a reference to the
 /// appended element.
-#[derive(Debug, Clone, PartialEq, Default, Archive, Serialize, Deserialize)]
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    Default,
+    Archive,
+    Serialize,
+    Deserialize,
+    serde::Serialize,
+    serde::Deserialize,
+)]
 #[archive(check_bytes)]
 #[repr(transparent)]
 pub struct FileStore<T> {
@@ -58,7 +96,20 @@ pub struct FileStore<T> {
 }
 
 /// A reference to a file in a `FileStore`.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Hash, Archive, Serialize, Deserialize)]
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Default,
+    Hash,
+    Archive,
+    Serialize,
+    Deserialize,
+    serde::Serialize,
+    serde::Deserialize,
+)]
 #[archive(check_bytes)]
 #[archive_attr(derive(Hash, PartialEq, Eq))]
 #[repr(transparent)]
@@ -72,13 +123,38 @@ impl PartialEq<usize> for MatchFileRef {
     }
 }
 
+impl MatchFileRef {
+    /// The index this ref resolves to in whichever `FileStore` it was handed out by.
+    #[inline]
+    pub(crate) fn index(&self) -> usize {
+        self.idx
+    }
+}
+
+impl ArchivedMatchFileRef {
+    /// The index this ref resolves to in whichever `ArchivedFileStore` it was handed out by.
+    #[inline]
+    pub(crate) fn index(&self) -> usize {
+        self.idx as usize
+    }
+}
+
 impl<T> FileStore<T> {
     #[inline]
     pub fn len(&self) -> usize {
         self.files.len()
     }
+
+    /// Every valid [`MatchFileRef`] into this store, in insertion order.
+    #[inline]
+    pub fn keys(&self) -> impl Iterator<Item = MatchFileRef> + '_ {
+        (0..self.files.len()).map(|idx| MatchFileRef { idx })
+    }
 }
 
+/// The set of match files loaded so far but not yet resolved, indexed by [`MatchFileRef`].
+pub(crate) type MatchFileStore = FileStore<LoadedMatchFile>;
+
 impl FileStore<LoadedMatchFile> {
     #[inline]
     pub(crate) fn new() -> Self {
@@ -102,41 +178,104 @@ impl FileStore<LoadedMatchFile> {
 
     /// Resolve all imports with the given map.
     ///
-    /// This function consumes the `FileStore` and returns a new one with resolved imports.
+    /// This function consumes the `FileStore` and returns a new one with resolved imports, along
+    /// with a non-fatal warning for every import that didn't resolve to any entry in
+    /// `match_file_map` (e.g. one dropped by a narrow/exclude pattern, or one whose own load
+    /// failed) -- such an import is still dropped from the resolved file's import list, but no
+    /// longer silently.
     /// Any [`MatchFileRef`] should remain valid for the new `FileStore`.
     pub(crate) fn resolve(
         self,
         match_file_map: &HashMap<PathBuf, MatchFileRef>,
-    ) -> FileStore<ResolvedMatchFile> {
+    ) -> (FileStore<ResolvedMatchFile>, Vec<NonFatalErrorSet>) {
+        let mut non_fatal_error_sets = Vec::new();
+
         let indexed_files = self
             .files
             .into_iter()
             .map(|match_file| {
+                let source_path = match_file.source_path;
+                let mut unresolved_imports = Vec::new();
+
                 let resolved_imports = match_file
                     .import_paths
                     .into_iter()
-                    .filter_map(|path| match_file_map.get(&path).copied())
+                    .filter_map(|import| match match_file_map.get(&import.path).copied() {
+                        Some(target) => Some(ImportRef {
+                            target,
+                            filter: import.filter,
+                        }),
+                        None => {
+                            unresolved_imports.push(ErrorRecord::warn(anyhow!(
+                                "import {:?} did not resolve to any loaded match file",
+                                import.path
+                            )));
+                            None
+                        }
+                    })
                     .collect::<_>();
+
+                if !unresolved_imports.is_empty() {
+                    non_fatal_error_sets.push(NonFatalErrorSet::new(
+                        &source_path,
+                        unresolved_imports,
+                    ));
+                }
+
                 ResolvedMatchFile {
                     imports: resolved_imports,
                     content: match_file.content,
-                    source_path: match_file.source_path,
+                    source_path,
                 }
             })
             .collect();
-        FileStore {
-            files: indexed_files,
-        }
+
+        (
+            FileStore {
+                files: indexed_files,
+            },
+            non_fatal_error_sets,
+        )
     }
 }
 
+/// An import edge in a [`ResolvedMatchFile`]'s import list: the imported file, plus the optional
+/// selective-import filter that applies when pulling matches/vars from it (see
+/// [`LoadedImportRef`]).
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    Default,
+    Archive,
+    Serialize,
+    Deserialize,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[archive(check_bytes)]
+pub struct ImportRef {
+    pub(crate) target: MatchFileRef,
+    pub(crate) filter: Option<Vec<CompactString>>,
+}
+
 /// Struct representing a match file, where all imports have been resolved.
 ///
 /// In contrast, a [`LoadedMatchFile`] contains unresolved imports.
-#[derive(Debug, Clone, PartialEq, Default, Archive, Serialize, Deserialize)]
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    Default,
+    Archive,
+    Serialize,
+    Deserialize,
+    serde::Serialize,
+    serde::Deserialize,
+)]
 #[archive(check_bytes)]
 pub struct ResolvedMatchFile {
-    pub(crate) imports: Vec<MatchFileRef>,
+    pub(crate) imports: Vec<ImportRef>,
     pub(crate) content: MatchFile,
     #[with(AsString)]
     pub(crate) source_path: PathBuf,
@@ -148,15 +287,95 @@ impl ArchivedResolvedMatchFile {
     }
 }
 
+impl ResolvedMatchFile {
+    pub(crate) fn source_path(&self) -> &Path {
+        &self.source_path
+    }
+
+    pub(crate) fn trigger_matches(&self) -> impl Iterator<Item = &TriggerMatch> {
+        self.content.trigger_matches.iter()
+    }
+}
+
 impl FileStore<ResolvedMatchFile> {
     #[inline]
     pub fn get(&self, idx: MatchFileRef) -> &ResolvedMatchFile {
         &self.files[idx.idx]
     }
+
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = &ResolvedMatchFile> {
+        self.files.iter()
+    }
+
+    /// A deterministic topological order over every loaded file, each file's imports appearing
+    /// before the file itself, so downstream match-precedence resolution sees a reproducible
+    /// order run to run. Ties (files with no dependency relationship) are broken by `MatchFileRef`
+    /// insertion order.
+    ///
+    /// An import cycle can't be fully topologically sorted; one is already reported as a
+    /// non-fatal error when the files are first loaded (see
+    /// `crate::matches::store::default::load_match_files_recursively`), and here it's simply broken by
+    /// skipping the back-edge, the same tolerance [`crate::matches::store::MatchStore`]'s own
+    /// traversal already relies on.
+    pub fn topological_order(&self) -> Vec<MatchFileRef> {
+        fn visit(
+            store: &FileStore<ResolvedMatchFile>,
+            node: MatchFileRef,
+            visiting: &mut [bool],
+            visited: &mut [bool],
+            order: &mut Vec<MatchFileRef>,
+        ) {
+            if visited[node.index()] || visiting[node.index()] {
+                return;
+            }
+            visiting[node.index()] = true;
+            for import in &store.get(node).imports {
+                visit(store, import.target, visiting, visited, order);
+            }
+            visiting[node.index()] = false;
+            visited[node.index()] = true;
+            order.push(node);
+        }
+
+        let mut visiting = vec![false; self.len()];
+        let mut visited = vec![false; self.len()];
+        let mut order = Vec::with_capacity(self.len());
+        for node in self.keys() {
+            visit(self, node, &mut visiting, &mut visited, &mut order);
+        }
+        order
+    }
 }
 
 impl ArchivedFileStore<ResolvedMatchFile> {
     pub fn get_source_paths(&self) -> impl Iterator<Item = &Path> {
         self.files.iter().map(|file| file.get_source_path())
     }
+
+    /// The archived counterpart of [`FileStore::get`], for a caller running directly off an
+    /// mmapped store (see [`crate::matches::store::ArchivedMatchStoreHandle`]) instead of paying
+    /// for a full deserialize.
+    #[inline]
+    pub fn get(&self, idx: MatchFileRef) -> &ArchivedResolvedMatchFile {
+        &self.files[idx.index()]
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// The archived counterpart of [`FileStore::into_enumerate`].
+    pub fn iter_enumerate(&self) -> impl Iterator<Item = (MatchFileRef, &ArchivedResolvedMatchFile)> {
+        self.files
+            .iter()
+            .enumerate()
+            .map(|(idx, file)| (MatchFileRef { idx }, file))
+    }
 }
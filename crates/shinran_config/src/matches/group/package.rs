@@ -0,0 +1,280 @@
+/*
+ * This file is part of espanso.
+ *
+ * Copyright (C) 2019-2021 Federico Terzi
+ *
+ * espanso is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * espanso is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with espanso.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Lets packages under the `match/packages` directory ship as a single `.tar.xz` file (optionally
+//! AES-256-CTR encrypted) instead of a live directory, so a large shared snippet collection can be
+//! distributed/updated as one file while still being imported exactly like any other match file
+//! directory once it's unpacked.
+//!
+//! [`materialize_packages`] is the only entry point: it scans the top level of the packages
+//! directory, and for every archive it finds, extracts it next to itself (`foo.tar.xz` ->
+//! `foo/`), so existing imports like `packages/foo/snippets.yml` keep working unchanged. Plain
+//! directories are left untouched. An archive is only re-extracted when it's new or has changed,
+//! tracked via a content digest written alongside the extracted directory.
+
+use std::{
+    ffi::OsStr,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use aes::cipher::{KeyIvInit, StreamCipher};
+use anyhow::{anyhow, bail, Context, Result};
+use pbkdf2::pbkdf2_hmac;
+use sha2::{Digest, Sha256};
+
+use crate::error::NonFatalErrorSet;
+
+type Aes256Ctr = ctr::Ctr64BE<aes::Aes256>;
+
+/// Largest amount of memory the xz decoder is allowed to use to decompress a package archive, so
+/// a crafted header can't make decompression allocate an unbounded amount of memory.
+const MAX_DECODER_MEMLIMIT: u64 = 1 << 26; // 64 MiB
+
+/// Magic bytes at the start of an encrypted package archive, followed by a 16-byte PBKDF2 salt
+/// and a 16-byte random IV. Chosen to be distinguishable from the real xz magic
+/// (`FD 37 7A 58 5A 00`) so the two formats can never be mistaken for one another.
+const ENCRYPTED_MAGIC: &[u8; 5] = b"SHPK1";
+
+/// Length in bytes of the per-archive PBKDF2 salt stored in the header, right after
+/// [`ENCRYPTED_MAGIC`].
+const SALT_LEN: usize = 16;
+
+/// Length in bytes of the AES-CTR IV stored in the header, right after the salt.
+const IV_LEN: usize = 16;
+
+/// PBKDF2-HMAC-SHA256 iteration count used to derive the AES key from a passphrase, in line with
+/// OWASP's current minimum recommendation for that construction.
+const PBKDF2_ROUNDS: u32 = 600_000;
+
+const XZ_MAGIC: &[u8; 6] = &[0xFD, b'7', b'z', b'X', b'Z', 0x00];
+
+/// The digest file written alongside an extracted package directory, recording the archive bytes
+/// it was extracted from so a later run can tell whether it needs to re-extract.
+const DIGEST_FILE_NAME: &str = ".shinran-package-digest";
+
+/// How a single top-level entry in the packages directory is stored on disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PackageFormat {
+    /// Already a plain directory; nothing to do.
+    Directory,
+    /// A plain `.tar.xz` archive.
+    TarXz,
+    /// An AES-256-CTR encrypted `.tar.xz` archive (see [`ENCRYPTED_MAGIC`]).
+    EncryptedTarXz,
+}
+
+/// Scan the top level of `packages_dir` and extract every `.tar.xz`/encrypted archive found there
+/// into a sibling directory named after it, so match-file imports can keep referring to
+/// `packages/<name>/...` regardless of whether `<name>` ships as a directory or an archive.
+///
+/// `passphrase` is used to decrypt encrypted archives; if it's `None`, the OS keyring is tried
+/// instead, looked up by the archive's file stem. Failures are collected as non-fatal errors
+/// (missing passphrase, bad key, corrupt archive, ...) rather than aborting the whole load, the
+/// same way a broken match file import is handled.
+pub(crate) fn materialize_packages(
+    packages_dir: &Path,
+    passphrase: Option<&str>,
+) -> Vec<NonFatalErrorSet> {
+    let Ok(entries) = std::fs::read_dir(packages_dir) else {
+        return Vec::new();
+    };
+
+    let mut non_fatal_error_sets = Vec::new();
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let format = match detect_format(&path) {
+            Some(format) => format,
+            None => continue,
+        };
+        if format == PackageFormat::Directory {
+            continue;
+        }
+
+        if let Err(err) = materialize_one(&path, format, passphrase) {
+            non_fatal_error_sets.push(NonFatalErrorSet::single_error(&path, err));
+        }
+    }
+
+    non_fatal_error_sets
+}
+
+/// Auto-detect how `path` stores its package: a live directory, a plain `.tar.xz`, or an
+/// AES-256-CTR encrypted `.tar.xz` (extension-sniffed first, then confirmed against the file's
+/// leading magic bytes so a misnamed file doesn't get mis-parsed).
+fn detect_format(path: &Path) -> Option<PackageFormat> {
+    if path.is_dir() {
+        return Some(PackageFormat::Directory);
+    }
+
+    if !has_extension(path, "enc") && !has_tar_xz_extension(path) {
+        return None;
+    }
+
+    let mut magic = [0u8; 6];
+    let mut file = std::fs::File::open(path).ok()?;
+    let read = file.read(&mut magic).ok()?;
+
+    if read >= ENCRYPTED_MAGIC.len() && magic[..ENCRYPTED_MAGIC.len()] == *ENCRYPTED_MAGIC {
+        Some(PackageFormat::EncryptedTarXz)
+    } else if read >= XZ_MAGIC.len() && magic == *XZ_MAGIC {
+        Some(PackageFormat::TarXz)
+    } else {
+        None
+    }
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension() == Some(OsStr::new(extension))
+}
+
+/// Whether `path`'s last two extensions spell `.tar.xz`.
+fn has_tar_xz_extension(path: &Path) -> bool {
+    has_extension(path, "xz")
+        && Path::new(path.file_stem().unwrap_or_default()).extension() == Some(OsStr::new("tar"))
+}
+
+/// Extract `archive_path` into `archive_path`'s parent directory, named after its file stem
+/// (`foo.tar.xz` -> `foo/`), unless an up-to-date extraction is already there.
+fn materialize_one(
+    archive_path: &Path,
+    format: PackageFormat,
+    passphrase: Option<&str>,
+) -> Result<()> {
+    let dest_dir = destination_dir(archive_path)?;
+
+    let bytes = std::fs::read(archive_path)
+        .with_context(|| format!("unable to read package archive: {archive_path:?}"))?;
+    let digest = digest_of(&bytes);
+
+    if extraction_is_fresh(&dest_dir, &digest) {
+        return Ok(());
+    }
+
+    let tar_xz_bytes = match format {
+        PackageFormat::TarXz => bytes,
+        PackageFormat::EncryptedTarXz => {
+            let package_name = archive_path
+                .file_stem()
+                .and_then(OsStr::to_str)
+                .unwrap_or("package");
+            decrypt(&bytes, package_name, passphrase)?
+        }
+        PackageFormat::Directory => unreachable!("directories are filtered out before this point"),
+    };
+
+    extract_tar_xz(&tar_xz_bytes, &dest_dir)?;
+
+    std::fs::write(dest_dir.join(DIGEST_FILE_NAME), &digest)
+        .with_context(|| format!("unable to write package digest for {dest_dir:?}"))?;
+
+    Ok(())
+}
+
+/// The directory an archive should be extracted into: its own path with every extension (`.xz`,
+/// `.tar`, `.enc`) stripped.
+fn destination_dir(archive_path: &Path) -> Result<PathBuf> {
+    let parent = archive_path
+        .parent()
+        .ok_or_else(|| anyhow!("package archive has no parent directory: {archive_path:?}"))?;
+    let mut stem = archive_path
+        .file_name()
+        .ok_or_else(|| anyhow!("package archive has no file name: {archive_path:?}"))?
+        .to_string_lossy()
+        .into_owned();
+    for suffix in [".enc", ".xz", ".tar"] {
+        stem = stem.strip_suffix(suffix).unwrap_or(&stem).to_string();
+    }
+    Ok(parent.join(stem))
+}
+
+/// Whether `dest_dir` already holds an extraction of the archive whose content digest is `digest`.
+fn extraction_is_fresh(dest_dir: &Path, digest: &[u8; 32]) -> bool {
+    let Ok(recorded) = std::fs::read(dest_dir.join(DIGEST_FILE_NAME)) else {
+        return false;
+    };
+    recorded == digest
+}
+
+fn digest_of(bytes: &[u8]) -> [u8; 32] {
+    Sha256::digest(bytes).into()
+}
+
+/// Decrypt an encrypted package archive: `SHPK1` magic, a 16-byte PBKDF2 salt, a 16-byte IV, then
+/// the AES-256-CTR ciphertext of the inner `.tar.xz`. The key is derived from `passphrase` if
+/// given (PBKDF2-HMAC-SHA256 over the passphrase bytes, salted with the archive's own salt),
+/// otherwise looked up in the OS keyring under the `shinran` service using `package_name` as the
+/// account.
+fn decrypt(bytes: &[u8], package_name: &str, passphrase: Option<&str>) -> Result<Vec<u8>> {
+    let header_len = ENCRYPTED_MAGIC.len() + SALT_LEN + IV_LEN;
+    if bytes.len() < header_len {
+        bail!("encrypted package archive is too short to contain a header");
+    }
+    if &bytes[..ENCRYPTED_MAGIC.len()] != ENCRYPTED_MAGIC {
+        bail!("encrypted package archive has an invalid magic header");
+    }
+    let salt = &bytes[ENCRYPTED_MAGIC.len()..ENCRYPTED_MAGIC.len() + SALT_LEN];
+    let iv = &bytes[ENCRYPTED_MAGIC.len() + SALT_LEN..header_len];
+    let ciphertext = &bytes[header_len..];
+
+    let key = derive_key(salt, package_name, passphrase)?;
+    let mut plaintext = ciphertext.to_vec();
+    let mut cipher = Aes256Ctr::new(key.as_slice().into(), iv.into());
+    cipher.apply_keystream(&mut plaintext);
+
+    Ok(plaintext)
+}
+
+/// Derive the 256-bit AES key for `package_name` via PBKDF2-HMAC-SHA256 (salted with the
+/// archive's own `salt`, see [`PBKDF2_ROUNDS`]), from `passphrase` if given or, failing that,
+/// from whatever the OS keyring has stored for it.
+fn derive_key(salt: &[u8], package_name: &str, passphrase: Option<&str>) -> Result<[u8; 32]> {
+    let passphrase = match passphrase {
+        Some(passphrase) => passphrase.to_string(),
+        None => keyring::Entry::new("shinran", package_name)
+            .and_then(|entry| entry.get_password())
+            .with_context(|| {
+                format!(
+                    "package {package_name:?} is encrypted, but no passphrase was given and none \
+                     was found in the OS keyring"
+                )
+            })?,
+    };
+    let mut key = [0u8; 32];
+    pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, PBKDF2_ROUNDS, &mut key);
+    Ok(key)
+}
+
+/// Decompress `tar_xz_bytes` as a real `.xz` container (e.g. what `tar cJf` produces), capping
+/// the decoder's memory usage at [`MAX_DECODER_MEMLIMIT`], and unpack it into `dest_dir`.
+fn extract_tar_xz(tar_xz_bytes: &[u8], dest_dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(dest_dir)
+        .with_context(|| format!("unable to create package directory: {dest_dir:?}"))?;
+
+    let stream = xz2::stream::Stream::new_stream_decoder(MAX_DECODER_MEMLIMIT, 0)
+        .context("unable to create xz decoder")?;
+    let decoder = xz2::read::XzDecoder::new_stream(tar_xz_bytes, stream);
+
+    tar::Archive::new(decoder)
+        .unpack(dest_dir)
+        .with_context(|| format!("unable to unpack package archive into {dest_dir:?}"))?;
+
+    Ok(())
+}
@@ -18,20 +18,29 @@
  */
 
 use anyhow::{anyhow, Context, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
 use std::path::{Path, PathBuf};
 use thiserror::Error;
 
 use crate::error::ErrorRecord;
+use crate::matches::group::loader::yaml::parse::YAMLImportEntry;
+use crate::matches::group::LoadedImportRef;
 
-/// Resolve the given paths by turning relative paths into absolute paths and canonicalizing them.
+/// Resolve the given import entries by turning relative paths into absolute paths and
+/// canonicalizing them.
 ///
 /// The paths are resolved starting from the given match file path.
 ///
 /// Note that this function does not check yet whether the resolved paths are valid files.
+///
+/// An entry may also be a glob pattern (e.g. `packages/**/*.yml`), in which case it expands to
+/// every match file under its base directory that the pattern matches; see
+/// [`resolve_glob_import`]. A [`YAMLImportEntry::Selective`] entry's filter is attached to every
+/// [`LoadedImportRef`] it resolves to, including each file a glob/selective pattern expands to.
 pub fn resolve_paths(
     match_file_path: &Path,
-    paths: &[String],
-) -> Result<(Vec<PathBuf>, Vec<ErrorRecord>)> {
+    imports: &[YAMLImportEntry],
+) -> Result<(Vec<LoadedImportRef>, Vec<ErrorRecord>)> {
     let mut resolved_paths = Vec::new();
 
     // Get the containing directory
@@ -49,8 +58,37 @@ pub fn resolve_paths(
     };
 
     let mut non_fatal_errors = Vec::new();
+    // Used to drop a glob import that would otherwise re-include the importing file itself,
+    // which would just turn into an immediate import cycle.
+    let canonical_self = dunce::canonicalize(match_file_path).ok();
+
+    for entry in imports {
+        let (path, filter) = match entry {
+            YAMLImportEntry::Full(path) => (path.as_str(), None),
+            YAMLImportEntry::Selective { from, import } => (from.as_str(), Some(import.clone())),
+        };
+
+        if is_remote_url(path) {
+            resolved_paths.push(LoadedImportRef {
+                path: PathBuf::from(normalize_url(path)),
+                filter,
+            });
+            continue;
+        }
+
+        if is_glob_pattern(path) {
+            resolved_paths.extend(
+                resolve_glob_import(current_dir, path, &mut non_fatal_errors)
+                    .into_iter()
+                    .filter(|resolved| Some(resolved) != canonical_self.as_ref())
+                    .map(|path| LoadedImportRef {
+                        path,
+                        filter: filter.clone(),
+                    }),
+            );
+            continue;
+        }
 
-    for path in paths {
         let import_path = PathBuf::from(path);
 
         // Absolute or relative import
@@ -65,7 +103,10 @@ pub fn resolve_paths(
         {
             Ok(canonical_path) => {
                 if canonical_path.exists() && canonical_path.is_file() {
-                    resolved_paths.push(canonical_path);
+                    resolved_paths.push(LoadedImportRef {
+                        path: canonical_path,
+                        filter,
+                    });
                 } else {
                     // Best effort imports
                     non_fatal_errors.push(ErrorRecord::error(anyhow!(
@@ -81,6 +122,253 @@ pub fn resolve_paths(
     Ok((resolved_paths, non_fatal_errors))
 }
 
+/// Whether `pattern` should be treated as a glob rather than a literal import path.
+fn is_glob_pattern(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '[', '{'])
+}
+
+/// Whether an import entry is a remote `http(s)://` URL rather than a filesystem path, the same
+/// way a leading `/` (or drive letter) marks an absolute path.
+pub(crate) fn is_remote_url(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+/// Normalize a remote import URL so the same resource imported from two different files (e.g.
+/// with differing case in the host, or a trailing slash) maps to the same cache key.
+pub(crate) fn normalize_url(url: &str) -> String {
+    let trimmed = url.trim_end_matches('/');
+    let Some((scheme, rest)) = trimmed.split_once("://") else {
+        return trimmed.to_string();
+    };
+
+    match rest.split_once('/') {
+        Some((authority, path)) => {
+            format!("{}://{}/{path}", scheme, authority.to_ascii_lowercase())
+        }
+        None => format!("{}://{}", scheme, rest.to_ascii_lowercase()),
+    }
+}
+
+/// Split a glob pattern into its literal base directory (every leading path component that
+/// contains no wildcard) and the remaining wildcard tail, so the caller can walk only the base
+/// directory instead of stat-ing the whole config tree.
+fn split_glob_base(pattern: &str) -> (PathBuf, &str) {
+    let components: Vec<&str> = pattern.split('/').collect();
+    let wildcard_idx = components
+        .iter()
+        .position(|component| is_glob_pattern(component))
+        .unwrap_or(components.len());
+
+    let base = components[..wildcard_idx].join("/");
+    let tail_start = components[..wildcard_idx]
+        .iter()
+        .map(|c| c.len() + 1)
+        .sum::<usize>()
+        .min(pattern.len());
+
+    (PathBuf::from(base), &pattern[tail_start..])
+}
+
+/// Resolve a single glob import entry into the concrete files it matches.
+///
+/// The pattern is split into a literal base directory and a wildcard tail; only that base
+/// directory is walked, and each candidate path is tested against the compiled [`GlobSet`]
+/// as the walk proceeds, rather than expanding the glob into a full file list up front.
+fn resolve_glob_import(
+    current_dir: &Path,
+    pattern: &str,
+    non_fatal_errors: &mut Vec<ErrorRecord>,
+) -> Vec<PathBuf> {
+    let (base, tail) = split_glob_base(pattern);
+    let base_dir = if base.as_os_str().is_empty() {
+        current_dir.to_path_buf()
+    } else if base.is_relative() {
+        current_dir.join(base)
+    } else {
+        base
+    };
+
+    let canonical_base = match dunce::canonicalize(&base_dir) {
+        Ok(canonical_base) if canonical_base.is_dir() => canonical_base,
+        _ => {
+            non_fatal_errors.push(ErrorRecord::error(anyhow!(
+                "unable to resolve glob import {:?}: base directory {:?} does not exist",
+                pattern,
+                base_dir
+            )));
+            return Vec::new();
+        }
+    };
+
+    let full_pattern = canonical_base.join(tail);
+    let glob_set = match Glob::new(&full_pattern.to_string_lossy()) {
+        Ok(glob) => {
+            let mut builder = GlobSetBuilder::new();
+            builder.add(glob);
+            match builder.build() {
+                Ok(glob_set) => glob_set,
+                Err(err) => {
+                    non_fatal_errors.push(ErrorRecord::error(anyhow!(
+                        "invalid glob import pattern {:?}: {}",
+                        pattern,
+                        err
+                    )));
+                    return Vec::new();
+                }
+            }
+        }
+        Err(err) => {
+            non_fatal_errors.push(ErrorRecord::error(anyhow!(
+                "invalid glob import pattern {:?}: {}",
+                pattern,
+                err
+            )));
+            return Vec::new();
+        }
+    };
+
+    let mut matches = Vec::new();
+    walk_and_match(&canonical_base, &glob_set, &mut matches);
+    matches.sort();
+    matches
+}
+
+/// Walk `dir` recursively, pushing every file whose path matches `glob_set`.
+fn walk_and_match(dir: &Path, glob_set: &GlobSet, matches: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            walk_and_match(&path, glob_set, matches);
+        } else if glob_set.is_match(&path) {
+            matches.push(path);
+        }
+    }
+}
+
+/// Compile `include`/`exclude` glob lists (relative to `base_dir`) and walk `base_dir` once,
+/// keeping every file matched by an include pattern and discarding anything matched by an
+/// exclude pattern as the walk proceeds, rather than collecting every include match first and
+/// filtering it afterwards. An excluded directory is pruned entirely, so e.g. `drafts/**` skips
+/// walking into `drafts/` at all. The walk also honors per-directory `.shinranignore`/
+/// `.gitignore` files along the way (see [`crate::ignore_walk`]); anything they skip is reported
+/// as a non-fatal warning rather than silently vanishing.
+///
+/// Invalid glob patterns are reported as non-fatal errors rather than causing a panic; the rest
+/// of the valid patterns still apply.
+pub(crate) fn scan_directory(
+    base_dir: &Path,
+    include: &[String],
+    exclude: &[String],
+) -> (Vec<PathBuf>, Vec<ErrorRecord>) {
+    let mut non_fatal_errors = Vec::new();
+
+    let canonical_base = match dunce::canonicalize(base_dir) {
+        Ok(canonical_base) if canonical_base.is_dir() => canonical_base,
+        _ => {
+            non_fatal_errors.push(ErrorRecord::error(anyhow!(
+                "unable to scan match directory: {:?} is not a directory",
+                base_dir
+            )));
+            return (Vec::new(), non_fatal_errors);
+        }
+    };
+
+    let include_set = build_glob_set(&canonical_base, include, &mut non_fatal_errors);
+    let exclude_set = build_glob_set(&canonical_base, exclude, &mut non_fatal_errors);
+    let wanted = |path: &Path| include_set.is_match(path) && !exclude_set.is_match(path);
+    let should_descend = |path: &Path| !exclude_set.is_match(path);
+
+    let (matches, ignored) =
+        crate::ignore_walk::walk_respecting_ignore_files(&canonical_base, &wanted, &should_descend);
+    for path in ignored {
+        non_fatal_errors.push(ErrorRecord::warn(anyhow!(
+            "skipping {:?}: excluded by a `.shinranignore`/`.gitignore` rule",
+            path
+        )));
+    }
+
+    (matches, non_fatal_errors)
+}
+
+/// Compile `patterns` (each joined onto `base_dir`, the same way a glob import's tail is joined
+/// onto its base) into a single [`GlobSet`], reporting but otherwise skipping any pattern that
+/// fails to parse.
+fn build_glob_set(
+    base_dir: &Path,
+    patterns: &[String],
+    non_fatal_errors: &mut Vec<ErrorRecord>,
+) -> GlobSet {
+    let mut builder = GlobSetBuilder::new();
+
+    for pattern in patterns {
+        let full_pattern = base_dir.join(pattern);
+        match Glob::new(&full_pattern.to_string_lossy()) {
+            Ok(glob) => {
+                builder.add(glob);
+            }
+            Err(err) => {
+                non_fatal_errors.push(ErrorRecord::error(anyhow!(
+                    "invalid glob pattern {:?}: {}",
+                    pattern,
+                    err
+                )));
+            }
+        }
+    }
+
+    builder.build().unwrap_or_else(|err| {
+        non_fatal_errors.push(ErrorRecord::error(anyhow!(
+            "failed to compile glob patterns: {}",
+            err
+        )));
+        GlobSet::empty()
+    })
+}
+
+/// Compile `excludes` into a single [`GlobSet`] for narrowing an already-resolved import graph
+/// (see [`crate::matches::store::MatchStore::load`]), mirroring sparse/narrow checkout semantics:
+/// unlike [`build_glob_set`], these patterns aren't anchored to one base directory, so
+/// a pattern with no explicit directory component (e.g. `"legacy.yml"`) is implicitly treated as
+/// `"**/legacy.yml"` and matches that file however deep the import graph reaches it.
+pub(crate) fn build_narrow_exclude_set(excludes: &[String]) -> (GlobSet, Vec<ErrorRecord>) {
+    let mut non_fatal_errors = Vec::new();
+    let mut builder = GlobSetBuilder::new();
+
+    for pattern in excludes {
+        let anchored = if pattern.contains('/') {
+            pattern.clone()
+        } else {
+            format!("**/{pattern}")
+        };
+        match Glob::new(&anchored) {
+            Ok(glob) => {
+                builder.add(glob);
+            }
+            Err(err) => {
+                non_fatal_errors.push(ErrorRecord::error(anyhow!(
+                    "invalid exclude pattern {:?}: {}",
+                    pattern,
+                    err
+                )));
+            }
+        }
+    }
+
+    let glob_set = builder.build().unwrap_or_else(|err| {
+        non_fatal_errors.push(ErrorRecord::error(anyhow!(
+            "failed to compile exclude patterns: {}",
+            err
+        )));
+        GlobSet::empty()
+    });
+
+    (glob_set, non_fatal_errors)
+}
+
 #[derive(Error, Debug)]
 pub enum ResolvePathError {
     #[error("resolving path failed: `{0}`")]
@@ -89,11 +377,23 @@ pub enum ResolvePathError {
 
 #[cfg(test)]
 pub mod tests {
+    use compact_str::CompactString;
     use shinran_helpers::use_test_directory;
 
     use super::*;
     use std::fs::create_dir_all;
 
+    /// Build a plain (non-selective) import entry from a path/glob/URL string.
+    fn full(path: &str) -> YAMLImportEntry {
+        YAMLImportEntry::Full(path.to_string())
+    }
+
+    /// Build a `LoadedImportRef` with no selective-import filter, for comparing against plain
+    /// import resolutions.
+    fn unfiltered(path: PathBuf) -> LoadedImportRef {
+        LoadedImportRef { path, filter: None }
+    }
+
     #[test]
     fn resolve_imports_paths_works_correctly() {
         use_test_directory(|_, match_dir, _| {
@@ -113,21 +413,147 @@ pub mod tests {
             std::fs::write(&absolute_file, "test").unwrap();
 
             let imports = vec![
-                "another.yml".to_string(),
-                "sub/sub.yml".to_string(),
-                absolute_file.to_string_lossy().to_string(),
-                "sub/invalid.yml".to_string(), // Should be skipped
+                full("another.yml"),
+                full("sub/sub.yml"),
+                full(&absolute_file.to_string_lossy()),
+                full("sub/invalid.yml"), // Should be skipped
             ];
 
-            let (resolve_paths, errors) = resolve_paths(&base_file, &imports).unwrap();
+            let (resolved_paths, errors) = resolve_paths(&base_file, &imports).unwrap();
 
-            assert_eq!(resolve_paths, vec![another_file, sub_file, absolute_file,]);
+            assert_eq!(
+                resolved_paths,
+                vec![
+                    unfiltered(another_file),
+                    unfiltered(sub_file),
+                    unfiltered(absolute_file),
+                ]
+            );
 
             // The "sub/invalid.yml" should generate an error
             assert_eq!(errors.len(), 1);
         });
     }
 
+    #[test]
+    fn resolve_imports_glob_pattern_pulls_in_matching_files() {
+        use_test_directory(|_, match_dir, _| {
+            let packages_dir = match_dir.join("packages");
+            let nested_dir = packages_dir.join("nested");
+            create_dir_all(&nested_dir).unwrap();
+
+            let base_file = match_dir.join("base.yml");
+            std::fs::write(&base_file, "test").unwrap();
+
+            let top_level = packages_dir.join("top.yml");
+            std::fs::write(&top_level, "test").unwrap();
+
+            let nested_file = nested_dir.join("nested.yml");
+            std::fs::write(&nested_file, "test").unwrap();
+
+            let ignored_file = packages_dir.join("top.txt");
+            std::fs::write(&ignored_file, "test").unwrap();
+
+            let imports = vec![full("packages/**/*.yml")];
+
+            let (resolved_paths, errors) = resolve_paths(&base_file, &imports).unwrap();
+
+            assert_eq!(errors.len(), 0);
+            assert_eq!(
+                resolved_paths,
+                vec![unfiltered(nested_file), unfiltered(top_level)]
+            );
+        });
+    }
+
+    #[test]
+    fn resolve_imports_brace_alternate_pattern_pulls_in_matching_files() {
+        use_test_directory(|_, match_dir, _| {
+            let packages_dir = match_dir.join("packages");
+            create_dir_all(&packages_dir).unwrap();
+
+            let base_file = match_dir.join("base.yml");
+            std::fs::write(&base_file, "test").unwrap();
+
+            let emojis_file = packages_dir.join("emojis.yml");
+            std::fs::write(&emojis_file, "test").unwrap();
+
+            let snippets_file = packages_dir.join("snippets.yml");
+            std::fs::write(&snippets_file, "test").unwrap();
+
+            let ignored_file = packages_dir.join("other.yml");
+            std::fs::write(&ignored_file, "test").unwrap();
+
+            let imports = vec![full("packages/{emojis,snippets}.yml")];
+
+            let (resolved_paths, errors) = resolve_paths(&base_file, &imports).unwrap();
+
+            assert_eq!(errors.len(), 0);
+            assert_eq!(
+                resolved_paths,
+                vec![unfiltered(emojis_file), unfiltered(snippets_file)]
+            );
+        });
+    }
+
+    #[test]
+    fn resolve_imports_glob_pattern_excludes_the_importing_file_itself() {
+        use_test_directory(|_, match_dir, _| {
+            let base_file = match_dir.join("base.yml");
+            std::fs::write(&base_file, "test").unwrap();
+
+            let sibling_file = match_dir.join("sibling.yml");
+            std::fs::write(&sibling_file, "test").unwrap();
+
+            // A glob matching every file in the directory would otherwise re-include "base.yml"
+            // and import itself.
+            let imports = vec![full("*.yml")];
+
+            let (resolved_paths, errors) = resolve_paths(&base_file, &imports).unwrap();
+
+            assert_eq!(errors.len(), 0);
+            assert_eq!(resolved_paths, vec![unfiltered(sibling_file)]);
+        });
+    }
+
+    #[test]
+    fn resolve_imports_glob_pattern_with_missing_base_dir_is_non_fatal() {
+        use_test_directory(|_, match_dir, _| {
+            let base_file = match_dir.join("base.yml");
+            std::fs::write(&base_file, "test").unwrap();
+
+            let imports = vec![full("missing/*.yml")];
+
+            let (resolved_paths, errors) = resolve_paths(&base_file, &imports).unwrap();
+
+            assert_eq!(resolved_paths.len(), 0);
+            assert_eq!(errors.len(), 1);
+        });
+    }
+
+    #[test]
+    fn resolve_imports_remote_url_is_passed_through_normalized() {
+        use_test_directory(|_, match_dir, _| {
+            let base_file = match_dir.join("base.yml");
+            std::fs::write(&base_file, "test").unwrap();
+
+            let imports = vec![
+                full("https://Example.com/snippets/emojis.yml/"),
+                full("not-a-url.yml"), // Should be reported as missing
+            ];
+
+            let (resolved_paths, errors) = resolve_paths(&base_file, &imports).unwrap();
+
+            assert_eq!(
+                resolved_paths,
+                vec![unfiltered(PathBuf::from(
+                    "https://example.com/snippets/emojis.yml"
+                ))]
+            );
+            assert_eq!(errors.len(), 1);
+        });
+    }
+
     #[test]
     fn resolve_imports_paths_parent_relative_path() {
         use_test_directory(|_, match_dir, _| {
@@ -140,13 +566,46 @@ pub mod tests {
             let sub_file = sub_dir.join("sub.yml");
             std::fs::write(&sub_file, "test").unwrap();
 
-            let imports = vec!["../base.yml".to_string()];
+            let imports = vec![full("../base.yml")];
 
             let (resolved_paths, errors) = resolve_paths(&sub_file, &imports).unwrap();
 
-            assert_eq!(resolved_paths, vec![base_file]);
+            assert_eq!(resolved_paths, vec![unfiltered(base_file)]);
+
+            assert_eq!(errors.len(), 0);
+        });
+    }
+
+    #[test]
+    fn resolve_imports_selective_entry_carries_its_filter() {
+        use_test_directory(|_, match_dir, _| {
+            let base_file = match_dir.join("base.yml");
+            std::fs::write(&base_file, "test").unwrap();
+
+            let emojis_file = match_dir.join("emojis.yml");
+            std::fs::write(&emojis_file, "test").unwrap();
+
+            let imports = vec![YAMLImportEntry::Selective {
+                from: "emojis.yml".to_string(),
+                import: vec![
+                    CompactString::const_new("smile"),
+                    CompactString::const_new("heart"),
+                ],
+            }];
+
+            let (resolved_paths, errors) = resolve_paths(&base_file, &imports).unwrap();
 
             assert_eq!(errors.len(), 0);
+            assert_eq!(
+                resolved_paths,
+                vec![LoadedImportRef {
+                    path: emojis_file,
+                    filter: Some(vec![
+                        CompactString::const_new("smile"),
+                        CompactString::const_new("heart")
+                    ]),
+                }]
+            );
         });
     }
 }
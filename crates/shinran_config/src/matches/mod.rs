@@ -17,7 +17,11 @@
  * along with espanso.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use shinran_types::{MatchCause, MatchEffect, TriggerCause, Variable};
+use std::collections::HashMap;
+
+use shinran_types::{
+    MatchCause, MatchEffect, RegexCause, RegexMatch, TriggerCause, TriggerMatch, Variable,
+};
 
 pub(crate) mod group;
 pub mod store;
@@ -44,6 +48,44 @@ impl Default for LoadedMatch {
 }
 
 impl LoadedMatch {
+    /// Build a `LoadedMatch` from a trigger match, expanding a `MatchEffect::Alias` into the
+    /// concrete effect it names: first looked up in `aliases` (the owning profile's table),
+    /// then `default_aliases` (the default profile's), so a profile can share the default's
+    /// aliases without redeclaring them.
+    pub fn from_trigger(
+        trigger_match: &TriggerMatch,
+        aliases: &HashMap<String, MatchEffect>,
+        default_aliases: &HashMap<String, MatchEffect>,
+    ) -> Self {
+        Self {
+            cause: MatchCause::Trigger(TriggerCause {
+                triggers: trigger_match.triggers.clone(),
+                word_boundary: trigger_match.word_boundary,
+                propagate_case: trigger_match.propagate_case,
+                uppercase_style: trigger_match.uppercase_style,
+            }),
+            effect: resolve_alias(&trigger_match.base_match.effect, aliases, default_aliases),
+            label: trigger_match.base_match.label.clone(),
+            search_terms: trigger_match.base_match.search_terms.clone(),
+        }
+    }
+
+    /// Same as [`Self::from_trigger`], but for a regex match.
+    pub fn from_regex(
+        regex_match: &RegexMatch,
+        aliases: &HashMap<String, MatchEffect>,
+        default_aliases: &HashMap<String, MatchEffect>,
+    ) -> Self {
+        Self {
+            cause: MatchCause::Regex(RegexCause {
+                regex: regex_match.regex.clone(),
+            }),
+            effect: resolve_alias(&regex_match.base_match.effect, aliases, default_aliases),
+            label: regex_match.base_match.label.clone(),
+            search_terms: regex_match.base_match.search_terms.clone(),
+        }
+    }
+
     // TODO: test
     pub fn description(&self) -> &str {
         if let Some(label) = &self.label {
@@ -70,3 +112,21 @@ impl LoadedMatch {
             .collect()
     }
 }
+
+/// Expand a `MatchEffect::Alias` by name, trying `aliases` first and `default_aliases` second.
+/// An alias that isn't defined anywhere resolves to `MatchEffect::None` rather than failing the
+/// whole match, since a typo'd alias shouldn't take down matches that don't use it.
+fn resolve_alias(
+    effect: &MatchEffect,
+    aliases: &HashMap<String, MatchEffect>,
+    default_aliases: &HashMap<String, MatchEffect>,
+) -> MatchEffect {
+    match effect {
+        MatchEffect::Alias(name) => aliases
+            .get(name)
+            .or_else(|| default_aliases.get(name))
+            .cloned()
+            .unwrap_or(MatchEffect::None),
+        other => other.clone(),
+    }
+}
@@ -18,102 +18,129 @@
  */
 
 use crate::{
-    error::NonFatalErrorSet,
-    matches::group::{loader, MatchFile, MatchFileRef, MatchFileStore},
+    error::{ErrorRecord, NonFatalErrorSet},
+    matches::group::{
+        loader, path, FileStore, ImportRef, LoadedMatchFile, MatchFileRef, MatchFileStore,
+    },
 };
-use anyhow::Context;
-use rkyv::{with::AsString, Archive, Deserialize, Serialize};
+use anyhow::{anyhow, Context};
+use compact_str::CompactString;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use rkyv::{Archive, Deserialize, Serialize};
 use shinran_types::{MatchesAndGlobalVars, RegexMatch, TriggerMatch, Variable};
 use std::{
     collections::{HashMap, HashSet},
     path::{Path, PathBuf},
 };
 
-/// Struct representing a match file, where all imports have been resolved.
-///
-/// In contrast, a [`LoadedMatchFile`] contains unresolved imports.
-#[derive(Debug, Clone, PartialEq, Default, Archive, Serialize, Deserialize)]
-#[archive(check_bytes)]
-pub struct ResolvedMatchFile {
-    imports: Vec<MatchFileRef>,
-    content: MatchFile,
-    #[with(AsString)]
-    source_path: PathBuf,
-}
+pub use crate::matches::group::{ArchivedResolvedMatchFile, ResolvedMatchFile};
 
-impl ArchivedResolvedMatchFile {
-    pub fn get_source_path(&self) -> &Path {
-        Path::new(self.source_path.as_str())
-    }
-}
-
-/// The MatchStore contains all matches that we have loaded.
-///
-/// We have a hash map of all match files, indexed by their file system path.
-#[derive(Archive, Serialize, Deserialize)]
+/// The MatchStore contains all matches that we have loaded, indexed directly by
+/// [`MatchFileRef`], which is itself a dense index into the same `Vec`.
+#[derive(Archive, Serialize, Deserialize, serde::Serialize, serde::Deserialize)]
 #[archive(check_bytes)]
 pub struct MatchStore {
-    // TODO: This HashMap should be a Vec, with the index being the MatchFileRef.
-    indexed_files: HashMap<MatchFileRef, ResolvedMatchFile>,
+    indexed_files: FileStore<ResolvedMatchFile>,
 }
 
 impl MatchStore {
+    /// Load `paths` and everything they (transitively) import.
+    ///
+    /// `excludes` narrows the result the same way a sparse/narrow checkout would: a match file
+    /// whose absolute path matches one of these globs is skipped even if it's only reached
+    /// through an import, not just when it's one of `paths` itself (see
+    /// [`path::build_narrow_exclude_set`] for the pattern-anchoring rules). Pass an empty slice
+    /// to load everything `paths` reaches, same as before this parameter existed.
     pub fn load(
         paths: &[PathBuf],
+        excludes: &[String],
     ) -> (Self, HashMap<PathBuf, MatchFileRef>, Vec<NonFatalErrorSet>) {
         let mut non_fatal_error_sets = Vec::new();
         let mut match_file_map = HashMap::new();
         let mut loaded_files = MatchFileStore::new();
 
+        let (exclude_set, exclude_errors) = path::build_narrow_exclude_set(excludes);
+        if !exclude_errors.is_empty() {
+            non_fatal_error_sets.push(NonFatalErrorSet::new(
+                Path::new("<excludes>"),
+                exclude_errors,
+            ));
+        }
+
         // Because match files can import other match files,
         // we have to load them recursively starting from the top-level ones.
         load_match_files_recursively(
             &mut loaded_files,
             &mut match_file_map,
             paths,
+            &exclude_set,
             &mut non_fatal_error_sets,
         );
 
-        let mut indexed_files = HashMap::new();
+        let (indexed_files, resolve_error_sets) = loaded_files.resolve(&match_file_map);
+        non_fatal_error_sets.extend(resolve_error_sets);
 
-        for (path, match_file) in loaded_files.into_enumerate() {
-            let imports = match_file
-                .import_paths
-                .iter()
-                .filter_map(|path| match_file_map.get(path).copied())
-                .collect::<_>();
+        (Self { indexed_files }, match_file_map, non_fatal_error_sets)
+    }
 
-            let indexed_file = ResolvedMatchFile {
-                imports,
-                content: match_file.content,
-                source_path: match_file.source_path,
-            };
-            indexed_files.insert(path, indexed_file);
+    /// Same as [`MatchStore::load`], but instead of an explicit list of top-level files, takes a
+    /// `base_dir` plus `include`/`exclude` glob lists and discovers the top-level files itself:
+    /// `base_dir` is walked once, keeping every file matched by `include` and discarding anything
+    /// matched by `exclude` as the walk proceeds (see [`path::scan_directory`]). The discovered
+    /// files are then fed into the same recursive import resolution as `load`, reusing `exclude`
+    /// there too so it also applies to files only reached through an import rather than found by
+    /// the initial directory scan.
+    pub fn load_from_directory(
+        base_dir: &Path,
+        include: &[String],
+        exclude: &[String],
+    ) -> (Self, HashMap<PathBuf, MatchFileRef>, Vec<NonFatalErrorSet>) {
+        let (paths, scan_errors) = path::scan_directory(base_dir, include, exclude);
+
+        let (match_store, match_file_map, mut non_fatal_error_sets) = Self::load(&paths, exclude);
+
+        if !scan_errors.is_empty() {
+            non_fatal_error_sets.push(NonFatalErrorSet::new(base_dir, scan_errors));
         }
 
-        (Self { indexed_files }, match_file_map, non_fatal_error_sets)
+        (match_store, match_file_map, non_fatal_error_sets)
     }
 
     /// Returns all matches and global vars that were defined in the given paths.
     ///
     /// This function recursively loads all the matches in the given paths and their imports.
+    ///
+    /// Each path in `paths` is treated as the root of its own import layering: a trigger or
+    /// global var defined (or unset) by a file always wins over the same key inherited from one
+    /// of *its* imports, but two unrelated roots passed in together don't shadow each other.
     pub fn collect_matches_and_global_vars<'store>(
         &'store self,
         paths: &[MatchFileRef],
     ) -> MatchesAndGlobalVars<'store> {
-        let mut visited_paths = HashSet::new();
+        let mut visited_paths = vec![false; self.indexed_files.len()];
         let mut visited_trigger_matches = Vec::new();
         let mut visited_regex_matches = Vec::new();
         let mut visited_global_vars = Vec::new();
 
-        query_matches_for_paths(
-            &self.indexed_files,
-            &mut visited_paths,
-            &mut visited_trigger_matches,
-            &mut visited_regex_matches,
-            &mut visited_global_vars,
-            paths,
-        );
+        for path in paths {
+            let mut claimed_triggers = HashSet::new();
+            let mut claimed_global_vars = HashSet::new();
+
+            let root = ImportRef {
+                target: *path,
+                filter: None,
+            };
+            query_matches_for_paths(
+                &self.indexed_files,
+                &mut visited_paths,
+                &mut visited_trigger_matches,
+                &mut visited_regex_matches,
+                &mut visited_global_vars,
+                &mut claimed_triggers,
+                &mut claimed_global_vars,
+                std::slice::from_ref(&root),
+            );
+        }
 
         MatchesAndGlobalVars {
             trigger_matches: visited_trigger_matches.into_iter().collect(),
@@ -122,71 +149,284 @@ impl MatchStore {
         }
     }
 
+    /// Same as [`MatchStore::collect_matches_and_global_vars`], but instead of an explicit list
+    /// of [`MatchFileRef`]s, selects the root paths by matching `patterns` against every loaded
+    /// file's path (e.g. activate every file under `apps/terminal/**` for a given window class).
+    /// All patterns are compiled into a single [`GlobSet`] and matched in one pass over
+    /// `loaded_paths()`, so this stays cheap even with hundreds of loaded files. An invalid
+    /// pattern simply matches nothing rather than erroring out, since this is an ad hoc query
+    /// rather than something loaded once up front.
+    pub fn collect_matches_for_patterns<'store>(
+        &'store self,
+        patterns: &[String],
+    ) -> MatchesAndGlobalVars<'store> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in patterns {
+            if let Ok(glob) = Glob::new(pattern) {
+                builder.add(glob);
+            }
+        }
+        let glob_set = builder.build().unwrap_or_else(|_| GlobSet::empty());
+
+        let matched_paths: Vec<MatchFileRef> = self
+            .indexed_files
+            .iter()
+            .zip(self.indexed_files.keys())
+            .filter_map(|(file, file_ref)| {
+                glob_set.is_match(file.source_path()).then_some(file_ref)
+            })
+            .collect();
+
+        self.collect_matches_and_global_vars(&matched_paths)
+    }
+
     pub fn loaded_paths(&self) -> Vec<MatchFileRef> {
-        self.indexed_files.keys().copied().collect()
+        self.indexed_files.keys().collect()
     }
-}
 
-impl ArchivedMatchStore {
+    /// Every loaded match file in a deterministic order with each file's imports appearing
+    /// before it, for a caller (e.g. an incremental reload) that needs match precedence resolved
+    /// reproducibly rather than in arbitrary load order. See
+    /// [`FileStore::topological_order`](crate::matches::group::FileStore::topological_order).
+    pub fn topological_order(&self) -> Vec<MatchFileRef> {
+        self.indexed_files.topological_order()
+    }
+
+    /// The source path of every loaded match file, in the same order as [`Self::loaded_paths`].
+    /// Used to re-derive the root paths to pass back into [`MatchStore::load`] for an
+    /// incremental reload, the live counterpart to [`ArchivedMatchStore::get_source_paths`].
     pub fn get_source_paths(&self) -> impl Iterator<Item = &Path> {
         self.indexed_files
             .iter()
-            .map(|(_, file)| file.get_source_path())
+            .map(ResolvedMatchFile::source_path)
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &ResolvedMatchFile> {
+        self.indexed_files.iter()
+    }
+}
+
+impl ArchivedMatchStore {
+    pub fn get_source_paths(&self) -> impl Iterator<Item = &Path> {
+        self.indexed_files.get_source_paths()
+    }
+
+    /// The archived counterpart of [`MatchStore::loaded_paths`]/[`FileStore::get`], for a caller
+    /// running trigger lookups directly against an [`ArchivedMatchStoreHandle`] instead of a
+    /// fully-deserialized `MatchStore`.
+    #[inline]
+    pub fn get(&self, idx: MatchFileRef) -> &ArchivedResolvedMatchFile {
+        self.indexed_files.get(idx)
+    }
+
+    pub fn loaded_paths(&self) -> impl Iterator<Item = MatchFileRef> + '_ {
+        self.indexed_files.iter_enumerate().map(|(idx, _)| idx)
+    }
+}
+
+/// A zero-copy, mmapped view of a [`MatchStore`] that was serialized to disk with rkyv, for a
+/// caller that wants to run trigger lookups directly off the archive's bytes instead of paying
+/// for [`MatchStore`]'s full deserialize into owned `Vec`s -- a meaningful startup-latency win
+/// once a rule set gets large. Dropping this unmaps the file.
+pub struct ArchivedMatchStoreHandle {
+    mmap: memmap2::Mmap,
+}
+
+impl ArchivedMatchStoreHandle {
+    /// `mmap` `path` and validate it as an archived [`MatchStore`] with `rkyv::check_archived_root`,
+    /// returning `None` on any miss: the file doesn't exist, or its bytes don't pass `check_bytes`
+    /// validation (e.g. it was written by an incompatible version, or is simply corrupt).
+    pub fn open(path: &Path) -> Option<Self> {
+        let file = std::fs::File::open(path).ok()?;
+        // Safety: the caller is expected to treat the cache file as immutable once written (see
+        // e.g. `Configuration`'s cache, which always replaces it via a rename rather than
+        // mutating it in place).
+        let mmap = unsafe { memmap2::Mmap::map(&file) }.ok()?;
+        rkyv::check_archived_root::<MatchStore>(&mmap).ok()?;
+        Some(Self { mmap })
+    }
+
+    pub fn get(&self) -> &ArchivedMatchStore {
+        // Never panics: `open` already ran `check_bytes` over these exact same bytes.
+        rkyv::check_archived_root::<MatchStore>(&self.mmap).expect("validated in `open`")
+    }
+}
+
+/// Whether a trigger match imported through a selective `from ... import [...]` entry should be
+/// pulled in, i.e. whether any of its triggers is named in the filter. `filter: None` means the
+/// import is unfiltered ("import everything"), so everything passes.
+fn trigger_passes_filter(triggers: &[CompactString], filter: Option<&[CompactString]>) -> bool {
+    match filter {
+        None => true,
+        Some(names) => triggers.iter().any(|t| names.contains(t)),
+    }
+}
+
+/// Same as [`trigger_passes_filter`], but for a single global var name.
+fn var_passes_filter(name: &str, filter: Option<&[CompactString]>) -> bool {
+    match filter {
+        None => true,
+        Some(names) => names.iter().any(|n| n == name),
     }
 }
 
+/// Walks the import graph reachable from `imports` with an explicit stack instead of recursion,
+/// so a deeply-nested (if not circular) import chain can't blow the call stack. The stack is fed
+/// depth-first in the same order the old recursive version visited nodes, since the claiming
+/// logic below depends on *when* a file is visited relative to its siblings and imports.
+#[allow(clippy::too_many_arguments)]
 fn query_matches_for_paths<'store>(
-    indexed_files: &'store HashMap<MatchFileRef, ResolvedMatchFile>,
-    visited_paths: &mut HashSet<MatchFileRef>,
+    indexed_files: &'store FileStore<ResolvedMatchFile>,
+    visited_paths: &mut [bool],
     visited_trigger_matches: &mut Vec<&'store TriggerMatch>,
     visited_regex_matches: &mut Vec<&'store RegexMatch>,
     visited_global_vars: &mut Vec<&'store Variable>,
-    paths: &[MatchFileRef],
+    claimed_triggers: &mut HashSet<&'store str>,
+    claimed_global_vars: &mut HashSet<&'store str>,
+    imports: &'store [ImportRef],
 ) {
-    for path in paths {
-        if visited_paths.contains(path) {
+    let mut stack: Vec<&'store ImportRef> = imports.iter().rev().collect();
+
+    while let Some(import) = stack.pop() {
+        let path = import.target;
+        if visited_paths[path.index()] {
             continue; // Already visited
         }
 
-        visited_paths.insert(*path);
+        visited_paths[path.index()] = true;
+
+        let file = indexed_files.get(path);
+        let filter = import.filter.as_deref();
 
-        let file = indexed_files.get(path).unwrap();
-        visited_trigger_matches.extend(file.content.trigger_matches.iter());
+        // A trigger or global var already claimed by an importing file (higher layer) shadows
+        // the same key here; anything not claimed yet is ours to keep and to claim in turn, so
+        // that *this* file's imports can't reintroduce it either. A selective import additionally
+        // only pulls in the triggers/vars named in its filter. A match with `is_override` set is
+        // the one exception: it steals its trigger(s) back from whichever match already claimed
+        // them, letting a deeper import force its definition to win.
+        for trigger_match in &file.content.trigger_matches {
+            let already_claimed = trigger_match
+                .triggers
+                .iter()
+                .any(|trigger| claimed_triggers.contains(trigger.as_str()));
+            if already_claimed && !trigger_match.is_override {
+                continue;
+            }
+            if !trigger_passes_filter(&trigger_match.triggers, filter) {
+                continue;
+            }
+            if already_claimed {
+                visited_trigger_matches.retain(|visited: &&TriggerMatch| {
+                    !visited
+                        .triggers
+                        .iter()
+                        .any(|trigger| trigger_match.triggers.contains(trigger))
+                });
+            }
+            visited_trigger_matches.push(trigger_match);
+        }
         visited_regex_matches.extend(file.content.regex_matches.iter());
-        visited_global_vars.extend(file.content.global_vars.iter());
-
-        query_matches_for_paths(
-            indexed_files,
-            visited_paths,
-            visited_trigger_matches,
-            visited_regex_matches,
-            visited_global_vars,
-            &file.imports,
-        );
+        for var in &file.content.global_vars {
+            if claimed_global_vars.contains(var.name.as_str()) {
+                continue;
+            }
+            if !var_passes_filter(&var.name, filter) {
+                continue;
+            }
+            visited_global_vars.push(var);
+        }
+
+        for trigger_match in &file.content.trigger_matches {
+            if !trigger_passes_filter(&trigger_match.triggers, filter) {
+                continue;
+            }
+            for trigger in &trigger_match.triggers {
+                claimed_triggers.insert(trigger.as_str());
+            }
+        }
+        for unset_trigger in &file.content.unset_triggers {
+            claimed_triggers.insert(unset_trigger.as_str());
+        }
+        for var in &file.content.global_vars {
+            if !var_passes_filter(&var.name, filter) {
+                continue;
+            }
+            claimed_global_vars.insert(var.name.as_str());
+        }
+
+        // Push in reverse so the first import is popped (and thus fully explored) next, matching
+        // the depth-first, first-import-first order of the original recursive walk.
+        stack.extend(file.imports.iter().rev());
     }
 }
 
-/// Load the files in the given paths and their imports recursively.
+/// Load the given paths and their imports, walking the import graph with an explicit stack
+/// (instead of recursion) so a deeply-nested import chain can't blow the call stack.
+///
+/// Each stack entry carries the `ancestors` depth it was pushed at, so that popping back to a
+/// shallower entry can truncate `ancestors` down to match — the explicit-stack equivalent of
+/// returning from a recursive call. Before descending into an import, we check whether it's
+/// already on `ancestors`; if so, it closes an import cycle, which we report (with the full
+/// chain, e.g. `base.yml -> _another.yml -> sub.yml -> _another.yml`) as a non-fatal error rather
+/// than looping forever or silently dropping the file. A path that was already loaded via some
+/// *other* branch of the import graph (a diamond, not a cycle) is still skipped silently, same as
+/// before.
 ///
-/// This function fills up the `groups` HashMap with the loaded match groups.
+/// This function fills up `loaded_files` and `match_file_map` with the loaded match groups.
 fn load_match_files_recursively(
     loaded_files: &mut MatchFileStore,
     match_file_map: &mut HashMap<PathBuf, MatchFileRef>,
     paths: &[PathBuf],
+    exclude_set: &GlobSet,
     non_fatal_error_sets: &mut Vec<NonFatalErrorSet>,
 ) {
-    for match_file_path in paths {
-        if match_file_map.contains_key(match_file_path) {
+    let mut stack: Vec<(PathBuf, usize)> = paths.iter().rev().cloned().map(|p| (p, 0)).collect();
+    let mut ancestors: Vec<PathBuf> = Vec::new();
+
+    while let Some((match_file_path, depth)) = stack.pop() {
+        ancestors.truncate(depth);
+
+        if exclude_set.is_match(&match_file_path) {
+            non_fatal_error_sets.push(NonFatalErrorSet::new(
+                &match_file_path,
+                vec![ErrorRecord::warn(anyhow!(
+                    "excluded by narrow/exclude pattern, skipping match file"
+                ))],
+            ));
+            continue;
+        }
+
+        if ancestors.contains(&match_file_path) {
+            let chain = ancestors
+                .iter()
+                .chain(std::iter::once(&match_file_path))
+                .map(|p| p.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            non_fatal_error_sets.push(NonFatalErrorSet::single_error(
+                &match_file_path,
+                anyhow!("circular import detected: {chain}"),
+            ));
+            continue;
+        }
+
+        if match_file_map.contains_key(&match_file_path) {
             continue; // Already loaded
         }
 
-        let file_path = match_file_path.to_owned();
-        match loader::load_match_file(file_path)
-            .with_context(|| format!("unable to load match group {match_file_path:?}"))
-        {
+        let url = match_file_path.to_string_lossy();
+        let load_result = if path::is_remote_url(&url) {
+            fetch_and_load_remote_match_file(&url)
+        } else {
+            loader::load_match_file(match_file_path.to_owned())
+        }
+        .with_context(|| format!("unable to load match group {match_file_path:?}"));
+
+        match load_result {
             Ok((group, non_fatal_error_set)) => {
                 // TODO: Restructure code to avoid cloning here.
-                let imports = &group.import_paths.clone();
+                let imports: Vec<PathBuf> =
+                    group.import_paths.iter().map(|i| i.path.clone()).collect();
                 let file_ref = loaded_files.add(group);
                 match_file_map.insert(match_file_path.clone(), file_ref);
 
@@ -194,20 +434,83 @@ fn load_match_files_recursively(
                     non_fatal_error_sets.push(non_fatal_error_set);
                 }
 
-                load_match_files_recursively(
-                    loaded_files,
-                    match_file_map,
-                    imports,
-                    non_fatal_error_sets,
-                );
+                ancestors.push(match_file_path);
+                let child_depth = ancestors.len();
+                // Push in reverse so the first import is popped (and thus fully explored) next,
+                // matching the depth-first, first-import-first order of the original recursive walk.
+                stack.extend(imports.into_iter().rev().map(|p| (p, child_depth)));
             }
             Err(err) => {
-                non_fatal_error_sets.push(NonFatalErrorSet::single_error(match_file_path, err));
+                non_fatal_error_sets.push(NonFatalErrorSet::single_error(&match_file_path, err));
             }
         }
     }
 }
 
+/// Fetch a remote match file over http(s), write it into a content-addressed cache directory,
+/// and parse the cached copy through the same [`loader::load_match_file`] pipeline a local file
+/// would go through.
+///
+/// The returned [`LoadedMatchFile::source_path`] is set to the original `url` rather than the
+/// cache path, so provenance (and error messages) still point at the import the user wrote.
+fn fetch_and_load_remote_match_file(
+    url: &str,
+) -> anyhow::Result<(LoadedMatchFile, Option<NonFatalErrorSet>)> {
+    let body = ureq::get(url)
+        .call()
+        .with_context(|| format!("failed to fetch remote match file: {url}"))?
+        .into_string()
+        .with_context(|| format!("failed to read response body for remote match file: {url}"))?;
+
+    let cache_path = write_to_content_addressed_cache(url, &body)
+        .with_context(|| format!("failed to cache remote match file: {url}"))?;
+
+    let (mut group, non_fatal_error_set) = loader::load_match_file(cache_path)?;
+    group.source_path = PathBuf::from(url);
+
+    Ok((group, non_fatal_error_set))
+}
+
+/// Write `content` into the remote-match-file cache, keyed by a cryptographic digest of its
+/// content so the same bytes always land at the same path, and return the cache path.
+///
+/// Lives under the per-user runtime cache directory (the same `dirs::cache_dir()` every other
+/// cache in this codebase uses -- see `shinran_lib`/`shinran_backend`'s `path::resolve_paths`),
+/// not a shared world-writable directory like `std::env::temp_dir()`: another local user must
+/// not be able to plant a file at a predictable path and have it substituted for a legitimate
+/// fetch. The digest itself is SHA-256 rather than `DefaultHasher` (a fast, non-cryptographic
+/// 64-bit hash never meant to resist a deliberately crafted collision) so "same path" really
+/// does mean "same content".
+fn write_to_content_addressed_cache(url: &str, content: &str) -> std::io::Result<PathBuf> {
+    use sha2::{Digest, Sha256};
+
+    let digest: [u8; 32] = Sha256::digest(content.as_bytes()).into();
+    let digest_hex: String = digest.iter().map(|byte| format!("{byte:02x}")).collect();
+
+    let extension = Path::new(url)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("yml");
+
+    let cache_dir = dirs::cache_dir()
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "no per-user cache directory is available",
+            )
+        })?
+        .join("shinran")
+        .join("remote-imports");
+    std::fs::create_dir_all(&cache_dir)?;
+
+    let cache_path = cache_dir.join(format!("{digest_hex}.{extension}"));
+    if !cache_path.exists() {
+        std::fs::write(&cache_path, content)?;
+    }
+
+    Ok(cache_path)
+}
+
 #[cfg(test)]
 mod tests {
     use shinran_helpers::use_test_directory;
@@ -304,14 +607,13 @@ mod tests {
             .unwrap();
 
             let (match_store, file_map, non_fatal_error_sets) =
-                MatchStore::load(&[base_file.clone()]);
+                MatchStore::load(&[base_file.clone()], &[]);
             assert_eq!(non_fatal_error_sets.len(), 0);
             assert_eq!(match_store.indexed_files.len(), 3);
 
             let base_group = &match_store
                 .indexed_files
-                .get(file_map.get(&base_file).unwrap())
-                .unwrap()
+                .get(*file_map.get(&base_file).unwrap())
                 .content
                 .trigger_matches;
 
@@ -319,8 +621,7 @@ mod tests {
 
             let another_group = &match_store
                 .indexed_files
-                .get(file_map.get(&another_file).unwrap())
-                .unwrap()
+                .get(*file_map.get(&another_file).unwrap())
                 .content
                 .trigger_matches;
             assert_eq!(
@@ -330,14 +631,199 @@ mod tests {
 
             let sub_group = &match_store
                 .indexed_files
-                .get(file_map.get(&sub_file).unwrap())
-                .unwrap()
+                .get(*file_map.get(&sub_file).unwrap())
                 .content
                 .trigger_matches;
             assert_eq!(sub_group, &create_matches(&[("hello", "world3")]));
         });
     }
 
+    #[test]
+    fn match_store_load_excludes_a_file_reached_only_through_an_import() {
+        use_test_directory(|_, match_dir, _| {
+            let sub_dir = match_dir.join("sub");
+            create_dir_all(&sub_dir).unwrap();
+
+            let base_file = match_dir.join("base.yml");
+            std::fs::write(
+                &base_file,
+                r#"
+      imports:
+        - "sub/legacy.yml"
+
+      matches:
+        - trigger: "hello"
+          replace: "world"
+      "#,
+            )
+            .unwrap();
+
+            let legacy_file = sub_dir.join("legacy.yml");
+            std::fs::write(
+                &legacy_file,
+                r#"
+      matches:
+        - trigger: "old"
+          replace: "stuff"
+      "#,
+            )
+            .unwrap();
+
+            let (match_store, file_map, non_fatal_error_sets) =
+                MatchStore::load(&[base_file.clone()], &["legacy.yml".to_string()]);
+
+            // `legacy.yml` is only reachable through an import, not one of the top-level
+            // `paths`, yet the exclude pattern still keeps it out.
+            assert_eq!(match_store.indexed_files.len(), 1);
+            assert!(!file_map.contains_key(&legacy_file));
+            // One set for the excluded file itself, one for `base.yml`'s now-unresolved import.
+            assert_eq!(non_fatal_error_sets.len(), 2);
+            assert_eq!(non_fatal_error_sets[0].errors.len(), 1);
+
+            let base_group = &match_store
+                .indexed_files
+                .get(*file_map.get(&base_file).unwrap())
+                .content
+                .trigger_matches;
+            assert_eq!(base_group, &create_matches(&[("hello", "world")]));
+        });
+    }
+
+    #[test]
+    fn match_store_load_from_directory_applies_include_and_exclude() {
+        use_test_directory(|_, match_dir, _| {
+            let drafts_dir = match_dir.join("drafts");
+            create_dir_all(&drafts_dir).unwrap();
+
+            let kept_file = match_dir.join("base.yml");
+            std::fs::write(
+                &kept_file,
+                r#"
+      matches:
+        - trigger: "hello"
+          replace: "world"
+      "#,
+            )
+            .unwrap();
+
+            let underscore_file = match_dir.join("_private.yml");
+            std::fs::write(
+                &underscore_file,
+                r#"
+      matches:
+        - trigger: "skip"
+          replace: "me"
+      "#,
+            )
+            .unwrap();
+
+            let draft_file = drafts_dir.join("draft.yml");
+            std::fs::write(
+                &draft_file,
+                r#"
+      matches:
+        - trigger: "draft"
+          replace: "me"
+      "#,
+            )
+            .unwrap();
+
+            let (match_store, file_map, non_fatal_error_sets) = MatchStore::load_from_directory(
+                &match_dir,
+                &["**/*.yml".to_string()],
+                &["_*.yml".to_string(), "drafts/**".to_string()],
+            );
+            assert_eq!(non_fatal_error_sets.len(), 0);
+            assert_eq!(match_store.indexed_files.len(), 1);
+            assert!(file_map.contains_key(&kept_file));
+            assert!(!file_map.contains_key(&underscore_file));
+            assert!(!file_map.contains_key(&draft_file));
+        });
+    }
+
+    #[test]
+    fn archived_match_store_handle_open_returns_none_for_a_missing_file() {
+        use_test_directory(|base, _, _| {
+            assert!(ArchivedMatchStoreHandle::open(&base.join("no-such.rkyv")).is_none());
+        });
+    }
+
+    #[test]
+    fn match_store_warns_about_an_import_that_never_resolves() {
+        use_test_directory(|_, match_dir, _| {
+            let base_file = match_dir.join("base.yml");
+            std::fs::write(
+                &base_file,
+                r#"
+      imports:
+        - "missing.yml"
+
+      matches:
+        - trigger: "hello"
+          replace: "world"
+      "#,
+            )
+            .unwrap();
+
+            let (match_store, file_map, non_fatal_error_sets) =
+                MatchStore::load(&[base_file.clone()], &[]);
+
+            // The file itself still loads fine; only its dangling import is reported.
+            assert_eq!(match_store.indexed_files.len(), 1);
+            assert_eq!(non_fatal_error_sets.len(), 1);
+            assert_eq!(non_fatal_error_sets[0].errors.len(), 1);
+
+            let base_group = &match_store
+                .indexed_files
+                .get(*file_map.get(&base_file).unwrap())
+                .content
+                .trigger_matches;
+            assert_eq!(base_group, &create_matches(&[("hello", "world")]));
+        });
+    }
+
+    #[test]
+    fn match_store_topological_order_puts_imports_before_importers() {
+        use_test_directory(|_, match_dir, _| {
+            let base_file = match_dir.join("base.yml");
+            std::fs::write(
+                &base_file,
+                r#"
+      imports:
+        - "_another.yml"
+
+      matches:
+        - trigger: "hello"
+          replace: "world"
+      "#,
+            )
+            .unwrap();
+
+            let another_file = match_dir.join("_another.yml");
+            std::fs::write(
+                &another_file,
+                r#"
+      matches:
+        - trigger: "foo"
+          replace: "bar"
+      "#,
+            )
+            .unwrap();
+
+            let (match_store, file_map, non_fatal_error_sets) =
+                MatchStore::load(&[base_file.clone()], &[]);
+            assert_eq!(non_fatal_error_sets.len(), 0);
+
+            let base_ref = *file_map.get(&base_file).unwrap();
+            let another_ref = *file_map.get(&another_file).unwrap();
+
+            let order = match_store.topological_order();
+            let base_pos = order.iter().position(|&r| r == base_ref).unwrap();
+            let another_pos = order.iter().position(|&r| r == another_ref).unwrap();
+            assert!(another_pos < base_pos);
+        });
+    }
+
     #[test]
     fn match_store_handles_circular_dependency() {
         use_test_directory(|_, match_dir, _| {
@@ -388,10 +874,13 @@ mod tests {
             )
             .unwrap();
 
-            let (match_store, _, non_fatal_error_sets) = MatchStore::load(&[base_file]);
+            let (match_store, _, non_fatal_error_sets) = MatchStore::load(&[base_file], &[]);
 
+            // All three files still load successfully...
             assert_eq!(match_store.indexed_files.len(), 3);
-            assert_eq!(non_fatal_error_sets.len(), 0);
+            // ...but the cycle closing back through `_another.yml` is reported.
+            assert_eq!(non_fatal_error_sets.len(), 1);
+            assert_eq!(non_fatal_error_sets[0].errors.len(), 1);
         });
     }
 
@@ -451,7 +940,7 @@ mod tests {
             .unwrap();
 
             let (match_store, file_map, non_fatal_error_sets) =
-                MatchStore::load(&[base_file.clone()]);
+                MatchStore::load(&[base_file.clone()], &[]);
             assert_eq!(non_fatal_error_sets.len(), 0);
 
             let match_set =
@@ -465,14 +954,11 @@ mod tests {
 
             sort_matches(&mut matches);
 
+            // `base.yml`'s own "hello" shadows the same trigger inherited from both of its
+            // imports, so only one "hello" survives.
             assert_eq!(
                 matches,
-                create_matches(&[
-                    ("foo", "bar"),
-                    ("hello", "world"),
-                    ("hello", "world2"),
-                    ("hello", "world3"),
-                ])
+                create_matches(&[("foo", "bar"), ("hello", "world")])
             );
             let mut vars = match_set
                 .global_vars
@@ -544,8 +1030,11 @@ mod tests {
             .unwrap();
 
             let (match_store, file_map, non_fatal_error_sets) =
-                MatchStore::load(&[base_file.clone()]);
-            assert_eq!(non_fatal_error_sets.len(), 0);
+                MatchStore::load(&[base_file.clone()], &[]);
+            // The cycle itself (`_another.yml` importing back into `sub.yml`, which imports
+            // `_another.yml` again) is reported as a single non-fatal error set...
+            assert_eq!(non_fatal_error_sets.len(), 1);
+            assert_eq!(non_fatal_error_sets[0].errors.len(), 1);
 
             let match_set =
                 match_store.collect_matches_and_global_vars(&[*file_map.get(&base_file).unwrap()]);
@@ -556,14 +1045,12 @@ mod tests {
                 .collect::<Vec<TriggerMatch>>();
             sort_matches(&mut matches);
 
+            // ...but the load still succeeds and keeps the same shadowing as the non-circular
+            // case; the cycle just makes `_another.yml` reachable from `sub.yml` as well, which
+            // the `visited_paths` dedup already handles.
             assert_eq!(
                 matches,
-                create_matches(&[
-                    ("foo", "bar"),
-                    ("hello", "world"),
-                    ("hello", "world2"),
-                    ("hello", "world3"),
-                ])
+                create_matches(&[("foo", "bar"), ("hello", "world")])
             );
 
             let mut vars = match_set
@@ -630,7 +1117,7 @@ mod tests {
             .unwrap();
 
             let paths = [base_file, sub_file];
-            let (match_store, file_map, non_fatal_error_sets) = MatchStore::load(&paths);
+            let (match_store, file_map, non_fatal_error_sets) = MatchStore::load(&paths, &[]);
             assert_eq!(non_fatal_error_sets.len(), 0);
 
             let match_set = match_store.collect_matches_and_global_vars(&[
@@ -644,14 +1131,11 @@ mod tests {
                 .collect::<Vec<TriggerMatch>>();
             sort_matches(&mut matches);
 
+            // `base.yml` shadows `_another.yml`'s "hello", but `sub.yml` is queried as its own,
+            // unrelated root, so its "hello" isn't shadowed by `base.yml`'s.
             assert_eq!(
                 matches,
-                create_matches(&[
-                    ("foo", "bar"),
-                    ("hello", "world"),
-                    ("hello", "world2"),
-                    ("hello", "world3"),
-                ])
+                create_matches(&[("foo", "bar"), ("hello", "world"), ("hello", "world3")])
             );
 
             let mut vars = match_set
@@ -721,7 +1205,7 @@ mod tests {
             .unwrap();
 
             let (match_store, file_map, non_fatal_error_sets) =
-                MatchStore::load(&[base_file.clone()]);
+                MatchStore::load(&[base_file.clone()], &[]);
             assert_eq!(non_fatal_error_sets.len(), 0);
 
             let match_set = match_store.collect_matches_and_global_vars(&[
@@ -735,14 +1219,12 @@ mod tests {
                 .collect::<Vec<TriggerMatch>>();
             sort_matches(&mut matches);
 
+            // `sub.yml` is reached through `base.yml`'s import chain first, where its "hello" is
+            // shadowed by `base.yml`'s own, and it's then skipped entirely as its own root since
+            // `visited_paths` already marked it visited.
             assert_eq!(
                 matches,
-                create_matches(&[
-                    ("foo", "bar"),
-                    ("hello", "world"),
-                    ("hello", "world2"),
-                    ("hello", "world3"), // This appears only once, though it appears 2 times
-                ])
+                create_matches(&[("foo", "bar"), ("hello", "world")])
             );
 
             let mut vars = match_set
@@ -756,5 +1238,279 @@ mod tests {
         });
     }
 
+    #[test]
+    fn match_store_query_unset_triggers_drops_inherited_trigger() {
+        use_test_directory(|_, match_dir, _| {
+            let base_file = match_dir.join("base.yml");
+            std::fs::write(
+                &base_file,
+                r#"
+      imports:
+        - "_another.yml"
+
+      unset_triggers:
+        - "foo"
+
+      matches:
+        - trigger: "hello"
+          replace: "world"
+      "#,
+            )
+            .unwrap();
+
+            let another_file = match_dir.join("_another.yml");
+            std::fs::write(
+                another_file,
+                r#"
+      matches:
+        - trigger: "foo"
+          replace: "bar"
+        - trigger: "baz"
+          replace: "qux"
+      "#,
+            )
+            .unwrap();
+
+            let (match_store, file_map, non_fatal_error_sets) =
+                MatchStore::load(&[base_file.clone()], &[]);
+            assert_eq!(non_fatal_error_sets.len(), 0);
+
+            let match_set =
+                match_store.collect_matches_and_global_vars(&[*file_map.get(&base_file).unwrap()]);
+            let mut matches = match_set
+                .trigger_matches
+                .into_iter()
+                .map(|m| m.clone())
+                .collect::<Vec<TriggerMatch>>();
+            sort_matches(&mut matches);
+
+            // "foo" is unset by `base.yml`, so it's dropped even though `_another.yml` defines it.
+            assert_eq!(
+                matches,
+                create_matches(&[("baz", "qux"), ("hello", "world")])
+            );
+        });
+    }
+
+    #[test]
+    fn match_store_query_override_wins_against_an_importing_file() {
+        use_test_directory(|_, match_dir, _| {
+            let base_file = match_dir.join("base.yml");
+            std::fs::write(
+                &base_file,
+                r#"
+      imports:
+        - "_another.yml"
+
+      matches:
+        - trigger: "hello"
+          replace: "world"
+      "#,
+            )
+            .unwrap();
+
+            let another_file = match_dir.join("_another.yml");
+            std::fs::write(
+                another_file,
+                r#"
+      matches:
+        - trigger: "hello"
+          replace: "overridden"
+          override: true
+        - trigger: "baz"
+          replace: "qux"
+      "#,
+            )
+            .unwrap();
+
+            let (match_store, file_map, non_fatal_error_sets) =
+                MatchStore::load(&[base_file.clone()], &[]);
+            assert_eq!(non_fatal_error_sets.len(), 0);
+
+            let match_set =
+                match_store.collect_matches_and_global_vars(&[*file_map.get(&base_file).unwrap()]);
+            let mut matches = match_set
+                .trigger_matches
+                .into_iter()
+                .map(|m| m.clone())
+                .collect::<Vec<TriggerMatch>>();
+            sort_matches(&mut matches);
+
+            // `_another.yml`'s "hello" carries `override: true`, so it wins despite `base.yml`
+            // (the importing, nearer-to-root file) already having claimed that trigger.
+            assert_eq!(
+                matches,
+                create_matches(&[("baz", "qux"), ("hello", "overridden")])
+            );
+        });
+    }
+
+    #[test]
+    fn match_store_query_selective_import_only_pulls_named_triggers_and_vars() {
+        use_test_directory(|_, match_dir, _| {
+            let base_file = match_dir.join("base.yml");
+            std::fs::write(
+                &base_file,
+                r#"
+      imports:
+        - from: "emojis.yml"
+          import: ["smile"]
+
+      matches:
+        - trigger: "hello"
+          replace: "world"
+      "#,
+            )
+            .unwrap();
+
+            let emojis_file = match_dir.join("emojis.yml");
+            std::fs::write(
+                &emojis_file,
+                r#"
+      global_vars:
+        - name: var1
+          type: test
+        - name: var2
+          type: test
+
+      matches:
+        - trigger: "smile"
+          replace: ":)"
+        - trigger: "heart"
+          replace: "<3"
+      "#,
+            )
+            .unwrap();
+
+            let (match_store, file_map, non_fatal_error_sets) =
+                MatchStore::load(&[base_file.clone()], &[]);
+            assert_eq!(non_fatal_error_sets.len(), 0);
+
+            let match_set =
+                match_store.collect_matches_and_global_vars(&[*file_map.get(&base_file).unwrap()]);
+            let mut matches = match_set
+                .trigger_matches
+                .into_iter()
+                .map(|m| m.clone())
+                .collect::<Vec<TriggerMatch>>();
+            sort_matches(&mut matches);
+
+            // Only "smile" was named in the `import` filter, so "heart" is left out even though
+            // `emojis.yml` defines it.
+            assert_eq!(
+                matches,
+                create_matches(&[("hello", "world"), ("smile", ":)")])
+            );
+
+            // No global var was named, so none of them are pulled in either.
+            assert_eq!(match_set.global_vars.len(), 0);
+        });
+    }
+
+    #[test]
+    fn match_store_query_selective_import_filter_does_not_apply_once_already_visited() {
+        use_test_directory(|_, match_dir, _| {
+            let base_file = match_dir.join("base.yml");
+            std::fs::write(
+                &base_file,
+                r#"
+      imports:
+        - from: "emojis.yml"
+          import: ["heart"]
+
+      matches:
+        - trigger: "hello"
+          replace: "world"
+      "#,
+            )
+            .unwrap();
+
+            let emojis_file = match_dir.join("emojis.yml");
+            std::fs::write(
+                &emojis_file,
+                r#"
+      matches:
+        - trigger: "smile"
+          replace: ":)"
+        - trigger: "heart"
+          replace: "<3"
+      "#,
+            )
+            .unwrap();
+
+            let (match_store, file_map, non_fatal_error_sets) =
+                MatchStore::load(&[base_file.clone(), emojis_file.clone()], &[]);
+            assert_eq!(non_fatal_error_sets.len(), 0);
+
+            // `emojis.yml` is queried as its own root *before* `base.yml`'s selective import of
+            // it, so it's visited unfiltered first and `base.yml`'s filter never applies.
+            let match_set = match_store.collect_matches_and_global_vars(&[
+                *file_map.get(&emojis_file).unwrap(),
+                *file_map.get(&base_file).unwrap(),
+            ]);
+            let mut matches = match_set
+                .trigger_matches
+                .into_iter()
+                .map(|m| m.clone())
+                .collect::<Vec<TriggerMatch>>();
+            sort_matches(&mut matches);
+
+            assert_eq!(
+                matches,
+                create_matches(&[("heart", "<3"), ("hello", "world"), ("smile", ":)")])
+            );
+        });
+    }
+
+    #[test]
+    fn match_store_collect_matches_for_patterns_selects_by_glob() {
+        use_test_directory(|_, match_dir, _| {
+            let terminal_dir = match_dir.join("apps").join("terminal");
+            create_dir_all(&terminal_dir).unwrap();
+
+            let terminal_file = terminal_dir.join("shortcuts.yml");
+            std::fs::write(
+                &terminal_file,
+                r#"
+      matches:
+        - trigger: "ls"
+          replace: "list"
+      "#,
+            )
+            .unwrap();
+
+            let browser_dir = match_dir.join("apps").join("browser");
+            create_dir_all(&browser_dir).unwrap();
+
+            let browser_file = browser_dir.join("shortcuts.yml");
+            std::fs::write(
+                &browser_file,
+                r#"
+      matches:
+        - trigger: "gh"
+          replace: "github.com"
+      "#,
+            )
+            .unwrap();
+
+            let (match_store, _, non_fatal_error_sets) =
+                MatchStore::load(&[terminal_file.clone(), browser_file.clone()], &[]);
+            assert_eq!(non_fatal_error_sets.len(), 0);
+
+            let pattern = format!("{}/**", terminal_dir.to_string_lossy());
+            let match_set = match_store.collect_matches_for_patterns(&[pattern]);
+            let mut matches = match_set
+                .trigger_matches
+                .into_iter()
+                .map(|m| m.clone())
+                .collect::<Vec<TriggerMatch>>();
+            sort_matches(&mut matches);
+
+            // Only the file under `apps/terminal/**` matches the pattern, so `browser`'s match
+            // isn't pulled in.
+            assert_eq!(matches, create_matches(&[("ls", "list")]));
+        });
+    }
+
     // TODO: add fatal and non-fatal error cases
 }
@@ -0,0 +1,146 @@
+/*
+ * This file is part of espanso.
+ *
+ * Copyright (C) 2019-2021 Federico Terzi
+ *
+ * espanso is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * espanso is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with espanso.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Installable snippet "packages": a `packages/` directory under the config base path, parallel
+//! to `config/` and `match/`, where each subdirectory ships a `manifest.yml` (name, version,
+//! optional author, its match files, and an optional default config) alongside the `.yml` match
+//! files it lists. [`discover_packages`] is called once from [`crate::load`]; every package it
+//! finds contributes its match files to [`crate::MatchStore::load`]'s root paths and is also
+//! wired into the default profile (see `ProfileStore::add_package_match_files`) so packages
+//! apply unconditionally and [`crate::validate::validate`]'s duplicate-trigger check can flag a
+//! collision between two packages (or a package and a user snippet) instead of leaving it to
+//! silent load order.
+
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::error::{ErrorRecord, NonFatalErrorSet};
+
+const MANIFEST_FILE_NAME: &str = "manifest.yml";
+
+/// The parsed content of a `packages/<name>/manifest.yml`.
+#[derive(Debug, Clone, Deserialize)]
+struct Manifest {
+    name: String,
+    version: String,
+    #[serde(default)]
+    #[allow(dead_code)] // not surfaced yet, but part of the on-disk format
+    author: Option<String>,
+    /// Paths to this package's match files, relative to the package's own directory.
+    #[serde(default)]
+    matches: Vec<String>,
+    /// An optional default config this package ships, relative to the package's own directory.
+    /// Only existence-checked for now; folding it into a profile is left to the host.
+    #[serde(default)]
+    default_config: Option<String>,
+}
+
+/// One successfully-loaded package: its identity plus the match-file paths that were folded
+/// into the `MatchStore`, so a host can enumerate (and uninstall) installed packages.
+#[derive(Debug, Clone)]
+pub struct LoadedPackage {
+    pub name: String,
+    pub version: String,
+    pub resolved_paths: Vec<PathBuf>,
+}
+
+/// Discover every `packages/<name>/manifest.yml` directly under `packages_dir`. A manifest that
+/// fails to parse, or lists a match file (or default config) that doesn't exist on disk, is
+/// reported as a [`NonFatalErrorSet`] rather than aborting the whole load; such a package is
+/// still returned with whichever of its match files did resolve. Returns an empty result,
+/// without error, if `packages_dir` doesn't exist at all — packages are entirely optional.
+pub(crate) fn discover_packages(packages_dir: &Path) -> (Vec<LoadedPackage>, Vec<NonFatalErrorSet>) {
+    let Ok(entries) = std::fs::read_dir(packages_dir) else {
+        return (Vec::new(), Vec::new());
+    };
+
+    let mut packages = Vec::new();
+    let mut non_fatal_errors = Vec::new();
+
+    for entry in entries.flatten() {
+        let package_dir = entry.path();
+        if !package_dir.is_dir() {
+            continue;
+        }
+
+        let manifest_path = package_dir.join(MANIFEST_FILE_NAME);
+        if !manifest_path.is_file() {
+            continue;
+        }
+
+        match load_one(&manifest_path, &package_dir) {
+            Ok((package, warnings)) => {
+                if !warnings.is_empty() {
+                    non_fatal_errors.push(NonFatalErrorSet::new(&manifest_path, warnings));
+                }
+                packages.push(package);
+            }
+            Err(err) => {
+                non_fatal_errors.push(NonFatalErrorSet::single_error(&manifest_path, err));
+            }
+        }
+    }
+
+    (packages, non_fatal_errors)
+}
+
+/// Parse one manifest and resolve its `matches`/`default_config` entries against `package_dir`.
+/// A missing entry is collected as a warning rather than failing the package outright; only a
+/// manifest that doesn't parse as YAML at all (or is missing `name`/`version`) is fatal to it.
+fn load_one(
+    manifest_path: &Path,
+    package_dir: &Path,
+) -> anyhow::Result<(LoadedPackage, Vec<ErrorRecord>)> {
+    let content = std::fs::read_to_string(manifest_path)?;
+    let manifest: Manifest = serde_yaml_ng::from_str(&content)?;
+
+    let mut resolved_paths = Vec::new();
+    let mut warnings = Vec::new();
+    for relative in &manifest.matches {
+        let path = package_dir.join(relative);
+        if path.is_file() {
+            resolved_paths.push(path);
+        } else {
+            warnings.push(ErrorRecord::warn(anyhow::anyhow!(
+                "package {:?} references a match file that doesn't exist: {relative}",
+                manifest.name
+            )));
+        }
+    }
+
+    if let Some(default_config) = &manifest.default_config {
+        let path = package_dir.join(default_config);
+        if !path.is_file() {
+            warnings.push(ErrorRecord::warn(anyhow::anyhow!(
+                "package {:?} references a default config that doesn't exist: {default_config}",
+                manifest.name
+            )));
+        }
+    }
+
+    Ok((
+        LoadedPackage {
+            name: manifest.name,
+            version: manifest.version,
+            resolved_paths,
+        },
+        warnings,
+    ))
+}
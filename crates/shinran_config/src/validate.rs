@@ -0,0 +1,103 @@
+/*
+ * This file is part of espanso.
+ *
+ * Copyright (C) 2019-2021 Federico Terzi
+ *
+ * espanso is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * espanso is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with espanso.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::config::{ProfileFile, ProfileStore};
+use crate::matches::store::MatchStore;
+
+/// One problem found while validating a loaded configuration.
+///
+/// Unlike the `NonFatalErrorSet`s produced while loading, a `Diagnostic` is collected without
+/// aborting the walk that found it, so a `shinran check`-style caller can print every problem
+/// from a single run instead of fixing them one at a time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub file: PathBuf,
+    pub field: String,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn new(file: impl Into<PathBuf>, field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            file: file.into(),
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+/// Walk every profile in `store` and every match file it references, collecting every problem
+/// found rather than stopping at the first one.
+pub fn validate(store: &ProfileStore, match_store: &MatchStore) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    validate_custom_profile_filters(store, &mut diagnostics);
+    validate_duplicate_triggers(match_store, &mut diagnostics);
+
+    diagnostics
+}
+
+/// A custom profile with no filter expression can never match an app, since
+/// `Filters::is_match` returns `false` when nothing was set — it would silently never apply.
+fn validate_custom_profile_filters(store: &ProfileStore, diagnostics: &mut Vec<Diagnostic>) {
+    for profile in store.custom_profiles() {
+        if !profile.filter.has_any() {
+            diagnostics.push(Diagnostic::new(
+                profile_source_path(profile),
+                "filter",
+                "this profile has no filter_title/filter_class/filter_exec/filter_expr, so it \
+                 can never be selected by active_config"
+                    .to_string(),
+            ));
+        }
+    }
+}
+
+/// The same trigger registered in more than one match file reachable from a profile is
+/// ambiguous about which replacement wins, so flag it instead of leaving it to load order.
+fn validate_duplicate_triggers(match_store: &MatchStore, diagnostics: &mut Vec<Diagnostic>) {
+    let mut seen: HashMap<&str, &Path> = HashMap::new();
+
+    for file in match_store.iter() {
+        let source_path = file.source_path();
+        for trigger_match in file.trigger_matches() {
+            for trigger in &trigger_match.triggers {
+                if let Some(first_path) = seen.insert(trigger.as_str(), source_path) {
+                    if first_path != source_path {
+                        diagnostics.push(Diagnostic::new(
+                            source_path,
+                            "trigger",
+                            format!(
+                                "trigger {trigger:?} is also defined in {}",
+                                first_path.display()
+                            ),
+                        ));
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn profile_source_path(profile: &ProfileFile) -> &Path {
+    profile.source_path.as_path()
+}
@@ -0,0 +1,225 @@
+/*
+ * This file is part of espanso.
+ *
+ * Copyright (C) 2019-2021 Federico Terzi
+ *
+ * espanso is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * espanso is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with espanso.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Filesystem watch + incremental reload layered on top of [`crate::load`], for long-running
+//! hosts (e.g. the IBus engine) that want to pick up edits without restarting. [`load_and_watch`]
+//! loads `base_path` once, then spawns a background thread watching its `config/` and `match/`
+//! directories; a burst of filesystem events (an editor writing several files in quick
+//! succession, or doing an atomic write-then-rename) is coalesced over a short debounce window
+//! before being turned into one [`ReloadEvent`].
+//!
+//! A change confined to `match/` only rebuilds the [`MatchStore`], reusing the already-resolved
+//! [`ProfileStore`] as-is: its [`MatchFileRef`](crate::matches::group::MatchFileRef)s stay valid
+//! because [`MatchStore::load`] reassigns them deterministically, in the same traversal order,
+//! for the same root paths. A change under `config/` rebuilds both, since the set of profiles,
+//! their `includes` patterns, or the root match paths those resolve to may themselves have
+//! changed. A `match/` change that introduces a file the current `MatchStore` has never seen is
+//! treated the same way: it might be a new root file some profile's `includes` glob now matches,
+//! which only re-resolving `ProfileStore`'s globs would discover, so that case also falls back to
+//! the full pipeline rather than risking a reload that silently misses it.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{mpsc as std_mpsc, Arc},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use notify::{RecursiveMode, Watcher};
+
+use crate::{
+    config::{self, ProfileStore},
+    error::NonFatalErrorSet,
+    matches::store::MatchStore,
+    LoadableConfig,
+};
+
+/// How long to wait after the last filesystem event before reloading, so a burst of editor
+/// writes collapses into a single reload instead of several in quick succession.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// A freshly reloaded configuration snapshot, emitted whenever a relevant file under `config/`
+/// or `match/` changes. Wrapped in `Arc` (rather than owned, like [`LoadableConfig`]) since the
+/// watcher thread keeps its own reference to the current snapshot to decide, on the next change,
+/// whether it can reuse the `ProfileStore` unchanged.
+pub struct ReloadEvent {
+    pub profile_store: Arc<ProfileStore>,
+    pub match_store: Arc<MatchStore>,
+    pub non_fatal_errors: Vec<NonFatalErrorSet>,
+}
+
+/// Load `base_path` once (same as [`crate::load`]), then spawn a background watcher over its
+/// `config/` and `match/` directories. Returns that initial snapshot alongside a channel
+/// receiver (which implements `Stream`, so it can be awaited like any other async stream) that
+/// yields a [`ReloadEvent`] after each debounced batch of changes. Dropping the receiver stops
+/// the watcher.
+pub fn load_and_watch(
+    base_path: &Path,
+    cli_overrides: &HashMap<String, String>,
+) -> Result<(LoadableConfig, async_std::channel::Receiver<ReloadEvent>)> {
+    let initial = crate::load(base_path, cli_overrides)?;
+
+    let config_dir = base_path.join("config");
+    let match_dir = base_path.join("match");
+
+    let (fs_tx, fs_rx) = std_mpsc::channel();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        if let Ok(event) = event {
+            // A send error just means the watcher thread has already exited (e.g. the reload
+            // receiver was dropped); there's nothing to do but drop the event.
+            let _ = fs_tx.send(event);
+        }
+    })
+    .context("failed to create configuration filesystem watcher")?;
+    watcher
+        .watch(&config_dir, RecursiveMode::Recursive)
+        .with_context(|| format!("failed to watch config directory: {config_dir:?}"))?;
+    if match_dir.is_dir() {
+        watcher
+            .watch(&match_dir, RecursiveMode::Recursive)
+            .with_context(|| format!("failed to watch match directory: {match_dir:?}"))?;
+    }
+
+    let (event_tx, event_rx) = async_std::channel::unbounded();
+    let base_path = base_path.to_path_buf();
+    let cli_overrides = cli_overrides.clone();
+
+    thread::spawn(move || {
+        // Keep the watcher alive for as long as this thread keeps reading from its channel.
+        let _watcher = watcher;
+
+        // The watcher thread tracks its own snapshot, seeded by loading once more, so it has
+        // something to diff the next change against; `initial` itself was already handed back
+        // to the caller above and isn't available here.
+        let Ok((profile_store, match_store, _, _)) = crate::load(&base_path, &cli_overrides) else {
+            return;
+        };
+        let mut profile_store = Arc::new(profile_store);
+        let mut match_store = Arc::new(match_store);
+
+        while let Ok(first_event) = fs_rx.recv() {
+            let mut changed_paths = first_event.paths;
+            // Drain the rest of the burst until the channel goes quiet for `DEBOUNCE`.
+            while let Ok(event) = fs_rx.recv_timeout(DEBOUNCE) {
+                changed_paths.extend(event.paths);
+            }
+
+            let config_changed = changed_paths
+                .iter()
+                .any(|path| path.starts_with(&config_dir));
+
+            // A change under `match/` can be handled by the narrow, index-stable reload below
+            // only if every changed path is already one of the files the current `MatchStore`
+            // knows about (a plain edit). A path that isn't -- a brand new file -- might be a
+            // fresh root match file that some profile's `includes` glob now picks up, and
+            // discovering that requires re-resolving those globs against `ProfileStore`, which
+            // the narrow path deliberately skips. Falling back to the full pipeline in that case
+            // keeps the fast path honest instead of silently missing the new file.
+            let known_match_paths: HashSet<&Path> = match_store.get_source_paths().collect();
+            let unknown_file_appeared = changed_paths.iter().any(|path| {
+                !path.starts_with(&config_dir) && !known_match_paths.contains(path.as_path())
+            });
+
+            let non_fatal_errors = if config_changed || unknown_file_appeared {
+                let Some((new_profile_store, new_match_store, non_fatal_errors, _)) =
+                    reload_profiles_and_matches(&base_path, &cli_overrides)
+                else {
+                    continue;
+                };
+                profile_store = Arc::new(new_profile_store);
+                match_store = Arc::new(new_match_store);
+                non_fatal_errors
+            } else {
+                let root_paths: Vec<PathBuf> = match_store
+                    .get_source_paths()
+                    .map(Path::to_path_buf)
+                    .collect();
+                let (new_match_store, _file_map, non_fatal_errors) =
+                    MatchStore::load(&root_paths, &[]);
+                match_store = Arc::new(new_match_store);
+                non_fatal_errors
+            };
+
+            let event = ReloadEvent {
+                profile_store: Arc::clone(&profile_store),
+                match_store: Arc::clone(&match_store),
+                non_fatal_errors,
+            };
+            if event_tx.send_blocking(event).is_err() {
+                // The receiver was dropped; nothing left to watch for.
+                break;
+            }
+        }
+    });
+
+    Ok((initial, event_rx))
+}
+
+/// Rebuild both the `ProfileStore` and the `MatchStore` from scratch, the same way [`crate::load`]
+/// does (including re-discovering `packages/`), for a change that touched `base_path`'s `config/`
+/// directory or added a file the current `MatchStore` has never seen. Returns `None` (logging a warning) if `config/` no longer parses at all; the caller
+/// just keeps the previous snapshot in that case rather than tearing down the watch.
+fn reload_profiles_and_matches(
+    base_path: &Path,
+    cli_overrides: &HashMap<String, String>,
+) -> Option<LoadableConfig> {
+    let env_overrides = config::resolve::env_overrides();
+    let cli_overrides = config::resolve::cli_profile_overrides(cli_overrides);
+    let (loaded_profile_store, non_fatal_config_errors) =
+        match config::load_store(&base_path.join("config"), &env_overrides, &cli_overrides) {
+            Ok(loaded) => loaded,
+            Err(err) => {
+                log::warn!("failed to reload configuration after a filesystem change: {err:#}");
+                return None;
+            }
+        };
+    let (loaded_packages, non_fatal_package_errors) =
+        crate::packages::discover_packages(&base_path.join("packages"));
+
+    let mut root_paths: Vec<_> = loaded_profile_store
+        .get_all_match_file_paths()
+        .into_iter()
+        .collect();
+    for package in &loaded_packages {
+        root_paths.extend(package.resolved_paths.iter().cloned());
+    }
+
+    let (match_store, file_map, non_fatal_match_errors) = MatchStore::load(&root_paths, &[]);
+    let mut profile_store = ProfileStore::resolve_paths(loaded_profile_store, &file_map);
+    profile_store.add_package_match_files(loaded_packages.iter().flat_map(|package| {
+        package
+            .resolved_paths
+            .iter()
+            .filter_map(|path| file_map.get(path).copied())
+    }));
+
+    let mut non_fatal_errors = Vec::new();
+    non_fatal_errors.extend(non_fatal_config_errors);
+    non_fatal_errors.extend(non_fatal_package_errors);
+    non_fatal_errors.extend(non_fatal_match_errors);
+
+    Some((
+        profile_store,
+        match_store,
+        non_fatal_errors,
+        loaded_packages,
+    ))
+}
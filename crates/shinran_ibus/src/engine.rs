@@ -6,28 +6,45 @@ use std::{
     time::{Duration, Instant},
 };
 
+use arc_swap::ArcSwap;
 use async_std::task::{self, sleep};
 use event_listener::Event;
 use ibus_utils::{
-    ibus_constants, Attribute, IBusAttribute, IBusEnginePreedit, IBusText, Underline,
+    ibus_constants, Attribute, IBusAttribute, IBusEnginePreedit, IBusLookupTable, IBusText,
+    TableOrientation, Underline,
 };
-use log::{debug, info};
+use log::{debug, info, warn};
+use unicode_segmentation::UnicodeSegmentation;
 use xkeysym::Keysym;
 use zbus::{fdo, interface, object_server::SignalContext};
 
 use shinran_lib::Backend;
 
+/// Number of candidates shown per page of the lookup table, and the distance a Page Up/Page
+/// Down key moves the cursor.
+const PAGE_SIZE: u32 = 5;
+
 pub(crate) struct ShinranEngine {
     done: Arc<Event>,
     text: String,
+    /// Position of the cursor within `text`, in grapheme clusters (not bytes), so CJK, accented,
+    /// and emoji input all move the cursor one visible character at a time, the same convention
+    /// `shinran_backend::cursor::process_cursor_hint` uses for cursor-position units.
     cursor_pos: u32,
     start_time: Instant,
     new_key_pressed: bool,
-    backend: Arc<Backend<'static>>,
+    /// The active backend, behind an `ArcSwap` so a config-file reload (see
+    /// `shinran_lib::watch`) can hot-swap it without tearing down this engine or its D-Bus
+    /// registration.
+    backend: Arc<ArcSwap<Backend<'static>>>,
+    /// Trigger text of each candidate currently shown in the lookup table, in display order.
+    candidates: Vec<String>,
+    /// Index into `candidates` that's currently highlighted in the lookup table.
+    lookup_cursor: u32,
 }
 
 impl ShinranEngine {
-    pub fn new(done: Arc<Event>, backend: Arc<Backend<'static>>) -> Self {
+    pub fn new(done: Arc<Event>, backend: Arc<ArcSwap<Backend<'static>>>) -> Self {
         Self {
             done,
             text: String::new(),
@@ -35,6 +52,8 @@ impl ShinranEngine {
             start_time: Instant::now(),
             new_key_pressed: false,
             backend,
+            candidates: Vec::new(),
+            lookup_cursor: 0,
         }
     }
 
@@ -45,12 +64,28 @@ impl ShinranEngine {
         self.done.notify(1);
     }
 
-    async fn update_text(&self, ctxt: &SignalContext<'_>) -> zbus::Result<()> {
+    async fn update_text(&mut self, ctxt: &SignalContext<'_>) -> zbus::Result<()> {
         debug!(
             "UpdateText(text = '{}', cursorPos = {})",
             self.text, self.cursor_pos,
         );
 
+        // If the buffer is already an exact trigger (or regex match), expand it immediately
+        // rather than waiting for an explicit commit key, the same as espanso's own expansion
+        // behavior: a user shouldn't have to press Enter or pick a lookup-table entry just
+        // because their trigger also happens to be a prefix of some other one.
+        let backend = self.backend.load_full();
+        let trigger = self.text.clone();
+        let exact_match =
+            task::spawn_blocking(move || backend.check_trigger(&trigger, "").ok().flatten())
+                .await;
+        if let Some(body) = exact_match {
+            self.clear_text(ctxt).await?;
+            let ibus_text = IBusText::new(&body, &[]);
+            ShinranEngine::commit_text(ctxt, ibus_text.into()).await?;
+            return Ok(());
+        }
+
         let attributes = [IBusAttribute::new(
             Attribute::Underline(Underline::Single),
             0,
@@ -68,20 +103,81 @@ impl ShinranEngine {
         .await?;
 
         // Spawn a task to fetch the candidates in the background.
-        let backend = self.backend.clone();
+        let backend = self.backend.load_full();
         // TODO: Investigate whether this can be done without cloning the text.
         let trigger = self.text.clone();
         // `fuzzy_match` is a long-running CPU-bound operation, so we use `spawn_blocking`,
         // because we don't want to block the async runtime.
         let candidates = task::spawn_blocking(move || backend.fuzzy_match(&trigger)).await;
 
-        if !candidates.is_empty() {
-            let mut table = ibus_utils::IBusLookupTable::default();
-            for (candidate, _) in candidates.into_iter().take(5) {
-                table.append_candidate(candidate.0);
-            }
+        self.candidates = candidates
+            .into_iter()
+            .take(PAGE_SIZE as usize)
+            .map(|(candidate, _)| candidate.0.to_string())
+            .collect();
+        self.lookup_cursor = 0;
+
+        if !self.candidates.is_empty() {
+            self.show_lookup_table(ctxt).await?;
+        } else {
+            ShinranEngine::update_lookup_table(ctxt, IBusLookupTable::default().into(), false)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Rebuild the lookup table from `self.candidates`/`self.lookup_cursor` and send it, e.g.
+    /// after the highlighted candidate changes but the candidate list itself hasn't.
+    async fn show_lookup_table(&self, ctxt: &SignalContext<'_>) -> zbus::Result<()> {
+        let candidate_refs: Vec<&str> = self.candidates.iter().map(String::as_str).collect();
+        let table = IBusLookupTable::new(
+            PAGE_SIZE,
+            self.lookup_cursor,
+            true,
+            false,
+            TableOrientation::System,
+            &candidate_refs,
+            &[],
+        );
+        ShinranEngine::update_lookup_table(ctxt, table.into(), true).await
+    }
+
+    /// Move the lookup table cursor by `delta`, clamping to the candidate list bounds.
+    /// Returns whether the cursor actually moved.
+    fn move_lookup_cursor(&mut self, delta: i32) -> bool {
+        if self.candidates.is_empty() {
+            return false;
+        }
+        let max = self.candidates.len() as i32 - 1;
+        let new_pos = (self.lookup_cursor as i32 + delta).clamp(0, max) as u32;
+        if new_pos == self.lookup_cursor {
+            return false;
+        }
+        self.lookup_cursor = new_pos;
+        true
+    }
 
-            ShinranEngine::update_lookup_table(ctxt, table.into(), true).await?;
+    /// Render `trigger` through the same pipeline as a direct match and commit the result,
+    /// clearing the preedit text first.
+    async fn commit_trigger(
+        &mut self,
+        ctxt: &SignalContext<'_>,
+        trigger: &str,
+    ) -> zbus::Result<()> {
+        // Real app-identity detection isn't wired up yet; see `Backend::check_trigger`.
+        let output = match self.backend.load().check_trigger(trigger, "") {
+            Ok(output) => output,
+            Err(err) => {
+                // A misconfigured match (e.g. a variable dependency cycle) shouldn't take down
+                // the whole input method; fall back to leaving the trigger un-expanded.
+                warn!("check_trigger('{trigger}') failed: {err}");
+                None
+            }
+        };
+        self.clear_text(ctxt).await?;
+        if let Some(text) = output {
+            let ibus_text = IBusText::new(&text, &[]);
+            ShinranEngine::commit_text(ctxt, ibus_text.into()).await?;
         }
         Ok(())
     }
@@ -89,19 +185,31 @@ impl ShinranEngine {
     async fn clear_text(&mut self, ctxt: &SignalContext<'_>) -> zbus::Result<()> {
         self.text.clear();
         self.cursor_pos = 0;
+        self.candidates.clear();
+        self.lookup_cursor = 0;
         self.update_text(ctxt).await?;
         Ok(())
     }
 
     fn move_cursor(&mut self, offset: i32) {
-        let text_len = self.text.len() as i32;
+        let text_len = text_length(&self.text) as i32;
         self.cursor_pos = (self.cursor_pos as i32 + offset).clamp(0, text_len) as u32;
     }
+
+    /// Byte offset of the start of the `grapheme_idx`-th grapheme cluster in `self.text`, or
+    /// `self.text.len()` if `grapheme_idx` is at or past the end -- the only place `cursor_pos`'s
+    /// grapheme units get translated into the byte offsets `String` itself indexes by.
+    fn byte_index(&self, grapheme_idx: u32) -> usize {
+        self.text
+            .grapheme_indices(true)
+            .nth(grapheme_idx as usize)
+            .map_or(self.text.len(), |(i, _)| i)
+    }
 }
 
-/// Number of unicode characters in a string.
+/// Number of grapheme clusters in a string, the unit `ShinranEngine::cursor_pos` is tracked in.
 fn text_length(text: &str) -> u32 {
-    text.chars().count() as u32
+    text.graphemes(true).count() as u32
 }
 
 #[interface(name = "org.freedesktop.IBus.Engine")]
@@ -134,31 +242,42 @@ impl ShinranEngine {
         match keysym {
             Keysym::Return | Keysym::KP_Enter => {
                 if !self.text.is_empty() {
-                    let output = self.backend.check_trigger(&self.text).unwrap();
-                    self.clear_text(&ctxt).await?;
-                    if let Some(text) = output {
-                        let ibus_text = IBusText::new(&text, &[]);
-                        ShinranEngine::commit_text(&ctxt, ibus_text.into()).await?;
-                    }
+                    let trigger = self
+                        .candidates
+                        .get(self.lookup_cursor as usize)
+                        .cloned()
+                        .unwrap_or_else(|| self.text.clone());
+                    self.commit_trigger(&ctxt, &trigger).await?;
                 }
                 self.exit().await;
                 return Ok(true);
             }
+            Keysym::space if !self.candidates.is_empty() => {
+                // With an ambiguous prefix on-screen, space accepts the highlighted candidate
+                // instead of being typed literally, the same as Return above.
+                let trigger = self.candidates[self.lookup_cursor as usize].clone();
+                self.commit_trigger(&ctxt, &trigger).await?;
+                self.exit().await;
+                return Ok(true);
+            }
             Keysym::Escape => {
                 self.clear_text(&ctxt).await?;
                 self.exit().await;
             }
             Keysym::BackSpace => {
                 if self.cursor_pos > 0 {
-                    self.text.remove(self.cursor_pos as usize - 1);
+                    let start = self.byte_index(self.cursor_pos - 1);
+                    let end = self.byte_index(self.cursor_pos);
+                    self.text.replace_range(start..end, "");
                     self.cursor_pos -= 1;
                     self.update_text(&ctxt).await?;
                 }
             }
             Keysym::Delete | Keysym::KP_Delete => {
-                let pos = self.cursor_pos as usize;
-                if pos < self.text.len() {
-                    self.text.remove(pos);
+                if self.cursor_pos < text_length(&self.text) {
+                    let start = self.byte_index(self.cursor_pos);
+                    let end = self.byte_index(self.cursor_pos + 1);
+                    self.text.replace_range(start..end, "");
                     self.update_text(&ctxt).await?;
                 }
             }
@@ -170,17 +289,40 @@ impl ShinranEngine {
                 self.move_cursor(1);
                 self.update_text(&ctxt).await?;
             }
+            Keysym::Up | Keysym::KP_Up => {
+                if self.move_lookup_cursor(-1) {
+                    self.show_lookup_table(&ctxt).await?;
+                }
+            }
+            Keysym::Down | Keysym::KP_Down => {
+                if self.move_lookup_cursor(1) {
+                    self.show_lookup_table(&ctxt).await?;
+                }
+            }
+            Keysym::Page_Up | Keysym::KP_Page_Up => {
+                if self.move_lookup_cursor(-(PAGE_SIZE as i32)) {
+                    self.show_lookup_table(&ctxt).await?;
+                }
+            }
+            Keysym::Page_Down | Keysym::KP_Page_Down => {
+                if self.move_lookup_cursor(PAGE_SIZE as i32) {
+                    self.show_lookup_table(&ctxt).await?;
+                }
+            }
             key => {
                 if let Some(character) = key.key_char() {
-                    if character.is_ascii_graphic()
-                        || ('\u{00A0}'..='\u{00FF}').contains(&character)
-                    {
-                        let pos = self.cursor_pos as usize;
-                        if pos < self.text.len() {
-                            self.text.insert(pos, character);
-                        } else {
-                            self.text.push(character);
-                        }
+                    let candidate = character
+                        .to_digit(10)
+                        .filter(|&digit| (1..=9).contains(&digit))
+                        .and_then(|digit| self.candidates.get(digit as usize - 1).cloned());
+                    if let Some(trigger) = candidate {
+                        self.commit_trigger(&ctxt, &trigger).await?;
+                        self.exit().await;
+                        return Ok(true);
+                    }
+                    if !character.is_control() {
+                        let pos = self.byte_index(self.cursor_pos);
+                        self.text.insert(pos, character);
                         self.cursor_pos += 1;
                         self.update_text(&ctxt).await?;
                     }
@@ -238,22 +380,66 @@ impl ShinranEngine {
     fn cancel_hand_writing(&self, _n_strokes: u32) {}
 
     /// CandidateClicked method
-    fn candidate_clicked(&self, _index: u32, _button: u32, _state: u32) {}
+    async fn candidate_clicked(
+        &mut self,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+        index: u32,
+        _button: u32,
+        _state: u32,
+    ) -> fdo::Result<()> {
+        if let Some(trigger) = self.candidates.get(index as usize).cloned() {
+            self.commit_trigger(&ctxt, &trigger).await?;
+            self.exit().await;
+        }
+        Ok(())
+    }
 
     /// CursorDown method
-    fn cursor_down(&self) {}
+    async fn cursor_down(
+        &mut self,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) -> fdo::Result<()> {
+        if self.move_lookup_cursor(1) {
+            self.show_lookup_table(&ctxt).await?;
+        }
+        Ok(())
+    }
 
     /// CursorUp method
-    fn cursor_up(&self) {}
+    async fn cursor_up(
+        &mut self,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) -> fdo::Result<()> {
+        if self.move_lookup_cursor(-1) {
+            self.show_lookup_table(&ctxt).await?;
+        }
+        Ok(())
+    }
 
     /// FocusOutId method
     fn focus_out_id(&self, _object_path: &str) {}
 
     /// PageDown method
-    fn page_down(&self) {}
+    async fn page_down(
+        &mut self,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) -> fdo::Result<()> {
+        if self.move_lookup_cursor(PAGE_SIZE as i32) {
+            self.show_lookup_table(&ctxt).await?;
+        }
+        Ok(())
+    }
 
     /// PageUp method
-    fn page_up(&self) {}
+    async fn page_up(
+        &mut self,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) -> fdo::Result<()> {
+        if self.move_lookup_cursor(-(PAGE_SIZE as i32)) {
+            self.show_lookup_table(&ctxt).await?;
+        }
+        Ok(())
+    }
 
     /// PanelExtensionReceived method
     fn panel_extension_received(&self, _event: zbus::zvariant::Value<'_>) {}
@@ -2,10 +2,11 @@ use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::{Arc, LazyLock};
 
+use arc_swap::ArcSwap;
 use async_std::task;
 use event_listener::{Event, Listener};
 use log::{debug, error, info};
-use shinran_backend::{Backend, Configuration};
+use shinran_backend::{resolve_paths_and_passphrase, watch, Backend, Configuration, InstanceLock};
 use zbus::zvariant::{ObjectPath, OwnedObjectPath};
 use zbus::{connection, fdo, Address, ObjectServer};
 use zbus::{interface, AuthMechanism};
@@ -22,7 +23,7 @@ const FACTORY_PATH: &str = "/org/freedesktop/IBus/Factory";
 
 struct Factory {
     done: Arc<Event>,
-    backend: Arc<Backend<'static>>,
+    backend: Arc<ArcSwap<Backend<'static>>>,
 }
 
 #[interface(name = "org.freedesktop.IBus.Factory")]
@@ -68,13 +69,33 @@ async fn main() -> zbus::Result<()> {
         None
     };
 
+    // Make sure we're the only instance touching this runtime directory before registering
+    // anything on the bus; a previous instance that crashed without cleaning up shouldn't stop
+    // us from starting.
+    let (paths, package_passphrase) = resolve_paths_and_passphrase(&HashMap::new());
+    let _instance_lock = match InstanceLock::acquire(&paths.runtime) {
+        Ok(lock) => lock,
+        Err(err) => {
+            error!("{err}");
+            return Ok(());
+        }
+    };
+
     // Set up the backend.
     let backend = Backend::new(&CONFIG.0).unwrap();
+    let backend = Arc::new(ArcSwap::new(Arc::new(backend)));
+
+    // Recompile and hot-swap the backend whenever a match/config file changes on disk, without
+    // tearing down the engine registered below.
+    let _config_watcher = watch::spawn(paths, package_passphrase, backend.clone())
+        .map_err(|err| error!("unable to start config watcher: {err}"))
+        .ok();
+
     // Set up the factory.
     let event = Arc::new(Event::new());
     let factory = Factory {
         done: event.clone(),
-        backend: Arc::new(backend),
+        backend,
     };
     let done_listener = event.listen();
 
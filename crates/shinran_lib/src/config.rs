@@ -1,14 +1,71 @@
-use std::{alloc::Layout, collections::HashMap, ptr::NonNull};
+use std::{
+    alloc::Layout,
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    ptr::NonNull,
+    sync::{mpsc, Arc},
+    time::Duration,
+};
 
-use log::info;
+use arc_swap::ArcSwap;
+use log::{debug, info, warn};
+use memmap2::Mmap;
+use notify::{RecursiveMode, Watcher};
+use rayon::prelude::*;
 use rkyv::{
     ser::{serializers::AllocSerializer, ScratchSpace, Serializer},
     with::AsStringError,
     Archive, Deserialize, Fallible, Serialize,
 };
+use sha2::{Digest, Sha256};
 use shinran_config::{config::ProfileStore, matches::store::MatchStore};
+use thiserror::Error;
+
+use crate::{get_bool_override, get_path_override, get_string_override, load, path};
+
+/// How long [`Configuration::watch`] waits after the last filesystem event on a source file
+/// before treating a burst of saves as settled and reloading. Mirrors [`crate::watch::DEBOUNCE`];
+/// kept as its own constant since the two watchers operate at different layers (see
+/// [`Configuration::watch`]'s doc comment) and so aren't guaranteed to want the same value.
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(200);
 
-use crate::{get_path_override, load, path};
+/// File name (under [`path::Paths::runtime`]) the rkyv-archived [`Configuration`] is cached to.
+const CACHE_FILE_NAME: &str = "config.cache";
+/// File name for the small sidecar manifest recording every cached source file's stat, so a
+/// cache hit can be confirmed without reading (let alone parsing) any YAML.
+const CACHE_MANIFEST_FILE_NAME: &str = "config.cache.manifest";
+
+/// Fixed tag at the start of the cache file, checked before [`CACHE_SCHEMA_VERSION`] so a stray
+/// or truncated file is rejected outright rather than having its first four bytes misread as a
+/// version number.
+const CACHE_MAGIC: [u8; 4] = *b"SHRC";
+/// Bumped whenever `Configuration` (or `ProfileStore`/`MatchStore` underneath it) changes in a
+/// way `check_archived_root`'s byte-level validation might not catch -- e.g. a field that kept
+/// its byte shape but changed meaning. A cache written under a different version is rejected
+/// outright rather than trusted, since passing `check_bytes` doesn't guarantee it still means
+/// what this binary expects it to mean.
+const CACHE_SCHEMA_VERSION: u32 = 1;
+/// Bytes of fixed header written before the archived [`Configuration`] bytes: [`CACHE_MAGIC`],
+/// [`CACHE_SCHEMA_VERSION`] as a little-endian `u32`, a [`CACHE_COMPRESSION_FLAG_OFFSET`] byte
+/// recording whether the body is zstd-compressed, then reserved padding out to a 16-byte
+/// boundary, so an uncompressed archived root keeps the same alignment it would have had at the
+/// very start of the mmap.
+const CACHE_HEADER_LEN: usize = 16;
+/// Offset within the header of the single byte flagging whether the cache body is zstd-compressed
+/// (`1`) or a raw archived [`Configuration`] (`0`).
+const CACHE_COMPRESSION_FLAG_OFFSET: usize = 8;
+/// `zstd` compression level used when [`Configuration::new`]'s `cache_compression` override is
+/// enabled. Picked for zstd's own default trade-off between ratio and speed rather than anything
+/// shinran-specific.
+const CACHE_COMPRESSION_LEVEL: i32 = 3;
+
+/// A cached source file's identity when the cache was written: its path, byte length, and a
+/// SHA-256 digest of its contents. Content hashing (rather than mtime) is what actually makes the
+/// cache trustworthy under version-control workflows -- `git checkout`/`stash` can rewind an
+/// mtime to before the cache was written without touching the bytes, and some filesystems/editors
+/// only have second-level mtime resolution, either of which would make an mtime-based check miss
+/// a real change or flag a file as changed when it isn't.
+type SourceStat = (PathBuf, u64, [u8; 32]);
 
 /// A struct containing all the information that was loaded from match files and config files.
 #[derive(Archive, Serialize, Deserialize)]
@@ -21,50 +78,475 @@ pub struct Configuration {
 }
 
 impl Configuration {
-    pub fn new(cli_overrides: &HashMap<String, String>) -> Self {
-        let force_config_path =
-            get_path_override(cli_overrides, "config_dir", "SHINRAN_CONFIG_DIR");
-        let force_package_path =
-            get_path_override(cli_overrides, "package_dir", "SHINRAN_PACKAGE_DIR");
-        let force_runtime_path =
-            get_path_override(cli_overrides, "runtime_dir", "SHINRAN_RUNTIME_DIR");
-
-        let paths = path::resolve_paths(
-            force_config_path.as_deref(),
-            force_package_path.as_deref(),
-            force_runtime_path.as_deref(),
-        );
+    pub fn new(cli_overrides: &HashMap<String, String>) -> Result<Self, ConfigError> {
+        let (paths, package_passphrase) = resolve_paths_and_passphrase(cli_overrides);
+        info!("using runtime dir: {:?}", paths.runtime);
+
+        let cache_dir = resolve_cache_dir(cli_overrides, &paths);
+        let no_cache =
+            get_bool_override(cli_overrides, "no_cache", "SHINRAN_NO_CACHE").unwrap_or(false);
+
+        if !no_cache {
+            match load_cache(&cache_dir) {
+                Ok(cached) => {
+                    debug!("configuration cache hit, skipping full reload");
+                    return Ok(cached);
+                }
+                Err(CacheError::NotFound) => {
+                    debug!("no configuration cache yet, doing a full load");
+                }
+                Err(err) => {
+                    debug!("configuration cache rejected, rebuilding: {err}");
+                }
+            }
+        }
+
+        let cfg = Self::from_paths(&paths, package_passphrase.as_deref())?;
+        if let Err(err) = write_cache(&cache_dir, &cfg, cli_overrides) {
+            warn!("unable to write configuration cache: {err}");
+        }
+        Ok(cfg)
+    }
+
+    /// Build a `Configuration` from an already-resolved [`path::Paths`], re-running the same
+    /// steps [`Configuration::new`] does after path resolution. Used both by `new` and by
+    /// [`crate::watch`] to recompile after a config-file change, without re-deriving `Paths` (and
+    /// re-reading CLI overrides/env vars) on every reload. Always does a full reload, bypassing
+    /// the cache, since a reload is only ever triggered by a change `new`'s cache check wouldn't
+    /// have seen yet.
+    pub(crate) fn from_paths(
+        paths: &path::Paths,
+        package_passphrase: Option<&str>,
+    ) -> Result<Self, ConfigError> {
         info!("reading configs from: {:?}", paths.config);
         info!("reading packages from: {:?}", paths.packages);
-        info!("using runtime dir: {:?}", paths.runtime);
 
-        let config_result = load::load_config(&paths.config).expect("unable to load config");
+        for error_set in shinran_config::materialize_packages(&paths.packages, package_passphrase) {
+            log::warn!("unable to extract package archive: {error_set:?}");
+        }
+
+        let config_result = load::load_config(&paths.config)
+            .map_err(|err| ConfigError::Parse(paths.config.clone(), err))?;
 
-        let home_path = dirs::home_dir().expect("unable to obtain home dir path");
+        let home_path = dirs::home_dir().ok_or(ConfigError::MissingDirectory("home directory"))?;
         let base_path = &paths.config;
         let packages_path = &paths.packages;
         let renderer = shinran_render::Renderer::new(base_path, &home_path, packages_path);
 
-        let cfg = Configuration {
+        Ok(Configuration {
             profile_store: config_result.profile_store,
             match_store: config_result.match_store,
             renderer,
+        })
+    }
+
+    /// Every source file this `Configuration` was built from (profile files plus match files) —
+    /// everything that needs watching to notice every change that would change this
+    /// `Configuration` if re-parsed. See [`Configuration::watch`].
+    pub fn get_source_paths(&self) -> impl Iterator<Item = &Path> {
+        self.profile_store
+            .get_source_paths()
+            .chain(self.match_store.get_source_paths())
+    }
+
+    /// Like [`Configuration::new`], but keeps the result live: watches every path
+    /// [`Configuration::get_source_paths`] returns and, once a burst of changes settles (see
+    /// [`WATCH_DEBOUNCE`]), rebuilds the whole `Configuration` and atomically swaps it into the
+    /// returned `ArcSwap`, so a caller reading through it never observes a half-updated state. A
+    /// reload that fails to parse is logged and leaves the previous, still-good `Configuration`
+    /// in place.
+    ///
+    /// This is a different, narrower watcher than [`crate::watch::spawn`]: that one recursively
+    /// watches `paths.config`/`paths.packages` and swaps a `Backend`, so it also notices
+    /// wholly new files; this one watches exactly the source paths the last successful load
+    /// read, and swaps a bare `Configuration`, for an embedder that wants a live configuration
+    /// without the match-matching `Backend` layer on top. Adding a new file that nothing
+    /// currently watched `includes` won't trigger a reload here until something else does.
+    pub fn watch(
+        cli_overrides: &HashMap<String, String>,
+    ) -> Result<(Arc<ArcSwap<Configuration>>, WatcherHandle), ConfigError> {
+        let (paths, package_passphrase) = resolve_paths_and_passphrase(cli_overrides);
+        let cfg = Self::from_paths(&paths, package_passphrase.as_deref())?;
+        let source_paths: Vec<PathBuf> = cfg.get_source_paths().map(Path::to_path_buf).collect();
+        let current = Arc::new(ArcSwap::from_pointee(cfg));
+
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            // Any send failure just means the debounce thread has shut down; nothing to do.
+            let _ = tx.send(event);
+        })
+        .map_err(|err| ConfigError::Watch(format!("{err}")))?;
+        for source_path in &source_paths {
+            if let Err(err) = watcher.watch(source_path, RecursiveMode::NonRecursive) {
+                warn!("unable to watch configuration source {source_path:?}: {err}");
+            }
+        }
+
+        let watched = Arc::clone(&current);
+        std::thread::spawn(move || {
+            watch_and_reload(&rx, &paths, package_passphrase.as_deref(), &watched);
+        });
+
+        Ok((current, WatcherHandle { _watcher: watcher }))
+    }
+}
+
+/// Owns the background watcher thread and the `notify` watcher backing [`Configuration::watch`].
+/// Dropping this stops watching for changes; keep it alive for as long as live reload should
+/// stay active.
+pub struct WatcherHandle {
+    _watcher: notify::RecommendedWatcher,
+}
+
+/// Drain `rx`, waiting for a [`WATCH_DEBOUNCE`]-long quiet period after the last event before
+/// treating the burst as settled and reloading `current`. Exits once the watcher (and thus the
+/// sending half of `rx`) is dropped.
+fn watch_and_reload(
+    rx: &mpsc::Receiver<notify::Result<notify::Event>>,
+    paths: &path::Paths,
+    package_passphrase: Option<&str>,
+    current: &Arc<ArcSwap<Configuration>>,
+) {
+    let mut pending = false;
+
+    loop {
+        match rx.recv_timeout(WATCH_DEBOUNCE) {
+            Ok(Ok(_event)) => pending = true,
+            Ok(Err(err)) => warn!("configuration watcher error: {err}"),
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                if pending {
+                    pending = false;
+                    match Configuration::from_paths(paths, package_passphrase) {
+                        Ok(cfg) => {
+                            current.store(Arc::new(cfg));
+                            info!("configuration reloaded after a filesystem change");
+                        }
+                        Err(err) => {
+                            warn!(
+                                "configuration reload failed, keeping the previous configuration: {err}"
+                            );
+                        }
+                    }
+                }
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => return,
+        }
+    }
+}
+
+/// Errors that can occur while building a [`Configuration`], each carrying enough context (the
+/// offending path, the underlying error) to report something actionable instead of a bare
+/// `unwrap`/`expect` panic.
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("failed to read {0}: {1}")]
+    Io(PathBuf, #[source] std::io::Error),
+    #[error("failed to load configuration from {0}: {1}")]
+    Parse(PathBuf, #[source] anyhow::Error),
+    #[error("failed to serialize configuration cache: {0}")]
+    Serialize(String),
+    #[error("unable to locate the {0}")]
+    MissingDirectory(&'static str),
+    #[error("unable to start watching for configuration changes: {0}")]
+    Watch(String),
+}
+
+impl<E: std::fmt::Debug> From<MySerializerError<E>> for ConfigError {
+    fn from(err: MySerializerError<E>) -> Self {
+        ConfigError::Serialize(format!("{err:?}"))
+    }
+}
+
+/// Why a cached [`Configuration`] was rejected (or couldn't be read at all), so a caller can
+/// react to -- or just log -- the specific reason instead of a generic "cache miss". `NotFound` is
+/// the expected, unremarkable case on a first run or with `--no-cache`; every other variant means
+/// there was a cache file but it couldn't be trusted.
+#[derive(Error, Debug)]
+pub enum CacheError {
+    #[error("no cache present")]
+    NotFound,
+    #[error("failed to read {0}: {1}")]
+    Io(PathBuf, #[source] std::io::Error),
+    #[error("cache manifest failed to deserialize: {0}")]
+    Deserialize(String),
+    #[error("cache failed byte-level validation: {0}")]
+    ByteCheck(String),
+    #[error("cache was written by schema version {found}, this binary expects {CACHE_SCHEMA_VERSION}")]
+    SchemaVersionMismatch { found: u32 },
+    #[error("{0:?} changed since the cache was written")]
+    Stale(PathBuf),
+    #[error("new source file(s) appeared since the cache was written: {0:?}")]
+    NewFilesAdded(Vec<PathBuf>),
+}
+
+/// The bytes backing an [`ArchivedConfigurationHandle`]: a zero-copy mmap for an uncompressed
+/// cache, or an owned, correctly-aligned buffer for a compressed one, since decompression has to
+/// produce a fresh buffer anyway and rkyv needs it aligned for [`rkyv::check_archived_root`].
+enum CacheBytes {
+    Mapped(Mmap),
+    Decompressed(rkyv::AlignedVec),
+}
+
+/// A view of a cached [`Configuration`], for a caller that wants to run directly off the cache
+/// file's bytes instead of paying for [`Configuration::new`]'s full deserialize. Zero-copy when
+/// the cache is uncompressed; for a compressed cache (see [`CACHE_COMPRESSION_FLAG_OFFSET`]) this
+/// still pays for decompression once, but skips rkyv's own deserialize pass. Dropping this
+/// releases the underlying mmap or buffer.
+pub struct ArchivedConfigurationHandle {
+    bytes: CacheBytes,
+}
+
+impl ArchivedConfigurationHandle {
+    /// Open the cache file under `runtime_dir`, returning a [`CacheError`] on any miss: no cache
+    /// file, a stale or missing manifest, a header with the wrong magic tag or schema version, or
+    /// a cache that fails `check_bytes` validation.
+    pub fn open(runtime_dir: &Path) -> Result<Self, CacheError> {
+        cache_is_fresh(runtime_dir)?;
+
+        let cache_path = runtime_dir.join(CACHE_FILE_NAME);
+        let file = std::fs::File::open(&cache_path)
+            .map_err(|err| CacheError::Io(cache_path.clone(), err))?;
+        // Safety: the cache file is never mutated in place; it's only ever replaced wholesale by
+        // `write_cache`, which writes to a temp file and renames it into place.
+        let mmap =
+            unsafe { Mmap::map(&file) }.map_err(|err| CacheError::Io(cache_path.clone(), err))?;
+
+        let compressed = Self::validated_header(&mmap)?;
+        let bytes = if compressed {
+            let raw = zstd::decode_all(&mmap[CACHE_HEADER_LEN..])
+                .map_err(|err| CacheError::Io(cache_path.clone(), err))?;
+            let mut aligned = rkyv::AlignedVec::with_capacity(raw.len());
+            aligned.extend_from_slice(&raw);
+            CacheBytes::Decompressed(aligned)
+        } else {
+            CacheBytes::Mapped(mmap)
         };
-        // We can construct our serializer in much the same way as we always do
-        let mut serializer = MySerializer::<AllocSerializer<1024>>::default();
-        // then manually serialize our value
-        serializer.serialize_value(&cfg).unwrap();
-        // and finally, dig all the way down to our byte buffer
-        let bytes = serializer.into_inner().into_serializer().into_inner();
 
-        // Retrieve source paths from the archived configuration.
-        let archived = rkyv::check_archived_root::<Configuration>(&bytes[..]).unwrap();
-        let mut paths = Vec::new();
-        paths.extend(archived.profile_store.get_source_paths());
-        paths.extend(archived.match_store.get_source_paths());
+        // Validate once up front so every other caller of `get` can assume it always succeeds.
+        let handle = Self { bytes };
+        rkyv::check_archived_root::<Configuration>(handle.body())
+            .map_err(|err| CacheError::ByteCheck(format!("{err}")))?;
+        Ok(handle)
+    }
+
+    /// Check the header's magic tag and schema version, returning whether the body is
+    /// zstd-compressed -- an `Err` means the file isn't a cache this version of the binary wrote,
+    /// and must be rebuilt rather than trusted, even if the bytes happen to pass
+    /// `check_archived_root`.
+    fn validated_header(mmap: &[u8]) -> Result<bool, CacheError> {
+        let header = mmap.get(..CACHE_HEADER_LEN).ok_or_else(|| {
+            CacheError::ByteCheck("cache file is too short to contain a header".to_string())
+        })?;
+        if header[..4] != CACHE_MAGIC {
+            return Err(CacheError::ByteCheck(
+                "cache file has no recognized magic tag".to_string(),
+            ));
+        }
+        let version = u32::from_le_bytes(
+            header[4..8]
+                .try_into()
+                .expect("slice is exactly 4 bytes long"),
+        );
+        if version != CACHE_SCHEMA_VERSION {
+            return Err(CacheError::SchemaVersionMismatch { found: version });
+        }
+        Ok(header[CACHE_COMPRESSION_FLAG_OFFSET] != 0)
+    }
+
+    /// The raw archived [`Configuration`] bytes: the mmap past its header, or the fully
+    /// decompressed buffer, whichever backs this handle.
+    fn body(&self) -> &[u8] {
+        match &self.bytes {
+            CacheBytes::Mapped(mmap) => &mmap[CACHE_HEADER_LEN..],
+            CacheBytes::Decompressed(aligned) => aligned.as_slice(),
+        }
+    }
+
+    pub fn get(&self) -> &ArchivedConfiguration {
+        // Never panics: `open` already ran `check_bytes` over the exact same bytes.
+        rkyv::check_archived_root::<Configuration>(self.body())
+            .expect("validated when this handle was opened")
+    }
+}
+
+/// Load the cached [`Configuration`] from `runtime_dir`, deserializing it from the mmapped cache
+/// file instead of re-parsing any YAML, or a [`CacheError`] explaining why the cache couldn't be
+/// used (see [`ArchivedConfigurationHandle::open`]).
+fn load_cache(runtime_dir: &Path) -> Result<Configuration, CacheError> {
+    let handle = ArchivedConfigurationHandle::open(runtime_dir)?;
+    handle
+        .get()
+        .deserialize(&mut rkyv::Infallible)
+        .map_err(|err| CacheError::Deserialize(format!("{err:?}")))
+}
+
+/// Whether the cache file under `runtime_dir` is still valid: its manifest exists, is readable,
+/// every source file it lists still exists with an unchanged size and content hash, and no new
+/// source file has appeared alongside one it already knew about. Hashing every source file is the
+/// expensive part once a user has dozens of packages, so the sources are stat-ed/hashed across a
+/// `rayon` thread pool rather than one at a time; `find_any` still short-circuits as soon as one
+/// entry comes back stale.
+fn cache_is_fresh(runtime_dir: &Path) -> Result<(), CacheError> {
+    let manifest_path = runtime_dir.join(CACHE_MANIFEST_FILE_NAME);
+    let manifest_bytes = std::fs::read(&manifest_path).map_err(|err| {
+        if err.kind() == std::io::ErrorKind::NotFound {
+            CacheError::NotFound
+        } else {
+            CacheError::Io(manifest_path.clone(), err)
+        }
+    })?;
+    let sources = postcard::from_bytes::<Vec<SourceStat>>(&manifest_bytes)
+        .map_err(|err| CacheError::Deserialize(format!("{err}")))?;
+
+    if let Some((path, _, _)) = sources
+        .par_iter()
+        .find_any(|(path, len, digest)| stat_source(path) != (path.clone(), *len, *digest))
+    {
+        return Err(CacheError::Stale(path.clone()));
+    }
 
-        cfg
+    let new_files = detect_new_files(&sources);
+    if !new_files.is_empty() {
+        return Err(CacheError::NewFilesAdded(new_files));
     }
+
+    Ok(())
+}
+
+/// Source-file extensions any of shinran's match/profile loaders understand -- used to recognize
+/// a newly-added file in a directory [`cache_is_fresh`] already knows about, without re-running
+/// full config discovery just to answer "did anything new show up here?".
+const SUPPORTED_SOURCE_EXTENSIONS: [&str; 4] = ["yml", "yaml", "json", "toml"];
+
+/// Scan the parent directory of every path in `sources` for a file with a recognized source
+/// extension that isn't already one of `sources`' own paths -- i.e. a file that's appeared since
+/// the cache was written, which `sources`' own size/hash check can't catch since it only revisits
+/// paths it already knew about.
+fn detect_new_files(sources: &[SourceStat]) -> Vec<PathBuf> {
+    let known: HashSet<&Path> = sources.iter().map(|(path, _, _)| path.as_path()).collect();
+    let dirs: HashSet<&Path> = sources.iter().filter_map(|(path, _, _)| path.parent()).collect();
+
+    let mut new_files: Vec<PathBuf> = dirs
+        .into_iter()
+        .filter_map(|dir| std::fs::read_dir(dir).ok())
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            !known.contains(path.as_path())
+                && path
+                    .extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| SUPPORTED_SOURCE_EXTENSIONS.contains(&ext))
+        })
+        .collect();
+    new_files.sort();
+    new_files
+}
+
+/// Serialize `cfg` and write it (together with its source-file manifest) to `runtime_dir`,
+/// atomically replacing whatever cache was there before. A failure here only costs the next
+/// startup a recompile, so [`Configuration::new`] logs it and otherwise ignores it rather than
+/// failing the load it was trying to speed up.
+///
+/// If the `cache_compression`/`SHINRAN_CACHE_COMPRESSION` override is on, the body is
+/// zstd-compressed first, trading a little CPU at write (and at every later read) for a much
+/// smaller file on disk -- worthwhile once a config grows enough match/profile files that the raw
+/// archived `Configuration` itself becomes the bulk of the runtime dir's footprint.
+fn write_cache(
+    runtime_dir: &Path,
+    cfg: &Configuration,
+    cli_overrides: &HashMap<String, String>,
+) -> Result<(), ConfigError> {
+    let mut serializer = MySerializer::<AllocSerializer<1024>>::default();
+    serializer.serialize_value(cfg)?;
+    let bytes = serializer.into_inner().into_serializer().into_inner();
+
+    let archived = rkyv::check_archived_root::<Configuration>(&bytes)
+        .map_err(|err| ConfigError::Serialize(format!("{err}")))?;
+    let source_paths: Vec<&Path> = archived
+        .profile_store
+        .get_source_paths()
+        .chain(archived.match_store.get_source_paths())
+        .collect();
+    let mut sources: Vec<SourceStat> = source_paths.par_iter().copied().map(stat_source).collect();
+    sources.sort();
+    sources.dedup();
+
+    let manifest_bytes =
+        postcard::to_allocvec(&sources).map_err(|err| ConfigError::Serialize(format!("{err}")))?;
+
+    let compress =
+        get_bool_override(cli_overrides, "cache_compression", "SHINRAN_CACHE_COMPRESSION")
+            .unwrap_or(false);
+    let body = if compress {
+        zstd::encode_all(&bytes[..], CACHE_COMPRESSION_LEVEL)
+            .map_err(|err| ConfigError::Serialize(format!("failed to compress cache: {err}")))?
+    } else {
+        bytes.into_vec()
+    };
+
+    let mut header = [0u8; CACHE_HEADER_LEN];
+    header[..4].copy_from_slice(&CACHE_MAGIC);
+    header[4..8].copy_from_slice(&CACHE_SCHEMA_VERSION.to_le_bytes());
+    header[CACHE_COMPRESSION_FLAG_OFFSET] = u8::from(compress);
+
+    let cache_path = runtime_dir.join(CACHE_FILE_NAME);
+    let cache_tmp_path = cache_path.with_extension("cache.tmp");
+    std::fs::write(&cache_tmp_path, [&header[..], &body[..]].concat())
+        .map_err(|err| ConfigError::Io(cache_tmp_path.clone(), err))?;
+    std::fs::rename(&cache_tmp_path, &cache_path)
+        .map_err(|err| ConfigError::Io(cache_path, err))?;
+
+    let manifest_path = runtime_dir.join(CACHE_MANIFEST_FILE_NAME);
+    std::fs::write(&manifest_path, manifest_bytes)
+        .map_err(|err| ConfigError::Io(manifest_path, err))
+}
+
+/// Stat `path`'s size and hash its contents with SHA-256. A path that can't be read (e.g. already
+/// deleted) gets a fixed sentinel length and an all-ones digest so it reliably counts as changed
+/// rather than spuriously matching another missing path.
+fn stat_source(path: &Path) -> SourceStat {
+    let Ok(bytes) = std::fs::read(path) else {
+        return (path.to_path_buf(), u64::MAX, [0xff; 32]);
+    };
+    let digest: [u8; 32] = Sha256::digest(&bytes).into();
+    (path.to_path_buf(), bytes.len() as u64, digest)
+}
+
+/// Apply the `config_dir`/`package_dir`/`runtime_dir`/`package_passphrase` CLI overrides (and
+/// their `SHINRAN_*` env var fallbacks) and resolve the resulting [`path::Paths`], exactly like
+/// [`Configuration::new`] does internally. Exposed so a caller that wants to reload the config
+/// later (e.g. [`crate::watch`]) can resolve `Paths` once up front instead of threading
+/// `cli_overrides` through, and so those paths are guaranteed to be the ones `new` itself used.
+pub fn resolve_paths_and_passphrase(
+    cli_overrides: &HashMap<String, String>,
+) -> (path::Paths, Option<String>) {
+    let force_config_path = get_path_override(cli_overrides, "config_dir", "SHINRAN_CONFIG_DIR");
+    let force_package_path = get_path_override(cli_overrides, "package_dir", "SHINRAN_PACKAGE_DIR");
+    let force_runtime_path = get_path_override(cli_overrides, "runtime_dir", "SHINRAN_RUNTIME_DIR");
+    let package_passphrase = get_string_override(
+        cli_overrides,
+        "package_passphrase",
+        "SHINRAN_PACKAGE_PASSPHRASE",
+    );
+
+    let paths = path::resolve_paths(
+        force_config_path.as_deref(),
+        force_package_path.as_deref(),
+        force_runtime_path.as_deref(),
+    );
+
+    (paths, package_passphrase)
+}
+
+/// Where [`Configuration::new`] reads and writes its cache, honoring a `cache_dir`/
+/// `SHINRAN_CACHE_DIR` override the same way [`resolve_paths_and_passphrase`] honors
+/// `runtime_dir`/`SHINRAN_RUNTIME_DIR`, so a power user or CI runner can pin (or isolate) the
+/// cache without having to relocate the whole runtime dir along with it.
+fn resolve_cache_dir(cli_overrides: &HashMap<String, String>, paths: &path::Paths) -> PathBuf {
+    get_path_override(cli_overrides, "cache_dir", "SHINRAN_CACHE_DIR")
+        .unwrap_or_else(|| paths.runtime.clone())
 }
 
 // This will be our serializer wrappper, it just contains another serializer inside of it and
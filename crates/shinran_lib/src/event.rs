@@ -17,8 +17,19 @@
  * along with espanso.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use std::sync::atomic::{AtomicU32, Ordering};
+
 pub type SourceId = u32;
 
+/// The next [`SourceId`] to hand out, shared by every source of events (keyboard, config
+/// watcher, ...) so ids stay unique and monotonically increasing across the whole process.
+static NEXT_SOURCE_ID: AtomicU32 = AtomicU32::new(1);
+
+/// Allocate a fresh, process-wide unique [`SourceId`] for a new chain of events.
+pub fn next_source_id() -> SourceId {
+    NEXT_SOURCE_ID.fetch_add(1, Ordering::Relaxed)
+}
+
 #[derive(Debug, Clone)]
 pub struct Event {
     // The source id is a unique, monothonically increasing number
@@ -47,6 +58,10 @@ pub enum EventType {
     Exit(ExitMode),
     // ShowText(ui::ShowTextEvent),
     ShowLogs,
+    /// A config/match-file change was detected, debounced, and successfully recompiled; the
+    /// [`crate::Backend`] behind the running engine has already been swapped to the new config
+    /// by the time this is emitted. See [`crate::watch`].
+    ConfigReloaded,
 }
 
 #[derive(Debug, Clone)]
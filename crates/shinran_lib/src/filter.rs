@@ -0,0 +1,239 @@
+/*
+ * This file is part of espanso.
+ *
+ * Copyright (C) 2019-2021 Federico Terzi
+ *
+ * espanso is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * espanso is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with espanso.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Compiled, evaluable counterpart to [`shinran_types::MatchFilter`], plus the live-state
+//! evaluator for [`shinran_types::Filter`] (a match's optional `condition`).
+//!
+//! The raw filter kept on a match just lists glob pattern strings, so it can round-trip through
+//! rkyv/serde. [`CompiledFilter::compile`] turns those strings into `globset::GlobSet`s once,
+//! when [`crate::match_cache::MatchCache`] builds its trigger map, so the hot trigger-lookup path
+//! (`accepts`) only ever evaluates already-built matchers.
+
+use std::collections::HashMap;
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use shinran_types::{Filter, MatchFilter, Value};
+
+/// Whether a match is available for the current foreground app, evaluated against an
+/// app-identity string combining the window class, title, and exec path.
+#[derive(Debug, Clone)]
+pub(crate) enum CompiledFilter {
+    Always,
+    Include(GlobSet),
+    Exclude(GlobSet),
+    Union(Vec<CompiledFilter>),
+    Difference(Box<CompiledFilter>, Box<CompiledFilter>),
+}
+
+impl CompiledFilter {
+    /// Compile a [`MatchFilter`]'s glob patterns into their `GlobSet` form.
+    pub(crate) fn compile(spec: &MatchFilter) -> Self {
+        match spec {
+            MatchFilter::Always => CompiledFilter::Always,
+            MatchFilter::Include(patterns) => CompiledFilter::Include(build_glob_set(patterns)),
+            MatchFilter::Exclude(patterns) => CompiledFilter::Exclude(build_glob_set(patterns)),
+            MatchFilter::Union(children) => {
+                CompiledFilter::Union(children.iter().map(CompiledFilter::compile).collect())
+            }
+            MatchFilter::Difference(base, excluded) => CompiledFilter::Difference(
+                Box::new(CompiledFilter::compile(base)),
+                Box::new(CompiledFilter::compile(excluded)),
+            ),
+        }
+    }
+
+    /// Whether the match this filter guards applies to `app_identity`.
+    pub(crate) fn accepts(&self, app_identity: &str) -> bool {
+        match self {
+            CompiledFilter::Always => true,
+            CompiledFilter::Include(set) => set.is_match(app_identity),
+            CompiledFilter::Exclude(set) => !set.is_match(app_identity),
+            CompiledFilter::Union(filters) => filters.iter().any(|f| f.accepts(app_identity)),
+            CompiledFilter::Difference(base, excluded) => {
+                base.accepts(app_identity) && !excluded.accepts(app_identity)
+            }
+        }
+    }
+}
+
+/// Build a `GlobSet` from `patterns`, logging and skipping any pattern that fails to parse
+/// instead of rejecting the whole match.
+fn build_glob_set(patterns: &[String]) -> GlobSet {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        match Glob::new(pattern) {
+            Ok(glob) => {
+                builder.add(glob);
+            }
+            Err(err) => log::warn!("ignoring invalid app filter pattern {pattern:?}: {err}"),
+        }
+    }
+    builder.build().unwrap_or_else(|_| {
+        GlobSetBuilder::new()
+            .build()
+            .expect("an empty GlobSet always builds")
+    })
+}
+
+/// Live state a [`Filter`] (a match's optional `condition`) is evaluated against, gathered just
+/// before a trigger is allowed to fire.
+///
+/// Real window-title detection and variable resolution aren't wired up to this call site yet
+/// (the same gap noted on [`crate::Backend::check_trigger`]'s `app_identity` parameter), so
+/// `window_title` and `vars` are currently always empty. Config loading rejects `title(...)`/
+/// `var(...)` conditions outright (see `shinran_config`'s `build_condition`) precisely because
+/// of this gap, so in practice only `app(...)`/`time(...)` leaves reach `evaluate` today --
+/// `window_title`/`vars` stay here, rather than being dropped, so [`evaluate`] and
+/// [`Filter::TitleMatches`]/[`Filter::VarEquals`] remain correct and testable once that wiring
+/// lands.
+pub(crate) struct FilterContext<'a> {
+    pub(crate) app_identity: &'a str,
+    pub(crate) window_title: &'a str,
+    /// Time of day, in minutes since midnight (0..1440), as [`current_time_of_day_minutes`]
+    /// reports it (UTC).
+    pub(crate) time_of_day_minutes: u16,
+    pub(crate) vars: &'a HashMap<String, Value>,
+}
+
+/// Evaluate `filter` against `ctx`, short-circuiting `And`/`Or` the same way `&&`/`||` would.
+pub(crate) fn evaluate(filter: &Filter, ctx: &FilterContext) -> bool {
+    match filter {
+        Filter::And(children) => children.iter().all(|child| evaluate(child, ctx)),
+        Filter::Or(children) => children.iter().any(|child| evaluate(child, ctx)),
+        Filter::Not(inner) => !evaluate(inner, ctx),
+        Filter::AppEquals(app) => ctx.app_identity == app,
+        Filter::TitleMatches(regex) => regex.is_match(ctx.window_title),
+        Filter::TimeBetween(start, end) => time_in_range(ctx.time_of_day_minutes, *start, *end),
+        Filter::VarEquals(name, value) => ctx.vars.get(name) == Some(value),
+    }
+}
+
+/// Current wall-clock time of day, in minutes since midnight (0..1440). This is UTC rather than
+/// truly local time: a timezone-aware clock isn't a dependency here yet, so `time(...)`
+/// conditions are the same scope-limited scaffolding as `FilterContext`'s `window_title`/`vars`.
+pub(crate) fn current_time_of_day_minutes() -> u16 {
+    let since_epoch = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    ((since_epoch.as_secs() % 86400) / 60) as u16
+}
+
+/// Whether `t` falls in `[start, end)`, wrapping past midnight when `start > end` (e.g.
+/// `22:00`..`06:00` covers the overnight span).
+fn time_in_range(t: u16, start: u16, end: u16) -> bool {
+    if start <= end {
+        (start..end).contains(&t)
+    } else {
+        t >= start || t < end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn always_accepts_everything() {
+        assert!(CompiledFilter::compile(&MatchFilter::Always).accepts("firefox"));
+    }
+
+    #[test]
+    fn include_only_accepts_matching_patterns() {
+        let filter = CompiledFilter::compile(&MatchFilter::Include(vec!["*firefox*".to_string()]));
+        assert!(filter.accepts("org.mozilla.firefox"));
+        assert!(!filter.accepts("org.gnome.terminal"));
+    }
+
+    #[test]
+    fn exclude_rejects_matching_patterns() {
+        let filter = CompiledFilter::compile(&MatchFilter::Exclude(vec!["*terminal*".to_string()]));
+        assert!(!filter.accepts("org.gnome.terminal"));
+        assert!(filter.accepts("org.mozilla.firefox"));
+    }
+
+    #[test]
+    fn difference_requires_base_without_excluded() {
+        let filter = CompiledFilter::compile(&MatchFilter::Difference(
+            Box::new(MatchFilter::Include(vec!["*code*".to_string()])),
+            Box::new(MatchFilter::Include(vec!["*vscode-insiders*".to_string()])),
+        ));
+        assert!(filter.accepts("code"));
+        assert!(!filter.accepts("vscode-insiders"));
+    }
+
+    #[test]
+    fn union_accepts_if_any_child_accepts() {
+        let filter = CompiledFilter::compile(&MatchFilter::Union(vec![
+            MatchFilter::Include(vec!["*firefox*".to_string()]),
+            MatchFilter::Include(vec!["*chrome*".to_string()]),
+        ]));
+        assert!(filter.accepts("firefox"));
+        assert!(filter.accepts("chrome"));
+        assert!(!filter.accepts("terminal"));
+    }
+
+    fn ctx<'a>(app_identity: &'a str, time_of_day_minutes: u16, vars: &'a HashMap<String, Value>) -> FilterContext<'a> {
+        FilterContext {
+            app_identity,
+            window_title: "",
+            time_of_day_minutes,
+            vars,
+        }
+    }
+
+    #[test]
+    fn app_equals_matches_the_app_identity_exactly() {
+        let vars = HashMap::new();
+        let filter = Filter::AppEquals("firefox".to_string());
+        assert!(evaluate(&filter, &ctx("firefox", 0, &vars)));
+        assert!(!evaluate(&filter, &ctx("org.mozilla.firefox", 0, &vars)));
+    }
+
+    #[test]
+    fn time_between_wraps_past_midnight() {
+        let vars = HashMap::new();
+        let filter = Filter::TimeBetween(22 * 60, 6 * 60);
+        assert!(evaluate(&filter, &ctx("", 23 * 60, &vars)));
+        assert!(evaluate(&filter, &ctx("", 60, &vars)));
+        assert!(!evaluate(&filter, &ctx("", 12 * 60, &vars)));
+    }
+
+    #[test]
+    fn var_equals_checks_a_resolved_variable() {
+        let mut vars = HashMap::new();
+        vars.insert("lang".to_string(), Value::String("en".to_string()));
+        let filter = Filter::VarEquals("lang".to_string(), Value::String("en".to_string()));
+        assert!(evaluate(&filter, &ctx("", 0, &vars)));
+
+        let filter = Filter::VarEquals("lang".to_string(), Value::String("fr".to_string()));
+        assert!(!evaluate(&filter, &ctx("", 0, &vars)));
+    }
+
+    #[test]
+    fn and_or_not_short_circuit_like_boolean_operators() {
+        let vars = HashMap::new();
+        let filter = Filter::And(vec![
+            Filter::AppEquals("mail".to_string()),
+            Filter::Not(Box::new(Filter::TimeBetween(22 * 60, 6 * 60))),
+        ]);
+        assert!(evaluate(&filter, &ctx("mail", 12 * 60, &vars)));
+        assert!(!evaluate(&filter, &ctx("mail", 23 * 60, &vars)));
+        assert!(!evaluate(&filter, &ctx("other", 12 * 60, &vars)));
+    }
+}
@@ -14,13 +14,25 @@ mod config;
 mod cursor;
 mod engine;
 mod event;
+mod filter;
 mod load;
+pub mod lock;
 mod match_cache;
 mod path;
 mod regex;
 mod render;
-
-pub use config::Configuration;
+mod search;
+mod suggest;
+mod trigger_index;
+pub mod watch;
+
+pub use config::{
+    resolve_paths_and_passphrase, ArchivedConfiguration, ArchivedConfigurationHandle, CacheError,
+    ConfigError, Configuration, WatcherHandle,
+};
+pub use lock::{InstanceLock, LockError};
+pub use path::Paths;
+pub use search::{SearchHit, SearchHitKind, SearchRef};
 
 fn get_regex_matches(
     _: &ProfileStore,
@@ -38,6 +50,17 @@ fn get_regex_matches(
 pub struct Backend<'store> {
     adapter: render::RendererAdapter<'store>,
     fuzzy_matcher: Arc<Mutex<nucleo_matcher::Matcher>>,
+    /// Keeps the `Configuration` that `adapter` borrows from alive for as long as this `Backend`
+    /// is, when built via [`Backend::from_config`]. `None` when built via [`Backend::new`] from
+    /// a `Configuration` the caller already keeps alive for the whole process (e.g. the `'static`
+    /// `CONFIG` in `main.rs`), which doesn't need a second owner.
+    ///
+    /// SAFETY: must stay declared after every field that (transitively) borrows from it --
+    /// fields drop in declaration order, and `from_config`'s `'static` reference is only valid
+    /// for as long as this `Arc`'s allocation is alive. Moving `_config` above `adapter`, or
+    /// adding a new borrowing field below it, would drop the `Configuration` while `adapter`
+    /// still points at it.
+    _config: Option<Arc<Configuration>>,
 }
 
 impl<'store> Backend<'store> {
@@ -54,14 +77,41 @@ impl<'store> Backend<'store> {
         Ok(Backend {
             adapter,
             fuzzy_matcher: Arc::new(Mutex::new(matcher)),
+            _config: None,
         })
     }
 
-    pub fn check_trigger(&self, trigger: &str) -> anyhow::Result<Option<String>> {
+    /// Like [`Backend::new`], but takes ownership of `config` instead of borrowing it, so the
+    /// returned `Backend<'static>` carries its own generation of `Configuration` with it and
+    /// drops it (freeing the profile store, match store, and compiled renderer/arena) once the
+    /// `Backend` itself is dropped — unlike leaking a fresh `Configuration` on every reload
+    /// (see `watch::reload`), which would grow without bound over a long-running process.
+    pub fn from_config(config: Arc<Configuration>) -> anyhow::Result<Backend<'static>> {
+        // SAFETY: `config` is retained in the returned `Backend`'s `_config` field for as long as
+        // the `Backend` lives, and the heap allocation an `Arc` points at never moves, so this
+        // reference stays valid for exactly as long as the `'static` lifetime tag promises in
+        // practice: the lifetime of `self`, not the `'static` of the actual process. This in turn
+        // relies on `_config` being declared after `adapter` in `Backend` (see the SAFETY note on
+        // that field) so `adapter`'s borrow is dropped before the `Arc` it points into.
+        let config_ref: &'static Configuration = unsafe { &*Arc::as_ptr(&config) };
+        let mut backend = Backend::new(config_ref)?;
+        backend._config = Some(config);
+        Ok(backend)
+    }
+
+    /// `app_identity` should combine the foreground window's class, title, and exec path into a
+    /// single string; it's matched against any `MatchFilter` restricting a trigger to specific
+    /// apps. Real app-identity detection isn't wired up yet (see
+    /// [`render::RendererAdapter::active_profile`]), so callers currently pass `""`.
+    pub fn check_trigger(
+        &self,
+        trigger: &str,
+        app_identity: &str,
+    ) -> anyhow::Result<Option<String>> {
         let active_profile = self.adapter.active_profile();
         let matches = self
             .adapter
-            .find_matches_from_trigger(trigger, active_profile);
+            .find_matches_from_trigger(trigger, active_profile, app_identity);
         let match_ = if let Some(match_) = matches.into_iter().next() {
             match_
         } else {
@@ -72,11 +122,67 @@ impl<'store> Backend<'store> {
                 return Ok(None);
             }
         };
+        // `match_.trigger` rather than the raw `trigger` argument, since a `right_word` match may
+        // have had a confirming separator character stripped off the end (see
+        // `match_cache::CombinedMatchCache::find_matches_from_trigger`).
         self.adapter
-            .render(match_.id, Some(trigger), match_.args, active_profile)
+            .render(match_.id, Some(&match_.trigger), match_.args, active_profile)
             .map(|body| Some(cursor::process_cursor_hint(body).0))
     }
 
+    /// Rank the known triggers (user-defined and builtin) by Levenshtein distance to `input` and
+    /// return up to `max` of them within a small edit-distance threshold, closest first. Meant
+    /// for a caller to offer "did you mean?" suggestions once [`Backend::check_trigger`] has come
+    /// back empty.
+    pub fn suggest_triggers(&self, input: &str, max: usize) -> Vec<(&str, usize)> {
+        let active_profile = self.adapter.active_profile();
+        let combined_cache = &self.adapter.combined_cache;
+        let user_triggers = combined_cache
+            .user_match_cache
+            .matches(active_profile)
+            .keys()
+            .copied();
+        let triggers: Vec<&str> = user_triggers
+            .chain(combined_cache.builtin_triggers())
+            .collect();
+        suggest::suggest(input, triggers.into_iter(), max)
+    }
+
+    /// Fuzzily rank every user trigger, builtin match, and regex match's source pattern together,
+    /// for a command-palette-style picker. Returns up to `limit` hits, highest score first, each
+    /// carrying the character indices its query matched so a caller can bold them.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<SearchHit<'store>> {
+        let active_profile = self.adapter.active_profile();
+        let combined_cache = &self.adapter.combined_cache;
+
+        let atom = get_simple_atom(query);
+        let mut matcher = self.fuzzy_matcher.lock().unwrap();
+
+        let mut hits = search::search_user_triggers(
+            &atom,
+            &mut matcher,
+            combined_cache
+                .user_match_cache
+                .matches(active_profile)
+                .iter()
+                .map(|(&trigger, &m)| (trigger, m)),
+        );
+        hits.extend(search::search_builtin_matches(
+            &atom,
+            &mut matcher,
+            combined_cache.builtin_matches(),
+        ));
+        hits.extend(search::search_regex_matches(
+            &atom,
+            &mut matcher,
+            combined_cache.regex_matcher.matches(),
+        ));
+
+        hits.sort_by(|a, b| b.score.cmp(&a.score));
+        hits.truncate(limit);
+        hits
+    }
+
     pub fn fuzzy_match(&self, trigger: &str) -> Vec<(TriggerAndRef<'store>, u16)> {
         let active_profile = self.adapter.active_profile();
         let user_matches = self
@@ -139,6 +245,33 @@ fn get_path_override(
     }
 }
 
+/// Same idea as [`get_path_override`], but for a plain string setting that isn't a filesystem
+/// path (e.g. a passphrase), so there's nothing to validate beyond trimming it.
+fn get_string_override(
+    cli_overrides: &HashMap<String, String>,
+    argument: &str,
+    env_var: &str,
+) -> Option<String> {
+    if let Some(value) = cli_overrides.get(argument) {
+        Some(value.trim().to_string())
+    } else {
+        std::env::var(env_var).ok().map(|value| value.trim().to_string())
+    }
+}
+
+/// Same idea as [`get_string_override`], but parsed as a boolean flag: `"1"`/`"true"`/`"yes"`
+/// (case-insensitive) is `true`, any other value present is `false`. Returns `None` if neither
+/// the CLI override nor the env var was set, so the caller can fall back to its own default
+/// instead of treating "unset" the same as "explicitly off".
+fn get_bool_override(
+    cli_overrides: &HashMap<String, String>,
+    argument: &str,
+    env_var: &str,
+) -> Option<bool> {
+    get_string_override(cli_overrides, argument, env_var)
+        .map(|value| matches!(value.to_lowercase().as_str(), "1" | "true" | "yes"))
+}
+
 #[cfg(test)]
 mod tests {
     use std::path::Path;
@@ -175,7 +308,7 @@ mod tests {
             "config_dir".to_string(),
             base_path.to_str().unwrap().to_string(),
         );
-        Configuration::new(&cli_overrides)
+        Configuration::new(&cli_overrides).unwrap()
     }
 
     #[test]
@@ -188,7 +321,24 @@ mod tests {
             "#;
             let stores = make_stores(match_definition, base_path, match_dir, config_dir);
             let backend = Backend::new(&stores).unwrap();
-            let result = backend.check_trigger("hello").unwrap().unwrap();
+            let result = backend.check_trigger("hello", "").unwrap().unwrap();
+            assert_eq!(result, "world");
+        });
+    }
+
+    #[test]
+    fn test_right_word_boundary_requires_a_trailing_separator() {
+        use_test_directory(|base_path, match_dir, config_dir| {
+            let match_definition = r#"
+                    matches:
+                      - trigger: "hi"
+                        replace: "world"
+                        right_word: true
+            "#;
+            let stores = make_stores(match_definition, base_path, match_dir, config_dir);
+            let backend = Backend::new(&stores).unwrap();
+            assert!(backend.check_trigger("hi", "").unwrap().is_none());
+            let result = backend.check_trigger("hi ", "").unwrap().unwrap();
             assert_eq!(result, "world");
         });
     }
@@ -203,7 +353,7 @@ mod tests {
             "#;
             let stores = make_stores(match_definition, base_path, match_dir, config_dir);
             let backend = Backend::new(&stores).unwrap();
-            let result = backend.check_trigger("greet(Bob)").unwrap().unwrap();
+            let result = backend.check_trigger("greet(Bob)", "").unwrap().unwrap();
             assert_eq!(result, "Hi Bob!");
         });
     }
@@ -223,7 +373,7 @@ mod tests {
             "#;
             let stores = make_stores(match_definition, base_path, match_dir, config_dir);
             let backend = Backend::new(&stores).unwrap();
-            backend.check_trigger("now").unwrap().unwrap();
+            backend.check_trigger("now", "").unwrap().unwrap();
             // assert_eq!(result, "It's 14:45");
         });
     }
@@ -244,7 +394,7 @@ mod tests {
             "#;
             let stores = make_stores(match_definition, base_path, match_dir, config_dir);
             let backend = Backend::new(&stores).unwrap();
-            let result = backend.check_trigger(":hello").unwrap().unwrap();
+            let result = backend.check_trigger(":hello", "").unwrap().unwrap();
             assert_eq!(result, "hello Jon");
         });
     }
@@ -274,7 +424,7 @@ mod tests {
             "#;
             let stores = make_stores(match_definition, base_path, match_dir, config_dir);
             let backend = Backend::new(&stores).unwrap();
-            let result = backend.check_trigger(":hello").unwrap().unwrap();
+            let result = backend.check_trigger(":hello", "").unwrap().unwrap();
             assert_eq!(result, "hello Jon Snow");
         });
     }
@@ -297,7 +447,7 @@ mod tests {
             "#;
             let stores = make_stores(match_definition, base_path, match_dir, config_dir);
             let backend = Backend::new(&stores).unwrap();
-            let result = backend.check_trigger(":nested").unwrap().unwrap();
+            let result = backend.check_trigger(":nested", "").unwrap().unwrap();
             assert_eq!(result, "This is a nested match");
         });
     }
@@ -320,7 +470,7 @@ mod tests {
             "#;
             let stores = make_stores(match_definition, base_path, match_dir, config_dir);
             let backend = Backend::new(&stores).unwrap();
-            let result = backend.check_trigger(":greet2").unwrap().unwrap();
+            let result = backend.check_trigger(":greet2", "").unwrap().unwrap();
             assert_eq!(result, "This is a nested match");
         });
     }
@@ -344,7 +494,7 @@ mod tests {
             let stores = make_stores(match_definition, base_path, match_dir, config_dir);
             let backend = Backend::new(&stores).unwrap();
             // TODO: Figure out whether this should be an error or not.
-            backend.check_trigger(":nested").unwrap_err();
+            backend.check_trigger(":nested", "").unwrap_err();
         });
     }
 
@@ -358,9 +508,9 @@ mod tests {
             "#;
             let stores = make_stores(match_definition, base_path, match_dir, config_dir);
             let backend = Backend::new(&stores).unwrap();
-            let result = backend.check_trigger(":euro").unwrap().unwrap();
+            let result = backend.check_trigger(":euro", "").unwrap().unwrap();
             assert_eq!(result, "€");
-            let result = backend.check_trigger(":Euro").unwrap_err();
+            let result = backend.check_trigger(":Euro", "").unwrap_err();
             assert_eq!(result.to_string(), "match not found");
         });
     }
@@ -376,11 +526,11 @@ mod tests {
             "#;
             let stores = make_stores(match_definition, base_path, match_dir, config_dir);
             let backend = Backend::new(&stores).unwrap();
-            let result = backend.check_trigger("alh").unwrap().unwrap();
+            let result = backend.check_trigger("alh", "").unwrap().unwrap();
             assert_eq!(result, "although");
-            let result = backend.check_trigger("Alh").unwrap().unwrap();
+            let result = backend.check_trigger("Alh", "").unwrap().unwrap();
             assert_eq!(result, "Although");
-            let result = backend.check_trigger("ALH").unwrap().unwrap();
+            let result = backend.check_trigger("ALH", "").unwrap().unwrap();
             assert_eq!(result, "ALTHOUGH");
         });
     }
@@ -397,9 +547,9 @@ mod tests {
             "#;
             let stores = make_stores(match_definition, base_path, match_dir, config_dir);
             let backend = Backend::new(&stores).unwrap();
-            let result = backend.check_trigger(";ols").unwrap().unwrap();
+            let result = backend.check_trigger(";ols", "").unwrap().unwrap();
             assert_eq!(result, "ordinary least squares");
-            let result = backend.check_trigger(";Ols").unwrap().unwrap();
+            let result = backend.check_trigger(";Ols", "").unwrap().unwrap();
             assert_eq!(result, "Ordinary Least Squares");
         });
     }
@@ -414,9 +564,9 @@ mod tests {
         "#;
             let stores = make_stores(match_definition, base_path, match_dir, config_dir);
             let backend = Backend::new(&stores).unwrap();
-            let result = backend.check_trigger("hello").unwrap().unwrap();
+            let result = backend.check_trigger("hello", "").unwrap().unwrap();
             assert_eq!(result, "world");
-            let result = backend.check_trigger("hi").unwrap().unwrap();
+            let result = backend.check_trigger("hi", "").unwrap().unwrap();
             assert_eq!(result, "world");
         });
     }
@@ -0,0 +1,200 @@
+/*
+ * This file is part of espanso.
+ *
+ * Copyright (C) 2019-2021 Federico Terzi
+ *
+ * espanso is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * espanso is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with espanso.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Single-instance lock for `Paths::runtime`, so two `shinran` processes don't both try to
+//! register the same IBus engine and leave the D-Bus name half-claimed.
+//!
+//! The lock is a `shinran.lock` file holding the owning process's PID. A crashed instance leaves
+//! this file behind, so [`InstanceLock::acquire`] checks whether the recorded PID is still alive
+//! before refusing to start: it opens a pidfd for the PID, which (unlike a bare `kill(pid, 0)`)
+//! can't be fooled by the PID having been recycled by an unrelated process started later, falling
+//! back to a signal-0 existence probe on kernels too old to support pidfds (pre-5.3). If the
+//! holder is dead, the lock is reclaimed in place.
+//!
+//! Creating the lock file is atomic (`OpenOptions::create_new`), not a separate
+//! read-then-write: two instances launched at nearly the same time must not both observe no live
+//! holder and both believe they won the race.
+
+use std::{
+    fs, io,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use log::{info, warn};
+use thiserror::Error;
+
+const LOCK_FILE_NAME: &str = "shinran.lock";
+
+#[derive(Error, Debug)]
+pub enum LockError {
+    #[error("another shinran instance is already running (pid {0})")]
+    AlreadyRunning(u32),
+
+    #[error("unable to access lock file: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// Holds the single-instance lock for as long as this process is alive. Dropping it (including
+/// via the normal `Destroy`/exit path unwinding back out of `main`) releases the lock by deleting
+/// `shinran.lock`, so a subsequent start doesn't have to reclaim a stale file.
+pub struct InstanceLock {
+    path: PathBuf,
+}
+
+impl InstanceLock {
+    /// Acquire the single-instance lock in `runtime_dir`. If a lock file already exists but the
+    /// PID it records is no longer running, it's treated as stale and reclaimed; otherwise this
+    /// returns [`LockError::AlreadyRunning`].
+    pub fn acquire(runtime_dir: &Path) -> Result<InstanceLock, LockError> {
+        let path = runtime_dir.join(LOCK_FILE_NAME);
+
+        loop {
+            match create_lock_file(&path) {
+                Ok(()) => return Ok(InstanceLock { path }),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                    // Another process (or a stale file) beat us to creating it; fall through to
+                    // decide whether that holder is still alive.
+                }
+                Err(err) => return Err(err.into()),
+            }
+
+            let Some(pid) = read_lock_pid(&path)? else {
+                // The file vanished between our failed create and this read (e.g. the previous
+                // holder just exited and cleaned up); just retry the atomic create.
+                continue;
+            };
+            if is_process_alive(pid) {
+                return Err(LockError::AlreadyRunning(pid));
+            }
+            info!("previous instance (pid {pid}) is no longer running, reclaiming stale lock");
+            if let Err(err) = fs::remove_file(&path) {
+                if err.kind() != io::ErrorKind::NotFound {
+                    return Err(err.into());
+                }
+            }
+        }
+    }
+}
+
+/// Atomically create and write the lock file, failing with [`io::ErrorKind::AlreadyExists`] if
+/// another process already holds it. Using `create_new` makes "does a lock file exist" and
+/// "write ours" a single kernel operation instead of a separate check followed by a separate
+/// write, so two processes racing to acquire the lock can't both observe no holder and both
+/// succeed.
+fn create_lock_file(path: &Path) -> io::Result<()> {
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)?;
+    file.write_all(std::process::id().to_string().as_bytes())
+}
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_file(&self.path) {
+            warn!("unable to remove lock file {:?}: {err}", self.path);
+        }
+    }
+}
+
+/// Read and parse the PID recorded in `path`, treating a missing file as "no lock held".
+fn read_lock_pid(path: &Path) -> io::Result<Option<u32>> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(contents.trim().parse().ok()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Check whether `pid` still refers to a running process, preferring a pidfd (immune to the
+/// classic PID-reuse race) and falling back to a signal-0 probe when `pidfd_open` isn't
+/// available.
+fn is_process_alive(pid: u32) -> bool {
+    use rustix::process::{pidfd_open, test_kill_process, Pid, PidfdFlags};
+
+    let Some(pid) = Pid::from_raw(pid as i32) else {
+        return false;
+    };
+
+    match pidfd_open(pid, PidfdFlags::empty()) {
+        Ok(_pidfd) => true,
+        Err(rustix::io::Errno::NOSYS) => test_kill_process(pid).is_ok(),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_lock_pid_missing_file_is_none() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(LOCK_FILE_NAME);
+        assert_eq!(read_lock_pid(&path).unwrap(), None);
+    }
+
+    #[test]
+    fn read_lock_pid_garbage_content_is_none() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(LOCK_FILE_NAME);
+        fs::write(&path, "not a pid").unwrap();
+        assert_eq!(read_lock_pid(&path).unwrap(), None);
+    }
+
+    #[test]
+    fn read_lock_pid_parses_a_valid_pid() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(LOCK_FILE_NAME);
+        fs::write(&path, "1234").unwrap();
+        assert_eq!(read_lock_pid(&path).unwrap(), Some(1234));
+    }
+
+    #[test]
+    fn acquire_fails_while_the_holder_is_still_alive() {
+        let dir = tempfile::tempdir().unwrap();
+        let lock = InstanceLock::acquire(dir.path()).unwrap();
+        let err = InstanceLock::acquire(dir.path()).unwrap_err();
+        assert!(matches!(err, LockError::AlreadyRunning(pid) if pid == std::process::id()));
+        drop(lock);
+    }
+
+    #[test]
+    fn acquire_reclaims_a_stale_lock_left_by_a_dead_pid() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(LOCK_FILE_NAME);
+        // A PID vanishingly unlikely to refer to a live process right now.
+        fs::write(&path, "999999999").unwrap();
+
+        let lock = InstanceLock::acquire(dir.path()).unwrap();
+        assert_eq!(read_lock_pid(&lock.path).unwrap(), Some(std::process::id()));
+    }
+
+    #[test]
+    fn drop_removes_the_lock_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(LOCK_FILE_NAME);
+
+        let lock = InstanceLock::acquire(dir.path()).unwrap();
+        assert!(path.exists());
+        drop(lock);
+        assert!(!path.exists());
+    }
+}
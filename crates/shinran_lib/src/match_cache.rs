@@ -17,20 +17,38 @@
  * along with espanso.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use std::borrow::Cow;
 use std::collections::HashMap;
 
 use shinran_config::{
     config::ProfileFile, config::ProfileRef, config::ProfileStore, matches::store::MatchStore,
 };
-use shinran_types::{MatchRef, RegexMatch, TriggerMatch, Variable};
+use shinran_types::{Filter, MatchRef, RegexMatch, TriggerMatch, Variable, WordBoundary};
 
 use crate::engine::DetectedMatch;
+use crate::filter::{self, CompiledFilter, FilterContext};
 use crate::regex::RegexMatcher;
+use crate::trigger_index::TriggerIndex;
 
 use super::builtin::BuiltInMatch;
 
 pub struct MatchCache<'store> {
     trigger_profiles: HashMap<ProfileRef, HashMap<&'store str, &'store TriggerMatch>>,
+    // Keyed in lockstep with `trigger_profiles`: one `CompiledFilter` per trigger, precompiled
+    // here so [`CombinedMatchCache::find_matches_from_trigger`] only ever evaluates a `GlobSet`
+    // match, never parses a pattern on the hot path.
+    trigger_filters: HashMap<ProfileRef, HashMap<&'store str, CompiledFilter>>,
+    // One `&Filter` per trigger that declares a `condition`, borrowed straight from the match
+    // (unlike `trigger_filters`, there's no compilation step -- a `Filter` is already directly
+    // evaluable), consulted alongside `trigger_filters` in `find_matches_from_trigger`.
+    trigger_conditions: HashMap<ProfileRef, HashMap<&'store str, &'store Filter>>,
+    // An Aho-Corasick automaton over the same triggers as `trigger_profiles`, so the longest
+    // trigger ending at the end of the typed buffer can be found in a single pass instead of
+    // testing the whole buffer against every registered trigger in turn.
+    trigger_index: HashMap<ProfileRef, TriggerIndex<'store>>,
+    // A parallel automaton over every trigger lowercased, consulted for `propagate_case`
+    // matches typed in the wrong case; see `CombinedMatchCache::lookup_user_trigger`.
+    trigger_index_casefold: HashMap<ProfileRef, TriggerIndex<'store>>,
     // TODO: This should be a hash map of `RegexMatcher`s.
     regex_profiles: HashMap<ProfileRef, Vec<&'store RegexMatch>>,
     global_var_profiles: HashMap<ProfileRef, HashMap<&'store str, &'store Variable>>,
@@ -39,20 +57,48 @@ pub struct MatchCache<'store> {
 impl<'store> MatchCache<'store> {
     pub fn load(profile_store: &'store ProfileStore, match_store: &'store MatchStore) -> Self {
         let mut trigger_profiles = HashMap::new();
+        let mut trigger_filters = HashMap::new();
+        let mut trigger_conditions = HashMap::new();
+        let mut trigger_index = HashMap::new();
+        let mut trigger_index_casefold = HashMap::new();
         let mut regex_profiles = HashMap::new();
         let mut global_var_profiles = HashMap::new();
 
         for profile_ref in profile_store.all_configs() {
             let profile = profile_store.get(profile_ref);
-            let (trigger_map, global_var_map, regex_matches) =
+            let (trigger_map, filter_map, condition_map, global_var_map, regex_matches) =
                 create_profile_cache(profile, match_store);
+
+            trigger_index.insert(
+                profile_ref,
+                TriggerIndex::build(
+                    trigger_map
+                        .iter()
+                        .map(|(&trigger, &m)| (Cow::Borrowed(trigger), m)),
+                ),
+            );
+            trigger_index_casefold.insert(
+                profile_ref,
+                TriggerIndex::build(
+                    trigger_map
+                        .iter()
+                        .map(|(&trigger, &m)| (Cow::Owned(trigger.to_ascii_lowercase()), m)),
+                ),
+            );
+
             trigger_profiles.insert(profile_ref, trigger_map);
+            trigger_filters.insert(profile_ref, filter_map);
+            trigger_conditions.insert(profile_ref, condition_map);
             regex_profiles.insert(profile_ref, regex_matches);
             global_var_profiles.insert(profile_ref, global_var_map);
         }
 
         Self {
             trigger_profiles,
+            trigger_filters,
+            trigger_conditions,
+            trigger_index,
+            trigger_index_casefold,
             regex_profiles,
             global_var_profiles,
         }
@@ -62,6 +108,25 @@ impl<'store> MatchCache<'store> {
         &self.trigger_profiles[&profile_ref]
     }
 
+    /// The precompiled app filter guarding `trigger` in `profile_ref`, if that trigger exists.
+    pub(crate) fn filter(&self, profile_ref: ProfileRef, trigger: &str) -> Option<&CompiledFilter> {
+        self.trigger_filters.get(&profile_ref)?.get(trigger)
+    }
+
+    /// The `condition` guarding `trigger` in `profile_ref`, if that trigger exists and declares
+    /// one.
+    pub(crate) fn condition(&self, profile_ref: ProfileRef, trigger: &str) -> Option<&'store Filter> {
+        self.trigger_conditions.get(&profile_ref)?.get(trigger).copied()
+    }
+
+    fn trigger_index(&self, profile_ref: ProfileRef) -> &TriggerIndex<'store> {
+        &self.trigger_index[&profile_ref]
+    }
+
+    fn trigger_index_casefold(&self, profile_ref: ProfileRef) -> &TriggerIndex<'store> {
+        &self.trigger_index_casefold[&profile_ref]
+    }
+
     pub fn regex_matches(&self, profile_ref: ProfileRef) -> &Vec<&'store RegexMatch> {
         &self.regex_profiles[&profile_ref]
     }
@@ -76,10 +141,14 @@ fn create_profile_cache<'store>(
     match_store: &'store MatchStore,
 ) -> (
     HashMap<&'store str, &'store TriggerMatch>,
+    HashMap<&'store str, CompiledFilter>,
+    HashMap<&'store str, &'store Filter>,
     HashMap<&'store str, &'store Variable>,
     Vec<&'store RegexMatch>,
 ) {
     let mut trigger_map = HashMap::new();
+    let mut filter_map = HashMap::new();
+    let mut condition_map = HashMap::new();
     let mut global_var_map = HashMap::new();
 
     let file_paths = profile.match_file_paths();
@@ -87,8 +156,13 @@ fn create_profile_cache<'store>(
 
     for m in collection.trigger_matches {
         let triggers = &m.triggers;
+        let filter = CompiledFilter::compile(&m.base_match.app_filter);
         for trigger in triggers {
             trigger_map.insert(trigger.as_str(), m);
+            filter_map.insert(trigger.as_str(), filter.clone());
+            if let Some(condition) = &m.base_match.condition {
+                condition_map.insert(trigger.as_str(), condition);
+            }
         }
     }
 
@@ -97,7 +171,13 @@ fn create_profile_cache<'store>(
         global_var_map.insert(var_name, var);
     }
 
-    (trigger_map, global_var_map, collection.regex_matches)
+    (
+        trigger_map,
+        filter_map,
+        condition_map,
+        global_var_map,
+        collection.regex_matches,
+    )
 }
 
 pub struct CombinedMatchCache<'store> {
@@ -133,6 +213,20 @@ impl<'store> CombinedMatchCache<'store> {
         }
     }
 
+    /// Every builtin trigger, across all builtin matches. Used by [`crate::suggest`] to include
+    /// builtins among the candidates offered for a mistyped trigger.
+    pub(crate) fn builtin_triggers(&self) -> impl Iterator<Item = &str> {
+        self.builtin_match_cache
+            .values()
+            .flat_map(|m| m.triggers.iter().map(String::as_str))
+    }
+
+    /// Every builtin match. Used by [`crate::search`] to rank builtin actions alongside user
+    /// triggers and regex matches.
+    pub(crate) fn builtin_matches(&self) -> impl Iterator<Item = &BuiltInMatch> {
+        self.builtin_match_cache.values()
+    }
+
     // pub fn get(&self, match_id: usize) -> Option<MatchVariant<'_>> {
     //     if let Some(user_match) = self.user_match_cache.cache.get(&match_id) {
     //         return Some(MatchVariant::User(user_match));
@@ -173,38 +267,103 @@ impl<'store> CombinedMatchCache<'store> {
     //     ids
     // }
 
+    /// The longest registered trigger ending at the end of `buffer`, searched first in exact
+    /// case, then (if nothing matched) case-folded -- the case-folded hit is only considered a
+    /// match if the resulting `TriggerMatch`'s `propagate_case` is set, which is checked during
+    /// rendering rather than here. `stem_lookup` selects which `word_boundary` values are
+    /// eligible: `false` for a plain lookup against the raw buffer (anything but a `right_word`
+    /// requirement, which can't yet be confirmed), `true` for a lookup against a buffer that's
+    /// already had its trailing separator peeled off (only a `right_word` requirement, now that
+    /// the separator confirms it). Either way, a `left_word` requirement is checked against
+    /// whatever character in `buffer` (if any) immediately precedes the matched trigger.
+    fn lookup_user_trigger(
+        &self,
+        buffer: &str,
+        active_profile: ProfileRef,
+        accepted: &impl Fn(&str) -> bool,
+        stem_lookup: bool,
+    ) -> Option<(&'store TriggerMatch, &str)> {
+        let boundary_eligible = |word_boundary: WordBoundary| {
+            matches!(word_boundary, WordBoundary::Right | WordBoundary::Both) == stem_lookup
+        };
+
+        if let Some((m, len_chars, left_boundary_ok)) =
+            scan_index(buffer, self.user_match_cache.trigger_index(active_profile))
+        {
+            let text = tail_chars(buffer, len_chars);
+            if left_boundary_ok && boundary_eligible(m.word_boundary) && accepted(text) {
+                return Some((m, text));
+            }
+        }
+
+        let lowercase_buffer = buffer.to_ascii_lowercase();
+        if let Some((m, len_chars, left_boundary_ok)) = scan_index(
+            &lowercase_buffer,
+            self.user_match_cache.trigger_index_casefold(active_profile),
+        ) {
+            let text = tail_chars(buffer, len_chars);
+            if left_boundary_ok
+                && boundary_eligible(m.word_boundary)
+                && accepted(&text.to_ascii_lowercase())
+            {
+                return Some((m, text));
+            }
+        }
+
+        None
+    }
+
     pub(crate) fn find_matches_from_trigger(
         &self,
         trigger: &str,
         active_profile: ProfileRef,
+        app_identity: &str,
     ) -> Vec<DetectedMatch> {
-        let mut user_matches: Option<DetectedMatch> = self
-            .user_match_cache
-            .matches(active_profile)
-            .get(trigger)
-            .map(|&m| DetectedMatch {
+        let no_vars = HashMap::new();
+        let filter_ctx = FilterContext {
+            app_identity,
+            window_title: "",
+            time_of_day_minutes: filter::current_time_of_day_minutes(),
+            vars: &no_vars,
+        };
+        let accepted = |key: &str| {
+            self.user_match_cache
+                .filter(active_profile, key)
+                .map_or(true, |filter| filter.accepts(app_identity))
+                && self
+                    .user_match_cache
+                    .condition(active_profile, key)
+                    .map_or(true, |condition| filter::evaluate(condition, &filter_ctx))
+        };
+
+        // `trigger` is the whole buffer typed since the last commit; `lookup_user_trigger` finds
+        // the longest registered trigger ending at its end in a single automaton pass, so a
+        // match no longer needs the entire buffer to equal it exactly. A `right_word`
+        // requirement needs one more separator character typed *after* the trigger to be
+        // confirmed, so it's only satisfied by the second candidate below, built from the
+        // buffer with that trailing character peeled off.
+        let mut user_matches = self
+            .lookup_user_trigger(trigger, active_profile, &accepted, false)
+            .map(|(m, text)| DetectedMatch {
                 id: MatchRef::Trigger(m),
-                trigger: trigger.to_string(),
+                trigger: text.to_string(),
                 left_separator: None,
                 right_separator: None,
                 args: HashMap::new(),
             });
 
         if user_matches.is_none() {
-            // Try making the trigger lowercase.
-            // However, this is only considered a match if `propagate_case` is set to true.
-            // This needs to be checked during the rendering.
-            user_matches = self
-                .user_match_cache
-                .matches(active_profile)
-                .get(&trigger.to_ascii_lowercase()[..])
-                .map(|&m| DetectedMatch {
-                    id: MatchRef::Trigger(m),
-                    trigger: trigger.to_string(),
-                    left_separator: None,
-                    right_separator: None,
-                    args: HashMap::new(),
-                });
+            if let Some((stem, separator)) = strip_trailing_separator(trigger) {
+                user_matches = self
+                    .lookup_user_trigger(stem, active_profile, &accepted, true)
+                    .map(|(m, text)| DetectedMatch {
+                        id: MatchRef::Trigger(m),
+                        trigger: text.to_string(),
+                        left_separator: None,
+                        right_separator: Some(separator.to_string()),
+                        args: HashMap::new(),
+                    });
+            }
         }
 
         let builtin_matches: Vec<DetectedMatch> = self
@@ -233,3 +392,51 @@ impl<'store> CombinedMatchCache<'store> {
         matches
     }
 }
+
+/// Run `index` against `buffer` and return `(match, trigger_len_chars, left_boundary_ok)`, where
+/// `left_boundary_ok` is whether the character (if any) in `buffer` immediately preceding the
+/// matched trigger is not itself part of a word -- i.e. whether a `left_word` requirement on the
+/// match is satisfied.
+fn scan_index<'store>(
+    buffer: &str,
+    index: &TriggerIndex<'store>,
+) -> Option<(&'store TriggerMatch, usize, bool)> {
+    let (m, len_chars) = index.longest_match_at_end(buffer)?;
+    let start_byte = tail_start_byte(buffer, len_chars);
+    let prev_is_word_char = buffer[..start_byte]
+        .chars()
+        .next_back()
+        .is_some_and(|c| c.is_alphanumeric() || c == '_');
+    Some((m, len_chars, start_byte == 0 || !prev_is_word_char))
+}
+
+/// The byte offset of `buffer`'s last `len_chars` characters.
+fn tail_start_byte(buffer: &str, len_chars: usize) -> usize {
+    let start = buffer.chars().count().saturating_sub(len_chars);
+    buffer
+        .char_indices()
+        .nth(start)
+        .map_or(buffer.len(), |(i, _)| i)
+}
+
+/// `buffer`'s last `len_chars` characters, in `buffer`'s own casing -- used to recover the
+/// originally typed text of a match found via a case-folded [`TriggerIndex`].
+fn tail_chars(buffer: &str, len_chars: usize) -> &str {
+    &buffer[tail_start_byte(buffer, len_chars)..]
+}
+
+/// If `buffer` ends in a character that isn't part of a word, split it off and return
+/// `(text_before_it, that_character)` -- a candidate stem/separator pair for a `right_word`
+/// match that buffer's trailing character just confirmed.
+fn strip_trailing_separator(buffer: &str) -> Option<(&str, char)> {
+    let last = buffer.chars().next_back()?;
+    if last.is_alphanumeric() || last == '_' {
+        return None;
+    }
+    let stem = &buffer[..buffer.len() - last.len_utf8()];
+    if stem.is_empty() {
+        None
+    } else {
+        Some((stem, last))
+    }
+}
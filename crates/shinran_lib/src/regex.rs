@@ -20,18 +20,28 @@
 use std::collections::HashMap;
 
 use log::error;
-use regex::{Regex, RegexSet};
-use shinran_types::{MatchIdx, RegexMatch};
+use regex::RegexSet;
+use shinran_types::{MatchIdx, RegexMatch, RegexWrapper};
 
 use crate::engine::DetectedMatch;
 
 pub struct RegexMatcher<'store> {
     ids: Vec<&'store RegexMatch>,
-    // The RegexSet is used to efficiently determine which regexes match
-    regex_set: RegexSet,
 
-    // The single regexes are then used to find the captures
-    regexes: Vec<Regex>,
+    // The single regexes are then used to find the captures. `RegexWrapper` (rather than a plain
+    // `regex::Regex`) so a trigger can use lookaround/backreferences once it's past the `RegexSet`
+    // prefilter, which only needs to know "does something match", not which group did.
+    regexes: Vec<RegexWrapper>,
+
+    // The RegexSet is used to efficiently determine which of the `regexes` that `regex` itself
+    // can compile are worth checking at all. Only indices into `ids`/`regexes` whose pattern
+    // compiled under plain `regex` go in here (in the same relative order); a pattern that needs
+    // lookaround/backreferences (only `fancy_regex`, via `RegexWrapper`, can compile it) can't be
+    // represented in a `RegexSet` at all, so it's tracked in `unfiltered_indices` instead and
+    // checked directly on every lookup.
+    regex_set: RegexSet,
+    prefiltered_indices: Vec<usize>,
+    unfiltered_indices: Vec<usize>,
 }
 
 impl<'store> RegexMatcher<'store> {
@@ -74,53 +84,89 @@ impl<'store> RegexMatcher<'store> {
     pub fn find_matches(&self, trigger: &str) -> Vec<DetectedMatch> {
         let mut matches = Vec::new();
 
-        for index in self.regex_set.matches(trigger) {
-            let (Some(id), Some(regex)) = (self.ids.get(index), self.regexes.get(index)) else {
+        for set_index in self.regex_set.matches(trigger) {
+            let Some(&index) = self.prefiltered_indices.get(set_index) else {
                 error!(
                     "received inconsistent index from regex set with index: {}",
-                    index
+                    set_index
                 );
                 continue;
             };
+            self.push_match_at(index, trigger, &mut matches);
+        }
 
-            let Some(captures) = regex.captures(trigger) else {
-                continue;
-            };
+        for &index in &self.unfiltered_indices {
+            self.push_match_at(index, trigger, &mut matches);
+        }
 
-            let full_match = captures.get(0).map_or("", |m| m.as_str());
-            if !full_match.is_empty() {
-                // Now extract the captured names as variables
-                let variables: HashMap<String, String> = regex
-                    .capture_names()
-                    .flatten()
-                    .filter_map(|n| Some((n.to_string(), captures.name(n)?.as_str().to_string())))
-                    .collect();
-
-                let result = DetectedMatch {
-                    id: MatchIdx::Regex(*id),
-                    trigger: full_match.to_string(),
-                    left_separator: None,
-                    right_separator: None,
-                    args: variables,
-                };
-
-                matches.push(result);
+        matches
+    }
+
+    /// Check the regex at `index` against `trigger` and, if it matches, append the
+    /// [`DetectedMatch`] it produces to `matches`.
+    fn push_match_at(&self, index: usize, trigger: &str, matches: &mut Vec<DetectedMatch<'store>>) {
+        let (Some(id), Some(regex)) = (self.ids.get(index), self.regexes.get(index)) else {
+            error!("received inconsistent index into regex matcher: {}", index);
+            return;
+        };
+
+        let Some(full_match) = regex.find(trigger) else {
+            return;
+        };
+
+        if !full_match.is_empty() {
+            // Expose every capture group as a variable, both by number (`$1`, `$2`, ...,
+            // for the body renderer) and by name (`${word}`) when the group has one.
+            let mut variables: HashMap<String, String> = HashMap::new();
+            if let Some(captures) = regex.captures(trigger) {
+                for (index, (name, text)) in captures.into_iter().enumerate() {
+                    variables.insert((index + 1).to_string(), text.clone());
+                    if let Some(name) = name {
+                        variables.insert(name, text);
+                    }
+                }
             }
+
+            matches.push(DetectedMatch {
+                id: MatchIdx::Regex(*id),
+                trigger: full_match.to_string(),
+                left_separator: None,
+                right_separator: None,
+                args: variables,
+            });
         }
-        matches
+    }
+
+    /// Every regex match this matcher was built from. Used by [`crate::search`] to rank regex
+    /// matches (by their source pattern) alongside user triggers and builtin actions.
+    pub(crate) fn matches(&self) -> impl Iterator<Item = &'store RegexMatch> + '_ {
+        self.ids.iter().copied()
     }
 
     pub fn new(matches: Vec<&'store RegexMatch>) -> Self {
         let mut ids = Vec::new();
         let mut regexes = Vec::new();
-        let mut good_regexes = Vec::new();
+        let mut prefilterable_patterns = Vec::new();
+        let mut prefiltered_indices = Vec::new();
+        let mut unfiltered_indices = Vec::new();
 
         for m in matches {
-            match Regex::new(&m.regex) {
+            match RegexWrapper::new(&m.regex) {
                 Ok(regex) => {
-                    good_regexes.push(&m.regex);
+                    let index = ids.len();
                     ids.push(m);
                     regexes.push(regex);
+
+                    // Only a pattern plain `regex` can also compile belongs in the `RegexSet`
+                    // prefilter -- one that needs lookaround/backreferences (only `fancy_regex`
+                    // supports those) would make `RegexSet::new` itself fail, so it's checked
+                    // directly on every lookup instead (see `unfiltered_indices` above).
+                    if regex::Regex::new(&m.regex).is_ok() {
+                        prefilterable_patterns.push(m.regex.as_str());
+                        prefiltered_indices.push(index);
+                    } else {
+                        unfiltered_indices.push(index);
+                    }
                 }
                 Err(err) => {
                     error!("unable to compile regex: '{}', error: {:?}", m.regex, err);
@@ -128,12 +174,15 @@ impl<'store> RegexMatcher<'store> {
             }
         }
 
-        let regex_set = RegexSet::new(&good_regexes).expect("unable to build regex set");
+        let regex_set = RegexSet::new(&prefilterable_patterns)
+            .expect("every pattern in prefilterable_patterns already compiled on its own");
 
         Self {
             ids,
-            regex_set,
             regexes,
+            regex_set,
+            prefiltered_indices,
+            unfiltered_indices,
         }
     }
 }
@@ -190,10 +239,12 @@ mod tests {
         let match1 = &RegexMatch {
             regex: "hello".to_string(),
             base_match: BaseMatch::default(),
+            ..Default::default()
         };
         let match2 = &RegexMatch {
             regex: "num\\d{1,3}s".to_string(),
             base_match: BaseMatch::default(),
+            ..Default::default()
         };
         let matcher = RegexMatcher::new(vec![match1, match2]);
         assert_eq!(get_matches_after_str("hi", &matcher), vec![]);
@@ -221,16 +272,18 @@ mod tests {
         let match1 = &RegexMatch {
             regex: "hello\\((?P<name>.*?)\\)".to_string(),
             base_match: BaseMatch::default(),
+            ..Default::default()
         };
         let match2 = &RegexMatch {
             regex: "multi\\((?P<name1>.*?),(?P<name2>.*?)\\)".to_string(),
             base_match: BaseMatch::default(),
+            ..Default::default()
         };
         let matcher = RegexMatcher::new(vec![match1, match2]);
         assert_eq!(get_matches_after_str("hi", &matcher), vec![]);
         assert_eq!(
             get_matches_after_str("say hello(mary)", &matcher),
-            vec![match_result(match1, "hello(mary)", &[("name", "mary")])]
+            vec![match_result(match1, "hello(mary)", &[("1", "mary"), ("name", "mary")])]
         );
         assert_eq!(get_matches_after_str("hello(mary", &matcher), vec![]);
         assert_eq!(
@@ -238,32 +291,51 @@ mod tests {
             vec![match_result(
                 match2,
                 "multi(mary,jane)",
-                &[("name1", "mary"), ("name2", "jane")]
+                &[("1", "mary"), ("name1", "mary"), ("2", "jane"), ("name2", "jane")]
             )]
         );
     }
 
+    #[test]
+    fn matcher_supports_a_trigger_using_lookaround_and_backreferences() {
+        // Plain `regex` can't compile this pattern at all, so it must bypass the `RegexSet`
+        // prefilter entirely rather than making `RegexMatcher::new` panic while building it.
+        let match1 = &RegexMatch {
+            regex: r"(?<!\w):(\w+):\1$".to_string(),
+            base_match: BaseMatch::default(),
+            ..Default::default()
+        };
+        let matcher = RegexMatcher::new(vec![match1]);
+        assert_eq!(
+            get_matches_after_str(":shinran:shinran", &matcher),
+            vec![match_result(match1, ":shinran:shinran", &[("1", "shinran")])]
+        );
+        assert_eq!(get_matches_after_str(":shinran:other", &matcher), vec![]);
+    }
+
     #[test]
     fn matcher_max_buffer_size() {
         let match1 = &RegexMatch {
             regex: "hello\\((?P<name>.*?)\\)".to_string(),
             base_match: BaseMatch::default(),
+            ..Default::default()
         };
         let match2 = &RegexMatch {
             regex: "multi\\((?P<name1>.*?),(?P<name2>.*?)\\)".to_string(),
             base_match: BaseMatch::default(),
+            ..Default::default()
         };
         let matcher = RegexMatcher::new(vec![match1, match2]);
         assert_eq!(
             get_matches_after_str("say hello(mary)", &matcher),
-            vec![match_result(match1, "hello(mary)", &[("name", "mary")])]
+            vec![match_result(match1, "hello(mary)", &[("1", "mary"), ("name", "mary")])]
         );
         assert_eq!(
             get_matches_after_str("hello(very long name over buffer)", &matcher),
             vec![match_result(
                 match1,
                 "hello(very long name over buffer)",
-                &[("name", "very long name over buffer")]
+                &[("1", "very long name over buffer"), ("name", "very long name over buffer")]
             )]
         );
     }
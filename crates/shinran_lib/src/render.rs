@@ -91,11 +91,10 @@ impl<'store> RendererAdapter<'store> {
                     Some(m.uppercase_style),
                 )
             }
-            MatchIdx::Regex(idx) => (
-                &self.stores.matches.regex_matches.get(idx).1.effect,
-                false,
-                None,
-            ),
+            MatchIdx::Regex(idx) => {
+                let m = &self.stores.matches.regex_matches.get(idx).1;
+                (&m.base_match.effect, m.propagate_case, Some(m.uppercase_style))
+            }
             MatchIdx::BuiltIn(_) => {
                 unreachable!()
             }
@@ -116,6 +115,10 @@ impl<'store> RendererAdapter<'store> {
             },
         };
 
+        // Captured before `trigger_vars` is consumed below, so the plugin post-processing call
+        // further down still has the trigger-time vars to put in its `PluginInput`.
+        let plugin_vars = trigger_vars.clone();
+
         // If some trigger vars are specified, augment the template with them
         let augmented_template = if trigger_vars.is_empty() {
             None
@@ -154,11 +157,23 @@ impl<'store> RendererAdapter<'store> {
                 .global_vars(active_profile),
         };
 
-        match self
-            .stores
-            .renderer
-            .render_template(template, context, &options)
-        {
+        // Plugins key off the trigger that was actually typed, so there's nothing for them to
+        // post-process when a match was reached some other way (e.g. a regex match).
+        let result = if let Some(trigger) = trigger {
+            self.stores.renderer.render_template_with_plugins(
+                template,
+                context,
+                &options,
+                trigger,
+                &plugin_vars,
+            )
+        } else {
+            self.stores
+                .renderer
+                .render_template(template, context, &options)
+        };
+
+        match result {
             shinran_render::RenderResult::Success(body) => Ok(body),
             shinran_render::RenderResult::Aborted => Err(RendererError::Aborted.into()),
             shinran_render::RenderResult::Error(err) => {
@@ -172,9 +187,10 @@ impl<'store> RendererAdapter<'store> {
         &self,
         trigger: &str,
         active_profile: ProfileRef,
+        app_identity: &str,
     ) -> Vec<crate::engine::DetectedMatch> {
         self.combined_cache
-            .find_matches_from_trigger(trigger, active_profile)
+            .find_matches_from_trigger(trigger, active_profile, app_identity)
     }
 
     #[inline]
@@ -183,7 +199,6 @@ impl<'store> RendererAdapter<'store> {
     }
 }
 
-// TODO: test
 fn calculate_casing_style(
     trigger: &str,
     uppercasing_style: Option<UpperCasingStyle>,
@@ -230,3 +245,54 @@ fn calculate_casing_style(
         CasingStyle::None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_letter_trigger_uses_the_letters_own_case() {
+        assert_eq!(calculate_casing_style("a", None), CasingStyle::None);
+        assert_eq!(calculate_casing_style("A", None), CasingStyle::Uppercase);
+    }
+
+    #[test]
+    fn single_letter_trigger_honors_the_uppercasing_style() {
+        assert_eq!(
+            calculate_casing_style("A", Some(UpperCasingStyle::Capitalize)),
+            CasingStyle::Capitalize
+        );
+        assert_eq!(
+            calculate_casing_style("A", Some(UpperCasingStyle::CapitalizeWords)),
+            CasingStyle::CapitalizeWords
+        );
+    }
+
+    #[test]
+    fn leading_non_alphabetic_characters_are_skipped() {
+        assert_eq!(calculate_casing_style("123Hello", None), CasingStyle::Capitalize);
+        assert_eq!(calculate_casing_style("123HELLO", None), CasingStyle::Uppercase);
+        assert_eq!(calculate_casing_style("...hello", None), CasingStyle::None);
+    }
+
+    #[test]
+    fn all_uppercase_trigger_is_uppercase_regardless_of_style() {
+        assert_eq!(
+            calculate_casing_style("HELLO", Some(UpperCasingStyle::CapitalizeWords)),
+            CasingStyle::Uppercase
+        );
+    }
+
+    #[test]
+    fn capitalize_words_style_is_picked_for_a_leading_capital_word() {
+        assert_eq!(
+            calculate_casing_style("Hello world", Some(UpperCasingStyle::CapitalizeWords)),
+            CasingStyle::CapitalizeWords
+        );
+    }
+
+    #[test]
+    fn lowercase_trigger_has_no_casing_style() {
+        assert_eq!(calculate_casing_style("hello", None), CasingStyle::None);
+    }
+}
@@ -0,0 +1,138 @@
+/*
+ * This file is part of espanso.
+ *
+ * Copyright (C) 2019-2021 Federico Terzi
+ *
+ * espanso is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * espanso is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with espanso.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Unified ranked fuzzy search across every match kind (user triggers, builtin matches, regex
+//! match source patterns), for a command-palette-style picker. See [`crate::Backend::search`].
+
+use nucleo_matcher::{pattern::Atom, Matcher, Utf32Str};
+use shinran_types::{RegexMatch, TriggerMatch};
+
+use crate::builtin::BuiltInMatch;
+
+/// Which kind of match a [`SearchHit`] came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchHitKind {
+    User,
+    BuiltIn,
+    Regex,
+}
+
+/// The underlying match a [`SearchHit`] points back to.
+pub enum SearchRef<'store> {
+    User(&'store TriggerMatch),
+    BuiltIn(i32),
+    Regex(&'store RegexMatch),
+}
+
+/// One ranked result from [`crate::Backend::search`].
+pub struct SearchHit<'store> {
+    pub label: String,
+    pub kind: SearchHitKind,
+    pub match_ref: SearchRef<'store>,
+    pub score: u32,
+    /// Character indices into `label` that the query matched, so a caller can bold them.
+    pub highlights: Vec<u32>,
+}
+
+/// Try every candidate text in turn against `atom`, keeping whichever scores highest. A match can
+/// surface under more than one candidate text (e.g. a builtin's label and its triggers); we only
+/// want one [`SearchHit`] per match, showing whichever text it matched best on.
+fn best_candidate(
+    atom: &Atom,
+    matcher: &mut Matcher,
+    candidates: impl IntoIterator<Item = impl AsRef<str>>,
+) -> Option<(String, u32, Vec<u32>)> {
+    let mut best: Option<(String, u32, Vec<u32>)> = None;
+    let mut buf = Vec::new();
+    let mut indices = Vec::new();
+
+    for candidate in candidates {
+        let candidate = candidate.as_ref();
+        buf.clear();
+        indices.clear();
+        let haystack = Utf32Str::new(candidate, &mut buf);
+        if let Some(score) = atom.indices(haystack, matcher, &mut indices) {
+            let is_better = best
+                .as_ref()
+                .map_or(true, |(_, best_score, _)| score > *best_score);
+            if is_better {
+                best = Some((candidate.to_string(), score, indices.clone()));
+            }
+        }
+    }
+
+    best
+}
+
+pub(crate) fn search_user_triggers<'store>(
+    atom: &Atom,
+    matcher: &mut Matcher,
+    triggers: impl Iterator<Item = (&'store str, &'store TriggerMatch)>,
+) -> Vec<SearchHit<'store>> {
+    triggers
+        .filter_map(|(trigger, m)| {
+            best_candidate(atom, matcher, [trigger]).map(|(label, score, highlights)| SearchHit {
+                label,
+                kind: SearchHitKind::User,
+                match_ref: SearchRef::User(m),
+                score,
+                highlights,
+            })
+        })
+        .collect()
+}
+
+pub(crate) fn search_builtin_matches<'store>(
+    atom: &Atom,
+    matcher: &mut Matcher,
+    builtins: impl Iterator<Item = &'store BuiltInMatch>,
+) -> Vec<SearchHit<'store>> {
+    builtins
+        .filter_map(|m| {
+            let candidates = std::iter::once(m.label).chain(m.triggers.iter().map(String::as_str));
+            best_candidate(atom, matcher, candidates).map(|(label, score, highlights)| SearchHit {
+                label,
+                kind: SearchHitKind::BuiltIn,
+                match_ref: SearchRef::BuiltIn(m.id),
+                score,
+                highlights,
+            })
+        })
+        .collect()
+}
+
+pub(crate) fn search_regex_matches<'store>(
+    atom: &Atom,
+    matcher: &mut Matcher,
+    regexes: impl Iterator<Item = &'store RegexMatch>,
+) -> Vec<SearchHit<'store>> {
+    regexes
+        .filter_map(|m| {
+            best_candidate(atom, matcher, [m.regex.as_str()]).map(|(label, score, highlights)| {
+                SearchHit {
+                    label,
+                    kind: SearchHitKind::Regex,
+                    match_ref: SearchRef::Regex(m),
+                    score,
+                    highlights,
+                }
+            })
+        })
+        .collect()
+}
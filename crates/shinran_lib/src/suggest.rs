@@ -0,0 +1,104 @@
+/*
+ * This file is part of espanso.
+ *
+ * Copyright (C) 2019-2021 Federico Terzi
+ *
+ * espanso is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * espanso is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with espanso.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! "Did you mean?" trigger suggestions for a trigger that didn't match anything, based on
+//! Levenshtein edit distance.
+
+/// Candidates farther from `input` than this aren't worth suggesting.
+fn threshold(input_len: usize) -> usize {
+    input_len / 3 + 1
+}
+
+/// Rank every candidate in `triggers` by edit distance to `input`, keeping only those within
+/// [`threshold`] and returning them sorted by ascending distance (candidates lengths differing
+/// from `input` by more than the threshold are skipped before running the DP). Ties keep the
+/// relative order `triggers` handed them in, since [`Vec::sort_by_key`] is stable.
+pub(crate) fn suggest<'a>(
+    input: &str,
+    triggers: impl Iterator<Item = &'a str>,
+    max: usize,
+) -> Vec<(&'a str, usize)> {
+    let input_chars: Vec<char> = input.chars().collect();
+    let max_distance = threshold(input_chars.len());
+
+    let mut candidates: Vec<(&str, usize)> = triggers
+        .filter(|trigger| trigger.chars().count().abs_diff(input_chars.len()) <= max_distance)
+        .filter_map(|trigger| {
+            let trigger_chars: Vec<char> = trigger.chars().collect();
+            let distance = levenshtein(&input_chars, &trigger_chars);
+            (distance <= max_distance).then_some((trigger, distance))
+        })
+        .collect();
+
+    candidates.sort_by_key(|&(_, distance)| distance);
+    candidates.truncate(max);
+    candidates
+}
+
+/// Classic rolling-row edit-distance DP between two `char` slices (so multi-byte triggers like
+/// `:euro` compare correctly rather than being split mid-codepoint).
+fn levenshtein(a: &[char], b: &[char]) -> usize {
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut cur = vec![0; b.len() + 1];
+
+    for (i, &a_char) in a.iter().enumerate() {
+        cur[0] = i + 1;
+        for (j, &b_char) in b.iter().enumerate() {
+            let substitution_cost = usize::from(a_char != b_char);
+            cur[j + 1] = (prev[j + 1] + 1)
+                .min(cur[j] + 1)
+                .min(prev[j] + substitution_cost);
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levenshtein_matches_known_distances() {
+        let a: Vec<char> = "kitten".chars().collect();
+        let b: Vec<char> = "sitting".chars().collect();
+        assert_eq!(levenshtein(&a, &b), 3);
+
+        let a: Vec<char> = "".chars().collect();
+        let b: Vec<char> = "abc".chars().collect();
+        assert_eq!(levenshtein(&a, &b), 3);
+
+        let a: Vec<char> = ":euro".chars().collect();
+        assert_eq!(levenshtein(&a, &a), 0);
+    }
+
+    #[test]
+    fn suggest_ranks_closest_first_and_respects_max() {
+        let triggers = [":gmial", ":gmail", ":hello", ":gmailx"];
+        let result = suggest(":gmail", triggers.into_iter(), 2);
+        assert_eq!(result, vec![(":gmail", 0), (":gmailx", 1)]);
+    }
+
+    #[test]
+    fn suggest_drops_candidates_past_the_threshold() {
+        let triggers = [":completely-unrelated-trigger"];
+        assert!(suggest(":hi", triggers.into_iter(), 5).is_empty());
+    }
+}
@@ -0,0 +1,191 @@
+/*
+ * This file is part of espanso.
+ *
+ * Copyright (C) 2019-2021 Federico Terzi
+ *
+ * espanso is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * espanso is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with espanso.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! An Aho-Corasick automaton over one profile's registered trigger strings, so
+//! [`crate::match_cache::CombinedMatchCache::find_matches_from_trigger`] can find the longest
+//! trigger ending at the end of the typed buffer in a single pass, rather than requiring the
+//! whole buffer to equal a registered trigger exactly. [`crate::match_cache::MatchCache::load`]
+//! builds one of these per profile from its exact-case triggers, plus a parallel one built from
+//! the same triggers lowercased, for `propagate_case` matches typed in the wrong case.
+
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+
+use shinran_types::TriggerMatch;
+
+/// A trigger recognized by [`TriggerIndex::longest_match_at_end`], paired with the registered
+/// match it belongs to.
+#[derive(Debug, Clone)]
+struct Match<'store> {
+    m: &'store TriggerMatch,
+    trigger: Cow<'store, str>,
+}
+
+#[derive(Debug, Default)]
+struct Node<'store> {
+    children: HashMap<char, usize>,
+    fail: usize,
+    outputs: Vec<Match<'store>>,
+}
+
+pub(crate) struct TriggerIndex<'store> {
+    nodes: Vec<Node<'store>>,
+}
+
+impl<'store> TriggerIndex<'store> {
+    /// Build the automaton from every `(trigger, match)` pair. `trigger` is borrowed for the
+    /// exact-case index built in [`crate::match_cache::MatchCache::load`] and owned (lowercased)
+    /// for its case-folded sibling.
+    pub(crate) fn build(
+        triggers: impl IntoIterator<Item = (Cow<'store, str>, &'store TriggerMatch)>,
+    ) -> Self {
+        let mut nodes = vec![Node::default()];
+
+        for (trigger, m) in triggers {
+            let mut current = 0;
+            for ch in trigger.chars() {
+                current = *nodes[current].children.entry(ch).or_insert_with(|| {
+                    nodes.push(Node::default());
+                    nodes.len() - 1
+                });
+            }
+            nodes[current].outputs.push(Match { m, trigger });
+        }
+
+        Self::link_failures(&mut nodes);
+
+        Self { nodes }
+    }
+
+    /// Compute the failure link of every node by BFS from the root: each node's failure link
+    /// points to the longest proper suffix of its path that is also a trie node (the root and
+    /// every depth-1 node fail to the root), and each node's outputs are unioned with its
+    /// failure target's outputs so suffix matches are also reported.
+    fn link_failures(nodes: &mut Vec<Node<'store>>) {
+        let mut queue = VecDeque::new();
+
+        let root_children: Vec<usize> = nodes[0].children.values().copied().collect();
+        for child in root_children {
+            nodes[child].fail = 0;
+            queue.push_back(child);
+        }
+
+        while let Some(current) = queue.pop_front() {
+            let children: Vec<(char, usize)> = nodes[current]
+                .children
+                .iter()
+                .map(|(&ch, &idx)| (ch, idx))
+                .collect();
+
+            for (ch, child) in children {
+                let mut fail = nodes[current].fail;
+                let fail_target = loop {
+                    if let Some(&next) = nodes[fail].children.get(&ch) {
+                        break next;
+                    } else if fail == 0 {
+                        break 0;
+                    } else {
+                        fail = nodes[fail].fail;
+                    }
+                };
+
+                nodes[child].fail = fail_target;
+                let inherited = nodes[fail_target].outputs.clone();
+                nodes[child].outputs.extend(inherited);
+                queue.push_back(child);
+            }
+        }
+    }
+
+    fn step(&self, mut state: usize, ch: char) -> usize {
+        loop {
+            if let Some(&next) = self.nodes[state].children.get(&ch) {
+                return next;
+            } else if state == 0 {
+                return 0;
+            } else {
+                state = self.nodes[state].fail;
+            }
+        }
+    }
+
+    /// Feed `buffer` through the automaton from scratch and return the longest registered
+    /// trigger ending exactly at the end of `buffer`, along with how many of its trailing chars
+    /// that trigger's registered spelling spans -- overlapping triggers are resolved by
+    /// preferring the longest.
+    pub(crate) fn longest_match_at_end(&self, buffer: &str) -> Option<(&'store TriggerMatch, usize)> {
+        let mut state = 0;
+        for ch in buffer.chars() {
+            state = self.step(state, ch);
+        }
+
+        self.nodes[state]
+            .outputs
+            .iter()
+            .map(|output| (output.m, output.trigger.chars().count()))
+            .max_by_key(|&(_, len_chars)| len_chars)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shinran_types::BaseMatch;
+
+    fn trigger_match() -> TriggerMatch {
+        TriggerMatch {
+            base_match: BaseMatch::default(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn finds_longest_trigger_ending_at_buffer_end() {
+        let short = trigger_match();
+        let long = trigger_match();
+        let index = TriggerIndex::build([
+            (Cow::Borrowed(":yo"), &short),
+            (Cow::Borrowed(":yolo"), &long),
+        ]);
+
+        let (m, len_chars) = index.longest_match_at_end("hey :yolo").unwrap();
+        assert!(std::ptr::eq(m, &long));
+        assert_eq!(len_chars, 5);
+    }
+
+    #[test]
+    fn no_match_when_buffer_does_not_end_in_a_trigger() {
+        let m = trigger_match();
+        let index = TriggerIndex::build([(Cow::Borrowed(":yo"), &m)]);
+
+        assert!(index.longest_match_at_end(":yonder").is_none());
+    }
+
+    #[test]
+    fn case_folded_index_matches_regardless_of_typed_case() {
+        let m = trigger_match();
+        let index = TriggerIndex::build([(Cow::Owned(":hw".to_string()), &m)]);
+
+        // The caller lowercases the typed buffer before looking it up in the case-folded index.
+        let (_, len_chars) = index
+            .longest_match_at_end(&":HW".to_ascii_lowercase())
+            .unwrap();
+        assert_eq!(len_chars, 3);
+    }
+}
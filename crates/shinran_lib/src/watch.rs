@@ -0,0 +1,143 @@
+/*
+ * This file is part of espanso.
+ *
+ * Copyright (C) 2019-2021 Federico Terzi
+ *
+ * espanso is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * espanso is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with espanso.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Filesystem-watch–based live config reload.
+//!
+//! Editors typically save by writing to a temp file and renaming it over the target, which looks
+//! like a delete-then-create (or a rename event) rather than a single write to the watched file,
+//! so [`spawn`] recursively watches the containing directories (`Paths::config` and
+//! `Paths::packages`) instead of individual files. Bursts of events from a single save (and from
+//! saving several files at once) are coalesced by waiting for a short quiet period
+//! ([`DEBOUNCE`]) with no further events before recompiling.
+//!
+//! A settled change allocates a fresh [`SourceId`] and, on success, swaps the freshly compiled
+//! [`Backend`] into `backend` so callers observe it without tearing down whatever's holding onto
+//! the `Arc<ArcSwap<_>>` (e.g. the IBus engine). A reload that fails to parse returns a
+//! [`crate::config::ConfigError`] instead of panicking, leaving the previous `Backend` in place
+//! and only logging a warning.
+
+use std::{
+    sync::{mpsc, Arc},
+    time::Duration,
+};
+
+use arc_swap::ArcSwap;
+use log::{debug, info, warn};
+use notify::{RecursiveMode, Watcher};
+
+use crate::{
+    config::Configuration,
+    event::{next_source_id, Event, EventType},
+    path::Paths,
+    Backend,
+};
+
+/// How long to wait after the last filesystem event before recompiling, so a burst of saves (or
+/// an editor's write-then-rename) triggers exactly one reload.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Owns the background watcher thread and the `notify` watcher driving it. Dropping this stops
+/// watching for changes; keep it alive for as long as live reload should stay active.
+pub struct ConfigWatcher {
+    _watcher: notify::RecommendedWatcher,
+}
+
+/// Start watching `paths.config` and `paths.packages` for changes, recompiling and swapping
+/// `backend` in place whenever a debounced change settles. The returned [`ConfigWatcher`] must be
+/// kept alive for the duration of the watch; dropping it shuts the watcher down.
+pub fn spawn(
+    paths: Paths,
+    package_passphrase: Option<String>,
+    backend: Arc<ArcSwap<Backend<'static>>>,
+) -> notify::Result<ConfigWatcher> {
+    let (tx, rx) = mpsc::channel();
+
+    let mut watcher = notify::recommended_watcher(move |event| {
+        // Any send failure just means the debounce thread has shut down; nothing to do about it.
+        let _ = tx.send(event);
+    })?;
+    watcher.watch(&paths.config, RecursiveMode::Recursive)?;
+    if paths.packages.is_dir() {
+        watcher.watch(&paths.packages, RecursiveMode::Recursive)?;
+    }
+
+    std::thread::spawn(move || debounce_and_reload(&rx, &paths, package_passphrase, &backend));
+
+    Ok(ConfigWatcher { _watcher: watcher })
+}
+
+/// Drain `rx`, waiting for a [`DEBOUNCE`]-long quiet period after the last event before treating
+/// the burst as settled and reloading. Exits once the watcher (and thus the sending half) is
+/// dropped.
+fn debounce_and_reload(
+    rx: &mpsc::Receiver<notify::Result<notify::Event>>,
+    paths: &Paths,
+    package_passphrase: Option<String>,
+    backend: &Arc<ArcSwap<Backend<'static>>>,
+) {
+    let mut pending = false;
+
+    loop {
+        match rx.recv_timeout(DEBOUNCE) {
+            Ok(Ok(_event)) => pending = true,
+            Ok(Err(err)) => warn!("config watcher error: {err}"),
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                if pending {
+                    pending = false;
+                    reload(paths, package_passphrase.as_deref(), backend);
+                }
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => return,
+        }
+    }
+}
+
+/// Recompile the configuration from `paths` and, if it parses cleanly, swap it into `backend` and
+/// emit [`EventType::ConfigReloaded`]. A failure (now a [`ConfigError`] returned from
+/// [`Configuration::from_paths`] rather than a panic) leaves the previous `Backend` in place and
+/// only logs a warning, so one bad save can't bring the whole process down.
+fn reload(
+    paths: &Paths,
+    package_passphrase: Option<&str>,
+    backend: &Arc<ArcSwap<Backend<'static>>>,
+) {
+    let source_id = next_source_id();
+
+    let config = match Configuration::from_paths(paths, package_passphrase) {
+        Ok(config) => config,
+        Err(err) => {
+            warn!("config reload failed, keeping the previous config: {err}");
+            return;
+        }
+    };
+
+    match Backend::from_config(Arc::new(config)) {
+        Ok(new_backend) => {
+            backend.store(Arc::new(new_backend));
+            info!("config reloaded after a filesystem change");
+            debug!(
+                "emitting {:?}",
+                Event::caused_by(source_id, EventType::ConfigReloaded)
+            );
+        }
+        Err(err) => {
+            warn!("config reload failed, keeping the previous config: {err}");
+        }
+    }
+}
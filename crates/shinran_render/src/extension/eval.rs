@@ -0,0 +1,123 @@
+/*
+ * This file is part of espanso.
+ *
+ * Copyright (C) 2019-2021 Federico Terzi
+ *
+ * espanso is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * espanso is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with espanso.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::HashMap;
+
+use rhai::{Dynamic, Engine, Map as RhaiMap, Scope as RhaiScope};
+use shinran_types::{Params, Value};
+use thiserror::Error;
+
+use crate::{Extension, ExtensionOutput, ExtensionResult, Scope};
+
+/// An expression that runs away (an infinite loop, unbounded recursion, a huge string/array
+/// build-up) would otherwise be evaluated inline on the input thread, the same as any other
+/// variable; these caps bound the damage such an expression -- accidental or malicious -- can
+/// do, without affecting any expression that actually terminates promptly.
+const MAX_OPERATIONS: u64 = 1_000_000;
+const MAX_EXPR_DEPTH: usize = 64;
+const MAX_CALL_LEVELS: usize = 64;
+const MAX_STRING_SIZE: usize = 1_000_000;
+const MAX_ARRAY_SIZE: usize = 10_000;
+const MAX_MAP_SIZE: usize = 10_000;
+
+/// Evaluates a small inline expression language (a [rhai](https://rhai.rs) script) against the
+/// `expression` param, without shelling out to an interpreter the way [`super::script`]'s
+/// `ScriptExtension` does. The engine has no access to the filesystem or network, and is capped
+/// (see the `MAX_*` constants above) so a runaway expression can't hang or exhaust the process.
+pub struct EvalExtension {
+    engine: Engine,
+}
+
+impl EvalExtension {
+    pub fn new() -> Self {
+        let mut engine = Engine::new();
+        engine
+            .set_max_operations(MAX_OPERATIONS)
+            .set_max_expr_depths(MAX_EXPR_DEPTH, MAX_EXPR_DEPTH)
+            .set_max_call_levels(MAX_CALL_LEVELS)
+            .set_max_string_size(MAX_STRING_SIZE)
+            .set_max_array_size(MAX_ARRAY_SIZE)
+            .set_max_map_size(MAX_MAP_SIZE);
+        Self { engine }
+    }
+}
+
+impl Default for EvalExtension {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Extension for EvalExtension {
+    fn name(&self) -> &str {
+        "eval"
+    }
+
+    fn calculate(&self, scope: &Scope, params: &Params) -> ExtensionResult {
+        let Some(Value::String(expression)) = params.get("expression") else {
+            return ExtensionResult::Error(EvalExtensionError::MissingExpression.into());
+        };
+
+        let mut rhai_scope = scope_to_rhai(scope);
+
+        let result = match self
+            .engine
+            .eval_with_scope::<Dynamic>(&mut rhai_scope, expression)
+        {
+            Ok(result) => result,
+            Err(err) => return ExtensionResult::Error(EvalExtensionError::Eval(*err).into()),
+        };
+
+        ExtensionResult::Success(ExtensionOutput::Single(result.to_string()))
+    }
+}
+
+/// Build a fresh [`RhaiScope`] from the renderer's [`Scope`], so an expression can reference the
+/// output of previously-computed variables by name.
+fn scope_to_rhai(scope: &Scope) -> RhaiScope<'static> {
+    let mut rhai_scope = RhaiScope::new();
+    for (name, output) in scope {
+        match output {
+            ExtensionOutput::Single(value) => {
+                rhai_scope.push(name.to_string(), value.clone());
+            }
+            ExtensionOutput::Multiple(map) => {
+                let map: HashMap<String, String> = map.clone();
+                let rhai_map: RhaiMap = map
+                    .into_iter()
+                    .map(|(key, value)| (key.into(), Dynamic::from(value)))
+                    .collect();
+                rhai_scope.push(name.to_string(), rhai_map);
+            }
+            ExtensionOutput::Nested(_) => {
+                rhai_scope.push(name.to_string(), super::rhai::output_to_dynamic(output));
+            }
+        }
+    }
+    rhai_scope
+}
+
+#[derive(Error, Debug)]
+enum EvalExtensionError {
+    #[error("missing 'expression' param")]
+    MissingExpression,
+
+    #[error("failed to evaluate expression: {0}")]
+    Eval(#[from] rhai::EvalAltResult),
+}
@@ -0,0 +1,180 @@
+/*
+ * This file is part of espanso.
+ *
+ * Copyright (C) 2019-2021 Federico Terzi
+ *
+ * espanso is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * espanso is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with espanso.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use rhai::{Array as RhaiArray, Dynamic, Engine, Map as RhaiMap, Scope as RhaiScope};
+use shinran_types::{Number, Params, Value};
+use thiserror::Error;
+
+use crate::{renderer::value_to_output, Extension, ExtensionOutput, ExtensionResult, Scope};
+
+/// See [`super::eval::EvalExtension`]'s identical constants for the rationale.
+const MAX_OPERATIONS: u64 = 1_000_000;
+const MAX_EXPR_DEPTH: usize = 64;
+const MAX_CALL_LEVELS: usize = 64;
+const MAX_STRING_SIZE: usize = 1_000_000;
+const MAX_ARRAY_SIZE: usize = 10_000;
+const MAX_MAP_SIZE: usize = 10_000;
+
+/// Runs a [rhai](https://rhai.rs) script in-process for `VarType::Rhai` variables, rather than
+/// spawning an external interpreter the way `VarType::Script`/`VarType::Shell` do. Unlike
+/// [`super::eval::EvalExtension`], whose `Scope` sees every variable computed so far and whose
+/// result is always stringified, the caller only binds this variable's own `depends_on` set into
+/// scope (see `renderer::mod`'s dispatch on `VarType::Rhai`), and the returned value is kept
+/// structured (see [`dynamic_to_value`]) so a script can hand back an array/object for a
+/// downstream `VarType::List`/`.field` reference instead of just text.
+pub struct RhaiExtension {
+    engine: Engine,
+}
+
+impl RhaiExtension {
+    pub fn new() -> Self {
+        let mut engine = Engine::new();
+        engine
+            .set_max_operations(MAX_OPERATIONS)
+            .set_max_expr_depths(MAX_EXPR_DEPTH, MAX_EXPR_DEPTH)
+            .set_max_call_levels(MAX_CALL_LEVELS)
+            .set_max_string_size(MAX_STRING_SIZE)
+            .set_max_array_size(MAX_ARRAY_SIZE)
+            .set_max_map_size(MAX_MAP_SIZE);
+        Self { engine }
+    }
+}
+
+impl Default for RhaiExtension {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Extension for RhaiExtension {
+    fn name(&self) -> &str {
+        "rhai"
+    }
+
+    fn calculate(&self, scope: &Scope, params: &Params) -> ExtensionResult {
+        let Some(Value::String(code)) = params.get("code") else {
+            return ExtensionResult::Error(RhaiExtensionError::MissingCode.into());
+        };
+
+        let mut rhai_scope = RhaiScope::new();
+        for (name, output) in scope {
+            rhai_scope.push((*name).to_string(), output_to_dynamic(output));
+        }
+        // Any param besides `code` itself (e.g. static config passed alongside the script) is
+        // also bound into scope, so a script doesn't have to duplicate it as a literal.
+        for (name, value) in params {
+            if name != "code" {
+                rhai_scope.push(name.clone(), value_to_dynamic(value));
+            }
+        }
+
+        let result = match self.engine.eval_with_scope::<Dynamic>(&mut rhai_scope, code) {
+            Ok(result) => result,
+            Err(err) => return ExtensionResult::Error(RhaiExtensionError::Eval(*err).into()),
+        };
+
+        ExtensionResult::Success(value_to_output(&dynamic_to_value(result)))
+    }
+}
+
+/// Converts a resolved sibling variable's output into the `Dynamic` bound into the script's
+/// `Scope`, recursing the same way [`value_to_dynamic`] does for the `Value` side of this
+/// crate's data model.
+pub(super) fn output_to_dynamic(output: &ExtensionOutput) -> Dynamic {
+    match output {
+        ExtensionOutput::Single(s) => Dynamic::from(s.clone()),
+        ExtensionOutput::Multiple(map) => {
+            let map: RhaiMap = map
+                .iter()
+                .map(|(key, value)| (key.clone().into(), Dynamic::from(value.clone())))
+                .collect();
+            Dynamic::from(map)
+        }
+        ExtensionOutput::Nested(map) => {
+            let map: RhaiMap = map
+                .iter()
+                .map(|(key, value)| (key.clone().into(), output_to_dynamic(value)))
+                .collect();
+            Dynamic::from(map)
+        }
+    }
+}
+
+/// The `Value`-to-`Dynamic` half of the bidirectional conversion, mirroring
+/// `shinran_config`'s YAML `convert_value` one data model over: `Null`/`Bool`/`Number`/`String`
+/// map onto the corresponding Rhai primitive, and `Array`/`Object` recurse into `rhai::Array`/
+/// `rhai::Map`.
+fn value_to_dynamic(value: &Value) -> Dynamic {
+    match value {
+        Value::Null => Dynamic::UNIT,
+        Value::Bool(b) => Dynamic::from(*b),
+        Value::Number(Number::Integer(n)) => Dynamic::from(*n),
+        Value::Number(Number::Float(n)) => Dynamic::from(*n),
+        Value::String(s) => Dynamic::from(s.clone()),
+        Value::Array(items) => {
+            let array: RhaiArray = items.iter().map(value_to_dynamic).collect();
+            Dynamic::from(array)
+        }
+        Value::Object(params) => {
+            let map: RhaiMap = params
+                .iter()
+                .map(|(key, value)| (key.clone().into(), value_to_dynamic(value)))
+                .collect();
+            Dynamic::from(map)
+        }
+    }
+}
+
+/// The `Dynamic`-to-`Value` half of the bidirectional conversion (see [`value_to_dynamic`]),
+/// used to turn a script's return value into something the rest of the renderer already knows
+/// how to inject.
+fn dynamic_to_value(dynamic: Dynamic) -> Value {
+    if dynamic.is_unit() {
+        return Value::Null;
+    }
+    if let Some(b) = dynamic.clone().try_cast::<bool>() {
+        return Value::Bool(b);
+    }
+    if let Some(n) = dynamic.clone().try_cast::<i64>() {
+        return Value::Number(Number::Integer(n));
+    }
+    if let Some(n) = dynamic.clone().try_cast::<f64>() {
+        return Value::Number(Number::Float(n));
+    }
+    if let Some(array) = dynamic.clone().try_cast::<RhaiArray>() {
+        return Value::Array(array.into_iter().map(dynamic_to_value).collect());
+    }
+    if let Some(map) = dynamic.clone().try_cast::<RhaiMap>() {
+        return Value::Object(
+            map.into_iter()
+                .map(|(key, value)| (key.to_string(), dynamic_to_value(value)))
+                .collect(),
+        );
+    }
+    Value::String(dynamic.to_string())
+}
+
+#[derive(Error, Debug)]
+enum RhaiExtensionError {
+    #[error("missing 'code' param")]
+    MissingCode,
+
+    #[error("failed to evaluate rhai script: {0}")]
+    Eval(#[from] rhai::EvalAltResult),
+}
@@ -20,11 +20,14 @@
 use enum_as_inner::EnumAsInner;
 use shinran_types::{Params, TriggerMatch, Variable};
 use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
 
 pub mod extension;
+pub mod plugin;
 mod renderer;
 
-pub use renderer::Renderer;
+pub use renderer::{CompiledTemplate, Renderer};
 
 // pub trait Renderer {
 //     fn render(
@@ -66,12 +69,21 @@ impl Default for Context<'static, 'static> {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct RenderOptions {
     pub casing_style: CasingStyle,
+    /// Extra named filters available to the `{{ var | name }}` transform pipeline (see
+    /// [`crate::renderer`]'s `transform` module), on top of the built-in ones. Lets a downstream
+    /// crate register its own filters without forking the pipeline.
+    pub custom_filters: HashMap<String, fn(&str) -> String>,
+    /// How injected variable values (not static body text) are escaped for the sink the
+    /// expansion lands in. A `Variable` can opt out of this with an `escape: false` param.
+    pub escape_mode: EscapeMode,
 }
 
 impl Default for RenderOptions {
     fn default() -> Self {
         Self {
             casing_style: CasingStyle::None,
+            custom_filters: HashMap::new(),
+            escape_mode: EscapeMode::None,
         }
     }
 }
@@ -84,17 +96,42 @@ pub enum CasingStyle {
     Uppercase,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EscapeMode {
+    #[default]
+    None,
+    Html,
+    Shell,
+}
+
 pub trait Extension {
     fn name(&self) -> &str;
     fn calculate(&self, scope: &Scope, params: &Params) -> ExtensionResult;
 }
 
+/// Like [`Extension`], but for extensions whose work is I/O-bound (shelling out, reading a
+/// file, fetching a URL) and so shouldn't run inline on the thread driving the input loop.
+///
+/// `Renderer` polls these on a small runtime with a per-call timeout, rather than blocking the
+/// caller for as long as the external operation takes.
+pub trait AsyncExtension {
+    fn name(&self) -> &str;
+    fn calculate<'a>(
+        &'a self,
+        scope: &'a Scope,
+        params: &'a Params,
+    ) -> Pin<Box<dyn Future<Output = ExtensionResult> + Send + 'a>>;
+}
+
 pub type Scope<'a> = HashMap<&'a str, ExtensionOutput>;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ExtensionOutput {
     Single(String),
     Multiple(HashMap<String, String>),
+    /// An arbitrarily-deep structured object, for extensions (echo/script/eval) that produce
+    /// nested data a template can index into with a dotted path, e.g. `{{ user.address.city }}`.
+    Nested(HashMap<String, ExtensionOutput>),
 }
 
 #[derive(Debug, EnumAsInner)]
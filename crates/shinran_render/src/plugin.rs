@@ -0,0 +1,262 @@
+/*
+ * This file is part of espanso.
+ *
+ * Copyright (C) 2019-2021 Federico Terzi
+ *
+ * espanso is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * espanso is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with espanso.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! WASM match-transformer plugins.
+//!
+//! A plugin is a `.wasm` module, discovered under `packages_path` at [`PluginRegistry::load`]
+//! time, that post-processes a match's fully rendered output before it's injected (custom
+//! shell-free computations, remote lookups, format conversions, ...). The host/guest boundary is
+//! marshalled with `rkyv`, the same way the on-disk configuration cache is (see
+//! `shinran_lib::config`): the host serializes a [`PluginInput`], copies the bytes into the
+//! guest's linear memory, calls the guest's exported `transform(ptr, len) -> (ptr, len)`, then
+//! validates the bytes the guest wrote back with `rkyv::check_archived_root::<PluginResponse>`.
+//!
+//! A plugin can't return a Rust error directly across the boundary, so it reports failure by
+//! writing back a [`PluginResponse::Error`] instead of [`PluginResponse::Output`] through that
+//! same channel. This is kept distinct from an interop failure (bad pointer/length, corrupt or
+//! truncated bytes, failed `check_bytes` validation) on the host side: one is the plugin
+//! misbehaving, the other is the plugin working correctly and reporting that it failed.
+
+use std::{collections::HashMap, path::Path};
+
+use log::warn;
+use rkyv::{Archive, Deserialize, Serialize};
+use wasmtime::{Config, Engine, Instance, Module, Store, TypedFunc};
+
+/// Caps how much work a single `transform` call can do before it's forcibly trapped, the same
+/// role the rhai extensions' `MAX_OPERATIONS` plays for rhai scripts: packages (including
+/// plugins) can come from remote/imported sources (see `shinran_config`'s package fetching), so a
+/// plugin with an infinite loop must not be able to hang the render path indefinitely.
+const MAX_FUEL: u64 = 10_000_000;
+
+/// What a plugin's `transform` export receives: the trigger that produced the match, the
+/// trigger-time variables it was rendered with, and the text it rendered to.
+#[derive(Archive, Serialize, Deserialize, Debug, Clone)]
+#[archive(check_bytes)]
+pub struct PluginInput {
+    pub trigger: String,
+    pub vars: HashMap<String, String>,
+    pub matched_text: String,
+}
+
+/// What a plugin's `transform` export returns on success: the text to inject in place of
+/// `PluginInput::matched_text`.
+#[derive(Archive, Serialize, Deserialize, Debug, Clone)]
+#[archive(check_bytes)]
+pub struct PluginOutput {
+    pub replacement: String,
+}
+
+/// A plugin-reported failure, carried back to the host through the same `rkyv` channel as
+/// [`PluginOutput`] (see [`PluginResponse`]), rather than as a Rust error the guest has no way to
+/// construct.
+#[derive(Archive, Serialize, Deserialize, Debug, Clone)]
+#[archive(check_bytes)]
+pub enum PluginError {
+    Failed(String),
+}
+
+/// What a plugin's `transform` export actually writes back: either [`PluginOutput`] or, if the
+/// plugin wants to report failure, [`PluginError`]. The host always validates and deserializes
+/// this wrapper, then unwraps the variant, rather than guessing which of the two the bytes are.
+#[derive(Archive, Serialize, Deserialize, Debug, Clone)]
+#[archive(check_bytes)]
+pub enum PluginResponse {
+    Output(PluginOutput),
+    Error(PluginError),
+}
+
+/// A failure invoking a plugin, kept distinct from [`PluginError`] (which is the plugin itself
+/// reporting failure): this is the host failing to even complete the call.
+#[derive(Debug, thiserror::Error)]
+pub enum PluginInvocationError {
+    #[error("plugin '{0}' has no memory export")]
+    NoMemory(String),
+    #[error("plugin '{0}' has no `transform` export")]
+    NoTransformExport(String),
+    #[error("plugin '{0}' has no `alloc` export")]
+    NoAllocExport(String),
+    #[error("plugin '{0}': failed to call into the guest: {1}")]
+    Trap(String, wasmtime::Error),
+    #[error("plugin '{0}' returned bytes that failed to validate: {1}")]
+    InvalidResponse(String, String),
+    #[error("plugin '{0}' reported an error: {1:?}")]
+    Plugin(String, PluginError),
+}
+
+/// One loaded `.wasm` module, compiled once at [`PluginRegistry::load`] time and instantiated
+/// fresh for every [`PluginRegistry::transform`] call, since a plugin is expected to be stateless
+/// between matches.
+struct LoadedPlugin {
+    name: String,
+    module: Module,
+}
+
+/// The set of transform plugins discovered under a packages directory. Applied, in load order, to
+/// every match's rendered output before it's injected.
+pub struct PluginRegistry {
+    engine: Engine,
+    plugins: Vec<LoadedPlugin>,
+}
+
+impl PluginRegistry {
+    /// Compile every `*.wasm` file directly under `packages_path`. A plugin that fails to compile
+    /// is skipped with a warning rather than failing the whole load, the same way
+    /// [`shinran_config::materialize_packages`] treats a broken package archive.
+    pub fn load(packages_path: &Path) -> Self {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config)
+            .expect("a Config that only enables fuel consumption is always valid");
+        let mut plugins = Vec::new();
+
+        let Ok(entries) = std::fs::read_dir(packages_path) else {
+            return Self { engine, plugins };
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("wasm") {
+                continue;
+            }
+            let name = path.to_string_lossy().into_owned();
+            match Module::from_file(&engine, &path) {
+                Ok(module) => plugins.push(LoadedPlugin { name, module }),
+                Err(err) => warn!("unable to load plugin '{name}': {err}"),
+            }
+        }
+
+        Self { engine, plugins }
+    }
+
+    /// Whether any plugin was successfully loaded. Lets a caller skip building a [`PluginInput`]
+    /// entirely on the common case where no plugins are installed.
+    pub fn is_empty(&self) -> bool {
+        self.plugins.is_empty()
+    }
+
+    /// Run `input` through every loaded plugin in order, feeding each plugin's replacement text
+    /// into the next one's `matched_text`. A plugin that fails (whether by interop error or by
+    /// reporting a [`PluginError`]) is logged and skipped, leaving the text unchanged by that
+    /// plugin rather than aborting the whole match.
+    pub fn transform(&self, input: &PluginInput) -> String {
+        let mut matched_text = input.matched_text.clone();
+
+        for plugin in &self.plugins {
+            let next_input = PluginInput {
+                trigger: input.trigger.clone(),
+                vars: input.vars.clone(),
+                matched_text,
+            };
+            matched_text = match self.invoke(plugin, &next_input) {
+                Ok(output) => output.replacement,
+                Err(err) => {
+                    warn!("{err}");
+                    next_input.matched_text
+                }
+            };
+        }
+
+        matched_text
+    }
+
+    /// Marshal `input` across the host/guest boundary for a single `plugin` and return its
+    /// response, or the [`PluginInvocationError`] that kept it from completing.
+    fn invoke(
+        &self,
+        plugin: &LoadedPlugin,
+        input: &PluginInput,
+    ) -> Result<PluginOutput, PluginInvocationError> {
+        let mut store = Store::new(&self.engine, ());
+        store
+            .set_fuel(MAX_FUEL)
+            .expect("the engine was built with Config::consume_fuel(true)");
+        let instance = Instance::new(&mut store, &plugin.module, &[])
+            .map_err(|err| PluginInvocationError::Trap(plugin.name.clone(), err))?;
+
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or_else(|| PluginInvocationError::NoMemory(plugin.name.clone()))?;
+        let alloc: TypedFunc<i32, i32> = instance
+            .get_typed_func(&mut store, "alloc")
+            .map_err(|_| PluginInvocationError::NoAllocExport(plugin.name.clone()))?;
+        let transform: TypedFunc<(i32, i32), (i32, i32)> = instance
+            .get_typed_func(&mut store, "transform")
+            .map_err(|_| PluginInvocationError::NoTransformExport(plugin.name.clone()))?;
+
+        let bytes = rkyv::to_bytes::<_, 1024>(input).map_err(|err| {
+            PluginInvocationError::InvalidResponse(plugin.name.clone(), format!("{err}"))
+        })?;
+
+        let guest_ptr = alloc
+            .call(&mut store, bytes.len() as i32)
+            .map_err(|err| PluginInvocationError::Trap(plugin.name.clone(), err))?;
+        memory
+            .write(&mut store, guest_ptr as usize, &bytes)
+            .map_err(|err| {
+                PluginInvocationError::Trap(plugin.name.clone(), wasmtime::Error::from(err))
+            })?;
+
+        let (out_ptr, out_len) = transform
+            .call(&mut store, (guest_ptr, bytes.len() as i32))
+            .map_err(|err| PluginInvocationError::Trap(plugin.name.clone(), err))?;
+
+        // `out_len`/`out_ptr` come straight from the untrusted guest, so they must be validated
+        // against the guest's actual memory before being used to size an allocation: a negative
+        // `out_len` would otherwise sign-extend to `usize::MAX` on a 64-bit host and abort the
+        // whole process when `vec![0u8; ...]` tries to allocate it.
+        let memory_size = memory.data_size(&store);
+        let out_of_bounds = out_ptr < 0
+            || out_len < 0
+            || (out_ptr as usize)
+                .checked_add(out_len as usize)
+                .map_or(true, |end| end > memory_size);
+        if out_of_bounds {
+            return Err(PluginInvocationError::InvalidResponse(
+                plugin.name.clone(),
+                format!(
+                    "transform returned an out-of-bounds buffer (ptr={out_ptr}, len={out_len}, memory size={memory_size})"
+                ),
+            ));
+        }
+
+        let mut response_bytes = vec![0u8; out_len as usize];
+        memory
+            .read(&store, out_ptr as usize, &mut response_bytes)
+            .map_err(|err| {
+                PluginInvocationError::Trap(plugin.name.clone(), wasmtime::Error::from(err))
+            })?;
+
+        let archived =
+            rkyv::check_archived_root::<PluginResponse>(&response_bytes).map_err(|err| {
+                PluginInvocationError::InvalidResponse(plugin.name.clone(), format!("{err}"))
+            })?;
+
+        match archived
+            .deserialize(&mut rkyv::Infallible)
+            .expect("PluginResponse's deserialize is infallible")
+        {
+            PluginResponse::Output(output) => Ok(output),
+            PluginResponse::Error(plugin_error) => Err(PluginInvocationError::Plugin(
+                plugin.name.clone(),
+                plugin_error,
+            )),
+        }
+    }
+}
@@ -0,0 +1,402 @@
+/*
+ * This file is part of espanso.
+ *
+ * Copyright (C) 2019-2021 Federico Terzi
+ *
+ * espanso is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * espanso is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with espanso.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Handlebars-style block sections (`{{#if var}}...{{else}}...{{/if}}` and
+//! `{{#each listvar}}...{{/each}}`) for template bodies, layered on top of the flat
+//! [`super::VAR_REGEX`] substitution: a body is only tokenized here if it actually contains a
+//! block tag, otherwise the cheaper flat substitution path in [`super`] is used instead.
+
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+use crate::{ExtensionOutput, Scope};
+
+use super::RendererError;
+
+/// A node of the tree produced by [`parse`], rendered against the renderer's [`Scope`] by
+/// [`render_nodes`] once every variable in the template has been computed.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum TemplateNode {
+    Text(String),
+    /// A `{{ path }}` reference; `path` may be a dotted chain (`user.address.city`) indexing
+    /// into a nested [`ExtensionOutput`].
+    Var { path: String },
+    If {
+        name: String,
+        then: Vec<TemplateNode>,
+        or_else: Vec<TemplateNode>,
+    },
+    Each {
+        name: String,
+        item_name: String,
+        body: Vec<TemplateNode>,
+    },
+}
+
+/// Whether `body` contains a block-section tag, i.e. whether it's worth tokenizing with [`parse`]
+/// rather than taking the flat `VAR_REGEX` substitution path.
+pub(crate) fn has_block_tags(body: &str) -> bool {
+    body.contains("{{#if") || body.contains("{{#each")
+}
+
+#[derive(Debug, Clone)]
+enum Token {
+    Text(String),
+    Var { path: String },
+    IfOpen(String),
+    Else,
+    IfClose,
+    EachOpen { name: String, item_name: String },
+    EachClose,
+}
+
+static TAG_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?x)
+        \{\{\s*\#if\s+(?P<if_name>\w+)\s*\}\}
+        |\{\{\s*else\s*\}\}
+        |\{\{\s*/if\s*\}\}
+        |\{\{\s*\#each\s+(?P<each_name>\w+)(\s+as\s+(?P<item_name>\w+))?\s*\}\}
+        |\{\{\s*/each\s*\}\}
+        |\{\{\s*(?P<var_path>\w+(?:\.\w+)*)\s*\}\}
+        ",
+    )
+    .unwrap()
+});
+
+fn tokenize(body: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut last_end = 0;
+
+    for caps in TAG_REGEX.captures_iter(body) {
+        let whole = caps.get(0).expect("capture group 0 always matches");
+        if whole.start() > last_end {
+            tokens.push(Token::Text(body[last_end..whole.start()].to_string()));
+        }
+        last_end = whole.end();
+
+        if let Some(name) = caps.name("if_name") {
+            tokens.push(Token::IfOpen(name.as_str().to_string()));
+        } else if let Some(name) = caps.name("each_name") {
+            let item_name = caps
+                .name("item_name")
+                .map_or_else(|| "this".to_string(), |m| m.as_str().to_string());
+            tokens.push(Token::EachOpen {
+                name: name.as_str().to_string(),
+                item_name,
+            });
+        } else if let Some(path) = caps.name("var_path") {
+            tokens.push(Token::Var {
+                path: path.as_str().to_string(),
+            });
+        } else if whole.as_str().contains("/if") {
+            tokens.push(Token::IfClose);
+        } else if whole.as_str().contains("/each") {
+            tokens.push(Token::EachClose);
+        } else {
+            tokens.push(Token::Else);
+        }
+    }
+
+    if last_end < body.len() {
+        tokens.push(Token::Text(body[last_end..].to_string()));
+    }
+
+    tokens
+}
+
+/// Tokenize `body` and build its block-section tree, erroring out on an unbalanced tag.
+pub(crate) fn parse(body: &str) -> Result<Vec<TemplateNode>, RendererError> {
+    let tokens = tokenize(body);
+    let mut iter = tokens.into_iter().peekable();
+    let nodes = parse_nodes(&mut iter, None)?;
+    if iter.peek().is_some() {
+        return Err(RendererError::UnbalancedBlockTag(
+            "unexpected closing tag with no matching block-open tag".to_string(),
+        ));
+    }
+    Ok(nodes)
+}
+
+type TokenIter = std::iter::Peekable<std::vec::IntoIter<Token>>;
+
+/// Parse a sequence of nodes up to (and consuming) the closing tag matching `closing`
+/// (`Some("if")`/`Some("each")`), or to the end of input when `closing` is `None`.
+fn parse_nodes(
+    tokens: &mut TokenIter,
+    closing: Option<&str>,
+) -> Result<Vec<TemplateNode>, RendererError> {
+    let mut nodes = Vec::new();
+    loop {
+        match tokens.peek() {
+            None => {
+                return if let Some(tag) = closing {
+                    Err(RendererError::UnbalancedBlockTag(format!(
+                        "missing {{{{/{tag}}}}}"
+                    )))
+                } else {
+                    Ok(nodes)
+                };
+            }
+            Some(Token::IfClose) => {
+                if closing == Some("if") {
+                    tokens.next();
+                    return Ok(nodes);
+                }
+                return Err(RendererError::UnbalancedBlockTag(
+                    "unexpected {{/if}} without a matching {{#if}}".to_string(),
+                ));
+            }
+            Some(Token::EachClose) => {
+                if closing == Some("each") {
+                    tokens.next();
+                    return Ok(nodes);
+                }
+                return Err(RendererError::UnbalancedBlockTag(
+                    "unexpected {{/each}} without a matching {{#each}}".to_string(),
+                ));
+            }
+            Some(Token::Else) => {
+                if closing == Some("if") {
+                    return Ok(nodes);
+                }
+                return Err(RendererError::UnbalancedBlockTag(
+                    "unexpected {{else}} outside an {{#if}} block".to_string(),
+                ));
+            }
+            _ => {}
+        }
+
+        match tokens.next().expect("just peeked Some above") {
+            Token::Text(text) => nodes.push(TemplateNode::Text(text)),
+            Token::Var { path } => nodes.push(TemplateNode::Var { path }),
+            Token::IfOpen(name) => {
+                let then = parse_nodes(tokens, Some("if"))?;
+                let or_else = if matches!(tokens.peek(), Some(Token::Else)) {
+                    tokens.next();
+                    parse_nodes(tokens, Some("if"))?
+                } else {
+                    Vec::new()
+                };
+                nodes.push(TemplateNode::If {
+                    name,
+                    then,
+                    or_else,
+                });
+            }
+            Token::EachOpen { name, item_name } => {
+                let body = parse_nodes(tokens, Some("each"))?;
+                nodes.push(TemplateNode::Each {
+                    name,
+                    item_name,
+                    body,
+                });
+            }
+            Token::IfClose | Token::EachClose | Token::Else => unreachable!("handled above"),
+        }
+    }
+}
+
+/// Render `nodes` against `scope`. The scope is copied into an owned map up front so that
+/// `{{#each}}` can bind a fresh value for `item_name` per iteration without fighting the
+/// borrow checker over [`Scope`]'s borrowed keys.
+pub(crate) fn render_nodes(nodes: &[TemplateNode], scope: &Scope) -> Result<String, RendererError> {
+    let owned_scope: HashMap<String, ExtensionOutput> = scope
+        .iter()
+        .map(|(&name, output)| (name.to_string(), output.clone()))
+        .collect();
+    render_nodes_owned(nodes, &owned_scope)
+}
+
+fn render_nodes_owned(
+    nodes: &[TemplateNode],
+    scope: &HashMap<String, ExtensionOutput>,
+) -> Result<String, RendererError> {
+    let mut out = String::new();
+    for node in nodes {
+        render_node_owned(node, scope, &mut out)?;
+    }
+    Ok(out)
+}
+
+fn render_node_owned(
+    node: &TemplateNode,
+    scope: &HashMap<String, ExtensionOutput>,
+    out: &mut String,
+) -> Result<(), RendererError> {
+    match node {
+        TemplateNode::Text(text) => out.push_str(text),
+        TemplateNode::Var { path } => {
+            out.push_str(&super::path::resolve_path(path, |name| scope.get(name))?);
+        }
+        TemplateNode::If {
+            name,
+            then,
+            or_else,
+        } => {
+            let branch = if is_truthy(name, scope) { then } else { or_else };
+            out.push_str(&render_nodes_owned(branch, scope)?);
+        }
+        TemplateNode::Each {
+            name,
+            item_name,
+            body,
+        } => {
+            for item in each_items(name, scope) {
+                let mut item_scope = scope.clone();
+                item_scope.insert(item_name.clone(), ExtensionOutput::Single(item));
+                out.push_str(&render_nodes_owned(body, &item_scope)?);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn is_truthy(name: &str, scope: &HashMap<String, ExtensionOutput>) -> bool {
+    match scope.get(name) {
+        Some(ExtensionOutput::Single(value)) => !value.is_empty(),
+        Some(ExtensionOutput::Multiple(map)) => !map.is_empty(),
+        Some(ExtensionOutput::Nested(map)) => !map.is_empty(),
+        None => false,
+    }
+}
+
+/// Items to bind `item_name` to in turn for an `{{#each}}` block: the (key-sorted, for
+/// determinism) values of an `ExtensionOutput::Multiple`, or the comma-separated parts of an
+/// `ExtensionOutput::Single`.
+fn each_items(name: &str, scope: &HashMap<String, ExtensionOutput>) -> Vec<String> {
+    match scope.get(name) {
+        Some(ExtensionOutput::Multiple(map)) => {
+            let mut entries: Vec<(&String, &String)> = map.iter().collect();
+            entries.sort_by_key(|(key, _)| key.as_str());
+            entries.into_iter().map(|(_, value)| value.clone()).collect()
+        }
+        Some(ExtensionOutput::Single(value)) if !value.is_empty() => value
+            .split(',')
+            .map(|item| item.trim().to_string())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn single_scope(pairs: &[(&str, &str)]) -> HashMap<String, ExtensionOutput> {
+        pairs
+            .iter()
+            .map(|&(name, value)| (name.to_string(), ExtensionOutput::Single(value.to_string())))
+            .collect()
+    }
+
+    fn render(body: &str, scope: &HashMap<String, ExtensionOutput>) -> String {
+        let nodes = parse(body).unwrap();
+        render_nodes_owned(&nodes, scope).unwrap()
+    }
+
+    #[test]
+    fn if_then_branch() {
+        let scope = single_scope(&[("var", "yes")]);
+        assert_eq!(
+            render("{{#if var}}A{{else}}B{{/if}}", &scope),
+            "A".to_string()
+        );
+    }
+
+    #[test]
+    fn if_else_branch_on_missing_var() {
+        let scope = single_scope(&[]);
+        assert_eq!(
+            render("{{#if var}}A{{else}}B{{/if}}", &scope),
+            "B".to_string()
+        );
+    }
+
+    #[test]
+    fn if_without_else_on_falsy_var_renders_nothing() {
+        let scope = single_scope(&[("var", "")]);
+        assert_eq!(render("x{{#if var}}A{{/if}}y", &scope), "xy".to_string());
+    }
+
+    #[test]
+    fn each_over_multiple() {
+        let mut map = HashMap::new();
+        map.insert("b".to_string(), "2".to_string());
+        map.insert("a".to_string(), "1".to_string());
+        let mut scope = HashMap::new();
+        scope.insert("list".to_string(), ExtensionOutput::Multiple(map));
+        assert_eq!(
+            render("{{#each list as item}}[{{item}}]{{/each}}", &scope),
+            "[1][2]".to_string()
+        );
+    }
+
+    #[test]
+    fn each_over_delimited_single_value() {
+        let scope = single_scope(&[("list", "a, b, c")]);
+        assert_eq!(
+            render("{{#each list}}({{this}}){{/each}}", &scope),
+            "(a)(b)(c)".to_string()
+        );
+    }
+
+    #[test]
+    fn nested_blocks() {
+        let mut scope = single_scope(&[("outer", "yes")]);
+        scope.insert(
+            "list".to_string(),
+            ExtensionOutput::Single("1,2".to_string()),
+        );
+        assert_eq!(
+            render(
+                "{{#if outer}}{{#each list}}{{this}}{{/each}}{{/if}}",
+                &scope
+            ),
+            "12".to_string()
+        );
+    }
+
+    #[test]
+    fn var_resolves_nested_path() {
+        let mut inner = HashMap::new();
+        inner.insert(
+            "city".to_string(),
+            ExtensionOutput::Single("Rome".to_string()),
+        );
+        let mut scope = HashMap::new();
+        scope.insert("user".to_string(), ExtensionOutput::Nested(inner));
+        assert_eq!(
+            render("hello {{user.city}}", &scope),
+            "hello Rome".to_string()
+        );
+    }
+
+    #[test]
+    fn unbalanced_if_is_an_error() {
+        assert!(parse("{{#if var}}A").is_err());
+    }
+
+    #[test]
+    fn unmatched_closing_tag_is_an_error() {
+        assert!(parse("A{{/if}}").is_err());
+    }
+}
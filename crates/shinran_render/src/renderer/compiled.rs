@@ -0,0 +1,283 @@
+/*
+ * This file is part of espanso.
+ *
+ * Copyright (C) 2019-2021 Federico Terzi
+ *
+ * espanso is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * espanso is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with espanso.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! A reusable, pre-parsed form of a [`TextEffect`]'s body, built once by [`Renderer::compile`]
+//! and rendered repeatedly by [`CompiledTemplate::render`] -- useful for an expansion previewed
+//! on every keystroke, where re-parsing the body and re-discovering its variable references on
+//! every render would be wasted work. [`Renderer::render_template`] is just a convenience
+//! wrapper around `self.compile(template).render(context, options)`.
+//!
+//! The body is lowered into one of three forms at compile time: a bare [`CompiledBody::Literal`]
+//! when it has no `{{ }}` reference at all, the `{{#if}}`/`{{#each}}` block-section tree already
+//! built by [`blocks::parse`] (the branch/loop nodes of [`blocks::TemplateNode`]) when it uses
+//! one, or, in the common case, a flat [`CompiledBody::Flat`] instruction list of
+//! [`Instruction::Literal`]/[`Instruction::InjectVar`] segments tokenized once from
+//! [`super::VAR_REGEX`]. `InjectVar` carries an index into `names` rather than the `{{ }}` text
+//! itself, so rendering walks the instruction list and looks each reference up by its
+//! precomputed path instead of re-running the regex. A body whose block tags `blocks::parse`
+//! rejects is kept as a [`CompiledBody::Error`] instead, reported on every render.
+
+use shinran_types::{TextEffect, TextFormat, TextInjectMode, Variable};
+
+use crate::{AsyncExtension, Context, Extension, RenderOptions, RenderResult, Scope};
+
+use super::path::resolve_path;
+use super::{apply_casing, blocks, resolve, resolve_local_variables, transform, util};
+use super::{RendererError, Renderer, VAR_REGEX};
+
+/// One step of a [`CompiledBody::Flat`] instruction list.
+#[derive(Debug, Clone, PartialEq)]
+enum Instruction {
+    Literal(String),
+    /// `names[index]` is the full dotted path (`user.address.city`) this reference names;
+    /// `pipeline`, if present, is the `{{ name | pipeline }}` transform text to apply to its
+    /// resolved value.
+    InjectVar {
+        index: usize,
+        pipeline: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum CompiledBody {
+    /// No `{{ }}` reference at all: the body is returned unchanged, byte for byte.
+    Literal(String),
+    Flat(Vec<Instruction>),
+    Blocks(Vec<blocks::TemplateNode>),
+    /// `blocks::parse` failed at compile time; re-reported by [`CompiledTemplate::render`] on
+    /// every call, the same as a fresh `render_template` would report it every time.
+    Error(String),
+}
+
+/// See the [module docs](self).
+pub struct CompiledTemplate<'r, M: Extension, A: AsyncExtension> {
+    renderer: &'r Renderer<M, A>,
+    body: CompiledBody,
+    /// The variable-index table [`Instruction::InjectVar`]'s indices resolve against, and (via
+    /// each entry's first path segment) the seed for [`resolve::resolve_names`].
+    names: Vec<String>,
+    vars: Vec<Variable>,
+    format: TextFormat,
+    force_mode: Option<TextInjectMode>,
+}
+
+impl<M: Extension, A: AsyncExtension> Renderer<M, A> {
+    /// Compile `template`'s body into a reusable [`CompiledTemplate`]. See the [module
+    /// docs](self) for what compiling buys you over calling [`Self::render_template`] directly.
+    pub fn compile(&self, template: &TextEffect) -> CompiledTemplate<'_, M, A> {
+        let (body, names) = compile_body(&template.body);
+        CompiledTemplate {
+            renderer: self,
+            body,
+            names,
+            vars: template.vars.clone(),
+            format: template.format.clone(),
+            force_mode: template.force_mode.clone(),
+        }
+    }
+}
+
+impl<M: Extension, A: AsyncExtension> CompiledTemplate<'_, M, A> {
+    pub fn render(&self, context: Context, options: &RenderOptions) -> RenderResult {
+        match &self.body {
+            CompiledBody::Literal(text) => RenderResult::Success(apply_casing(
+                &util::unescape_variable_inections(text),
+                &options.casing_style,
+            )),
+            CompiledBody::Error(message) => {
+                RenderResult::Error(RendererError::UnbalancedBlockTag(message.clone()).into())
+            }
+            CompiledBody::Flat(instructions) => self.render_with_scope(context, options, |scope| {
+                render_instructions(instructions, &self.names, scope, options)
+            }),
+            CompiledBody::Blocks(nodes) => self.render_with_scope(context, options, |scope| {
+                blocks::render_nodes(nodes, scope).map_err(|error| RenderResult::Error(error.into()))
+            }),
+        }
+    }
+
+    /// Shared by the [`CompiledBody::Flat`] and [`CompiledBody::Blocks`] arms of [`Self::render`]:
+    /// resolve `self.vars`' evaluation order from `self.names`, compute the scope, then hand it
+    /// to `substitute` to turn into the rendered body before applying the casing style.
+    fn render_with_scope(
+        &self,
+        context: Context,
+        options: &RenderOptions,
+        substitute: impl FnOnce(&Scope) -> Result<String, RenderResult>,
+    ) -> RenderResult {
+        let local_variables = resolve_local_variables(&self.vars, context);
+        let global_vars = context
+            .global_vars_map
+            .values()
+            .copied()
+            .collect::<Vec<_>>();
+        let initial_names = self.names.iter().map(|name| top_level(name)).collect();
+        let variables = match resolve::resolve_names(
+            initial_names,
+            &local_variables,
+            global_vars.as_slice(),
+        ) {
+            Ok(variables) => variables,
+            Err(err) => return RenderResult::Error(err),
+        };
+
+        let scope = match self.renderer.evaluate_variables(variables, context, options) {
+            Ok(scope) => scope,
+            Err(result) => return result,
+        };
+
+        let body = match substitute(&scope) {
+            Ok(output) => output,
+            Err(result) => return result,
+        };
+
+        RenderResult::Success(apply_casing(
+            &util::unescape_variable_inections(&body),
+            &options.casing_style,
+        ))
+    }
+
+    /// The text format this template was compiled from (e.g. for callers that need to decide how
+    /// to inject the rendered body).
+    pub fn format(&self) -> &TextFormat {
+        &self.format
+    }
+
+    pub fn force_mode(&self) -> Option<&TextInjectMode> {
+        self.force_mode.as_ref()
+    }
+}
+
+/// `name`'s first `.`-separated segment, i.e. the top-level `Variable` name it references (see
+/// [`super::VAR_REGEX`]'s `name` capture group).
+fn top_level(name: &str) -> &str {
+    name.split('.')
+        .next()
+        .expect("str::split always yields at least one segment")
+}
+
+fn compile_body(body: &str) -> (CompiledBody, Vec<String>) {
+    if !VAR_REGEX.is_match(body) {
+        return (CompiledBody::Literal(body.to_string()), Vec::new());
+    }
+
+    if blocks::has_block_tags(body) {
+        return match blocks::parse(body) {
+            Ok(nodes) => {
+                let mut names = Vec::new();
+                collect_block_names(&nodes, &mut names);
+                (CompiledBody::Blocks(nodes), names)
+            }
+            Err(RendererError::UnbalancedBlockTag(message)) => {
+                (CompiledBody::Error(message), Vec::new())
+            }
+            Err(other) => (CompiledBody::Error(other.to_string()), Vec::new()),
+        };
+    }
+
+    let mut names = Vec::new();
+    let instructions = compile_flat(body, &mut names);
+    (CompiledBody::Flat(instructions), names)
+}
+
+/// Tokenize `body` once into a flat [`Instruction`] list via [`VAR_REGEX`], interning each
+/// reference's path into `names` rather than keeping the matched text around.
+fn compile_flat(body: &str, names: &mut Vec<String>) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    let mut last_end = 0;
+
+    for caps in VAR_REGEX.captures_iter(body) {
+        let whole = caps.get(0).expect("capture group 0 always matches");
+        if whole.start() > last_end {
+            instructions.push(Instruction::Literal(
+                body[last_end..whole.start()].to_string(),
+            ));
+        }
+        last_end = whole.end();
+
+        let path = caps
+            .name("path")
+            .expect("VAR_REGEX always captures a path")
+            .as_str()
+            .to_string();
+        let pipeline = caps.name("pipeline").map(|m| m.as_str().to_string());
+        let index = names.len();
+        names.push(path);
+        instructions.push(Instruction::InjectVar { index, pipeline });
+    }
+
+    if last_end < body.len() {
+        instructions.push(Instruction::Literal(body[last_end..].to_string()));
+    }
+
+    instructions
+}
+
+/// Collect every `{{ }}`/`{{#if}}`/`{{#each}}` name referenced by `nodes`, in the order
+/// encountered, for [`resolve::resolve_names`] to seed its dependency search with.
+fn collect_block_names(nodes: &[blocks::TemplateNode], names: &mut Vec<String>) {
+    for node in nodes {
+        match node {
+            blocks::TemplateNode::Text(_) => {}
+            blocks::TemplateNode::Var { path } => names.push(path.clone()),
+            blocks::TemplateNode::If {
+                name,
+                then,
+                or_else,
+            } => {
+                names.push(name.clone());
+                collect_block_names(then, names);
+                collect_block_names(or_else, names);
+            }
+            blocks::TemplateNode::Each { name, body, .. } => {
+                names.push(name.clone());
+                collect_block_names(body, names);
+            }
+        }
+    }
+}
+
+/// Walk a [`CompiledBody::Flat`] instruction list, resolving each [`Instruction::InjectVar`]
+/// against `scope` by its precomputed path (and applying its transform pipeline, if any) instead
+/// of re-scanning the body text the way [`transform::apply`]/[`super::util::render_variables`]
+/// do for an uncompiled body.
+fn render_instructions(
+    instructions: &[Instruction],
+    names: &[String],
+    scope: &Scope,
+    options: &RenderOptions,
+) -> Result<String, RenderResult> {
+    let mut output = String::new();
+    for instruction in instructions {
+        match instruction {
+            Instruction::Literal(text) => output.push_str(text),
+            Instruction::InjectVar { index, pipeline } => {
+                let path = &names[*index];
+                let mut value = resolve_path(path, |name| scope.get(name))
+                    .map_err(|error| RenderResult::Error(error.into()))?;
+                if let Some(pipeline) = pipeline {
+                    value = transform::apply_pipeline(&value, pipeline, options)
+                        .map_err(|error| RenderResult::Error(error.into()))?;
+                }
+                output.push_str(&value);
+            }
+        }
+    }
+    Ok(output)
+}
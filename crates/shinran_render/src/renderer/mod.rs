@@ -17,37 +17,63 @@
  * along with espanso.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::{borrow::Cow, path::Path, sync::LazyLock};
+use std::{
+    borrow::Cow, collections::HashMap, future::Future, path::Path, pin::Pin, sync::LazyLock,
+    time::Duration,
+};
 
 use crate::{
     extension::{
-        date::DateExtension, echo::EchoExtension, random::RandomExtension, script::ScriptExtension,
-        shell::ShellExtension,
+        date::DateExtension, echo::EchoExtension, eval::EvalExtension, random::RandomExtension,
+        rhai::RhaiExtension, script::ScriptExtension, shell::ShellExtension,
     },
-    CasingStyle, Context, Extension, ExtensionOutput, ExtensionResult, RenderOptions, RenderResult,
-    Scope,
+    plugin::{PluginInput, PluginRegistry},
+    AsyncExtension, CasingStyle, Context, EscapeMode, Extension, ExtensionOutput, ExtensionResult,
+    RenderOptions, RenderResult, Scope,
 };
 use log::{error, warn};
 use regex::{Captures, Regex};
-use shinran_types::{MatchEffect, Params, TextEffect, Value, VarType, Variable};
+use shinran_types::{
+    MatchEffect, Number, Params, TextEffect, TextFormat, Value, VarType, Variable,
+};
 use thiserror::Error;
 
 use self::util::{inject_variables_into_params, render_variables};
 
+mod blocks;
+mod compiled;
+mod path;
 mod resolve;
+mod transform;
 mod util;
 
-pub(crate) static VAR_REGEX: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r"\{\{\s*((?P<name>\w+)(\.(?P<subname>(\w+)))?)\s*\}\}").unwrap());
+pub use compiled::CompiledTemplate;
+
+pub(crate) static VAR_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    // `path` captures the whole dotted reference (`user.address.city`), so a `{{ }}` can index
+    // arbitrarily deep into a nested `ExtensionOutput`; `name` is just its first segment, which
+    // is what resolve.rs's dependency graph cares about (the top-level variable produced by a
+    // `Variable`).
+    Regex::new(r"\{\{\s*(?P<path>(?P<name>\w+)(?:\.\w+)*)\s*(\|\s*(?P<pipeline>[^}]+?)\s*)?\}\}")
+        .unwrap()
+});
 static WORD_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(\w+)").unwrap());
 
-pub struct Renderer<M: Extension = NoOpExtension> {
+/// Default time budget given to an [`AsyncExtension`] before it's treated as hung and aborted.
+const DEFAULT_ASYNC_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub struct Renderer<M: Extension = NoOpExtension, A: AsyncExtension = NoOpAsyncExtension> {
     date_extension: DateExtension,
     echo_extension: EchoExtension,
     shell_extension: ShellExtension,
     script_extension: ScriptExtension,
     random_extension: RandomExtension,
+    eval_extension: EvalExtension,
+    rhai_extension: RhaiExtension,
     mock_extension: M,
+    async_extension: A,
+    async_timeout: Duration,
+    plugins: PluginRegistry,
 }
 
 pub struct NoOpExtension;
@@ -62,7 +88,23 @@ impl Extension for NoOpExtension {
     }
 }
 
-impl Renderer<NoOpExtension> {
+pub struct NoOpAsyncExtension;
+
+impl AsyncExtension for NoOpAsyncExtension {
+    fn name(&self) -> &str {
+        "NoOpAsync"
+    }
+
+    fn calculate<'a>(
+        &'a self,
+        _scope: &'a Scope,
+        _params: &'a Params,
+    ) -> Pin<Box<dyn Future<Output = ExtensionResult> + Send + 'a>> {
+        Box::pin(async { ExtensionResult::Aborted })
+    }
+}
+
+impl Renderer<NoOpExtension, NoOpAsyncExtension> {
     pub fn new(base_path: &Path, home_path: &Path, packages_path: &Path) -> Self {
         Self {
             date_extension: DateExtension::new(),
@@ -70,182 +112,474 @@ impl Renderer<NoOpExtension> {
             shell_extension: ShellExtension::new(base_path),
             script_extension: ScriptExtension::new(base_path, home_path, packages_path),
             random_extension: RandomExtension::new(),
+            eval_extension: EvalExtension::new(),
+            rhai_extension: RhaiExtension::new(),
             mock_extension: NoOpExtension,
+            async_extension: NoOpAsyncExtension,
+            async_timeout: DEFAULT_ASYNC_TIMEOUT,
+            plugins: PluginRegistry::load(packages_path),
         }
     }
 }
 
-impl<M: Extension> Renderer<M> {
+impl<M: Extension, A: AsyncExtension> Renderer<M, A> {
+    /// Compile `template` and immediately render it once. For an expansion rendered repeatedly
+    /// (e.g. a live keystroke-trigger preview), prefer calling [`Self::compile`] once and calling
+    /// [`CompiledTemplate::render`] on the result for each render instead, to avoid re-parsing
+    /// the body and re-discovering its variable references on every call.
     pub fn render_template(
         &self,
         template: &TextEffect,
         context: Context,
         options: &RenderOptions,
     ) -> RenderResult {
-        let body = if VAR_REGEX.is_match(&template.body) {
-            // Resolve unresolved variables with global variables, if necessary.
-            // TODO: Find out whether this code can actually ever be triggered.
-            let local_variables: Vec<&Variable> = if template
-                .vars
-                .iter()
-                .any(|var| matches!(var.var_type, VarType::Unresolved))
-            {
-                template
-                    .vars
-                    .iter()
-                    .filter_map(|var| {
-                        if matches!(var.var_type, VarType::Unresolved) {
-                            // Try to resolve it with a global variable.
-                            context.global_vars_map.get(&*var.name).copied()
-                        } else {
-                            Some(var)
-                        }
-                    })
-                    .collect()
-            } else {
-                template.vars.iter().collect()
-            };
-
-            // Here we execute a graph dependency resolution algorithm to determine a valid
-            // evaluation order for variables.
-            let global_vars = context
-                .global_vars_map
-                .values()
-                .copied()
-                .collect::<Vec<_>>();
-            let variables = match resolve::resolve_evaluation_order(
-                &template.body,
-                &local_variables,
-                global_vars.as_slice(),
-            ) {
-                Ok(variables) => variables,
-                Err(err) => return RenderResult::Error(err),
-            };
+        self.compile(template).render(context, options)
+    }
 
-            // Compute the variable outputs
-            let mut scope = Scope::new();
-            for variable in variables {
-                if matches!(variable.var_type, VarType::Match) {
-                    // Recursive call
-                    // Call render recursively
-                    let sub_template = get_trigger_from_var(variable)
-                        .and_then(|trigger| context.matches_map.get(trigger).copied())
-                        .map(|match_| &match_.base_match.effect);
-                    let Some(MatchEffect::Text(sub_template)) = sub_template else {
-                        error!("unable to find sub-match: {}", variable.name);
-                        return RenderResult::Error(RendererError::MissingSubMatch.into());
-                    };
-                    match self.render_template(sub_template, context, options) {
-                        RenderResult::Success(output) => {
-                            scope.insert(&variable.name, ExtensionOutput::Single(output));
-                        }
-                        result => return result,
-                    }
-                    continue;
+    /// Compute the outputs of `variables`, in the order given (already dependency-ordered by
+    /// [`resolve::resolve_evaluation_order`]), into a fresh [`Scope`]. Called by
+    /// [`crate::renderer::CompiledTemplate::render`], which [`Self::render_template`] delegates
+    /// to, before it turns the resulting scope into a rendered body.
+    pub(crate) fn evaluate_variables(
+        &self,
+        variables: Vec<&Variable>,
+        context: Context,
+        options: &RenderOptions,
+    ) -> Result<Scope, RenderResult> {
+        let mut scope = Scope::new();
+        for variable in variables {
+            if matches!(variable.var_type, VarType::Match) {
+                // Recursive call
+                // Call render recursively
+                let sub_template = get_trigger_from_var(variable)
+                    .and_then(|trigger| context.matches_map.get(trigger).copied())
+                    .map(|match_| &match_.base_match.effect);
+                let Some(MatchEffect::Text(sub_template)) = sub_template else {
+                    error!("unable to find sub-match: {}", variable.name);
+                    return Err(RenderResult::Error(RendererError::MissingSubMatch.into()));
                 };
-
-                let variable_params = if variable.inject_vars {
-                    match inject_variables_into_params(&variable.params, &scope) {
-                        Ok(augmented_params) => Cow::Owned(augmented_params),
-                        Err(err) => {
-                            error!(
-                                "unable to inject variables into params of variable '{}': {}",
-                                variable.name, err
-                            );
-
-                            // if variable.var_type == "form" {
-                            //     if let Some(RendererError::MissingVariable(_)) =
-                            //         err.downcast_ref::<RendererError>()
-                            //     {
-                            //         log_new_form_syntax_tip();
-                            //     }
-                            // }
-
-                            return RenderResult::Error(err);
-                        }
+                match self.render_template(sub_template, context, options) {
+                    RenderResult::Success(output) => {
+                        let output =
+                            escape_for_insertion(ExtensionOutput::Single(output), variable, options);
+                        scope.insert(&variable.name, output);
                     }
-                } else {
-                    Cow::Borrowed(&variable.params)
-                };
+                    result => return Err(result),
+                }
+                continue;
+            };
 
-                let extension_result = match &variable.var_type {
-                    VarType::Date => self.date_extension.calculate(&scope, &variable_params),
-                    VarType::Echo => self.echo_extension.calculate(&scope, &variable_params),
-                    VarType::Shell => self.shell_extension.calculate(&scope, &variable_params),
-                    VarType::Script => self.script_extension.calculate(&scope, &variable_params),
-                    VarType::Random => self.random_extension.calculate(&scope, &variable_params),
-                    VarType::Mock => self.mock_extension.calculate(&scope, &variable_params),
-                    VarType::Form => {
-                        // Do nothing.
-                        return RenderResult::Success("".to_string());
-                    }
-                    VarType::Unresolved | VarType::Match => {
-                        unreachable!()
+            if matches!(variable.var_type, VarType::Conditional) {
+                match self.render_conditional(variable, &scope, context, options) {
+                    Ok(output) => {
+                        let output =
+                            escape_for_insertion(ExtensionOutput::Single(output), variable, options);
+                        scope.insert(&variable.name, output);
                     }
-                };
+                    Err(result) => return Err(result),
+                }
+                continue;
+            };
 
-                match extension_result {
-                    ExtensionResult::Success(output) => {
+            if matches!(variable.var_type, VarType::List) {
+                match render_list(variable, &scope, options) {
+                    Ok(output) => {
+                        let output =
+                            escape_for_insertion(ExtensionOutput::Single(output), variable, options);
                         scope.insert(&variable.name, output);
                     }
-                    ExtensionResult::Aborted => {
-                        warn!(
-                            "rendering was aborted by extension: {:?}, on var: {}",
-                            variable.var_type, variable.name
-                        );
-                        return RenderResult::Aborted;
-                    }
-                    ExtensionResult::Error(err) => {
-                        warn!(
-                            "extension '{:?}' on var: '{}' reported an error: {}",
-                            variable.var_type, variable.name, err
+                    Err(result) => return Err(result),
+                }
+                continue;
+            };
+
+            let variable_params = if variable.inject_vars {
+                match inject_variables_into_params(&variable.params, &scope) {
+                    Ok(augmented_params) => Cow::Owned(augmented_params),
+                    Err(err) => {
+                        error!(
+                            "unable to inject variables into params of variable '{}': {}",
+                            variable.name, err
                         );
-                        return RenderResult::Error(err);
+
+                        // if variable.var_type == "form" {
+                        //     if let Some(RendererError::MissingVariable(_)) =
+                        //         err.downcast_ref::<RendererError>()
+                        //     {
+                        //         log_new_form_syntax_tip();
+                        //     }
+                        // }
+
+                        return Err(RenderResult::Error(err));
                     }
                 }
-            }
+            } else {
+                Cow::Borrowed(&variable.params)
+            };
 
-            // Replace the variables
-            match render_variables(&template.body, &scope) {
-                Ok(output) => output,
-                Err(error) => {
-                    return RenderResult::Error(error);
+            let extension_result = match &variable.var_type {
+                VarType::Date => self.date_extension.calculate(&scope, &variable_params),
+                VarType::Echo => self.echo_extension.calculate(&scope, &variable_params),
+                VarType::Shell => self.shell_extension.calculate(&scope, &variable_params),
+                VarType::Script => self.script_extension.calculate(&scope, &variable_params),
+                VarType::Random => self.random_extension.calculate(&scope, &variable_params),
+                VarType::Eval => self.eval_extension.calculate(&scope, &variable_params),
+                VarType::Rhai => {
+                    // Unlike the other extensions, a `Rhai` script only sees its declared
+                    // `depends_on` vars, not everything computed so far, so its script can't
+                    // accidentally take an undeclared dependency on evaluation order.
+                    let rhai_scope: Scope = variable
+                        .depends_on
+                        .iter()
+                        .filter_map(|name| {
+                            scope
+                                .get(name.as_str())
+                                .map(|output| (name.as_str(), output.clone()))
+                        })
+                        .collect();
+                    self.rhai_extension.calculate(&rhai_scope, &variable_params)
+                }
+                VarType::Mock => self.mock_extension.calculate(&scope, &variable_params),
+                VarType::Async => self.calculate_async(&scope, &variable_params),
+                VarType::Form => {
+                    // Do nothing.
+                    return Err(RenderResult::Success(String::new()));
+                }
+                VarType::Unresolved | VarType::Match | VarType::Conditional | VarType::List => {
+                    unreachable!()
+                }
+            };
+
+            match extension_result {
+                ExtensionResult::Success(output) => {
+                    let output = escape_for_insertion(output, variable, options);
+                    scope.insert(&variable.name, output);
+                }
+                ExtensionResult::Aborted => {
+                    warn!(
+                        "rendering was aborted by extension: {:?}, on var: {}",
+                        variable.var_type, variable.name
+                    );
+                    return Err(RenderResult::Aborted);
+                }
+                ExtensionResult::Error(err) => {
+                    warn!(
+                        "extension '{:?}' on var: '{}' reported an error: {}",
+                        variable.var_type, variable.name, err
+                    );
+                    return Err(RenderResult::Error(err));
                 }
             }
-        } else {
-            template.body.clone()
+        }
+        Ok(scope)
+    }
+
+    /// Like [`Self::render_template`], but on success also post-processes the rendered text
+    /// through every loaded plugin (see [`crate::plugin::PluginRegistry`]) before returning it.
+    /// `trigger`/`trigger_vars` are only used to build the [`PluginInput`] each plugin sees, and
+    /// have no effect on `render_template`'s own behavior. Intended for the top-level call on a
+    /// match (not the recursive sub-match calls inside `render_template`), so a plugin sees the
+    /// whole match's final text rather than being invoked once per embedded sub-match.
+    pub fn render_template_with_plugins(
+        &self,
+        template: &TextEffect,
+        context: Context,
+        options: &RenderOptions,
+        trigger: &str,
+        trigger_vars: &HashMap<String, String>,
+    ) -> RenderResult {
+        let result = self.render_template(template, context, options);
+
+        let RenderResult::Success(matched_text) = result else {
+            return result;
+        };
+
+        if self.plugins.is_empty() {
+            return RenderResult::Success(matched_text);
+        }
+
+        let input = PluginInput {
+            trigger: trigger.to_string(),
+            vars: trigger_vars.clone(),
+            matched_text,
         };
+        RenderResult::Success(self.plugins.transform(&input))
+    }
+
+    /// Drive the async extension to completion, giving it `self.async_timeout` before treating
+    /// it as hung and reporting [`ExtensionResult::Aborted`].
+    ///
+    /// This blocks the calling thread for up to `self.async_timeout`. Callers that must keep
+    /// rendering an input loop need to run it off that thread (e.g. on a worker task) and show
+    /// a "computing" preedit via `composing_update` until it resolves.
+    fn calculate_async(&self, scope: &Scope, params: &Params) -> ExtensionResult {
+        async_std::task::block_on(async {
+            match async_std::future::timeout(
+                self.async_timeout,
+                self.async_extension.calculate(scope, params),
+            )
+            .await
+            {
+                Ok(result) => result,
+                Err(_timed_out) => ExtensionResult::Aborted,
+            }
+        })
+    }
 
-        let body = util::unescape_variable_inections(&body);
-
-        // Process the casing style
-        let body_with_casing = match options.casing_style {
-            CasingStyle::None => body,
-            CasingStyle::Uppercase => body.to_uppercase(),
-            CasingStyle::Capitalize => {
-                // Capitalize the first letter
-                let mut v: Vec<char> = body.chars().collect();
-                v[0] = v[0].to_uppercase().next().unwrap();
-                v.into_iter().collect()
+    /// Render a `VarType::Conditional` variable: pick the `then` or `else` sub-template body
+    /// param depending on whether `var`'s resolved scope value is truthy, or (in `ifvar` mode)
+    /// whether it equals the `ifvar` param's variable's value, then render that sub-template
+    /// recursively. A present but empty `var` selects `else`; a missing `var` errors out unless
+    /// `absent_as_false` is set, mirroring the existing `missing_nested_match` error behavior by
+    /// default.
+    fn render_conditional(
+        &self,
+        variable: &Variable,
+        scope: &Scope,
+        context: Context,
+        options: &RenderOptions,
+    ) -> Result<String, RenderResult> {
+        let params = &variable.params;
+        let name = get_string_param(params, "var").ok_or_else(|| {
+            RenderResult::Error(RendererError::MissingVariable("var".to_string()).into())
+        })?;
+
+        let truthy = if let Some(other) = get_string_param(params, "ifvar") {
+            match (scope.get(name), scope.get(other)) {
+                (Some(ExtensionOutput::Single(a)), Some(ExtensionOutput::Single(b))) => a == b,
+                _ => false,
             }
-            CasingStyle::CapitalizeWords => {
-                // Capitalize the first letter of each word
-                WORD_REGEX
-                    .replace_all(&body, |caps: &Captures| {
-                        if let Some(word_match) = caps.get(0) {
-                            let mut v: Vec<char> = word_match.as_str().chars().collect();
-                            v[0] = v[0].to_uppercase().next().unwrap();
-                            let capitalized_word: String = v.into_iter().collect();
-                            capitalized_word
-                        } else {
-                            String::new()
-                        }
-                    })
-                    .to_string()
+        } else {
+            let absent_as_false = matches!(params.get("absent_as_false"), Some(Value::Bool(true)));
+            match scope.get(name) {
+                Some(ExtensionOutput::Single(value)) => !value.is_empty(),
+                Some(ExtensionOutput::Multiple(map)) => !map.is_empty(),
+                Some(ExtensionOutput::Nested(map)) => !map.is_empty(),
+                None if absent_as_false => false,
+                None => {
+                    return Err(RenderResult::Error(
+                        RendererError::MissingVariable(name.to_string()).into(),
+                    ))
+                }
             }
         };
 
-        RenderResult::Success(body_with_casing)
+        let branch_body = get_string_param(params, if truthy { "then" } else { "else" });
+        let Some(branch_body) = branch_body else {
+            return Ok(String::new());
+        };
+
+        let sub_template = TextEffect {
+            body: branch_body.to_string(),
+            vars: Vec::new(),
+            format: TextFormat::Plain,
+            force_mode: None,
+        };
+
+        match self.render_template(&sub_template, context, options) {
+            RenderResult::Success(output) => Ok(output),
+            result => Err(result),
+        }
+    }
+}
+
+fn get_string_param<'a>(params: &'a Params, key: &str) -> Option<&'a str> {
+    match params.get(key) {
+        Some(Value::String(value)) => Some(value.as_str()),
+        _ => None,
+    }
+}
+
+/// Render a `VarType::List` variable: iterate the `items` array param, rendering the `steps`
+/// sub-template once per element (with the `as`-named item binding, defaulting to `item`, and
+/// loop-local `index0`/`index1`/`first`/`last` state inserted into a per-iteration copy of
+/// `scope`), and concatenate the results. An empty `items` renders the optional `steps_empty`
+/// fallback instead.
+fn render_list(
+    variable: &Variable,
+    scope: &Scope,
+    options: &RenderOptions,
+) -> Result<String, RenderResult> {
+    let params = &variable.params;
+    let items = match params.get("items") {
+        Some(Value::Array(items)) => items,
+        _ => {
+            return Err(RenderResult::Error(
+                RendererError::MissingVariable("items".to_string()).into(),
+            ))
+        }
+    };
+    let steps = get_string_param(params, "steps").ok_or_else(|| {
+        RenderResult::Error(RendererError::MissingVariable("steps".to_string()).into())
+    })?;
+    let item_name = get_string_param(params, "as").unwrap_or("item");
+
+    if items.is_empty() {
+        return match get_string_param(params, "steps_empty") {
+            Some(steps_empty) => render_step(steps_empty, scope, options),
+            None => Ok(String::new()),
+        };
+    }
+
+    let last_index = items.len() - 1;
+    let mut output = String::new();
+    for (index, item) in items.iter().enumerate() {
+        let mut iteration_scope = scope.clone();
+        iteration_scope.insert(item_name, value_to_output(item));
+        iteration_scope.insert("index0", ExtensionOutput::Single(index.to_string()));
+        iteration_scope.insert("index1", ExtensionOutput::Single((index + 1).to_string()));
+        iteration_scope.insert("first", ExtensionOutput::Single((index == 0).to_string()));
+        iteration_scope.insert(
+            "last",
+            ExtensionOutput::Single((index == last_index).to_string()),
+        );
+        output.push_str(&render_step(steps, &iteration_scope, options)?);
+    }
+    Ok(output)
+}
+
+/// Substitute `{{ }}` references in one `VarType::List` iteration's `steps`/`steps_empty` body,
+/// through the block-section tree (`{{#if}}`/`{{#each}}`) if it uses one, the transform-pipeline
+/// pass if it has a `{{ | }}`, or the cheaper flat substitution otherwise -- the same cascade
+/// [`crate::renderer::compiled`] lowers into a [`CompiledTemplate`] for a `TextEffect`'s own
+/// top-level body.
+pub(crate) fn render_step(
+    body: &str,
+    scope: &Scope,
+    options: &RenderOptions,
+) -> Result<String, RenderResult> {
+    if blocks::has_block_tags(body) {
+        blocks::parse(body)
+            .and_then(|nodes| blocks::render_nodes(&nodes, scope))
+            .map_err(|error| RenderResult::Error(error.into()))
+    } else if transform::has_pipeline(body) {
+        transform::apply(body, scope, options).map_err(|error| RenderResult::Error(error.into()))
+    } else {
+        render_variables(body, scope).map_err(RenderResult::Error)
+    }
+}
+
+pub(crate) fn value_to_output(value: &Value) -> ExtensionOutput {
+    match value {
+        Value::Object(map) => ExtensionOutput::Nested(
+            map.iter()
+                .map(|(key, value)| (key.clone(), value_to_output(value)))
+                .collect(),
+        ),
+        other => ExtensionOutput::Single(value_to_plain_string(other)),
+    }
+}
+
+fn value_to_plain_string(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(Number::Integer(n)) => n.to_string(),
+        Value::Number(Number::Float(n)) => n.to_string(),
+        Value::String(s) => s.clone(),
+        Value::Array(items) => items
+            .iter()
+            .map(value_to_plain_string)
+            .collect::<Vec<_>>()
+            .join(", "),
+        Value::Object(_) => String::new(),
+    }
+}
+
+/// Escape `output`'s string leaves for `options.escape_mode`'s sink, unless `variable`'s own
+/// `escape` param is `false` (opting that one variable out, e.g. because it already contains
+/// intentional markup) or `options.escape_mode` is [`EscapeMode::None`].
+fn escape_for_insertion(
+    output: ExtensionOutput,
+    variable: &Variable,
+    options: &RenderOptions,
+) -> ExtensionOutput {
+    if options.escape_mode == EscapeMode::None {
+        return output;
+    }
+    if matches!(variable.params.get("escape"), Some(Value::Bool(false))) {
+        return output;
+    }
+    escape_output(output, options.escape_mode)
+}
+
+fn escape_output(output: ExtensionOutput, mode: EscapeMode) -> ExtensionOutput {
+    match output {
+        ExtensionOutput::Single(value) => ExtensionOutput::Single(escape_str(&value, mode)),
+        ExtensionOutput::Multiple(map) => ExtensionOutput::Multiple(
+            map.into_iter()
+                .map(|(key, value)| (key, escape_str(&value, mode)))
+                .collect(),
+        ),
+        ExtensionOutput::Nested(map) => ExtensionOutput::Nested(
+            map.into_iter()
+                .map(|(key, value)| (key, escape_output(value, mode)))
+                .collect(),
+        ),
+    }
+}
+
+fn escape_str(value: &str, mode: EscapeMode) -> String {
+    match mode {
+        EscapeMode::None => value.to_string(),
+        EscapeMode::Html => transform::html_escape(value),
+        EscapeMode::Shell => transform::shell_escape(value),
+    }
+}
+
+/// Resolve `VarType::Unresolved` variables in `vars` against `context`'s global variables, if any
+/// are present; otherwise return `vars` as-is.
+/// TODO: Find out whether the `Unresolved` case can actually ever be triggered.
+pub(crate) fn resolve_local_variables<'a>(
+    vars: &'a [Variable],
+    context: Context<'_, 'a>,
+) -> Vec<&'a Variable> {
+    if vars
+        .iter()
+        .any(|var| matches!(var.var_type, VarType::Unresolved))
+    {
+        vars.iter()
+            .filter_map(|var| {
+                if matches!(var.var_type, VarType::Unresolved) {
+                    // Try to resolve it with a global variable.
+                    context.global_vars_map.get(&*var.name).copied()
+                } else {
+                    Some(var)
+                }
+            })
+            .collect()
+    } else {
+        vars.iter().collect()
+    }
+}
+
+/// Apply `casing_style` to an already-substituted body, the last step of both
+/// [`Renderer::render_template`] and [`crate::renderer::CompiledTemplate::render`].
+pub(crate) fn apply_casing(body: &str, casing_style: &CasingStyle) -> String {
+    match casing_style {
+        CasingStyle::None => body.to_string(),
+        CasingStyle::Uppercase => body.to_uppercase(),
+        CasingStyle::Capitalize => {
+            // Capitalize the first letter
+            let mut v: Vec<char> = body.chars().collect();
+            v[0] = v[0].to_uppercase().next().unwrap();
+            v.into_iter().collect()
+        }
+        CasingStyle::CapitalizeWords => {
+            // Capitalize the first letter of each word
+            WORD_REGEX
+                .replace_all(body, |caps: &Captures| {
+                    if let Some(word_match) = caps.get(0) {
+                        let mut v: Vec<char> = word_match.as_str().chars().collect();
+                        v[0] = v[0].to_uppercase().next().unwrap();
+                        let capitalized_word: String = v.into_iter().collect();
+                        capitalized_word
+                    } else {
+                        String::new()
+                    }
+                })
+                .to_string()
+        }
     }
 }
 
@@ -268,6 +602,12 @@ pub enum RendererError {
 
     #[error("circular dependency: `{0}` -> `{1}`")]
     CircularDependency(String, String),
+
+    #[error("unbalanced block tag: {0}")]
+    UnbalancedBlockTag(String),
+
+    #[error("malformed transform: {0}")]
+    MalformedTransform(String),
 }
 
 #[cfg(test)]
@@ -315,14 +655,19 @@ mod tests {
         }
     }
 
-    fn get_renderer() -> Renderer<MockExtension> {
-        Renderer::<MockExtension> {
+    fn get_renderer() -> Renderer<MockExtension, NoOpAsyncExtension> {
+        Renderer::<MockExtension, NoOpAsyncExtension> {
             date_extension: DateExtension::new(),
             echo_extension: EchoExtension::new(),
             shell_extension: ShellExtension::new(Path::new(".")),
             script_extension: ScriptExtension::new(Path::new("."), Path::new("."), Path::new(".")),
             random_extension: RandomExtension::new(),
+            eval_extension: EvalExtension::new(),
+            rhai_extension: RhaiExtension::new(),
             mock_extension: MockExtension {},
+            async_extension: NoOpAsyncExtension,
+            async_timeout: DEFAULT_ASYNC_TIMEOUT,
+            plugins: PluginRegistry::load(Path::new(".")),
         }
     }
 
@@ -1036,6 +1381,47 @@ mod tests {
         assert!(matches!(res, RenderResult::Success(str) if str == "hello local"));
     }
 
+    #[test]
+    fn html_escape_mode_escapes_injected_variable_output() {
+        let renderer = get_renderer();
+        let template = template("<p>{{var}}</p>", &[("var", "<b>&'\"")]);
+        let res = renderer.render_template(
+            &template,
+            Context::default(),
+            &RenderOptions {
+                escape_mode: EscapeMode::Html,
+                ..RenderOptions::default()
+            },
+        );
+        assert!(
+            matches!(res, RenderResult::Success(str) if str == "<p>&lt;b&gt;&amp;&#39;&quot;</p>")
+        );
+    }
+
+    #[test]
+    fn escape_false_param_opts_a_variable_out_of_escaping() {
+        let renderer = get_renderer();
+        let mut template = template_for_str("<p>{{var}}</p>");
+        template.vars = vec![Variable {
+            name: "var".to_string(),
+            var_type: VarType::Mock,
+            params: Params::from_iter(vec![
+                ("echo".to_string(), Value::String("<b>markup</b>".to_string())),
+                ("escape".to_string(), Value::Bool(false)),
+            ]),
+            ..Default::default()
+        }];
+        let res = renderer.render_template(
+            &template,
+            Context::default(),
+            &RenderOptions {
+                escape_mode: EscapeMode::Html,
+                ..RenderOptions::default()
+            },
+        );
+        assert!(matches!(res, RenderResult::Success(str) if str == "<p><b>markup</b></p>"));
+    }
+
     #[test]
     fn variable_escape() {
         let renderer = get_renderer();
@@ -1044,4 +1430,33 @@ mod tests {
             renderer.render_template(&template, Context::default(), &RenderOptions::default());
         assert!(matches!(res, RenderResult::Success(str) if str == "hello {{var}}"));
     }
+
+    #[test]
+    fn compiled_template_renders_the_same_as_render_template() {
+        let renderer = get_renderer();
+        let template = template("hello {{var}}", &[("var", "world")]);
+        let compiled = renderer.compile(&template);
+        let res = compiled.render(Context::default(), &RenderOptions::default());
+        assert!(matches!(res, RenderResult::Success(str) if str == "hello world"));
+    }
+
+    #[test]
+    fn compiled_template_can_be_rendered_more_than_once() {
+        let renderer = get_renderer();
+        let template = template("hello {{var}}", &[("var", "world")]);
+        let compiled = renderer.compile(&template);
+        for _ in 0..3 {
+            let res = compiled.render(Context::default(), &RenderOptions::default());
+            assert!(matches!(res, RenderResult::Success(str) if str == "hello world"));
+        }
+    }
+
+    #[test]
+    fn compiled_template_without_any_variable_is_a_literal() {
+        let renderer = get_renderer();
+        let template = template_for_str("hello there");
+        let compiled = renderer.compile(&template);
+        let res = compiled.render(Context::default(), &RenderOptions::default());
+        assert!(matches!(res, RenderResult::Success(str) if str == "hello there"));
+    }
 }
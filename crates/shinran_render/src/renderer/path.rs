@@ -0,0 +1,137 @@
+/*
+ * This file is part of espanso.
+ *
+ * Copyright (C) 2019-2021 Federico Terzi
+ *
+ * espanso is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * espanso is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with espanso.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Dotted-path scope lookup shared by [`super::blocks`] and [`super::transform`]: resolves
+//! `{{ user.address.city }}`-style references by walking [`ExtensionOutput::Nested`] maps
+//! segment-by-segment, falling through to [`ExtensionOutput::Multiple`] for the last hop.
+
+use crate::ExtensionOutput;
+
+use super::RendererError;
+
+/// Resolve `path` (a `.`-separated sequence of identifiers) against a scope, via `lookup` for the
+/// first segment and [`ExtensionOutput::Nested`]/[`ExtensionOutput::Multiple`] traversal for the
+/// rest. Any missing or untraversable segment reports [`RendererError::MissingVariable`] naming
+/// the *full* path, not just the failing segment.
+pub(super) fn resolve_path<'a>(
+    path: &str,
+    lookup: impl FnOnce(&str) -> Option<&'a ExtensionOutput>,
+) -> Result<String, RendererError> {
+    let mut segments = path.split('.');
+    let first = segments
+        .next()
+        .expect("str::split always yields at least one segment");
+    let root = lookup(first).ok_or_else(|| missing(path))?;
+    resolve_rest(root, segments, path)
+}
+
+fn resolve_rest(
+    current: &ExtensionOutput,
+    mut segments: std::str::Split<'_, char>,
+    full_path: &str,
+) -> Result<String, RendererError> {
+    match segments.next() {
+        None => match current {
+            ExtensionOutput::Single(value) => Ok(value.clone()),
+            ExtensionOutput::Multiple(_) | ExtensionOutput::Nested(_) => Err(missing(full_path)),
+        },
+        Some(segment) => match current {
+            ExtensionOutput::Nested(map) => {
+                let next = map.get(segment).ok_or_else(|| missing(full_path))?;
+                resolve_rest(next, segments, full_path)
+            }
+            ExtensionOutput::Multiple(map) => {
+                if segments.next().is_some() {
+                    return Err(missing(full_path));
+                }
+                map.get(segment).cloned().ok_or_else(|| missing(full_path))
+            }
+            ExtensionOutput::Single(_) => Err(missing(full_path)),
+        },
+    }
+}
+
+fn missing(path: &str) -> RendererError {
+    RendererError::MissingVariable(path.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn resolves_single_level() {
+        let mut scope = HashMap::new();
+        scope.insert("var", ExtensionOutput::Single("value".to_string()));
+        assert_eq!(
+            resolve_path("var", |name| scope.get(name)).unwrap(),
+            "value"
+        );
+    }
+
+    #[test]
+    fn resolves_two_level_multiple() {
+        let mut map = HashMap::new();
+        map.insert("nested".to_string(), "dict".to_string());
+        let mut scope = HashMap::new();
+        scope.insert("var", ExtensionOutput::Multiple(map));
+        assert_eq!(
+            resolve_path("var.nested", |name| scope.get(name)).unwrap(),
+            "dict"
+        );
+    }
+
+    #[test]
+    fn resolves_three_level_nested() {
+        let mut inner = HashMap::new();
+        inner.insert(
+            "city".to_string(),
+            ExtensionOutput::Single("Rome".to_string()),
+        );
+        let mut outer = HashMap::new();
+        outer.insert("address".to_string(), ExtensionOutput::Nested(inner));
+        let mut scope = HashMap::new();
+        scope.insert("user", ExtensionOutput::Nested(outer));
+        assert_eq!(
+            resolve_path("user.address.city", |name| scope.get(name)).unwrap(),
+            "Rome"
+        );
+    }
+
+    #[test]
+    fn missing_segment_names_full_path() {
+        let mut inner = HashMap::new();
+        inner.insert(
+            "city".to_string(),
+            ExtensionOutput::Single("Rome".to_string()),
+        );
+        let mut scope = HashMap::new();
+        scope.insert("user", ExtensionOutput::Nested(inner));
+        let err = resolve_path("user.address.city", |name| scope.get(name)).unwrap_err();
+        assert_eq!(err.to_string(), "missing variable: `user.address.city`");
+    }
+
+    #[test]
+    fn missing_root_is_an_error() {
+        let scope: HashMap<&str, ExtensionOutput> = HashMap::new();
+        assert!(resolve_path("var", |name| scope.get(name)).is_err());
+    }
+}
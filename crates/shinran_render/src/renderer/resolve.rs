@@ -0,0 +1,300 @@
+/*
+ * This file is part of espanso.
+ *
+ * Copyright (C) 2019-2021 Federico Terzi
+ *
+ * espanso is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * espanso is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with espanso.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Dependency-ordered evaluation order for the variables a template body actually needs:
+//! discovers which `Variable`s (from `local_variables`, falling back to `global_vars` by name)
+//! are reachable by scanning `body`, then each newly-discovered variable's own `depends_on` list
+//! and (unless `inject_vars` is `false`) its params -- recursively through any nested
+//! `Value::Array`/`Value::Object` -- for further `{{name}}` references, and topologically sorts
+//! the result with Kahn's algorithm. A variable left unvisited once the algorithm's queue drains
+//! is part of a cycle, reported as [`RendererError::CircularDependency`].
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use shinran_types::{Value, Variable};
+
+use super::{RendererError, VAR_REGEX};
+
+/// Resolve the evaluation order for every `Variable` (local or global) needed to render `body`.
+/// `local_variables` shadow `global_vars` of the same name.
+pub(crate) fn resolve_evaluation_order<'a>(
+    body: &str,
+    local_variables: &[&'a Variable],
+    global_vars: &[&'a Variable],
+) -> anyhow::Result<Vec<&'a Variable>> {
+    resolve_names(referenced_names(body).collect(), local_variables, global_vars)
+}
+
+/// Like [`resolve_evaluation_order`], but seeded directly from a precomputed list of top-level
+/// names instead of scanning a body string -- used by [`super::compiled::CompiledTemplate`],
+/// which already knows the names its compiled instructions reference.
+pub(crate) fn resolve_names<'a>(
+    initial_names: Vec<&str>,
+    local_variables: &[&'a Variable],
+    global_vars: &[&'a Variable],
+) -> anyhow::Result<Vec<&'a Variable>> {
+    let mut by_name: HashMap<&str, &'a Variable> = HashMap::new();
+    for var in global_vars {
+        by_name.insert(var.name.as_str(), var);
+    }
+    for var in local_variables {
+        by_name.insert(var.name.as_str(), var);
+    }
+
+    // Discover, transitively, every variable actually needed to render `body`, and the
+    // prerequisite -> dependent edges between them.
+    let mut needed: Vec<&'a Variable> = Vec::new();
+    let mut seen: HashSet<&str> = HashSet::new();
+    let mut edges: HashMap<&str, Vec<&str>> = HashMap::new();
+
+    let mut worklist: VecDeque<&str> = initial_names.into();
+    while let Some(name) = worklist.pop_front() {
+        if !seen.insert(name) {
+            continue;
+        }
+        let Some(&var) = by_name.get(name) else {
+            continue;
+        };
+        needed.push(var);
+
+        for dep in &var.depends_on {
+            if by_name.contains_key(dep.as_str()) {
+                edges.entry(dep.as_str()).or_default().push(name);
+                worklist.push_back(dep.as_str());
+            }
+        }
+
+        // A variable with `inject_vars: false` never has its params substituted against the
+        // scope, so any `{{name}}`-shaped text inside them is just literal text, not a reference.
+        if var.inject_vars {
+            for param in var.params.values() {
+                scan_value_for_deps(param, &by_name, &mut edges, &mut worklist, name);
+            }
+        }
+    }
+
+    // Kahn's algorithm over the `needed` subgraph.
+    let mut in_degree: HashMap<&str, usize> =
+        needed.iter().map(|var| (var.name.as_str(), 0)).collect();
+    for dependents in edges.values() {
+        for &dependent in dependents {
+            if let Some(count) = in_degree.get_mut(dependent) {
+                *count += 1;
+            }
+        }
+    }
+
+    let mut queue: VecDeque<&str> = needed
+        .iter()
+        .map(|var| var.name.as_str())
+        .filter(|name| in_degree[name] == 0)
+        .collect();
+
+    let mut order: Vec<&'a Variable> = Vec::with_capacity(needed.len());
+    let mut visited: HashSet<&str> = HashSet::new();
+    while let Some(name) = queue.pop_front() {
+        if !visited.insert(name) {
+            continue;
+        }
+        order.push(by_name[name]);
+        if let Some(dependents) = edges.get(name) {
+            for &dependent in dependents {
+                let count = in_degree
+                    .get_mut(dependent)
+                    .expect("every edge target was inserted into in_degree above");
+                *count -= 1;
+                if *count == 0 {
+                    queue.push_back(dependent);
+                }
+            }
+        }
+    }
+
+    if order.len() != needed.len() {
+        let (prerequisite, dependent) = find_cycle_edge(&edges, &visited)
+            .unwrap_or_else(|| {
+                let stuck = needed
+                    .iter()
+                    .map(|var| var.name.as_str())
+                    .find(|name| !visited.contains(name))
+                    .expect("order is shorter than needed, so some name must be unvisited");
+                (stuck, stuck)
+            });
+        return Err(
+            RendererError::CircularDependency(prerequisite.to_string(), dependent.to_string())
+                .into(),
+        );
+    }
+
+    Ok(order)
+}
+
+/// Recursively scan `value` for `{{name}}` references to known variables, registering a
+/// dependency edge (and queuing the referenced name for discovery) for each one found --
+/// `Value::Array`/`Value::Object` hold further `Value`s of their own, so a reference buried
+/// inside one creates an implicit dependency just as a top-level `Value::String` does.
+fn scan_value_for_deps<'a>(
+    value: &'a Value,
+    by_name: &HashMap<&str, &'a Variable>,
+    edges: &mut HashMap<&'a str, Vec<&'a str>>,
+    worklist: &mut VecDeque<&'a str>,
+    dependent: &'a str,
+) {
+    match value {
+        Value::String(text) => {
+            for dep in referenced_names(text) {
+                if by_name.contains_key(dep) {
+                    edges.entry(dep).or_default().push(dependent);
+                    worklist.push_back(dep);
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                scan_value_for_deps(item, by_name, edges, worklist, dependent);
+            }
+        }
+        Value::Object(fields) => {
+            for item in fields.values() {
+                scan_value_for_deps(item, by_name, edges, worklist, dependent);
+            }
+        }
+        Value::Null | Value::Bool(_) | Value::Number(_) => {}
+    }
+}
+
+/// Find an edge whose endpoints are both unvisited, i.e. one that's actually part of a cycle
+/// rather than just downstream of one, to make the reported cycle more informative.
+fn find_cycle_edge<'a>(
+    edges: &HashMap<&'a str, Vec<&'a str>>,
+    visited: &HashSet<&'a str>,
+) -> Option<(&'a str, &'a str)> {
+    edges.iter().find_map(|(&prerequisite, dependents)| {
+        if visited.contains(prerequisite) {
+            return None;
+        }
+        dependents
+            .iter()
+            .find(|&&dependent| !visited.contains(dependent))
+            .map(|&dependent| (prerequisite, dependent))
+    })
+}
+
+/// The top-level variable names `{{ name }}`/`{{ name.nested }}`/`{{ name | transform }}`
+/// references in `body` name (i.e. [`VAR_REGEX`]'s `name` group, not its full dotted `path`).
+fn referenced_names(body: &str) -> impl Iterator<Item = &str> {
+    VAR_REGEX
+        .captures_iter(body)
+        .filter_map(|caps| caps.name("name").map(|m| m.as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use shinran_types::Params;
+
+    use super::*;
+
+    fn var(name: &str, depends_on: &[&str], params: &[(&str, &str)]) -> Variable {
+        Variable {
+            name: name.to_string(),
+            depends_on: depends_on.iter().map(|s| (*s).to_string()).collect(),
+            params: params
+                .iter()
+                .map(|(k, v)| (k.to_string(), Value::String((*v).to_string())))
+                .collect::<Params>(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn single_variable_referenced_in_body() {
+        let a = var("a", &[], &[]);
+        let order = resolve_evaluation_order("{{a}}", &[&a], &[]).unwrap();
+        assert_eq!(order, vec![&a]);
+    }
+
+    #[test]
+    fn unreferenced_variable_is_skipped() {
+        let a = var("a", &[], &[]);
+        let b = var("b", &[], &[]);
+        let order = resolve_evaluation_order("{{a}}", &[&a, &b], &[]).unwrap();
+        assert_eq!(order, vec![&a]);
+    }
+
+    #[test]
+    fn implicit_dependency_via_param_reference() {
+        let a = var("a", &[], &[]);
+        let b = var("b", &[], &[("echo", "{{a}}")]);
+        let order = resolve_evaluation_order("{{b}}", &[&a, &b], &[]).unwrap();
+        assert_eq!(order, vec![&a, &b]);
+    }
+
+    #[test]
+    fn explicit_depends_on_without_body_reference() {
+        let a = var("a", &[], &[]);
+        let b = var("b", &["a"], &[]);
+        let order = resolve_evaluation_order("{{b}}", &[&a, &b], &[]).unwrap();
+        assert_eq!(order, vec![&a, &b]);
+    }
+
+    #[test]
+    fn disabled_injection_does_not_create_a_dependency() {
+        let a = var("a", &[], &[]);
+        let mut b = var("b", &[], &[("echo", "{{a}} two")]);
+        b.inject_vars = false;
+        let order = resolve_evaluation_order("{{b}}", &[&a, &b], &[]).unwrap();
+        assert_eq!(order, vec![&b]);
+    }
+
+    #[test]
+    fn implicit_dependency_via_nested_array_param_reference() {
+        let a = var("a", &[], &[]);
+        let mut b = var("b", &[], &[]);
+        b.params.insert(
+            "items".to_string(),
+            Value::Array(vec![Value::String("{{a}}".to_string())]),
+        );
+        let order = resolve_evaluation_order("{{b}}", &[&a, &b], &[]).unwrap();
+        assert_eq!(order, vec![&a, &b]);
+    }
+
+    #[test]
+    fn implicit_dependency_via_nested_object_param_reference() {
+        let a = var("a", &[], &[]);
+        let mut b = var("b", &[], &[]);
+        let mut nested = Params::new();
+        nested.insert("echo".to_string(), Value::String("{{a}}".to_string()));
+        b.params.insert("options".to_string(), Value::Object(nested));
+        let order = resolve_evaluation_order("{{b}}", &[&a, &b], &[]).unwrap();
+        assert_eq!(order, vec![&a, &b]);
+    }
+
+    #[test]
+    fn cycle_is_reported_as_an_error() {
+        let a = var("a", &["b"], &[]);
+        let b = var("b", &["a"], &[]);
+        assert!(resolve_evaluation_order("{{a}}", &[&a, &b], &[]).is_err());
+    }
+
+    #[test]
+    fn missing_referenced_variable_is_not_an_error_here() {
+        let order = resolve_evaluation_order("{{missing}}", &[], &[]).unwrap();
+        assert!(order.is_empty());
+    }
+}
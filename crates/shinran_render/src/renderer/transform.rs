@@ -0,0 +1,456 @@
+/*
+ * This file is part of espanso.
+ *
+ * Copyright (C) 2019-2021 Federico Terzi
+ *
+ * espanso is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * espanso is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with espanso.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Per-variable transform pipelines in the `{{ name | transform }}` syntax: `upper`/`lower`/
+//! `capitalize` case transforms, `trim`, `html-escape`/`json-encode`, `default-if-empty:fallback`,
+//! `truncate:n`, a `replace:/pattern/replacement/` regex substitution, and `escape:shell`/
+//! `escape:json`/`escape:none` output escaping, applied to that one variable's resolved value only
+//! -- independent of the global `CasingStyle` in [`crate::RenderOptions`]. [`crate::RenderOptions`]
+//! may also carry a `custom_filters` map of additional named filters, consulted when a transform
+//! name doesn't match any built-in.
+
+use regex::Regex;
+
+use crate::{ExtensionOutput, RenderOptions, Scope};
+
+use super::path::resolve_path;
+use super::{RendererError, VAR_REGEX};
+
+/// Whether `body` contains at least one `{{ name | ... }}` reference, i.e. whether it's worth
+/// taking this substitution path rather than the flat one in [`super::util`].
+pub(crate) fn has_pipeline(body: &str) -> bool {
+    VAR_REGEX
+        .captures_iter(body)
+        .any(|caps| caps.name("pipeline").is_some())
+}
+
+/// Substitute every `{{ name }}`/`{{ name | ... }}` reference in `body`, applying each matched
+/// reference's transform pipeline (if any) to its resolved value before insertion.
+pub(crate) fn apply(
+    body: &str,
+    scope: &Scope,
+    options: &RenderOptions,
+) -> Result<String, RendererError> {
+    let mut result = String::with_capacity(body.len());
+    let mut last_end = 0;
+
+    for caps in VAR_REGEX.captures_iter(body) {
+        let whole = caps.get(0).expect("capture group 0 always matches");
+        result.push_str(&body[last_end..whole.start()]);
+        last_end = whole.end();
+
+        let path = caps
+            .name("path")
+            .expect("VAR_REGEX always captures a path")
+            .as_str();
+        let mut value = resolve_path(path, |name| scope.get(name))?;
+
+        if let Some(pipeline) = caps.name("pipeline") {
+            value = apply_pipeline(&value, pipeline.as_str(), options)?;
+        }
+
+        result.push_str(&value);
+    }
+    result.push_str(&body[last_end..]);
+
+    Ok(result)
+}
+
+/// Apply each `|`-delimited segment of `pipeline` to `value` in order.
+pub(super) fn apply_pipeline(
+    value: &str,
+    pipeline: &str,
+    options: &RenderOptions,
+) -> Result<String, RendererError> {
+    let mut value = value.to_string();
+    for segment in pipeline.split('|') {
+        value = apply_transform(&value, segment.trim(), options)?;
+    }
+    Ok(value)
+}
+
+fn apply_transform(
+    value: &str,
+    transform: &str,
+    options: &RenderOptions,
+) -> Result<String, RendererError> {
+    match transform {
+        "upper" | "uppercase" => Ok(value.to_uppercase()),
+        "lower" | "lowercase" => Ok(value.to_lowercase()),
+        "capitalize" => Ok(capitalize(value)),
+        "trim" => Ok(value.trim().to_string()),
+        "html-escape" => Ok(html_escape(value)),
+        "json-encode" => Ok(json_escape(value)),
+        _ => {
+            if let Some(rest) = transform.strip_prefix("replace:") {
+                apply_replace(value, rest)
+            } else if let Some(mode) = transform.strip_prefix("escape:") {
+                apply_escape(value, mode)
+                    .ok_or_else(|| RendererError::MalformedTransform(transform.to_string()))
+            } else if let Some(fallback) = transform.strip_prefix("default-if-empty:") {
+                Ok(if value.is_empty() {
+                    fallback.to_string()
+                } else {
+                    value.to_string()
+                })
+            } else if let Some(count) = transform.strip_prefix("truncate:") {
+                apply_truncate(value, count)
+                    .ok_or_else(|| RendererError::MalformedTransform(transform.to_string()))
+            } else if let Some(filter) = options.custom_filters.get(transform) {
+                Ok(filter(value))
+            } else {
+                Err(RendererError::MalformedTransform(transform.to_string()))
+            }
+        }
+    }
+}
+
+/// Truncate `value` to at most `count` characters, where `count` is parsed from the
+/// `truncate:count` transform's argument. Returns `None` if `count` isn't a valid number.
+fn apply_truncate(value: &str, count: &str) -> Option<String> {
+    let count: usize = count.parse().ok()?;
+    Some(value.chars().take(count).collect())
+}
+
+/// Escape `value` for safe interpolation into HTML text content.
+pub(super) fn html_escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for ch in value.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Escape `value` for safe interpolation into a shell command or a JSON string, or pass it
+/// through unchanged for `none`. Returns `None` on an unrecognized escape mode.
+fn apply_escape(value: &str, mode: &str) -> Option<String> {
+    match mode {
+        "shell" => Some(shell_escape(value)),
+        "json" => Some(json_escape(value)),
+        "none" => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+/// Single-quote `value` per POSIX shell quoting rules, so it can be safely substituted as one
+/// word/argument even if it contains spaces, quotes, or `$(...)`/backtick command substitutions.
+pub(super) fn shell_escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('\'');
+    for ch in value.chars() {
+        if ch == '\'' {
+            out.push_str("'\\''");
+        } else {
+            out.push(ch);
+        }
+    }
+    out.push('\'');
+    out
+}
+
+/// Produce a quoted, JSON-string-escaped representation of `value`, safe to splice directly into
+/// a JSON document as a string literal.
+fn json_escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for ch in value.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn capitalize(value: &str) -> String {
+    let mut chars = value.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().collect::<String>() + chars.as_str(),
+        None => String::new(),
+    }
+}
+
+/// Parse and apply a `/pattern/replacement/` regex substitution (the delimiter is whatever
+/// character immediately follows `replace:`, conventionally `/`), supporting `$1`-style capture
+/// references in `replacement` via [`Regex::replace_all`].
+fn apply_replace(value: &str, rest: &str) -> Result<String, RendererError> {
+    let malformed = || RendererError::MalformedTransform(format!("replace:{rest}"));
+
+    let delim = rest.chars().next().ok_or_else(malformed)?;
+    let body = &rest[delim.len_utf8()..];
+    let mut parts = body.splitn(2, delim);
+    let pattern = parts.next().ok_or_else(malformed)?;
+    let replacement = parts.next().ok_or_else(malformed)?;
+    let replacement = replacement.strip_suffix(delim).unwrap_or(replacement);
+
+    let re = Regex::new(pattern).map_err(|_| malformed())?;
+    Ok(re.replace_all(value, replacement).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn scope(pairs: &[(&str, &str)]) -> HashMap<&str, ExtensionOutput> {
+        pairs
+            .iter()
+            .map(|&(name, value)| (name, ExtensionOutput::Single(value.to_string())))
+            .collect()
+    }
+
+    #[test]
+    fn upper_transform() {
+        let scope = scope(&[("name", "world")]);
+        assert_eq!(
+            apply("hello {{ name | upper }}", &scope, &RenderOptions::default()).unwrap(),
+            "hello WORLD"
+        );
+    }
+
+    #[test]
+    fn lower_transform() {
+        let scope = scope(&[("name", "WORLD")]);
+        assert_eq!(
+            apply("hello {{ name | lower }}", &scope, &RenderOptions::default()).unwrap(),
+            "hello world"
+        );
+    }
+
+    #[test]
+    fn capitalize_transform() {
+        let scope = scope(&[("name", "world")]);
+        assert_eq!(
+            apply("hello {{ name | capitalize }}", &scope, &RenderOptions::default()).unwrap(),
+            "hello World"
+        );
+    }
+
+    #[test]
+    fn replace_transform_with_capture_reference() {
+        let scope = scope(&[("name", "room 42")]);
+        assert_eq!(
+            apply(r"{{ name | replace:/(\d+)/#$1/ }}", &scope, &RenderOptions::default()).unwrap(),
+            "room #42"
+        );
+    }
+
+    #[test]
+    fn chained_transforms() {
+        let scope = scope(&[("name", "room 42")]);
+        let options = RenderOptions::default();
+        assert_eq!(
+            apply(r"{{ name | replace:/(\d+)/#$1/ | upper }}", &scope, &options).unwrap(),
+            "ROOM #42"
+        );
+    }
+
+    #[test]
+    fn unaffected_variables_keep_global_casing_independence() {
+        let scope = scope(&[("a", "one"), ("b", "two")]);
+        assert_eq!(
+            apply("{{ a | upper }} {{ b }}", &scope, &RenderOptions::default()).unwrap(),
+            "ONE two"
+        );
+    }
+
+    #[test]
+    fn escape_shell_single_quotes_embedded_quotes() {
+        let scope = scope(&[("name", "it's $(rm -rf /)")]);
+        assert_eq!(
+            apply("{{ name | escape:shell }}", &scope, &RenderOptions::default()).unwrap(),
+            r"'it'\''s $(rm -rf /)'"
+        );
+    }
+
+    #[test]
+    fn escape_json_escapes_quotes_and_control_chars() {
+        let scope = scope(&[("name", "line1\n\"quoted\"")]);
+        assert_eq!(
+            apply("{{ name | escape:json }}", &scope, &RenderOptions::default()).unwrap(),
+            r#""line1\n\"quoted\"""#
+        );
+    }
+
+    #[test]
+    fn escape_none_passes_through_unchanged() {
+        let scope = scope(&[("name", "it's raw")]);
+        assert_eq!(
+            apply("{{ name | escape:none }}", &scope, &RenderOptions::default()).unwrap(),
+            "it's raw"
+        );
+    }
+
+    #[test]
+    fn unknown_escape_mode_is_an_error() {
+        let scope = scope(&[("name", "x")]);
+        assert!(apply("{{ name | escape:xml }}", &scope, &RenderOptions::default()).is_err());
+    }
+
+    #[test]
+    fn resolves_multi_level_nested_path() {
+        let mut inner = HashMap::new();
+        inner.insert(
+            "city".to_string(),
+            ExtensionOutput::Single("Rome".to_string()),
+        );
+        let mut outer = HashMap::new();
+        outer.insert("address".to_string(), ExtensionOutput::Nested(inner));
+        let mut scope = HashMap::new();
+        scope.insert("user", ExtensionOutput::Nested(outer));
+        assert_eq!(
+            apply("hello {{ user.address.city }}", &scope, &RenderOptions::default()).unwrap(),
+            "hello Rome"
+        );
+    }
+
+    #[test]
+    fn missing_nested_segment_is_an_error() {
+        let mut scope = HashMap::new();
+        scope.insert("user", ExtensionOutput::Nested(HashMap::new()));
+        assert!(apply("{{ user.address.city }}", &scope, &RenderOptions::default()).is_err());
+    }
+
+    #[test]
+    fn malformed_transform_is_an_error() {
+        let scope = scope(&[("name", "world")]);
+        assert!(apply("{{ name | nonsense }}", &scope, &RenderOptions::default()).is_err());
+    }
+
+    #[test]
+    fn malformed_replace_pattern_is_an_error() {
+        let scope = scope(&[("name", "world")]);
+        assert!(apply(r"{{ name | replace:/(/x/ }}", &scope, &RenderOptions::default()).is_err());
+    }
+
+    #[test]
+    fn trim_transform() {
+        let scope = scope(&[("name", "  world  ")]);
+        assert_eq!(
+            apply("hello {{ name | trim }}", &scope, &RenderOptions::default()).unwrap(),
+            "hello world"
+        );
+    }
+
+    #[test]
+    fn html_escape_transform() {
+        let scope = scope(&[("name", "<b>&'\"")]);
+        assert_eq!(
+            apply("{{ name | html-escape }}", &scope, &RenderOptions::default()).unwrap(),
+            "&lt;b&gt;&amp;&#39;&quot;"
+        );
+    }
+
+    #[test]
+    fn json_encode_transform() {
+        let scope = scope(&[("name", "a\"b")]);
+        assert_eq!(
+            apply("{{ name | json-encode }}", &scope, &RenderOptions::default()).unwrap(),
+            r#""a\"b""#
+        );
+    }
+
+    #[test]
+    fn default_if_empty_transform() {
+        let scope = scope(&[("name", "")]);
+        assert_eq!(
+            apply(
+                "{{ name | default-if-empty:fallback }}",
+                &scope,
+                &RenderOptions::default()
+            )
+            .unwrap(),
+            "fallback"
+        );
+    }
+
+    #[test]
+    fn default_if_empty_transform_leaves_nonempty_value_alone() {
+        let scope = scope(&[("name", "world")]);
+        assert_eq!(
+            apply(
+                "{{ name | default-if-empty:fallback }}",
+                &scope,
+                &RenderOptions::default()
+            )
+            .unwrap(),
+            "world"
+        );
+    }
+
+    #[test]
+    fn truncate_transform() {
+        let scope = scope(&[("name", "hello world")]);
+        assert_eq!(
+            apply("{{ name | truncate:5 }}", &scope, &RenderOptions::default()).unwrap(),
+            "hello"
+        );
+    }
+
+    #[test]
+    fn malformed_truncate_count_is_an_error() {
+        let scope = scope(&[("name", "hello world")]);
+        assert!(apply("{{ name | truncate:nope }}", &scope, &RenderOptions::default()).is_err());
+    }
+
+    #[test]
+    fn uppercase_and_lowercase_aliases() {
+        let scope = scope(&[("name", "World")]);
+        assert_eq!(
+            apply("{{ name | uppercase }}", &scope, &RenderOptions::default()).unwrap(),
+            "WORLD"
+        );
+        assert_eq!(
+            apply("{{ name | lowercase }}", &scope, &RenderOptions::default()).unwrap(),
+            "world"
+        );
+    }
+
+    #[test]
+    fn custom_filter_is_consulted_for_unknown_transform() {
+        fn shout(value: &str) -> String {
+            format!("{value}!!!")
+        }
+
+        let scope = scope(&[("name", "world")]);
+        let mut options = RenderOptions::default();
+        options
+            .custom_filters
+            .insert("shout".to_string(), shout as fn(&str) -> String);
+
+        assert_eq!(
+            apply("{{ name | shout }}", &scope, &options).unwrap(),
+            "world!!!"
+        );
+    }
+}
@@ -0,0 +1,378 @@
+use rkyv::with::AsString;
+use rkyv::{Archive, Serialize};
+
+use crate::{regex_wrapper::RegexWrapper, Number, Value};
+
+/// A boolean expression gating whether a match is allowed to fire, evaluated against live
+/// app/window/time/variable state right before expansion (see `shinran_lib::filter`'s
+/// `FilterContext`). Unlike [`crate::MatchFilter`] (glob patterns tested against a single
+/// app-identity string), this combines app, window-title, time-of-day, and resolved-variable
+/// predicates with full boolean logic, the same way [`crate::PlatformPredicate`] combines
+/// platform predicates.
+#[derive(Debug, Clone, PartialEq, Archive, Serialize, serde::Serialize, serde::Deserialize)]
+// Recursive type, same treatment as `Value`/`PlatformPredicate`.
+#[archive(bound(serialize = "__S: rkyv::ser::ScratchSpace + rkyv::ser::Serializer"))]
+#[archive(check_bytes)]
+#[archive_attr(check_bytes(
+    bound = "__C: rkyv::validation::ArchiveContext, <__C as rkyv::Fallible>::Error: std::error::Error"
+))]
+pub enum Filter {
+    And(
+        #[omit_bounds]
+        #[archive_attr(omit_bounds)]
+        Vec<Filter>,
+    ),
+    Or(
+        #[omit_bounds]
+        #[archive_attr(omit_bounds)]
+        Vec<Filter>,
+    ),
+    Not(
+        #[omit_bounds]
+        #[archive_attr(omit_bounds)]
+        Box<Filter>,
+    ),
+    /// True iff the foreground app's identity string equals this exactly.
+    AppEquals(String),
+    /// True iff the foreground window's title matches this regex.
+    TitleMatches(#[with(AsString)] RegexWrapper),
+    /// True iff the time of day the evaluator is given, in minutes since midnight, falls in
+    /// `[start, end)` (`shinran_lib` supplies the UTC time of day, not local time). Wraps past midnight when `start > end` (e.g. `22:00`..`06:00` covers the
+    /// overnight span).
+    TimeBetween(u16, u16),
+    /// True iff a resolved variable named `.0` is present and equals `.1`.
+    VarEquals(String, Value),
+}
+
+impl Filter {
+    /// Parse a function-call-style boolean expression, mirroring
+    /// [`crate::PlatformPredicate::parse`]'s `all(...)`/`any(...)`/`not(...)` grammar:
+    /// `and(...)`/`or(...)`/`not(...)` combinators plus `app("...")`, `title("...")`,
+    /// `time("HH:MM", "HH:MM")`, and `var(name, literal)` leaf predicates, e.g.
+    /// `and(app("firefox"), not(time("22:00", "06:00")))`.
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let tokens = tokenize(input)?;
+        let mut parser = Parser { tokens: &tokens, pos: 0 };
+        let filter = parser.parse_filter()?;
+        if parser.pos != tokens.len() {
+            return Err(format!("unexpected trailing input in filter {input:?}"));
+        }
+        Ok(filter)
+    }
+
+    /// The name of the first `title(...)` or `var(...)` leaf found, depth-first, if any.
+    /// Neither is wired up to live state yet -- [`shinran_lib::filter::FilterContext`]'s
+    /// `window_title` and `vars` are always empty at the one real call site -- so config
+    /// loading rejects a condition using either outright, rather than silently accept one that
+    /// can never fire as written.
+    pub fn first_unsupported_predicate(&self) -> Option<&'static str> {
+        match self {
+            Filter::And(children) | Filter::Or(children) => children
+                .iter()
+                .find_map(Filter::first_unsupported_predicate),
+            Filter::Not(inner) => inner.first_unsupported_predicate(),
+            Filter::AppEquals(_) | Filter::TimeBetween(_, _) => None,
+            Filter::TitleMatches(_) => Some("title"),
+            Filter::VarEquals(_, _) => Some("var"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Num(f64),
+    LParen,
+    RParen,
+    Comma,
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::LParen);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::RParen);
+            }
+            ',' => {
+                chars.next();
+                tokens.push(Token::Comma);
+            }
+            '"' => {
+                chars.next();
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => value.push(c),
+                        None => return Err(format!("unterminated string literal in filter {input:?}")),
+                    }
+                }
+                tokens.push(Token::Str(value));
+            }
+            c if c.is_ascii_digit() || c == '-' => {
+                let mut number = String::new();
+                number.push(c);
+                chars.next();
+                while let Some(&c) = chars.peek() {
+                    if c.is_ascii_digit() || c == '.' {
+                        number.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                let value = number
+                    .parse::<f64>()
+                    .map_err(|_| format!("invalid number literal {number:?} in filter {input:?}"))?;
+                tokens.push(Token::Num(value));
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut ident = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_alphanumeric() || c == '_' {
+                        ident.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                tokens.push(Token::Ident(ident));
+            }
+            other => return Err(format!("unexpected character {other:?} in filter {input:?}")),
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn advance(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.pos);
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: &Token) -> Result<(), String> {
+        match self.advance() {
+            Some(token) if token == expected => Ok(()),
+            other => Err(format!("expected {expected:?}, found {other:?}")),
+        }
+    }
+
+    fn parse_string_arg(&mut self) -> Result<String, String> {
+        self.expect(&Token::LParen)?;
+        let value = match self.advance().cloned() {
+            Some(Token::Str(s)) => s,
+            other => return Err(format!("expected a string literal, found {other:?}")),
+        };
+        self.expect(&Token::RParen)?;
+        Ok(value)
+    }
+
+    fn parse_literal(&mut self) -> Result<Value, String> {
+        match self.advance().cloned() {
+            Some(Token::Str(s)) => Ok(Value::String(s)),
+            Some(Token::Num(n)) => Ok(if n.fract() == 0.0 {
+                Value::Number(Number::Integer(n as i64))
+            } else {
+                Value::Number(Number::Float(n))
+            }),
+            Some(Token::Ident(ident)) => match ident.as_str() {
+                "true" => Ok(Value::Bool(true)),
+                "false" => Ok(Value::Bool(false)),
+                "null" => Ok(Value::Null),
+                other => Err(format!("unrecognized literal {other:?}")),
+            },
+            other => Err(format!("expected a literal value, found {other:?}")),
+        }
+    }
+
+    fn parse_filter(&mut self) -> Result<Filter, String> {
+        match self.advance().cloned() {
+            Some(Token::Ident(ident)) => match ident.as_str() {
+                "and" => Ok(Filter::And(self.parse_filter_list()?)),
+                "or" => Ok(Filter::Or(self.parse_filter_list()?)),
+                "not" => {
+                    self.expect(&Token::LParen)?;
+                    let inner = self.parse_filter()?;
+                    self.expect(&Token::RParen)?;
+                    Ok(Filter::Not(Box::new(inner)))
+                }
+                "app" => Ok(Filter::AppEquals(self.parse_string_arg()?)),
+                "title" => {
+                    let pattern = self.parse_string_arg()?;
+                    RegexWrapper::new(&pattern)
+                        .map(Filter::TitleMatches)
+                        .map_err(|err| format!("invalid title regex {pattern:?}: {err}"))
+                }
+                "time" => {
+                    self.expect(&Token::LParen)?;
+                    let start = self.parse_time_literal()?;
+                    self.expect(&Token::Comma)?;
+                    let end = self.parse_time_literal()?;
+                    self.expect(&Token::RParen)?;
+                    Ok(Filter::TimeBetween(start, end))
+                }
+                "var" => {
+                    self.expect(&Token::LParen)?;
+                    let name = match self.advance().cloned() {
+                        Some(Token::Ident(name)) => name,
+                        other => return Err(format!("expected a variable name, found {other:?}")),
+                    };
+                    self.expect(&Token::Comma)?;
+                    let value = self.parse_literal()?;
+                    self.expect(&Token::RParen)?;
+                    Ok(Filter::VarEquals(name, value))
+                }
+                other => Err(format!("unknown filter predicate {other:?}")),
+            },
+            other => Err(format!("expected a filter predicate, found {other:?}")),
+        }
+    }
+
+    fn parse_filter_list(&mut self) -> Result<Vec<Filter>, String> {
+        self.expect(&Token::LParen)?;
+        let mut filters = Vec::new();
+        if self.peek() != Some(&Token::RParen) {
+            loop {
+                filters.push(self.parse_filter()?);
+                if self.peek() == Some(&Token::Comma) {
+                    self.advance();
+                } else {
+                    break;
+                }
+            }
+        }
+        self.expect(&Token::RParen)?;
+        Ok(filters)
+    }
+
+    /// Parses an `"HH:MM"` string literal into minutes since midnight.
+    fn parse_time_literal(&mut self) -> Result<u16, String> {
+        let raw = match self.advance().cloned() {
+            Some(Token::Str(s)) => s,
+            other => return Err(format!("expected a time string \"HH:MM\", found {other:?}")),
+        };
+        let (hours, minutes) = raw
+            .split_once(':')
+            .ok_or_else(|| format!("invalid time literal {raw:?}, expected \"HH:MM\""))?;
+        let hours: u16 = hours
+            .parse()
+            .map_err(|_| format!("invalid hour in time literal {raw:?}"))?;
+        let minutes: u16 = minutes
+            .parse()
+            .map_err(|_| format!("invalid minute in time literal {raw:?}"))?;
+        if hours >= 24 || minutes >= 60 {
+            return Err(format!("time literal {raw:?} out of range"));
+        }
+        Ok(hours * 60 + minutes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_app_and_title_leaves() {
+        assert_eq!(
+            Filter::parse(r#"app("firefox")"#).unwrap(),
+            Filter::AppEquals("firefox".to_string())
+        );
+        assert_eq!(
+            Filter::parse(r#"title("inbox")"#).unwrap(),
+            Filter::TitleMatches(RegexWrapper::new("inbox").unwrap())
+        );
+    }
+
+    #[test]
+    fn parses_time_between_as_minutes_since_midnight() {
+        assert_eq!(
+            Filter::parse(r#"time("22:00", "06:30")"#).unwrap(),
+            Filter::TimeBetween(22 * 60, 6 * 60 + 30)
+        );
+    }
+
+    #[test]
+    fn parses_var_equals_with_typed_literals() {
+        assert_eq!(
+            Filter::parse(r#"var(lang, "en")"#).unwrap(),
+            Filter::VarEquals("lang".to_string(), Value::String("en".to_string()))
+        );
+        assert_eq!(
+            Filter::parse("var(count, 3)").unwrap(),
+            Filter::VarEquals("count".to_string(), Value::Number(Number::Integer(3)))
+        );
+        assert_eq!(
+            Filter::parse("var(enabled, true)").unwrap(),
+            Filter::VarEquals("enabled".to_string(), Value::Bool(true))
+        );
+    }
+
+    #[test]
+    fn parses_and_or_not_combinators() {
+        let filter = Filter::parse(r#"and(app("mail"), not(time("22:00", "06:00")))"#).unwrap();
+        assert_eq!(
+            filter,
+            Filter::And(vec![
+                Filter::AppEquals("mail".to_string()),
+                Filter::Not(Box::new(Filter::TimeBetween(22 * 60, 6 * 60))),
+            ])
+        );
+    }
+
+    #[test]
+    fn first_unsupported_predicate_finds_title_and_var_leaves_anywhere_in_the_tree() {
+        assert_eq!(
+            Filter::AppEquals("firefox".to_string()).first_unsupported_predicate(),
+            None
+        );
+        assert_eq!(
+            Filter::parse(r#"title("inbox")"#)
+                .unwrap()
+                .first_unsupported_predicate(),
+            Some("title")
+        );
+        assert_eq!(
+            Filter::parse(r#"var(lang, "en")"#)
+                .unwrap()
+                .first_unsupported_predicate(),
+            Some("var")
+        );
+        assert_eq!(
+            Filter::parse(r#"and(app("mail"), not(title("inbox")))"#)
+                .unwrap()
+                .first_unsupported_predicate(),
+            Some("title")
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_or_unknown_predicates() {
+        assert!(Filter::parse("app(firefox)").is_err());
+        assert!(Filter::parse(r#"nope("x")"#).is_err());
+        assert!(Filter::parse(r#"and(app("a")"#).is_err());
+        assert!(Filter::parse(r#"time("25:00", "06:00")"#).is_err());
+    }
+}
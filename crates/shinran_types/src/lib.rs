@@ -4,13 +4,20 @@ use compact_str::CompactString;
 use enum_as_inner::EnumAsInner;
 use rkyv::{Archive, Serialize};
 
+mod filter;
+mod platform;
 mod regex_wrapper;
+mod value_serde;
 
+pub use filter::Filter;
+pub use platform::PlatformPredicate;
 pub use regex_wrapper::RegexWrapper;
 
 pub type StructId = i32;
 
-#[derive(Debug, Clone, PartialEq, Default, Archive, Serialize)]
+#[derive(
+    Debug, Clone, PartialEq, Default, Archive, Serialize, serde::Serialize, serde::Deserialize,
+)]
 #[archive(check_bytes)]
 pub enum VarType {
     Date,
@@ -20,13 +27,31 @@ pub enum VarType {
     Random,
     Echo,
     Form,
+    /// Backed by an `AsyncExtension`: the renderer polls its future to completion under a
+    /// per-call timeout, aborting it once the timeout elapses. The render, and the thread that
+    /// called it, block until the future resolves or the timeout is reached.
+    Async,
+    /// Evaluates an inline expression against an embeddable scripting engine, rather than
+    /// shelling out to an interpreter the way `Script` does.
+    Eval,
+    /// Runs a [Rhai](https://rhai.rs) script in-process, like `Eval`, but its `Scope` is
+    /// pre-populated only from `depends_on` (rather than the whole render scope) and its
+    /// result is injected as a structured [`Value`] instead of being stringified.
+    Rhai,
+    /// A `then`/`else` conditional section, rendered as a sub-template depending on whether
+    /// another variable's resolved value is truthy (or, in `ifvar` mode, equal to a second
+    /// variable's value).
+    Conditional,
+    /// Iterates over an array-valued param, rendering a sub-template once per element with a
+    /// loop-local item binding and index/first/last state, and concatenating the results.
+    List,
     /// For nested matches: https://espanso.org/docs/matches/basics/#nested-matches
     Match,
     #[default]
     Unresolved,
 }
 
-#[derive(Debug, Clone, PartialEq, Archive, Serialize)]
+#[derive(Debug, Clone, PartialEq, Archive, Serialize, serde::Serialize, serde::Deserialize)]
 #[archive(check_bytes)]
 pub struct Variable {
     pub name: String,
@@ -130,7 +155,19 @@ impl MatchCause {
     }
 }
 
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Archive, Serialize)]
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    Archive,
+    Serialize,
+    serde::Serialize,
+    serde::Deserialize,
+)]
 #[archive(check_bytes)]
 pub enum WordBoundary {
     #[default]
@@ -150,7 +187,19 @@ pub struct TriggerCause {
     pub uppercase_style: UpperCasingStyle,
 }
 
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Archive, Serialize)]
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    Archive,
+    Serialize,
+    serde::Serialize,
+    serde::Deserialize,
+)]
 #[archive(check_bytes)]
 pub enum UpperCasingStyle {
     #[default]
@@ -162,16 +211,26 @@ pub enum UpperCasingStyle {
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
 pub struct RegexCause {
     pub regex: String,
+
+    pub propagate_case: bool,
+    pub uppercase_style: UpperCasingStyle,
 }
 
 // Effects
 
-#[derive(Debug, Clone, PartialEq, EnumAsInner, Archive, Serialize)]
+#[derive(
+    Debug, Clone, PartialEq, EnumAsInner, Archive, Serialize, serde::Serialize, serde::Deserialize,
+)]
 #[archive(check_bytes)]
 pub enum MatchEffect {
     None,
     Text(TextEffect),
     Image(ImageEffect),
+
+    /// A reference to a config-defined alias by name, resolved against the profile's `aliases`
+    /// table (falling back to the default profile's) before the match is ever matched against.
+    /// A match file should never carry one of these once loading is complete.
+    Alias(String),
 }
 
 impl Default for MatchEffect {
@@ -180,7 +239,7 @@ impl Default for MatchEffect {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Archive, Serialize)]
+#[derive(Debug, Clone, PartialEq, Archive, Serialize, serde::Serialize, serde::Deserialize)]
 #[archive(check_bytes)]
 pub struct TextEffect {
     pub body: String,
@@ -189,7 +248,9 @@ pub struct TextEffect {
     pub force_mode: Option<TextInjectMode>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Archive, Serialize)]
+#[derive(
+    Debug, Clone, PartialEq, Eq, Hash, Archive, Serialize, serde::Serialize, serde::Deserialize,
+)]
 #[archive(check_bytes)]
 pub enum TextFormat {
     Plain,
@@ -197,7 +258,9 @@ pub enum TextFormat {
     Html,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Archive, Serialize)]
+#[derive(
+    Debug, Clone, PartialEq, Eq, Hash, Archive, Serialize, serde::Serialize, serde::Deserialize,
+)]
 #[archive(check_bytes)]
 pub enum TextInjectMode {
     Keys,
@@ -215,13 +278,26 @@ impl Default for TextEffect {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Default, Archive, Serialize)]
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+    Hash,
+    Default,
+    Archive,
+    Serialize,
+    serde::Serialize,
+    serde::Deserialize,
+)]
 #[archive(check_bytes)]
 pub struct ImageEffect {
     pub path: String,
 }
 
-#[derive(Debug, Clone, Default, PartialEq, Archive, Serialize)]
+#[derive(
+    Debug, Clone, Default, PartialEq, Archive, Serialize, serde::Serialize, serde::Deserialize,
+)]
 #[archive(check_bytes)]
 pub struct BaseMatch {
     // pub id: i32,
@@ -230,9 +306,47 @@ pub struct BaseMatch {
     // Metadata
     pub label: Option<String>,
     pub search_terms: Vec<String>,
+
+    /// Which foreground apps this match is available in. Evaluated against an app-identity
+    /// string (window class/title/exec path); see [`MatchFilter`].
+    pub app_filter: MatchFilter,
+
+    /// A `cfg(...)`-style predicate gating which platform this match is active on, evaluated
+    /// once at load time; `None` means always active. See [`PlatformPredicate`].
+    pub platform: Option<PlatformPredicate>,
+
+    /// A boolean expression gating whether this match is allowed to fire, evaluated against
+    /// live app/window/time/variable state right before expansion rather than once at load
+    /// time; `None` means always allowed. See [`Filter`].
+    pub condition: Option<Filter>,
+}
+
+/// A matcher combinator deciding whether a match applies to the current foreground app, tested
+/// against a single app-identity string that combines the window class, title, and exec path.
+/// `Include`/`Exclude` patterns are globs (see `globset`). This is the raw, declared form kept on
+/// the match itself; [`crate`]'s consumers (e.g. `shinran_lib::MatchCache`) compile the patterns
+/// into a `GlobSet` once when building their lookup caches, rather than on every trigger check.
+#[derive(
+    Debug, Clone, Default, PartialEq, Archive, Serialize, serde::Serialize, serde::Deserialize,
+)]
+#[archive(check_bytes)]
+pub enum MatchFilter {
+    /// No restriction: available regardless of the foreground app.
+    #[default]
+    Always,
+    /// Available only when the app identity matches one of these glob patterns.
+    Include(Vec<String>),
+    /// Available everywhere except when the app identity matches one of these glob patterns.
+    Exclude(Vec<String>),
+    /// Available if any child filter accepts the app identity.
+    Union(Vec<MatchFilter>),
+    /// Available iff `.0` accepts the app identity and `.1` does not.
+    Difference(Box<MatchFilter>, Box<MatchFilter>),
 }
 
-#[derive(Debug, Clone, Default, PartialEq, Archive, Serialize)]
+#[derive(
+    Debug, Clone, Default, PartialEq, Archive, Serialize, serde::Serialize, serde::Deserialize,
+)]
 #[archive(check_bytes)]
 pub struct TriggerMatch {
     pub base_match: BaseMatch,
@@ -241,13 +355,24 @@ pub struct TriggerMatch {
     pub propagate_case: bool,
     pub uppercase_style: UpperCasingStyle,
     pub word_boundary: WordBoundary,
+
+    /// When `true`, this match wins a same-trigger collision against a match already claimed by
+    /// an importing file, inverting the usual nearest-root-wins import precedence for this one
+    /// trigger. Lets an imported "base" file mark a default as explicitly overridable and have a
+    /// deeper import supply the actual override, rather than only ever being shadowed by it.
+    pub is_override: bool,
 }
 
-#[derive(Debug, Clone, Default, PartialEq, Archive, Serialize)]
+#[derive(
+    Debug, Clone, Default, PartialEq, Archive, Serialize, serde::Serialize, serde::Deserialize,
+)]
 #[archive(check_bytes)]
 pub struct RegexMatch {
     pub base_match: BaseMatch,
     pub regex: String,
+
+    pub propagate_case: bool,
+    pub uppercase_style: UpperCasingStyle,
 }
 
 #[derive(Debug, Clone, PartialEq)]
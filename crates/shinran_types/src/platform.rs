@@ -0,0 +1,261 @@
+use rkyv::{Archive, Serialize};
+
+/// A `cfg(...)`-style boolean predicate gating whether a match is active on the current
+/// platform, evaluated once at load time against `std::env::consts`. `None` on the match
+/// itself means "always active"; this type is only the non-trivial case.
+///
+/// Recognized keys are `target_os` (`"windows"`, `"macos"`, `"linux"`), `target_family`
+/// (`"unix"`, `"windows"`), and `target_arch`. A bare identifier with no `= "value"` (e.g.
+/// `unix`) is a [`Self::Flag`], matching real `cfg(unix)`/`cfg(windows)` shorthand.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Archive, Serialize, serde::Serialize, serde::Deserialize)]
+// Recursive type, same treatment as `Value` in `lib.rs`.
+#[archive(bound(serialize = "__S: rkyv::ser::ScratchSpace + rkyv::ser::Serializer"))]
+#[archive(check_bytes)]
+#[archive_attr(check_bytes(
+    bound = "__C: rkyv::validation::ArchiveContext, <__C as rkyv::Fallible>::Error: std::error::Error"
+))]
+pub enum PlatformPredicate {
+    All(
+        #[omit_bounds]
+        #[archive_attr(omit_bounds)]
+        Vec<PlatformPredicate>,
+    ),
+    Any(
+        #[omit_bounds]
+        #[archive_attr(omit_bounds)]
+        Vec<PlatformPredicate>,
+    ),
+    Not(
+        #[omit_bounds]
+        #[archive_attr(omit_bounds)]
+        Box<PlatformPredicate>,
+    ),
+    /// `key = "value"`, e.g. `target_os = "linux"`.
+    Equals(String, String),
+    /// A bare key used as a flag, e.g. `unix`.
+    Flag(String),
+}
+
+impl PlatformPredicate {
+    /// Evaluate this predicate against the current platform's `std::env::consts`. An
+    /// unrecognized key or flag simply evaluates to `false`, rather than erroring: by the time
+    /// this runs, [`PlatformPredicate::parse`] has already validated the grammar.
+    pub fn evaluate(&self) -> bool {
+        match self {
+            Self::All(preds) => preds.iter().all(Self::evaluate),
+            Self::Any(preds) => preds.iter().any(Self::evaluate),
+            Self::Not(pred) => !pred.evaluate(),
+            Self::Equals(key, value) => match key.as_str() {
+                "target_os" => std::env::consts::OS == value,
+                "target_family" => std::env::consts::FAMILY == value,
+                "target_arch" => std::env::consts::ARCH == value,
+                _ => false,
+            },
+            Self::Flag(key) => match key.as_str() {
+                "unix" | "windows" => std::env::consts::FAMILY == key,
+                _ => false,
+            },
+        }
+    }
+
+    /// Parse a `cfg(...)`-like expression: `all(...)`/`any(...)`/`not(...)` combinators and
+    /// `key = "value"`/bare-key leaf predicates, e.g. `any(target_os = "linux", target_os =
+    /// "macos")`.
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let tokens = tokenize(input)?;
+        let mut parser = Parser { tokens: &tokens, pos: 0 };
+        let predicate = parser.parse_predicate()?;
+        if parser.pos != tokens.len() {
+            return Err(format!(
+                "unexpected trailing input in platform predicate {input:?}"
+            ));
+        }
+        Ok(predicate)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    LParen,
+    RParen,
+    Comma,
+    Eq,
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::LParen);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::RParen);
+            }
+            ',' => {
+                chars.next();
+                tokens.push(Token::Comma);
+            }
+            '=' => {
+                chars.next();
+                tokens.push(Token::Eq);
+            }
+            '"' => {
+                chars.next();
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => value.push(c),
+                        None => {
+                            return Err(format!(
+                                "unterminated string literal in platform predicate {input:?}"
+                            ))
+                        }
+                    }
+                }
+                tokens.push(Token::Str(value));
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut ident = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_alphanumeric() || c == '_' {
+                        ident.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                tokens.push(Token::Ident(ident));
+            }
+            other => {
+                return Err(format!(
+                    "unexpected character {other:?} in platform predicate {input:?}"
+                ))
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn advance(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.pos);
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: &Token) -> Result<(), String> {
+        match self.advance() {
+            Some(token) if token == expected => Ok(()),
+            other => Err(format!("expected {expected:?}, found {other:?}")),
+        }
+    }
+
+    fn parse_predicate(&mut self) -> Result<PlatformPredicate, String> {
+        match self.advance().cloned() {
+            Some(Token::Ident(ident)) => match ident.as_str() {
+                "all" => Ok(PlatformPredicate::All(self.parse_arg_list()?)),
+                "any" => Ok(PlatformPredicate::Any(self.parse_arg_list()?)),
+                "not" => {
+                    self.expect(&Token::LParen)?;
+                    let inner = self.parse_predicate()?;
+                    self.expect(&Token::RParen)?;
+                    Ok(PlatformPredicate::Not(Box::new(inner)))
+                }
+                key => {
+                    if self.peek() == Some(&Token::Eq) {
+                        self.advance();
+                        match self.advance().cloned() {
+                            Some(Token::Str(value)) => {
+                                Ok(PlatformPredicate::Equals(key.to_string(), value))
+                            }
+                            other => Err(format!(
+                                "expected a string literal after `{key} =`, found {other:?}"
+                            )),
+                        }
+                    } else {
+                        Ok(PlatformPredicate::Flag(key.to_string()))
+                    }
+                }
+            },
+            other => Err(format!("expected an identifier, found {other:?}")),
+        }
+    }
+
+    fn parse_arg_list(&mut self) -> Result<Vec<PlatformPredicate>, String> {
+        self.expect(&Token::LParen)?;
+        let mut args = Vec::new();
+        if self.peek() != Some(&Token::RParen) {
+            loop {
+                args.push(self.parse_predicate()?);
+                if self.peek() == Some(&Token::Comma) {
+                    self.advance();
+                } else {
+                    break;
+                }
+            }
+        }
+        self.expect(&Token::RParen)?;
+        Ok(args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_evaluates_a_target_os_equals_leaf() {
+        let predicate = PlatformPredicate::parse(r#"target_os = "linux""#).unwrap();
+        assert_eq!(
+            predicate,
+            PlatformPredicate::Equals("target_os".to_string(), "linux".to_string())
+        );
+        assert_eq!(predicate.evaluate(), std::env::consts::OS == "linux");
+    }
+
+    #[test]
+    fn parses_any_and_not_combinators() {
+        let predicate =
+            PlatformPredicate::parse(r#"any(target_os = "windows", not(unix))"#).unwrap();
+        assert_eq!(
+            predicate,
+            PlatformPredicate::Any(vec![
+                PlatformPredicate::Equals("target_os".to_string(), "windows".to_string()),
+                PlatformPredicate::Not(Box::new(PlatformPredicate::Flag("unix".to_string()))),
+            ])
+        );
+    }
+
+    #[test]
+    fn bare_flag_evaluates_against_target_family() {
+        let predicate = PlatformPredicate::parse("unix").unwrap();
+        assert_eq!(predicate.evaluate(), std::env::consts::FAMILY == "unix");
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        assert!(PlatformPredicate::parse("all(target_os = )").is_err());
+        assert!(PlatformPredicate::parse("target_os = \"linux\" extra").is_err());
+    }
+}
@@ -1,4 +1,4 @@
-use regex::Regex;
+use fancy_regex::Regex;
 use rkyv::{
     string::{ArchivedString, StringResolver},
     with::{ArchiveWith, AsString, AsStringError, DeserializeWith, SerializeWith},
@@ -10,9 +10,16 @@ use rkyv::{
 pub struct RegexWrapper(Regex);
 
 /// A wrapper around a regex that can be serialized and deserialized with rkyv.
+///
+/// Backed by [`fancy_regex`] rather than the plain `regex` crate: it's a superset that falls
+/// back to a backtracking VM (instead of `regex`'s linear-time NFA) only for patterns that
+/// actually use lookaround (`(?=...)`, `(?<=...)`) or backreferences (`\1`), so most patterns
+/// still run at `regex`-crate speed.
 impl RegexWrapper {
-    pub fn new(regex: Regex) -> Self {
-        Self(regex)
+    /// Compiles `pattern`, surfacing the error rather than panicking so a bad user-supplied
+    /// regex turns into a load-time diagnostic instead of a crash.
+    pub fn new(pattern: &str) -> Result<Self, fancy_regex::Error> {
+        Ok(Self(Regex::new(pattern)?))
     }
 
     /// Returns the original string of this regex.
@@ -20,9 +27,96 @@ impl RegexWrapper {
         self.0.as_str()
     }
 
-    /// Returns true if and only if there is a match for the regex anywhere in the haystack given.
+    /// Returns true if and only if there is a match for the regex anywhere in the haystack
+    /// given. Backtracking patterns (lookaround/backreferences) can hit `fancy_regex`'s step
+    /// limit on pathological input; that's treated as "no match" (logged as a warning) rather
+    /// than propagated, since a hung/aborted match shouldn't be indistinguishable from a crash
+    /// to callers that just want a bool.
     pub fn is_match(&self, haystack: &str) -> bool {
-        self.0.is_match(haystack)
+        match self.0.is_match(haystack) {
+            Ok(is_match) => is_match,
+            Err(err) => {
+                log::warn!(
+                    "regex '{}' gave up evaluating against the input ({}); treating as no match",
+                    self.to_str(),
+                    err
+                );
+                false
+            }
+        }
+    }
+
+    /// Returns the text of the first (leftmost) match in `haystack`, or `None` if there is no
+    /// match (or the step-limit is exceeded, logged the same way as [`Self::is_match`]).
+    pub fn find<'h>(&self, haystack: &'h str) -> Option<&'h str> {
+        match self.0.find(haystack) {
+            Ok(found) => found.map(|m| m.as_str()),
+            Err(err) => {
+                log::warn!(
+                    "regex '{}' gave up evaluating against the input ({}); treating as no match",
+                    self.to_str(),
+                    err
+                );
+                None
+            }
+        }
+    }
+
+    /// Returns every capture group from 1 onward (group 0, the whole match, is covered by
+    /// [`Self::find`]) as `(name, text)` pairs, in group-number order, so callers can build
+    /// both numbered (`$1`, `$2`, ...) and named (`${name}`) template variables from a single
+    /// pass. A group that didn't participate in the match (e.g. the losing side of a `|`) is
+    /// reported with an empty string rather than omitted, so group numbering stays aligned
+    /// with position in the returned list. Returns `None` if there is no match at all.
+    pub fn captures(&self, haystack: &str) -> Option<Vec<(Option<String>, String)>> {
+        let captures = match self.0.captures(haystack) {
+            Ok(Some(captures)) => captures,
+            Ok(None) => return None,
+            Err(err) => {
+                log::warn!(
+                    "regex '{}' gave up evaluating against the input ({}); treating as no match",
+                    self.to_str(),
+                    err
+                );
+                return None;
+            }
+        };
+
+        Some(
+            self.0
+                .capture_names()
+                .enumerate()
+                .skip(1)
+                .map(|(index, name)| {
+                    let text = captures
+                        .get(index)
+                        .map_or(String::new(), |m| m.as_str().to_string());
+                    (name.map(str::to_string), text)
+                })
+                .collect(),
+        )
+    }
+}
+
+impl PartialEq for RegexWrapper {
+    fn eq(&self, other: &Self) -> bool {
+        self.to_str() == other.to_str()
+    }
+}
+
+/// Round-trips through the pattern string, the same representation used for the rkyv
+/// [`AsString`] wrapper above, so a `RegexWrapper` field can derive `serde::Serialize`/
+/// `Deserialize` just by being present in the containing struct/enum.
+impl serde::Serialize for RegexWrapper {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.to_str())
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for RegexWrapper {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        RegexWrapper::new(&pattern).map_err(serde::de::Error::custom)
     }
 }
 
@@ -62,3 +156,58 @@ impl<D: Fallible + ?Sized> DeserializeWith<ArchivedString, RegexWrapper, D> for
         Ok(RegexWrapper(Regex::new(field.as_str()).unwrap()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookbehind_and_backreferences_are_supported() {
+        let wrapper = RegexWrapper::new(r"(?<!\w):(\w+):\1$").unwrap();
+        assert!(wrapper.is_match(":shinran:shinran"));
+        assert!(!wrapper.is_match(":shinran:other"));
+        assert!(!wrapper.is_match("x:shinran:shinran"));
+    }
+
+    #[test]
+    fn invalid_pattern_is_a_compile_error_not_a_panic() {
+        assert!(RegexWrapper::new("(unterminated").is_err());
+    }
+
+    #[test]
+    fn captures_returns_named_and_numbered_groups_in_order() {
+        let wrapper = RegexWrapper::new(r"shinran-(?P<word>\w+)-(\d+)").unwrap();
+        assert_eq!(wrapper.find("shinran-foo-42"), Some("shinran-foo-42"));
+        assert_eq!(
+            wrapper.captures("shinran-foo-42"),
+            Some(vec![
+                (Some("word".to_string()), "foo".to_string()),
+                (None, "42".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn captures_reports_a_non_participating_group_as_empty_without_shifting_positions() {
+        let wrapper = RegexWrapper::new(r"(a)|(b)").unwrap();
+        assert_eq!(
+            wrapper.captures("b"),
+            Some(vec![(None, String::new()), (None, "b".to_string())])
+        );
+    }
+
+    #[test]
+    fn captures_is_none_without_a_match() {
+        let wrapper = RegexWrapper::new(r"shinran-(\w+)").unwrap();
+        assert_eq!(wrapper.captures("nope"), None);
+    }
+
+    #[test]
+    fn a_step_limit_overrun_is_treated_as_no_match() {
+        // A classic catastrophic-backtracking pattern: the backtracking VM is forced to run
+        // (it uses a backreference), and fails to find a match only after exhausting the step
+        // budget on a string with no trailing 'b'.
+        let wrapper = RegexWrapper::new(r"(a+)+\1b").unwrap();
+        assert!(!wrapper.is_match(&"a".repeat(40)));
+    }
+}
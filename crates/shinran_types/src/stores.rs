@@ -1,4 +1,12 @@
-use crate::{BaseMatch, TriggerMatch, Variable};
+use std::collections::{HashMap, VecDeque};
+
+use crate::{BaseMatch, PlatformPredicate, TriggerMatch, Variable, WordBoundary};
+
+/// Whether a match with this `platform` predicate is active on the current platform; `None`
+/// means "always active".
+fn platform_active(platform: &Option<PlatformPredicate>) -> bool {
+    platform.as_ref().map_or(true, PlatformPredicate::evaluate)
+}
 
 #[derive(Debug)]
 #[repr(transparent)]
@@ -56,11 +64,17 @@ impl TrigMatchStore {
         }
     }
 
+    /// Add `m`, unless its [`BaseMatch::platform`] predicate evaluates to `false` on the
+    /// current platform, in which case it's silently skipped and `None` is returned.
     #[inline]
-    pub fn add(&mut self, triggers: Vec<String>, m: TriggerMatch) -> TrigMatchRef {
+    pub fn add(&mut self, triggers: Vec<String>, m: TriggerMatch) -> Option<TrigMatchRef> {
+        if !platform_active(&m.base_match.platform) {
+            return None;
+        }
+
         let idx = self.matches.len();
         self.matches.push((triggers, m));
-        TrigMatchRef { idx }
+        Some(TrigMatchRef { idx })
     }
 
     #[inline]
@@ -106,11 +120,17 @@ impl RegexMatchStore {
         }
     }
 
+    /// Add `m`, unless its [`BaseMatch::platform`] predicate evaluates to `false` on the
+    /// current platform, in which case it's silently skipped and `None` is returned.
     #[inline]
-    pub fn add(&mut self, regex: String, m: BaseMatch) -> RegexMatchRef {
+    pub fn add(&mut self, regex: String, m: BaseMatch) -> Option<RegexMatchRef> {
+        if !platform_active(&m.platform) {
+            return None;
+        }
+
         let idx = self.matches.len();
         self.matches.push((regex, m));
-        RegexMatchRef { idx }
+        Some(RegexMatchRef { idx })
     }
 
     #[inline]
@@ -126,3 +146,196 @@ impl RegexMatchStore {
             .map(|(idx, elem)| (RegexMatchRef { idx }, elem))
     }
 }
+
+/// A trigger recognized by a [`TriggerIndex`] at the end of the scanned buffer.
+#[derive(Debug, Clone, Copy)]
+pub struct DetectedMatch {
+    pub match_ref: TrigMatchRef,
+    /// Length of the matched trigger, in chars.
+    pub trigger_len: usize,
+    pub word_boundary: WordBoundary,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Output {
+    match_ref: TrigMatchRef,
+    trigger_len: usize,
+    word_boundary: WordBoundary,
+}
+
+#[derive(Debug, Default)]
+struct Node {
+    children: HashMap<char, usize>,
+    fail: usize,
+    outputs: Vec<Output>,
+}
+
+/// An Aho-Corasick automaton over every trigger string in a [`TrigMatchStore`], so the current
+/// input buffer can be scanned once per keystroke instead of linearly testing every trigger.
+///
+/// Rebuild this index whenever matches are added to the underlying [`TrigMatchStore`].
+#[derive(Debug, Default)]
+pub struct TriggerIndex {
+    nodes: Vec<Node>,
+}
+
+impl TriggerIndex {
+    /// Build the automaton from every `(trigger, match_ref, word_boundary)` triple.
+    pub fn build(triggers: &TrigMatchStore) -> Self {
+        let mut nodes = vec![Node::default()];
+
+        for (match_ref, (trigger_list, trigger_match)) in triggers.enumerate() {
+            for trigger in trigger_list {
+                let mut current = 0;
+                for ch in trigger.chars() {
+                    current = *nodes[current].children.entry(ch).or_insert_with(|| {
+                        nodes.push(Node::default());
+                        nodes.len() - 1
+                    });
+                }
+                nodes[current].outputs.push(Output {
+                    match_ref,
+                    trigger_len: trigger.chars().count(),
+                    word_boundary: trigger_match.word_boundary,
+                });
+            }
+        }
+
+        Self::link_failures(&mut nodes);
+
+        Self { nodes }
+    }
+
+    /// Compute the failure link of every node by BFS from the root: each node's failure link
+    /// points to the longest proper suffix of its path that is also a trie node (the root and
+    /// every depth-1 node fail to the root), and each node's outputs are unioned with its
+    /// failure target's outputs so suffix matches are also reported.
+    fn link_failures(nodes: &mut Vec<Node>) {
+        let mut queue = VecDeque::new();
+
+        let root_children: Vec<usize> = nodes[0].children.values().copied().collect();
+        for child in root_children {
+            nodes[child].fail = 0;
+            queue.push_back(child);
+        }
+
+        while let Some(current) = queue.pop_front() {
+            let children: Vec<(char, usize)> = nodes[current]
+                .children
+                .iter()
+                .map(|(&ch, &idx)| (ch, idx))
+                .collect();
+
+            for (ch, child) in children {
+                let mut fail = nodes[current].fail;
+                let fail_target = loop {
+                    if let Some(&next) = nodes[fail].children.get(&ch) {
+                        break next;
+                    } else if fail == 0 {
+                        break 0;
+                    } else {
+                        fail = nodes[fail].fail;
+                    }
+                };
+
+                nodes[child].fail = fail_target;
+                let inherited = nodes[fail_target].outputs.clone();
+                nodes[child].outputs.extend(inherited);
+                queue.push_back(child);
+            }
+        }
+    }
+
+    fn step(&self, mut state: usize, ch: char) -> usize {
+        loop {
+            if let Some(&next) = self.nodes[state].children.get(&ch) {
+                return next;
+            } else if state == 0 {
+                return 0;
+            } else {
+                state = self.nodes[state].fail;
+            }
+        }
+    }
+
+    /// Scan the full `buffer`, returning the longest trigger that ends exactly at the end of
+    /// the buffer, among those whose `word_boundary` requirement is satisfied according to
+    /// `is_left_boundary` (called with the char offset where the trigger would start).
+    pub fn longest_match_at_end(
+        &self,
+        buffer: &str,
+        is_left_boundary: impl Fn(usize) -> bool,
+    ) -> Option<DetectedMatch> {
+        let chars: Vec<char> = buffer.chars().collect();
+        let mut state = 0;
+        for &ch in &chars {
+            state = self.step(state, ch);
+        }
+
+        self.nodes[state]
+            .outputs
+            .iter()
+            .filter(|output| {
+                let start = chars.len().saturating_sub(output.trigger_len);
+                match output.word_boundary {
+                    WordBoundary::None | WordBoundary::Right => true,
+                    WordBoundary::Left | WordBoundary::Both => is_left_boundary(start),
+                }
+            })
+            .max_by_key(|output| output.trigger_len)
+            .map(|output| DetectedMatch {
+                match_ref: output.match_ref,
+                trigger_len: output.trigger_len,
+                word_boundary: output.word_boundary,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BaseMatch;
+
+    fn trigger_match(triggers: &[&str]) -> TriggerMatch {
+        TriggerMatch {
+            base_match: BaseMatch::default(),
+            triggers: triggers.iter().map(|&s| s.into()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn finds_longest_trigger_ending_at_buffer_end() {
+        let mut store = TrigMatchStore::new();
+        store.add(vec![":yo".to_string()], trigger_match(&[":yo"]));
+        store.add(vec![":yolo".to_string()], trigger_match(&[":yolo"]));
+
+        let index = TriggerIndex::build(&store);
+
+        let detected = index.longest_match_at_end("hey :yolo", |_| true).unwrap();
+        assert_eq!(detected.trigger_len, 5);
+    }
+
+    #[test]
+    fn no_match_when_buffer_does_not_end_in_a_trigger() {
+        let mut store = TrigMatchStore::new();
+        store.add(vec![":yo".to_string()], trigger_match(&[":yo"]));
+
+        let index = TriggerIndex::build(&store);
+
+        assert!(index.longest_match_at_end(":yonder", |_| true).is_none());
+    }
+
+    #[test]
+    fn honors_left_word_boundary() {
+        let mut store = TrigMatchStore::new();
+        let mut left_bound = trigger_match(&["hi"]);
+        left_bound.word_boundary = WordBoundary::Left;
+        store.add(vec!["hi".to_string()], left_bound);
+
+        let index = TriggerIndex::build(&store);
+
+        assert!(index.longest_match_at_end("hi", |_| true).is_some());
+        assert!(index.longest_match_at_end("hi", |_| false).is_none());
+    }
+}
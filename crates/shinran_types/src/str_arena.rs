@@ -1,3 +1,6 @@
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
 /// Reference to a string in the arena.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct StrRef {
@@ -23,26 +26,53 @@ pub struct StrVecRef {
     end: usize,
 }
 
-#[derive(Debug)]
-#[repr(transparent)]
+fn hash_str(s: &str) -> u64 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    s.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// An arena that interns every string/joined string-vec it's asked to allocate, so repeated
+/// triggers and replacement bodies across many match files only take up bytes once.
+#[derive(Debug, Default)]
 pub struct StrArena {
     buf: String,
+    // Keyed by a hash of the stored slice rather than an owned copy of it, to avoid paying for
+    // the string twice; a `Vec` per bucket resolves the (rare) hash collision by falling back to
+    // an exact comparison against `buf`.
+    interned: HashMap<u64, Vec<StrRef>>,
+    interned_vecs: HashMap<u64, Vec<StrVecRef>>,
 }
 
 impl StrArena {
     pub fn new() -> Self {
-        Self { buf: String::new() }
+        Self {
+            buf: String::new(),
+            interned: HashMap::new(),
+            interned_vecs: HashMap::new(),
+        }
     }
 
-    /// Allocate a string in the arena.
+    /// Allocate a string in the arena, or return the existing [`StrRef`] if an identical string
+    /// was already allocated.
     pub fn alloc(&mut self, s: &str) -> StrRef {
+        let hash = hash_str(s);
+        if let Some(candidates) = self.interned.get(&hash) {
+            if let Some(&existing) = candidates.iter().find(|&&r| self.get(r) == s) {
+                return existing;
+            }
+        }
+
         let start = self.buf.len();
         self.buf.push_str(s);
         let end = self.buf.len();
-        StrRef { start, end }
+        let r = StrRef { start, end };
+        self.interned.entry(hash).or_default().push(r);
+        r
     }
 
-    /// Allocate a vector of strings in the arena.
+    /// Allocate a vector of strings in the arena, interning the whole newline-joined block, or
+    /// return the existing [`StrVecRef`] if an identical block was already allocated.
     ///
     /// Returns `None` if any of the strings contain a newline character.
     pub fn alloc_all(&mut self, strings: &[&str]) -> Option<StrVecRef> {
@@ -51,17 +81,58 @@ impl StrArena {
                 return None;
             }
         }
-        let start = self.buf.len();
-        for s in strings {
-            self.buf.push_str(s);
-            self.buf.push('\n');
+
+        let joined = strings.join("\n");
+        let hash = hash_str(&joined);
+        if let Some(candidates) = self.interned_vecs.get(&hash) {
+            if let Some(&existing) = candidates.iter().find(|&&r| self.get_all_str(r) == joined) {
+                return Some(existing);
+            }
         }
-        // Remove the last newline character.
-        self.buf.pop();
+
+        let start = self.buf.len();
+        self.buf.push_str(&joined);
         let end = self.buf.len();
-        Some(StrVecRef { start, end })
+        let r = StrVecRef { start, end };
+        self.interned_vecs.entry(hash).or_default().push(r);
+        Some(r)
+    }
+
+    pub fn get(&self, r: StrRef) -> &str {
+        &self.buf[r.start..r.end]
+    }
+
+    fn get_all_str(&self, r: StrVecRef) -> &str {
+        &self.buf[r.start..r.end]
+    }
+
+    pub fn get_all(&self, r: StrVecRef) -> std::str::Split<'_, char> {
+        self.get_all_str(r).split('\n')
+    }
+
+    /// Shrink the backing buffer to fit and drop the interning maps, yielding an immutable,
+    /// cache-friendly arena for use during matching, once nothing will be allocated into it
+    /// again.
+    pub fn freeze(self) -> FrozenStrArena {
+        let len = self.interned.values().map(Vec::len).sum::<usize>()
+            + self.interned_vecs.values().map(Vec::len).sum::<usize>();
+
+        let mut buf = self.buf;
+        buf.shrink_to_fit();
+
+        FrozenStrArena { buf, len }
     }
+}
+
+/// An immutable, read-only [`StrArena`] produced by [`StrArena::freeze`]: the backing buffer is
+/// shrunk to fit and the interning map is dropped, since nothing is ever allocated into it again.
+#[derive(Debug)]
+pub struct FrozenStrArena {
+    buf: String,
+    len: usize,
+}
 
+impl FrozenStrArena {
     pub fn get(&self, r: StrRef) -> &str {
         &self.buf[r.start..r.end]
     }
@@ -69,6 +140,21 @@ impl StrArena {
     pub fn get_all(&self, r: StrVecRef) -> std::str::Split<'_, char> {
         self.buf[r.start..r.end].split('\n')
     }
+
+    /// Number of distinct strings/joined blocks interned, i.e. how many `alloc`/`alloc_all` calls
+    /// actually contributed new bytes rather than reusing an existing entry.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Size in bytes of the backing buffer, for reporting the memory savings interning bought.
+    pub fn byte_size(&self) -> usize {
+        self.buf.len()
+    }
 }
 
 #[cfg(test)]
@@ -93,4 +179,33 @@ mod tests {
         assert_eq!(iter.next(), Some("world"));
         assert_eq!(iter.next(), None);
     }
+
+    #[test]
+    fn alloc_interns_identical_strings() {
+        let mut arena = StrArena::new();
+        let r1 = arena.alloc("hello");
+        let r2 = arena.alloc("hello");
+        assert_eq!(r1, r2);
+        assert_eq!(arena.freeze().byte_size(), "hello".len());
+    }
+
+    #[test]
+    fn alloc_all_interns_identical_blocks() {
+        let mut arena = StrArena::new();
+        let r1 = arena.alloc_all(&["a", "b"]).unwrap();
+        let r2 = arena.alloc_all(&["a", "b"]).unwrap();
+        assert_eq!(r1, r2);
+        assert_eq!(arena.freeze().byte_size(), "a\nb".len());
+    }
+
+    #[test]
+    fn freeze_reports_distinct_entry_count() {
+        let mut arena = StrArena::new();
+        arena.alloc("hello");
+        arena.alloc("hello");
+        arena.alloc("world");
+        let frozen = arena.freeze();
+        assert_eq!(frozen.len(), 2);
+        assert!(!frozen.is_empty());
+    }
 }
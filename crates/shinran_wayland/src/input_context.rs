@@ -1,14 +1,25 @@
-use std::{iter::zip, rc::Rc, time::Duration};
+use std::{iter::zip, os::fd::AsFd, rc::Rc, time::Duration};
 
 use calloop::{
     timer::{TimeoutAction, Timer},
     Dispatcher, RegistrationToken,
 };
-use wayland_client::protocol::{wl_keyboard::KeyState, wl_surface::WlSurface};
+use shinran_config::config::RMLVOConfig;
+use wayland_client::{
+    protocol::{
+        wl_buffer::WlBuffer,
+        wl_keyboard::{self, KeyState},
+        wl_shm::WlShm,
+        wl_shm_pool::WlShmPool,
+        wl_surface::WlSurface,
+    },
+    Dispatch, QueueHandle,
+};
 use wayland_protocols_misc::{
     zwp_input_method_v2::client::{
         zwp_input_method_keyboard_grab_v2::ZwpInputMethodKeyboardGrabV2,
-        zwp_input_method_v2::ZwpInputMethodV2, zwp_input_popup_surface_v2::ZwpInputPopupSurfaceV2,
+        zwp_input_method_v2::{ContentHint, ContentPurpose, TextChangeCause, ZwpInputMethodV2},
+        zwp_input_popup_surface_v2::ZwpInputPopupSurfaceV2,
     },
     zwp_virtual_keyboard_v1::client::zwp_virtual_keyboard_v1::ZwpVirtualKeyboardV1,
 };
@@ -16,6 +27,12 @@ use xkbcommon::xkb::{self, Keysym};
 
 use shinran_lib::Backend;
 
+use crate::popup;
+
+/// Longest trigger buffer we keep around; generous enough for realistic triggers while keeping
+/// the per-keystroke trigger check cheap.
+const MAX_BUFFER_CHARS: usize = 64;
+
 pub(crate) struct InputContext<S> {
     pub(crate) seat_id: u32,
     pub(crate) seat_name: Option<String>,
@@ -27,11 +44,33 @@ pub(crate) struct InputContext<S> {
     pub(crate) xkb_context: xkb::Context,
     pub(crate) xkb_keymap: Option<xkb::Keymap>,
     pub(crate) xkb_state: Option<xkb::State>,
+    /// The locale's Compose state, used to collapse dead-key and multi-key sequences (e.g.
+    /// `´` then `e` producing `é`) into a single committed character. `None` if the locale has
+    /// no Compose file, in which case keys are matched directly off their resolved UTF-8 text.
+    xkb_compose_state: Option<xkb::compose::State>,
 
     // zwp_input_method_v2
     pub(crate) pending_activate: bool,
     pub(crate) pending_deactivate: bool,
     pub(crate) num_done_events: u32, // This number is needed for the commit method.
+    /// The focused text field's last-reported content hints/purpose, used to recognize
+    /// password/PIN fields (never grab the keyboard there, see [`Self::should_grab_keyboard`])
+    /// and fields that asked for no completion (see [`Self::should_expand`]). `None` until the
+    /// field has told us otherwise, which we treat permissively (completion enabled, not
+    /// sensitive) since most plain text fields never bother setting these at all.
+    content_hint: Option<ContentHint>,
+    content_purpose: Option<ContentPurpose>,
+    /// Reported by `TextChangeCause`; not consulted by any logic yet, but kept since the
+    /// compositor's input-method protocol bundles it with `content_type`/`surrounding_text`.
+    #[allow(dead_code)]
+    pub(crate) text_change_cause: Option<TextChangeCause>,
+    /// The focused field's surrounding text and the cursor's byte offset into it, as last
+    /// reported by `SurroundingText`. Used by [`Self::cursor_is_mid_word`] to avoid firing a
+    /// trigger when the cursor sits inside an existing word.
+    surrounding_text: Option<String>,
+    surrounding_cursor: u32,
+    #[allow(dead_code)] // not consulted yet; cached alongside `surrounding_cursor` per the protocol
+    surrounding_anchor: u32,
 
     // zwp_input_method_keyboard_grab_v2
     // Handling repeating keys.
@@ -45,11 +84,77 @@ pub(crate) struct InputContext<S> {
     // popup
     pub(crate) wl_surface: WlSurface,
     pub(crate) popup_surface: ZwpInputPopupSurfaceV2,
+    wl_shm: WlShm,
+    qh: QueueHandle<S>,
+    /// The focused text field's cursor rectangle, as last reported by
+    /// `ZwpInputPopupSurfaceV2::text_input_rectangle`, in the coordinate space of the text
+    /// input's surface. We only use its width as a sizing hint for the popup; positioning
+    /// relative to the text field is left entirely to the compositor.
+    cursor_rect: Option<CursorRect>,
 
     // backend
     backend: Rc<Backend<'static>>,
 }
 
+/// The result of resolving a keystroke's text, via [`InputContext::resolve_composed_text`].
+enum ComposedText {
+    /// The key doesn't represent a character at all (e.g. a modifier key).
+    None,
+    /// Part-way through a Compose sequence; nothing has been committed yet.
+    Composing,
+    /// A Compose sequence was just cancelled (e.g. an unrecognized continuation).
+    Cancelled,
+    /// The text this keystroke (or the Compose sequence it completed) produced.
+    Text(String),
+}
+
+/// Build a Compose state for the process locale (`LC_ALL`/`LC_CTYPE`/`LANG`, in that priority
+/// order, falling back to `"C"`), or `None` if the locale has no Compose file to load.
+fn new_compose_state(xkb_context: &xkb::Context) -> Option<xkb::compose::State> {
+    let locale = std::env::var("LC_ALL")
+        .or_else(|_| std::env::var("LC_CTYPE"))
+        .or_else(|_| std::env::var("LANG"))
+        .unwrap_or_else(|_| "C".to_owned());
+    let table =
+        xkb::compose::Table::new_from_locale(xkb_context, &locale, xkb::compose::COMPILE_NO_FLAGS)
+            .ok()?;
+    Some(table.new_state())
+}
+
+/// Compile a fallback keymap from `rmlvo` (the user's `keyboard_layout` configuration override,
+/// or an all-empty default that resolves to the system layout), mirroring SCTK's `RMLVO`-based
+/// `create_keyboard_handler`. This is what keeps `xkb_state` from staying `None` forever on a
+/// compositor that never sends us a `Keymap` event on its own.
+fn new_fallback_keymap(xkb_context: &xkb::Context, rmlvo: &RMLVOConfig) -> Option<xkb::Keymap> {
+    let rule_names = xkb::RuleNames {
+        rules: rmlvo.rules.as_deref().unwrap_or("").to_owned(),
+        model: rmlvo.model.as_deref().unwrap_or("").to_owned(),
+        layout: rmlvo.layout.as_deref().unwrap_or("").to_owned(),
+        variant: rmlvo.variant.as_deref().unwrap_or("").to_owned(),
+        options: rmlvo.options.clone(),
+    };
+    xkb::Keymap::new_from_names(xkb_context, &rule_names, xkb::KEYMAP_COMPILE_NO_FLAGS)
+}
+
+/// Upload `keymap` to `virtual_keyboard` over an anonymous `memfd`, the same transport the
+/// compositor itself uses to hand us a `Keymap` event.
+fn upload_keymap(virtual_keyboard: &ZwpVirtualKeyboardV1, keymap: &xkb::Keymap) {
+    let keymap_text = keymap.get_as_string(xkb::KEYMAP_FORMAT_TEXT_V1);
+    // The compositor reads this fd as a NUL-terminated string.
+    let size = keymap_text.len() + 1;
+    let Ok(fd) = rustix::fs::memfd_create("shinran-keymap", rustix::fs::MemfdFlags::CLOEXEC) else {
+        log::error!("Failed to create memfd for fallback keymap.");
+        return;
+    };
+    if rustix::fs::ftruncate(&fd, size as u64).is_err()
+        || rustix::io::write(&fd, keymap_text.as_bytes()).is_err()
+    {
+        log::warn!("Failed to write fallback keymap to memfd.");
+        return;
+    }
+    virtual_keyboard.keymap(wl_keyboard::KeymapFormat::XkbV1.into(), fd.as_fd(), size as u32);
+}
+
 impl<S> InputContext<S> {
     pub(crate) fn new(
         seat_id: u32,
@@ -57,16 +162,37 @@ impl<S> InputContext<S> {
         virtual_keyboard: ZwpVirtualKeyboardV1,
         wl_surface: WlSurface,
         popup_surface: ZwpInputPopupSurfaceV2,
+        wl_shm: WlShm,
+        qh: QueueHandle<S>,
         backend: Rc<Backend<'static>>,
+        keyboard_layout: Option<&RMLVOConfig>,
     ) -> Self {
+        let xkb_context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
+        let xkb_compose_state = new_compose_state(&xkb_context);
+
+        // Build a fallback keymap right away, so we have a usable keymap even if the compositor
+        // never sends us a `Keymap` event of its own (or sends one in a format we don't
+        // understand) -- which is the common case, not just when `keyboard_layout` happens to be
+        // configured: an all-empty `RMLVOConfig` resolves to the system default layout just fine.
+        // If the compositor does send a real one later, the `Keymap` event handler replaces
+        // `xkb_keymap`/`xkb_state` unconditionally, same as a genuine layout switch.
+        let default_rmlvo = RMLVOConfig::default();
+        let rmlvo = keyboard_layout.unwrap_or(&default_rmlvo);
+        let xkb_keymap = new_fallback_keymap(&xkb_context, rmlvo);
+        if let Some(keymap) = &xkb_keymap {
+            upload_keymap(&virtual_keyboard, keymap);
+        }
+        let xkb_state = xkb_keymap.as_ref().map(xkb::State::new);
+
         Self {
             seat_id,
             seat_name: None, // Set in `name` event in WlSeat.
             input_method,
             virtual_keyboard,
-            xkb_context: xkb::Context::new(xkb::CONTEXT_NO_FLAGS),
-            xkb_keymap: None, // Set in `keymap` event.
-            xkb_state: None,  // Set in `keymap` event.
+            xkb_context,
+            xkb_keymap, // Replaced wholesale by a real `keymap` event, if one ever arrives.
+            xkb_state,
+            xkb_compose_state,
             num_done_events: 0,
             pending_activate: false,
             pending_deactivate: false,
@@ -77,17 +203,107 @@ impl<S> InputContext<S> {
             repeat_timer: None, // Set as needed.
             wl_surface,
             popup_surface,
-            buffer: None, // Set as needed.
+            wl_shm,
+            qh,
+            cursor_rect: None, // Set in `text_input_rectangle` event.
+            buffer: None,      // Set as needed.
+            content_hint: None,         // Set in `content_type` event.
+            content_purpose: None,      // Set in `content_type` event.
+            text_change_cause: None,    // Set in `text_change_cause` event.
+            surrounding_text: None,     // Set in `surrounding_text` event.
+            surrounding_cursor: 0,
+            surrounding_anchor: 0,
             backend,
         }
     }
 
+    /// Record the focused text field's cursor rectangle, reported whenever it moves or resizes.
+    pub(crate) fn set_cursor_rect(&mut self, x: i32, y: i32, width: i32, height: i32) {
+        self.cursor_rect = Some(CursorRect {
+            x,
+            y,
+            width,
+            height,
+        });
+    }
+
+    /// Record the focused text field's content hints/purpose, reported whenever it gains focus
+    /// or its type changes.
+    pub(crate) fn set_content_type(&mut self, hint: ContentHint, purpose: ContentPurpose) {
+        self.content_hint = Some(hint);
+        self.content_purpose = Some(purpose);
+    }
+
+    /// Record the focused text field's surrounding text and the cursor's byte offset into it.
+    pub(crate) fn set_surrounding_text(&mut self, text: String, cursor: u32, anchor: u32) {
+        self.surrounding_text = Some(text);
+        self.surrounding_cursor = cursor;
+        self.surrounding_anchor = anchor;
+    }
+
+    /// Whether the focused field is one we should never see keystrokes for at all: a password or
+    /// PIN field, or one explicitly marked as holding hidden/sensitive data.
+    fn is_sensitive_field(&self) -> bool {
+        matches!(
+            self.content_purpose,
+            Some(ContentPurpose::Password | ContentPurpose::Pin)
+        ) || self.content_hint.is_some_and(|hint| {
+            hint.contains(ContentHint::HiddenText) || hint.contains(ContentHint::SensitiveData)
+        })
+    }
+
+    /// Whether the focused field asked for completion/autocorrection. Absent a hint, we assume
+    /// yes, since most plain text fields never bother setting one.
+    fn completion_enabled(&self) -> bool {
+        self.content_hint
+            .is_none_or(|hint| hint.contains(ContentHint::Completion))
+    }
+
+    /// Whether we should be expanding triggers into this field at all.
+    fn should_expand(&self) -> bool {
+        !self.is_sensitive_field() && self.completion_enabled()
+    }
+
+    /// Whether we should grab the keyboard for this field. We skip grabbing entirely for a
+    /// sensitive field, so the real keyboard reaches the focused surface directly and shinran
+    /// never sees those keystrokes at all.
+    pub(crate) fn should_grab_keyboard(&self) -> bool {
+        !self.is_sensitive_field()
+    }
+
+    /// Whether the cursor sits inside an existing word, judging by the character immediately
+    /// after it in the last-reported `surrounding_text`. Used to avoid firing a trigger when the
+    /// user has moved the cursor back into the middle of a word rather than just having typed it.
+    fn cursor_is_mid_word(&self) -> bool {
+        let Some(text) = &self.surrounding_text else {
+            return false;
+        };
+        let Some(ch) = text.get(self.surrounding_cursor as usize..).and_then(|s| s.chars().next())
+        else {
+            return false;
+        };
+        ch.is_alphanumeric() || ch == '_'
+    }
+
     fn append(&mut self, ch: char) {
         if let Some(ref mut preedit_str) = self.buffer {
             preedit_str.push(ch);
         } else {
             self.buffer = Some(ch.to_string());
         }
+
+        if let Some(buffer) = &mut self.buffer {
+            // Keep only a bounded suffix: long enough to contain the longest trigger, but
+            // short enough that the per-keystroke trigger check stays cheap.
+            let overflow = buffer.chars().count().saturating_sub(MAX_BUFFER_CHARS);
+            if overflow > 0 {
+                let keep_from = buffer
+                    .char_indices()
+                    .nth(overflow)
+                    .map_or(buffer.len(), |(idx, _)| idx);
+                buffer.drain(..keep_from);
+            }
+        }
     }
 
     pub(crate) fn mark_as_pressed(&mut self, keycode: xkb::Keycode) {
@@ -151,39 +367,129 @@ impl<S> InputContext<S> {
                 // return Some(true);
                 return None; // shutdown
             }
+            Keysym::BackSpace => {
+                if let Some(buffer) = &mut self.buffer {
+                    buffer.pop();
+                    if buffer.is_empty() {
+                        self.buffer = None;
+                    }
+                }
+                handled = Some(false);
+            }
             Keysym::KP_Space | Keysym::space => {
+                // A word-separating key breaks any potential trigger in progress.
+                self.buffer = None;
                 return Some(false);
             }
             _ => {
-                // If the key corresponds to an ASCII character, add it to the buffer.
-                // Otherwise, mark it as unhandled.
-                if let Some(ch) = char::from_u32(xkb_state.key_get_utf32(xkb_key)) {
-                    if ch.is_ascii() {
-                        if ch == '\0' {
-                            // If the key does not represent a character,
-                            // `key_get_utf32` returns 0.
+                // Resolve the key through xkbcommon's Compose engine first, so dead-key and
+                // multi-key sequences (e.g. `´` then `e` producing `é`) collapse into a single
+                // committed string instead of each keystroke being matched independently.
+                match self.resolve_composed_text(xkb_key, sym) {
+                    ComposedText::None => {
+                        // The key doesn't represent a character at all (e.g. a modifier key).
+                        handled = Some(false);
+                    }
+                    ComposedText::Composing | ComposedText::Cancelled => {
+                        // Mid-sequence, or a sequence was just cancelled; either way there is
+                        // nothing yet to feed into the trigger buffer.
+                        handled = Some(true);
+                    }
+                    ComposedText::Text(text) => {
+                        if !self.should_expand() {
+                            // This field is sensitive, or asked for no completion; forward the
+                            // key as-is without ever buffering it for trigger matching.
+                            self.buffer = None;
                             handled = Some(false);
                         } else {
-                            self.append(ch);
-                            handled = Some(true);
+                            handled = self.buffer_composed_text(&text);
+
+                            if handled == Some(true) && !self.cursor_is_mid_word() {
+                                if let Some(buffer) = &mut self.buffer {
+                                    if let Some(output) =
+                                        self.backend.check_trigger(buffer).unwrap()
+                                    {
+                                        self.composing_commit(output);
+                                        self.buffer = None;
+                                        self.hide_popup();
+                                        return Some(true);
+                                    }
+                                }
+                            }
                         }
-                    } else {
-                        handled = Some(false);
                     }
-                } else {
-                    handled = Some(false);
                 }
             }
         }
         if let Some(text) = &self.buffer {
             // TODO: Only update if the text has changed.
             self.composing_update(text.clone());
+            self.draw_popup();
+        } else {
+            self.hide_popup();
         }
         handled
     }
 
+    /// Resolve `xkb_key` to the text it produces, running it through the Compose engine (if the
+    /// locale has one) before falling back to the keymap's own resolved UTF-8. `sym` is the
+    /// keysym `handle_key` already looked up, so the Compose engine doesn't need to redo that.
+    fn resolve_composed_text(&mut self, xkb_key: xkb::Keycode, sym: Keysym) -> ComposedText {
+        if let Some(compose_state) = &mut self.xkb_compose_state {
+            if matches!(compose_state.feed(sym), xkb::compose::FeedResult::Accepted) {
+                match compose_state.status() {
+                    xkb::compose::Status::Composing => return ComposedText::Composing,
+                    xkb::compose::Status::Composed => {
+                        let text = compose_state.utf8();
+                        compose_state.reset();
+                        return match text {
+                            Some(text) if !text.is_empty() => ComposedText::Text(text),
+                            _ => ComposedText::None,
+                        };
+                    }
+                    xkb::compose::Status::Cancelled => {
+                        compose_state.reset();
+                        return ComposedText::Cancelled;
+                    }
+                    xkb::compose::Status::Nothing => {
+                        // No sequence involving this key; fall through to the keymap's own text.
+                    }
+                }
+            }
+        }
+
+        let text = self.xkb_state.as_ref().unwrap().key_get_utf8(xkb_key);
+        if text.is_empty() {
+            ComposedText::None
+        } else {
+            ComposedText::Text(text)
+        }
+    }
+
+    /// Feed resolved key text into the trigger buffer, applying the same rules `handle_key`
+    /// always has: a non-alphanumeric, non-`_`/`'` character breaks a potential trigger in
+    /// progress (same as whitespace), and control characters are ignored outright. Returns
+    /// `Some(true)` if the text was appended to the buffer, `Some(false)` otherwise.
+    fn buffer_composed_text(&mut self, text: &str) -> Option<bool> {
+        if text.chars().any(|ch| ch.is_control()) {
+            return Some(false);
+        }
+        if text
+            .chars()
+            .any(|ch| !(ch.is_alphanumeric() || ch == '_') && ch != '\'')
+        {
+            self.buffer = None;
+            return Some(false);
+        }
+        for ch in text.chars() {
+            self.append(ch);
+        }
+        Some(true)
+    }
+
     pub(crate) fn repeat_key(&mut self) -> TimeoutAction {
-        let repeat_rate = self.repeat_rate.expect("Repeat rate should have been set.");
+        // `repeat_rate` is already the per-tick interval (`1000 / rate`), not the raw rate.
+        let repeat_interval = self.repeat_rate.expect("Repeat rate should have been set.");
         let repeating = self
             .repeat_timer
             .as_mut()
@@ -192,8 +498,10 @@ impl<S> InputContext<S> {
         let key = u32::from(key_code) - 8;
         eprintln!("Timer repeats {}", key);
         let time = repeating.timestamp;
-        // Update the timestamp for the next repetition.
-        repeating.timestamp += 1000 / (repeat_rate.as_millis() as u32);
+        // The delay-vs-interval choice was already made when this timer was (re-)armed; from
+        // here on every subsequent firing is spaced by `repeat_interval`.
+        repeating.is_first = false;
+        repeating.timestamp += repeat_interval.as_millis() as u32;
         if self.handle_key(key_code).is_some_and(|x| !x) {
             self.virtual_keyboard
                 .key(time, key, KeyState::Pressed.into());
@@ -202,7 +510,7 @@ impl<S> InputContext<S> {
             eprintln!("Timer dropped.");
             return TimeoutAction::Drop;
         }
-        TimeoutAction::ToDuration(repeat_rate)
+        TimeoutAction::ToDuration(repeat_interval)
     }
 
     fn composing_update(&mut self, text: String) {
@@ -217,9 +525,60 @@ impl<S> InputContext<S> {
         self.input_method.commit(self.num_done_events);
     }
 
-    fn draw_popup(&mut self) {
-        todo!("Draw popup!");
+    /// Render the input popup for the current buffer: the preedit string as a header row,
+    /// followed by up to five fuzzy-matched candidates (there is no cursor yet, so nothing is
+    /// highlighted beyond the first row).
+    fn draw_popup(&mut self)
+    where
+        S: Dispatch<WlShmPool, ()> + Dispatch<WlBuffer, ()> + 'static,
+    {
+        let Some(buffer) = &self.buffer else {
+            self.hide_popup();
+            return;
+        };
+
+        let candidates: Vec<String> = self
+            .backend
+            .fuzzy_match(buffer)
+            .into_iter()
+            .take(5)
+            .map(|(candidate, _score)| candidate.0.to_owned())
+            .collect();
+
+        if candidates.is_empty() {
+            self.hide_popup();
+            return;
+        }
+
+        let width_hint = self.cursor_rect.map(|rect| rect.width);
+        let Some((surface_buffer, width, height)) =
+            popup::render_candidates(&self.wl_shm, &self.qh, buffer, &candidates, 0, width_hint)
+        else {
+            return;
+        };
+
+        self.wl_surface.attach(Some(&surface_buffer), 0, 0);
+        self.wl_surface.damage(0, 0, width, height);
+        self.wl_surface.commit();
     }
+
+    fn hide_popup(&mut self) {
+        self.wl_surface.attach(None, 0, 0);
+        self.wl_surface.commit();
+    }
+}
+
+/// The focused text field's cursor rectangle, as reported by
+/// `ZwpInputPopupSurfaceV2::text_input_rectangle`.
+#[derive(Clone, Copy)]
+struct CursorRect {
+    #[allow(dead_code)] // not used for positioning yet; only `width` feeds the popup size hint
+    x: i32,
+    #[allow(dead_code)]
+    y: i32,
+    width: i32,
+    #[allow(dead_code)]
+    height: i32,
 }
 
 pub(crate) struct RepeatTimer<S> {
@@ -227,4 +586,7 @@ pub(crate) struct RepeatTimer<S> {
     pub(crate) timestamp: u32,
     pub(crate) timer: Dispatcher<'static, Timer, S>,
     pub(crate) registration: RegistrationToken,
+    /// Whether this timer hasn't fired yet since it was last armed for `keycode`: it's currently
+    /// scheduled after `repeat_delay` rather than `repeat_rate`.
+    pub(crate) is_first: bool,
 }
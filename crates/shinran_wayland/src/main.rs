@@ -15,10 +15,13 @@ use slotmap::{new_key_type, SlotMap};
 use wayland_client::{
     delegate_noop,
     protocol::{
+        wl_buffer::WlBuffer,
         wl_compositor::WlCompositor,
         wl_keyboard::{self, KeyState},
         wl_registry,
         wl_seat::{self, WlSeat},
+        wl_shm::WlShm,
+        wl_shm_pool::WlShmPool,
         wl_surface::{self, WlSurface},
     },
     Connection, Dispatch, QueueHandle, WEnum,
@@ -28,7 +31,7 @@ use wayland_protocols_misc::{
         zwp_input_method_keyboard_grab_v2::{self, ZwpInputMethodKeyboardGrabV2},
         zwp_input_method_manager_v2::ZwpInputMethodManagerV2,
         zwp_input_method_v2::{self, ZwpInputMethodV2},
-        zwp_input_popup_surface_v2::ZwpInputPopupSurfaceV2,
+        zwp_input_popup_surface_v2::{self, ZwpInputPopupSurfaceV2},
     },
     zwp_virtual_keyboard_v1::client::{
         zwp_virtual_keyboard_manager_v1::ZwpVirtualKeyboardManagerV1,
@@ -38,8 +41,10 @@ use wayland_protocols_misc::{
 use xkbcommon::xkb;
 
 use shinran_backend::{Backend, Configuration};
+use shinran_config::config::RMLVOConfig;
 
 mod input_context;
+mod popup;
 
 use input_context::{InputContext, RepeatTimer};
 
@@ -62,6 +67,7 @@ fn main() {
 
     // Set up the backend.
     let backend = Backend::new(&CONFIG.0).unwrap();
+    let keyboard_layout = CONFIG.0.keyboard_layout();
 
     // Set up the Wayland connection.
     let conn = Connection::connect_to_env()
@@ -82,6 +88,7 @@ fn main() {
         seats: vec![],
         contexts: SlotMap::with_key(),
         wl_compositor: None,
+        wl_shm: None,
         input_method_manager: None,
         virtual_keyboard_manager: None,
         loop_handle: loop_handle.clone(),
@@ -92,7 +99,7 @@ fn main() {
     info!("Round trip complete.");
 
     // All the globals should be initialized now, so we can start initializing the protocols.
-    init_protocols(&mut state, &qh, Rc::new(backend));
+    init_protocols(&mut state, &qh, Rc::new(backend), keyboard_layout.as_ref());
     info!("Protocols initialized.");
 
     // Insert the `event_queue` into the calloop's event loop.
@@ -114,7 +121,12 @@ fn main() {
 /// Initialize the protocols we need for the input method.
 ///
 /// This can only be done after we have received all the global objects from the server.
-fn init_protocols(state: &mut State, qh: &QueueHandle<State>, backend: Rc<Backend<'static>>) {
+fn init_protocols(
+    state: &mut State,
+    qh: &QueueHandle<State>,
+    backend: Rc<Backend<'static>>,
+    keyboard_layout: Option<&RMLVOConfig>,
+) {
     let Some(input_method_manager) = &state.input_method_manager else {
         panic!("Compositor does not support zwp_input_method_manager_v2");
     };
@@ -127,6 +139,11 @@ fn init_protocols(state: &mut State, qh: &QueueHandle<State>, backend: Rc<Backen
         panic!("Compositor does not support wl_compositor");
     };
 
+    let Some(wl_shm) = &state.wl_shm else {
+        panic!("Compositor does not support wl_shm");
+    };
+    let wl_shm = wl_shm.clone();
+
     for (seat, seat_id) in state.seats.iter() {
         state.contexts.insert_with_key(|seat_index| {
             // We have to be a bit mindful of race conditions here.
@@ -148,7 +165,10 @@ fn init_protocols(state: &mut State, qh: &QueueHandle<State>, backend: Rc<Backen
                 virtual_keyboard,
                 wl_surface,
                 popup_surface,
+                wl_shm.clone(),
+                qh.clone(),
                 backend,
+                keyboard_layout,
             )
         });
     }
@@ -159,6 +179,7 @@ new_key_type! { struct SeatIndex; }
 struct State {
     running: bool,
     wl_compositor: Option<WlCompositor>,
+    wl_shm: Option<WlShm>,
     input_method_manager: Option<ZwpInputMethodManagerV2>,
     virtual_keyboard_manager: Option<ZwpVirtualKeyboardManagerV1>,
 
@@ -198,6 +219,10 @@ impl Dispatch<wl_registry::WlRegistry, ()> for State {
                     let compositor = registry.bind::<WlCompositor, _, _>(id, 4, qh, ());
                     state.wl_compositor = Some(compositor);
                 }
+                "wl_shm" => {
+                    let shm = registry.bind::<WlShm, _, _>(id, 1, qh, ());
+                    state.wl_shm = Some(shm);
+                }
                 "zwp_input_method_manager_v2" => {
                     let input_man = registry.bind::<ZwpInputMethodManagerV2, _, _>(id, 1, qh, ());
                     state.input_method_manager = Some(input_man);
@@ -283,19 +308,22 @@ impl Dispatch<ZwpInputMethodKeyboardGrabV2, SeatIndex> for State {
                             return;
                         }
                     }
-                    if input_context
-                        .xkb_keymap
-                        .as_ref()
-                        .unwrap()
-                        .key_repeats(keycode)
+                    if input_context.repeat_rate.is_some()
+                        && input_context
+                            .xkb_keymap
+                            .as_ref()
+                            .unwrap()
+                            .key_repeats(keycode)
                     {
                         let repeating = input_context.repeat_timer.as_mut().unwrap();
                         // Update the timer to repeat the new key.
                         debug!("Update repeat timer for {}", key + SCANCODE_OFFSET);
                         repeating.keycode = keycode;
+                        repeating.is_first = true;
                         let repeat_delay = input_context.repeat_delay.unwrap();
                         repeating.timestamp = time + repeat_delay.as_millis() as u32;
-                        // Set timer to start repeating starting from `repeat_delay` milliseconds.
+                        // Set timer to start repeating starting from `repeat_delay` milliseconds;
+                        // it only switches to the `repeat_rate` cadence once it re-arms itself.
                         repeating.timer.as_source_mut().set_duration(repeat_delay);
                         let token = repeating.registration;
                         // Update registration of the timer after we have updated the deadline.
@@ -343,9 +371,12 @@ impl Dispatch<ZwpInputMethodKeyboardGrabV2, SeatIndex> for State {
 
                 // Fourth check:
                 // A key was pressed and we have handled it, and it *could* be repeated.
+                // (A `repeat_rate` of `None` means the compositor asked for repeat to be
+                // disabled entirely, so no timer is created in that case.)
                 let seat_index = *seat_index;
                 let input_context = state.get_context(seat_index);
                 if matches!(key_state, KeyState::Pressed)
+                    && input_context.repeat_rate.is_some()
                     && input_context
                         .xkb_keymap
                         .as_ref()
@@ -354,7 +385,8 @@ impl Dispatch<ZwpInputMethodKeyboardGrabV2, SeatIndex> for State {
                     && handled
                 {
                     let repeat_delay = input_context.repeat_delay.unwrap();
-                    // Set timer to start repeating starting from `repeat_delay` milliseconds.
+                    // The first synthetic event fires after `repeat_delay`; `repeat_key` switches
+                    // to the `repeat_rate` cadence once it re-arms the timer.
                     let timer = Dispatcher::<'static, Timer, State>::new(
                         Timer::from_duration(repeat_delay),
                         move |_instant, _, state| state.get_context(seat_index).repeat_key(),
@@ -371,6 +403,7 @@ impl Dispatch<ZwpInputMethodKeyboardGrabV2, SeatIndex> for State {
                         timer,
                         timestamp: time + repeat_delay.as_millis() as u32,
                         keycode,
+                        is_first: true,
                     });
                     debug!("Repeat timer set for {}", key + SCANCODE_OFFSET);
                     return;
@@ -456,9 +489,11 @@ impl Dispatch<ZwpInputMethodKeyboardGrabV2, SeatIndex> for State {
             }
             zwp_input_method_keyboard_grab_v2::Event::RepeatInfo { rate, delay } => {
                 let input_context = state.get_context(*seat_index);
-                input_context.repeat_rate = Some(Duration::from_millis(rate as u64));
+                // Per the wayland convention, `rate == 0` means repeat is disabled entirely.
+                input_context.repeat_rate =
+                    (rate > 0).then(|| Duration::from_millis(1000 / rate as u64));
                 input_context.repeat_delay = Some(Duration::from_millis(delay as u64));
-                debug!("Repeat rate: {} ms, delay: {} ms.", rate, delay);
+                debug!("Repeat rate: {} chars/s, delay: {} ms.", rate, delay);
             }
             _ => unreachable!("Unknown event."),
         }
@@ -482,14 +517,22 @@ impl Dispatch<ZwpInputMethodV2, SeatIndex> for State {
             zwp_input_method_v2::Event::Deactivate => {
                 input_context.pending_deactivate = true;
             }
-            zwp_input_method_v2::Event::SurroundingText { .. } => {
-                // Nothing.
+            zwp_input_method_v2::Event::SurroundingText {
+                text,
+                cursor,
+                anchor,
+            } => {
+                input_context.set_surrounding_text(text, cursor, anchor);
             }
-            zwp_input_method_v2::Event::TextChangeCause { .. } => {
-                // Nothing.
+            zwp_input_method_v2::Event::TextChangeCause { cause } => {
+                if let WEnum::Value(cause) = cause {
+                    input_context.text_change_cause = Some(cause);
+                }
             }
-            zwp_input_method_v2::Event::ContentType { .. } => {
-                // Nothing.
+            zwp_input_method_v2::Event::ContentType { hint, purpose } => {
+                if let WEnum::Value(purpose) = purpose {
+                    input_context.set_content_type(hint, purpose);
+                }
             }
             zwp_input_method_v2::Event::Done => {
                 input_context.num_done_events += 1;
@@ -501,9 +544,14 @@ impl Dispatch<ZwpInputMethodV2, SeatIndex> for State {
                         input_context.pressed = [xkb::Keycode::default(); 64];
                     }
                 } else if input_context.pending_activate {
-                    // We don't have a keyboard grab, but we are activating.
-                    let keyboard_grab = input_method.grab_keyboard(qh, *seat_index);
-                    input_context.keyboard_grab = Some(keyboard_grab);
+                    // We don't have a keyboard grab, but we are activating. Skip grabbing
+                    // entirely for a sensitive field (password, PIN, hidden/sensitive-data
+                    // hints): the real keyboard then reaches the focused surface directly,
+                    // rather than shinran ever seeing those keystrokes at all.
+                    if input_context.should_grab_keyboard() {
+                        let keyboard_grab = input_method.grab_keyboard(qh, *seat_index);
+                        input_context.keyboard_grab = Some(keyboard_grab);
+                    }
                 }
                 input_context.pending_activate = false;
                 input_context.pending_deactivate = false;
@@ -538,6 +586,31 @@ impl Dispatch<WlSurface, SeatIndex> for State {
     }
 }
 
+impl Dispatch<ZwpInputPopupSurfaceV2, SeatIndex> for State {
+    fn event(
+        state: &mut Self,
+        _popup_surface: &ZwpInputPopupSurfaceV2,
+        event: zwp_input_popup_surface_v2::Event,
+        seat_index: &SeatIndex,
+        _: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwp_input_popup_surface_v2::Event::TextInputRectangle {
+                x,
+                y,
+                width,
+                height,
+            } => {
+                state
+                    .get_context(*seat_index)
+                    .set_cursor_rect(x, y, width, height);
+            }
+            _ => unreachable!("Unknown event."),
+        }
+    }
+}
+
 impl Dispatch<WlSeat, u32> for State {
     fn event(
         state: &mut Self,
@@ -571,7 +644,11 @@ delegate_noop!(State: ignore ZwpInputMethodManagerV2);
 delegate_noop!(State: ignore ZwpVirtualKeyboardV1);
 // Virtual keyboard manager has no events.
 delegate_noop!(State: ignore ZwpVirtualKeyboardManagerV1);
-// We'll ignore the event from ZwpInputPopupSurfaceV2 for now. (Event is "text_input_rectangle".)
-delegate_noop!(State: ignore ZwpInputPopupSurfaceV2);
 // WlCompositor has no events.
 delegate_noop!(State: ignore WlCompositor);
+// We don't need the format advertisements; Argb8888 is guaranteed to be supported.
+delegate_noop!(State: ignore WlShm);
+// WlShmPool has no events.
+delegate_noop!(State: ignore WlShmPool);
+// We release buffers immediately rather than tracking `release` events.
+delegate_noop!(State: ignore WlBuffer);
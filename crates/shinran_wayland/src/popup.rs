@@ -0,0 +1,120 @@
+//! Rendering support for the input popup surface (`zwp_input_popup_surface_v2`), which shows
+//! the current preedit string and candidate-disambiguation list.
+//!
+//! We keep our own `wl_shm`-backed buffer rather than a toolkit, in keeping with the rest of
+//! this crate talking to the compositor directly through `wayland-client`.
+
+use std::os::fd::AsFd;
+
+use wayland_client::{
+    protocol::{wl_buffer::WlBuffer, wl_shm, wl_shm_pool::WlShmPool},
+    QueueHandle,
+};
+
+/// Height, in pixels, of a single candidate row.
+const ROW_HEIGHT: i32 = 24;
+/// Width, in pixels, of the popup.
+const WIDTH: i32 = 240;
+
+const BACKGROUND: [u8; 4] = [40, 40, 40, 255];
+const ROW: [u8; 4] = [60, 60, 60, 255];
+const SELECTED_ROW: [u8; 4] = [70, 110, 180, 255];
+const PREEDIT_ROW: [u8; 4] = [90, 90, 50, 255];
+
+/// Minimum popup width, in pixels, regardless of what the compositor's cursor rectangle hints.
+const MIN_WIDTH: i32 = 80;
+
+/// Render the current preedit string (if any) as a header row, followed by `candidates.len()`
+/// candidate rows, into a freshly allocated `wl_shm` buffer, highlighting `selected`. Returns
+/// the buffer along with its pixel size. `width_hint` is normally the width of the
+/// `text_input_rectangle` the compositor reported for the focused text field; it becomes the
+/// popup's width (at least [`MIN_WIDTH`], and [`WIDTH`] when absent).
+///
+/// This only draws solid rows (a preedit header and a selection highlight); neither the preedit
+/// string nor the candidates are drawn as text.
+pub(crate) fn render_candidates<D>(
+    shm: &wl_shm::WlShm,
+    qh: &QueueHandle<D>,
+    preedit: &str,
+    candidates: &[String],
+    selected: usize,
+    width_hint: Option<i32>,
+) -> Option<(WlBuffer, i32, i32)>
+where
+    D: wayland_client::Dispatch<WlShmPool, ()> + wayland_client::Dispatch<WlBuffer, ()> + 'static,
+{
+    if candidates.is_empty() && preedit.is_empty() {
+        return None;
+    }
+
+    let width = width_hint.unwrap_or(WIDTH).max(MIN_WIDTH);
+    let has_preedit_row = !preedit.is_empty();
+    let row_count = candidates.len() + has_preedit_row as usize;
+    let height = ROW_HEIGHT * row_count as i32;
+    let stride = width * 4;
+    let size = (stride * height) as usize;
+
+    let fd = create_anonymous_file(size)?;
+
+    let mut data = vec![0u8; size];
+    if has_preedit_row {
+        fill_row(&mut data, width, 0, PREEDIT_ROW);
+    }
+    for (candidate_row, _candidate) in candidates.iter().enumerate() {
+        let row = candidate_row as i32 + has_preedit_row as i32;
+        let color = if candidate_row == selected {
+            SELECTED_ROW
+        } else {
+            ROW
+        };
+        fill_row(&mut data, width, row, color);
+    }
+    // Leave a one-pixel border of the background color between rows.
+    for row in 0..row_count as i32 {
+        fill_row_border(&mut data, width, row, BACKGROUND);
+    }
+
+    rustix::io::write(&fd, &data).ok()?;
+
+    let pool = shm.create_pool(fd.as_fd(), stride * height, qh, ());
+    let buffer = pool.create_buffer(
+        0,
+        width,
+        height,
+        stride,
+        wl_shm::Format::Argb8888,
+        qh,
+        (),
+    );
+    pool.destroy();
+
+    Some((buffer, width, height))
+}
+
+fn fill_row(data: &mut [u8], width: i32, row: i32, color: [u8; 4]) {
+    let stride = width * 4;
+    let start = (row * ROW_HEIGHT * stride) as usize;
+    let end = start + (ROW_HEIGHT * stride) as usize;
+    for pixel in data[start..end].chunks_exact_mut(4) {
+        pixel.copy_from_slice(&color);
+    }
+}
+
+fn fill_row_border(data: &mut [u8], width: i32, row: i32, color: [u8; 4]) {
+    let stride = width * 4;
+    let start = (row * ROW_HEIGHT * stride) as usize;
+    for pixel in data[start..start + stride as usize].chunks_exact_mut(4) {
+        pixel.copy_from_slice(&color);
+    }
+}
+
+/// Create an anonymous, already-unlinked shared-memory file of `size` bytes, suitable for
+/// handing a file descriptor to the compositor via `wl_shm_pool`.
+fn create_anonymous_file(size: usize) -> Option<std::fs::File> {
+    use rustix::fs::{MemfdFlags, SealFlags};
+
+    let fd = rustix::fs::memfd_create("shinran-popup", MemfdFlags::CLOEXEC).ok()?;
+    rustix::fs::ftruncate(&fd, size as u64).ok()?;
+    let _ = rustix::fs::fcntl_add_seals(&fd, SealFlags::SHRINK | SealFlags::SEAL);
+    Some(std::fs::File::from(fd))
+}